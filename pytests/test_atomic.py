@@ -0,0 +1,181 @@
+from common import gearsTest
+from common import runUntil
+import os
+
+def getAofCommands(env):
+    dir = env.cmd('CONFIG', 'GET', 'dir')[1]
+    aof_dir = os.path.join(dir, env.cmd('CONFIG', 'GET', 'appenddirname')[1])
+    commands = []
+    for name in sorted(os.listdir(aof_dir)):
+        if not name.endswith('.incr.aof'):
+            continue
+        with open(os.path.join(aof_dir, name), 'rb') as f:
+            data = f.read()
+        pos = 0
+        while pos < len(data):
+            end = data.index(b'\r\n', pos)
+            num_args = int(data[pos + 1:end])
+            pos = end + 2
+            command = []
+            for _ in range(num_args):
+                end = data.index(b'\r\n', pos)
+                arg_len = int(data[pos + 1:end])
+                pos = end + 2
+                command.append(data[pos:pos + arg_len])
+                pos += arg_len + 2
+            commands.append(command)
+    return commands
+
+@gearsTest()
+def testAtomicBlock(env):
+    """#!js name=lib
+redis.register_function("transfer", async function(async_client, from, to, amount){
+    return async_client.atomic((client)=>{
+        client.call('decrby', from, amount);
+        if (parseInt(client.call('get', from)) < 0) {
+            throw 'Insufficient funds';
+        }
+        client.call('incrby', to, amount);
+        return 'OK';
+    });
+});
+    """
+    env.expect('SET', 'a', '10').equal(True)
+    env.expect('RG.FCALL', 'lib', 'transfer', '2', 'a', 'b', '7').equal('OK')
+    env.expect('MGET', 'a', 'b').equal(['3', '7'])
+    env.expect('RG.FCALL', 'lib', 'transfer', '2', 'a', 'b', '7').error().contains('Insufficient funds')
+    env.expect('MGET', 'a', 'b').equal(['3', '7'])
+
+@gearsTest()
+def testAtomicBlockUndo(env):
+    """#!js name=lib
+redis.register_function("fail", async function(async_client){
+    return async_client.atomic((client)=>{
+        client.call('set', 'x', 'bar');
+        client.call('persist', 'x');
+        client.call('del', 'h');
+        client.call('rpush', 'l', '3');
+        client.call('set', 'new', '1');
+        var k = client.open_key('k');
+        k.string_set('bar');
+        throw 'failure';
+    });
+});
+    """
+    env.expect('SET', 'x', 'foo', 'EX', '1000').equal(True)
+    env.expect('HSET', 'h', 'foo', 'bar').equal(1)
+    env.expect('RPUSH', 'l', '1', '2').equal(2)
+    env.expect('SET', 'k', 'foo').equal(True)
+    env.expect('RG.FCALL', 'lib', 'fail', '0').error().contains('failure')
+    env.expect('GET', 'x').equal('foo')
+    env.assertTrue(env.cmd('TTL', 'x') > 900)
+    env.expect('HGETALL', 'h').equal(['foo', 'bar'])
+    env.expect('LRANGE', 'l', '0', '-1').equal(['1', '2'])
+    env.expect('EXISTS', 'new').equal(0)
+    env.expect('GET', 'k').equal('foo')
+    env.expect('TTL', 'k').equal(-1)
+
+@gearsTest()
+def testAtomicBlockErrors(env):
+    """#!js name=lib
+redis.register_function("not_a_function", async function(async_client){
+    return async_client.atomic('foo');
+});
+redis.register_function("async_block", async function(async_client){
+    return async_client.atomic(async (client)=>{
+        client.call('set', 'x', '1');
+    });
+});
+redis.register_function("nested", async function(async_client){
+    return async_client.atomic((client)=>{
+        client.call('set', 'x', '1');
+        async_client.block((c)=>{});
+    });
+});
+redis.register_function("use_after_block", async function(async_client){
+    var c = async_client.atomic((client)=>{
+        return client;
+    });
+    return c.call('ping');
+});
+    """
+    env.expect('RG.FCALL', 'lib', 'not_a_function', '0').error().contains("Argument to 'atomic' must be a function")
+    env.expect('RG.FCALL', 'lib', 'async_block', '0').error().contains("Function given to 'atomic' must not be async")
+    env.expect('EXISTS', 'x').equal(0)
+    env.expect('RG.FCALL', 'lib', 'nested', '0').error().contains('Main thread is already blocked')
+    env.expect('EXISTS', 'x').equal(0)
+    env.expect('RG.FCALL', 'lib', 'use_after_block', '0').error().contains('Used on invalid client')
+
+@gearsTest()
+def testAtomicBlockKeylessWrite(env):
+    """#!js name=lib
+redis.register_function("flush", async function(async_client){
+    return async_client.atomic((client)=>{
+        client.call('set', 'x', '1');
+        return client.call('flushall');
+    });
+});
+redis.register_function("ping", async function(async_client){
+    return async_client.atomic((client)=>{
+        return client.call('ping');
+    });
+});
+    """
+    env.expect('SET', 'y', '1').equal(True)
+    env.expect('RG.FCALL', 'lib', 'flush', '0').error().contains("Write command 'flushall' does not declare the keys it writes to and can not be used inside an atomic block")
+    env.expect('EXISTS', 'x').equal(0)
+    env.expect('GET', 'y').equal('1')
+    env.expect('RG.FCALL', 'lib', 'ping', '0').equal('PONG')
+
+@gearsTest(withReplicas=True, skipOnCluster=True, envArgs={'useAof': True})
+def testAtomicBlockReplication(env):
+    """#!js name=lib
+redis.register_function("update", async function(async_client, fail){
+    return async_client.atomic((client)=>{
+        client.call('get', 'y');
+        client.call('incr', 'x');
+        client.call('hset', 'h', 'foo', 'bar');
+        if (fail == 'yes') {
+            throw 'failure';
+        }
+        return 'OK';
+    });
+});
+    """
+    replica = env.getSlaveConnection()
+    env.expect('SET', 'x', '1').equal(True)
+    env.expect('RG.FCALL', 'lib', 'update', '0', 'yes').error().contains('failure')
+    env.expect('RG.FCALL', 'lib', 'update', '0', 'no').equal('OK')
+    env.expect('RG.FCALL', 'lib', 'update', '0', 'yes').error().contains('failure')
+    env.expect('WAIT', '1', '7000').equal(1)
+    runUntil(env, '2', lambda: replica.execute_command('GET', 'x'))
+    env.assertEqual(replica.execute_command('HGET', 'h', 'foo'), 'bar')
+    env.expect('GET', 'x').equal('2')
+
+    # each block, including the undo of a failed block, is wrapped with a single MULTI/EXEC
+    commands = [[arg.decode('utf-8', 'replace').lower() for arg in c] for c in getAofCommands(env)]
+    commands = [c for c in commands if c[0] != 'select']
+    start = commands.index(['set', 'x', '1']) + 1
+    blocks = []
+    in_block = False
+    for c in commands[start:]:
+        if c[0] == 'multi':
+            env.assertFalse(in_block, message='nested MULTI')
+            in_block = True
+            blocks.append([])
+        elif c[0] == 'exec':
+            env.assertTrue(in_block, message='EXEC without MULTI')
+            in_block = False
+        else:
+            env.assertTrue(in_block, message="'%s' outside of MULTI/EXEC" % c[0])
+            if blocks:
+                blocks[-1].append(c)
+    env.assertFalse(in_block)
+    env.assertEqual([sorted(c[0] for c in block) for block in blocks], [
+        ['del', 'hset', 'incr', 'restore'],
+        ['hset', 'incr'],
+        ['hset', 'incr', 'restore', 'restore'],
+    ])
+    for block in blocks:
+        # 'y' is only read by the block so it is never restored
+        env.assertFalse(any(c[1] == 'y' for c in block))
@@ -1,3 +1,6 @@
+import json
+import struct
+import hashlib
 from common import gearsTest
 from common import toDictionary
 from common import runUntil
@@ -239,6 +242,75 @@ redis.register_function("my_set", function(client, key, val){
     """
     env.expect('RG.FCALL', 'lib', 'my_set', '1', 'foo', 'bar').error().contains('was called while write is not allowed')
 
+@gearsTest()
+def testStrictKeysFlag(env):
+    """#!js name=lib
+redis.register_function("my_set", function(client, ...args){
+    return client.call('set', args[0], args[1]);
+},
+['strict-keys']);
+redis.register_function("my_mset", function(client, ...args){
+    return client.call('mset', ...args);
+},
+['strict-keys']);
+redis.register_function("my_ping", function(client){
+    return client.call('ping');
+},
+['strict-keys']);
+    """
+    env.expect('RG.FCALL', 'lib', 'my_set', '1', 'x', '1').equal('OK')
+    env.expect('RG.FCALL', 'lib', 'my_set', '0', 'x', '1').error().contains("Command 'set' accesses key 'x' which was not declared by the function")
+    env.expect('RG.FCALL_NO_KEYS', 'lib', 'my_set', '0', 'x', '1').error().contains("Command 'set' accesses key 'x' which was not declared by the function")
+    env.expect('RG.FCALL_NO_KEYS', 'lib', 'my_ping', '0').equal('PONG')
+    env.expect('RG.FCALL', 'lib', 'my_mset', '2', '{x}1', '{x}2', '{x}1', '1', '{x}2', '2').equal('OK')
+    env.expect('RG.FCALL', 'lib', 'my_mset', '2', 'x', 'y', 'x', '1', 'y', '2').error().contains("Declared keys belong to different slots")
+    env.expect('RG.FCALL', 'lib', 'my_mset', '1', 'x', 'x', '1', 'y', '2').error().contains("Command 'mset' accesses key 'y' which was not declared by the function")
+    functions = toDictionary(env.cmd('RG.FUNCTION', 'LIST', 'v'), 6)[0]['functions']
+    env.assertEqual([f['flags'] for f in functions], [['strict-keys']] * 3)
+
+@gearsTest()
+def testStrictKeysFlagSlotSpan(env):
+    """#!js name=lib
+redis.register_function("my_get", function(client, ...keys){
+    return keys.map((key) => client.call('get', key));
+},
+['strict-keys']);
+    """
+    env.cmd('set', '{a}x', '1')
+    env.cmd('set', '{b}y', '2')
+    env.cmd('set', '{a}y', '3')
+    env.expect('RG.FCALL', 'lib', 'my_get', '2', '{a}x', '{a}y').equal(['1', '3'])
+    # each command accesses a single slot but together they span two slots
+    env.expect('RG.FCALL', 'lib', 'my_get', '2', '{a}x', '{b}y').error().contains("Declared keys belong to different slots")
+
+@gearsTest()
+def testStrictKeysFlagKeyspaceCommands(env):
+    """#!js name=lib
+redis.register_function("my_call", function(client, ...args){
+    return client.call(...args);
+},
+['strict-keys']);
+    """
+    env.cmd('set', 'x', '1')
+    env.expect('RG.FCALL', 'lib', 'my_call', '0', 'scan', '0').error().contains("Command 'scan' accesses the entire key space which is not allowed on functions with the 'strict-keys' flag")
+    env.expect('RG.FCALL', 'lib', 'my_call', '1', 'x', 'keys', '*').error().contains("Command 'keys' accesses the entire key space")
+    env.expect('RG.FCALL', 'lib', 'my_call', '0', 'randomkey').error().contains("Command 'randomkey' accesses the entire key space")
+    env.expect('RG.FCALL', 'lib', 'my_call', '0', 'flushall').error().contains("Command 'flushall' accesses the entire key space")
+    env.expect('get', 'x').equal('1')
+
+@gearsTest()
+def testStrictKeysFlagAsync(env):
+    """#!js name=lib
+redis.register_function("my_set", async function(client, ...args){
+    return client.block(function(c){
+        return c.call('set', args[0], args[1]);
+    });
+},
+['strict-keys']);
+    """
+    env.expect('RG.FCALL', 'lib', 'my_set', '1', 'x', '1').equal('OK')
+    env.expect('RG.FCALL', 'lib', 'my_set', '0', 'x', '1').error().contains("Command 'set' accesses key 'x' which was not declared by the function")
+
 @gearsTest()
 def testBecomeReplicaWhenFunctionRunning(env):
     """#!js name=lib
@@ -689,3 +761,329 @@ redis.register_function("test", (c) => {
     for i in range(101):
         env.expect('RG.FUNCTION', 'LOAD', code % (i)).equal('OK')
 
+
+@gearsTest(decodeResponses=False)
+def testDumpAndRestore(env):
+    """#!js name=lib
+redis.register_function("test", () => {
+    return 1;
+});
+    """
+    payload = env.cmd('RG.FUNCTION', 'DUMP')
+    env.expect('RG.FUNCTION', 'RESTORE', payload).error().contains('Library lib already exists')
+    env.expect('RG.FUNCTION', 'DEL', 'lib').equal(b'OK')
+    env.expect('RG.FCALL', 'lib', 'test', '0').error().contains('Unknown library')
+    env.expect('RG.FUNCTION', 'RESTORE', payload).equal(b'OK')
+    env.expect('RG.FCALL', 'lib', 'test', '0').equal(1)
+    env.expect('RG.FUNCTION', 'RESTORE', payload, 'REPLACE').equal(b'OK')
+    env.expect('RG.FCALL', 'lib', 'test', '0').equal(1)
+    env.expect('RG.FUNCTION', 'RESTORE', payload, 'FLUSH').equal(b'OK')
+    env.expect('RG.FCALL', 'lib', 'test', '0').equal(1)
+
+@gearsTest(decodeResponses=False)
+def testRestoreCorruptedPayload(env):
+    """#!js name=lib
+redis.register_function("test", () => {
+    return 1;
+});
+    """
+    payload = env.cmd('RG.FUNCTION', 'DUMP')
+    env.expect('RG.FUNCTION', 'RESTORE', payload[:-1] + b'0', 'FLUSH').error().contains('checksum mismatch')
+    env.expect('RG.FUNCTION', 'RESTORE', b'foo', 'FLUSH').error().contains('not a valid RedisGears dump')
+    env.expect('RG.FUNCTION', 'RESTORE', payload, 'foo').error().contains('Unknown restore policy')
+    env.expect('RG.FCALL', 'lib', 'test', '0').equal(1)
+
+@gearsTest()
+def testRestoreFlushRevert(env):
+    """#!js name=lib
+redis.register_function("test", () => {
+    return 1;
+});
+    """
+    body = json.dumps([
+        {'name': 'lib2', 'code': "#!js name=lib2\nredis.register_function('test', () => {return 2;});", 'user': 'default', 'config': None, 'gears_box_info': None, 'stream_consumers': []},
+        {'name': 'lib3', 'code': "#!js name=lib3\nfoo();", 'user': 'default', 'config': None, 'gears_box_info': None, 'stream_consumers': []},
+    ])
+    payload = b'RGDUMP' + struct.pack('<I', 1) + body.encode() + hashlib.sha256(body.encode()).hexdigest().encode()
+    env.expect('RG.FUNCTION', 'RESTORE', payload, 'FLUSH').error().contains("Failed restoring library 'lib3'")
+    env.expect('RG.FCALL', 'lib', 'test', '0').equal(1)
+    env.expect('RG.FCALL', 'lib2', 'test', '0').error().contains('Unknown library')
+
+@gearsTest()
+def testRestoreReplaceRevert(env):
+    """#!js name=lib
+redis.register_function("test", () => {
+    return 1;
+});
+    """
+    body = json.dumps([
+        {'name': 'lib', 'code': "#!js name=lib\nredis.register_function('test', () => {return 2;});", 'user': 'default', 'config': None, 'gears_box_info': None, 'stream_consumers': []},
+        {'name': 'lib3', 'code': "#!js name=lib3\nfoo();", 'user': 'default', 'config': None, 'gears_box_info': None, 'stream_consumers': []},
+    ])
+    payload = b'RGDUMP' + struct.pack('<I', 2) + body.encode() + hashlib.sha256(body.encode()).hexdigest().encode()
+    env.expect('RG.FUNCTION', 'RESTORE', payload, 'REPLACE').error().contains("Failed restoring library 'lib3'")
+    # the library that was replaced before the failure is put back
+    env.expect('RG.FCALL', 'lib', 'test', '0').equal(1)
+    env.expect('RG.FCALL', 'lib3', 'test', '0').error().contains('Unknown library')
+
+@gearsTest(withReplicas=True, decodeResponses=False)
+def testRestoreReplicated(env):
+    """#!js name=lib
+redis.register_function("test", () => {
+    return 1;
+});
+    """
+    payload = env.cmd('RG.FUNCTION', 'DUMP')
+    env.expect('RG.FUNCTION', 'DEL', 'lib').equal(b'OK')
+    env.expect('RG.FUNCTION', 'RESTORE', payload).equal(b'OK')
+    env.expect('WAIT', '1', '7000').equal(1)
+    slave_conn = env.getSlaveConnection()
+    res = slave_conn.execute_command('RG.FUNCTION', 'LIST')
+    env.assertEqual(len(res), 1)
+
+@gearsTest()
+def testFunctionFlush(env):
+    """#!js name=lib
+redis.register_notifications_consumer("consumer", "", function(client, data) {
+    client.call('incr', 'notifications');
+});
+redis.register_function("test", () => {
+    return 1;
+});
+    """
+    env.expect('RG.FUNCTION', 'LOAD', "#!js name=lib2\nredis.register_function('test', () => {return 2;});").equal('OK')
+    env.expect('RG.FUNCTION', 'FLUSH').equal('OK')
+    env.expect('RG.FUNCTION', 'LIST').equal([])
+    env.expect('RG.FCALL', 'lib', 'test', '0').error().contains('Unknown library')
+    env.expect('SET', 'x', '1').equal(True)
+    env.expect('GET', 'notifications').equal(None)
+
+@gearsTest()
+def testFunctionFlushAsync(env):
+    """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, true, function(client) {
+    client.call('incr', 'records');
+});
+    """
+    env.expect('RG.FUNCTION', 'FLUSH', 'ASYNC').equal('OK')
+    env.expect('RG.FUNCTION', 'LIST').equal([])
+    env.cmd('XADD', 'stream:1', '*', 'foo', 'bar')
+    env.expect('GET', 'records').equal(None)
+    env.expect('RG.FUNCTION', 'FLUSH', 'foo').error().contains('Unknown flush mode')
+
+    # libraries released on the background do not interfere with new libraries
+    code = "#!js name=lib%d\nredis.register_function('test', () => {return %d;});"
+    for i in range(10):
+        env.expect('RG.FUNCTION', 'LOAD', code % (i, i)).equal('OK')
+    env.expect('RG.FUNCTION', 'FLUSH', 'ASYNC').equal('OK')
+    env.expect('RG.FUNCTION', 'LOAD', code % (1, 2)).equal('OK')
+    env.expect('RG.FCALL', 'lib1', 'test', '0').equal(2)
+    env.expect('RG.FCALL', 'lib2', 'test', '0').error().contains('Unknown library')
+
+@gearsTest(withReplicas=True)
+def testFunctionFlushReplicated(env):
+    """#!js name=lib
+redis.register_function("test", () => {
+    return 1;
+});
+    """
+    env.expect('RG.FUNCTION', 'FLUSH').equal('OK')
+    env.expect('WAIT', '1', '7000').equal(1)
+    slave_conn = env.getSlaveConnection()
+    res = slave_conn.execute_command('RG.FUNCTION', 'LIST')
+    env.assertEqual(len(res), 0)
+
+@gearsTest()
+def testFunctionStats(env):
+    """#!js name=lib
+redis.register_function("test", (c, arg) => {
+    if (arg == "fail") {
+        throw "Some Error";
+    }
+    return 1;
+});
+
+redis.register_function("test_async", async (c) => {
+    return 1;
+});
+    """
+    env.expect('RG.FCALL', 'lib', 'test', '0', 'ok').equal(1)
+    env.expect('RG.FCALL', 'lib', 'test', '0', 'fail').error().contains('Some Error')
+    env.expect('RG.FCALL', 'lib', 'test_async', '0').equal(1)
+    res = toDictionary(env.cmd('RG.FUNCTION', 'LIST', 'vv'), 6)
+    functions = {f['name']: f for f in res[0]['functions']}
+    env.assertEqual(functions['test']['num_calls'], 2)
+    env.assertEqual(functions['test']['num_finished'], 2)
+    env.assertEqual(functions['test']['num_errors'], 1)
+    env.assertEqual(functions['test']['num_async_calls'], 0)
+    env.assertContains('Some Error', functions['test']['last_error'])
+    env.assertEqual(functions['test_async']['num_calls'], 1)
+    env.assertEqual(functions['test_async']['num_async_calls'], 1)
+    runUntil(env, 1, lambda: {f['name']: f for f in toDictionary(env.cmd('RG.FUNCTION', 'LIST', 'vv'), 6)[0]['functions']}['test_async']['num_finished'])
+
+    env.expect('RG.FUNCTION', 'STATS', 'RESET', 'foo').error().contains('Unknown library')
+    env.expect('RG.FUNCTION', 'STATS', 'RESET', 'lib').equal('OK')
+    res = toDictionary(env.cmd('RG.FUNCTION', 'LIST', 'vv'), 6)
+    functions = {f['name']: f for f in res[0]['functions']}
+    env.assertEqual(functions['test']['num_calls'], 0)
+    env.assertEqual(functions['test']['num_errors'], 0)
+    env.assertEqual(functions['test']['last_error'], 'None')
+
+@gearsTest()
+def testLibraryHeaderProperties(env):
+    """#!js name=lib version=1.2.0 description="some library" api_version=1.0 min_gears_version=0.0.1
+redis.register_function("test", () => {
+    return 1;
+});
+    """
+    res = toDictionary(env.cmd('RG.FUNCTION', 'LIST'), 6)[0]
+    env.assertEqual(res['version'], '1.2.0')
+    env.assertEqual(res['description'], 'some library')
+    env.assertEqual(res['api_version'], '1.0')
+    env.assertEqual(res['min_gears_version'], '0.0.1')
+    env.expect('debug', 'reload').equal('OK')
+    res = toDictionary(env.cmd('RG.FUNCTION', 'LIST'), 6)[0]
+    env.assertEqual(res['version'], '1.2.0')
+    env.assertEqual(res['description'], 'some library')
+
+@gearsTest()
+def testLibraryHeaderErrors(env):
+    env.expect('RG.FUNCTION', 'LOAD', "#!js name=lib foo=bar\nredis.register_function('test', () => {return 1;});").error().contains("unknown property 'foo'")
+    env.expect('RG.FUNCTION', 'LOAD', "#!js name=lib version=a.b\nredis.register_function('test', () => {return 1;});").error().contains("Invalid version 'a.b'")
+    env.expect('RG.FUNCTION', 'LOAD', "#!js name=lib description=\"foo\nredis.register_function('test', () => {return 1;});").error().contains("missing closing quote")
+    env.expect('RG.FUNCTION', 'LOAD', "#!js name=lib api_version=2.0\nredis.register_function('test', () => {return 1;});").error().contains("api version '2.0' which is not supported")
+    env.expect('RG.FUNCTION', 'LOAD', "#!js name=lib min_gears_version=1000.0.0\nredis.register_function('test', () => {return 1;});").error().contains("requires RedisGears version '1000.0.0'")
+
+@gearsTest()
+def testLibraryVersionDowngrade(env):
+    """#!js name=lib version=1.2
+redis.register_function("test", () => {
+    return 1;
+});
+    """
+    code = "#!js name=lib version=%s\nredis.register_function('test', () => {return %d;});"
+    env.expect('RG.FUNCTION', 'LOAD', 'UPGRADE', code % ('1.1.9', 2)).error().contains("Can not downgrade library lib from version '1.2' to version '1.1.9'")
+    env.expect('RG.FCALL', 'lib', 'test', '0').equal(1)
+    env.expect('RG.FUNCTION', 'LOAD', 'UPGRADE', code % ('1.2.0', 2)).equal('OK')
+    env.expect('RG.FCALL', 'lib', 'test', '0').equal(2)
+    env.expect('RG.FUNCTION', 'LOAD', 'UPGRADE', 'FORCE', code % ('1.0', 3)).equal('OK')
+    env.expect('RG.FCALL', 'lib', 'test', '0').equal(3)
+    env.expect('RG.FUNCTION', 'LOAD', 'FORCE', code % ('1.0', 3)).error().contains('FORCE can only be used with UPGRADE')
+
+@gearsTest()
+def testLibraryDependencies(env):
+    """#!js name=common version=1.2.0
+redis.export_function('format', (id, amount) => {
+    return `order ${id}: ${amount}`;
+});
+    """
+    orders = """#!js name=orders requires=common@>=1.2
+var common = redis.import_library('common');
+redis.register_function('get_order', (client, id) => {
+    return common.format(id, client.call('get', id));
+});
+    """
+    env.expect('RG.FUNCTION', 'LOAD', orders).equal('OK')
+    env.cmd('set', 'x', '10')
+    env.expect('RG.FCALL', 'orders', 'get_order', '0', 'x').equal('order x: 10')
+    res = toDictionary(env.cmd('RG.FUNCTION', 'LIST', 'LIBRARY', 'orders'), 6)[0]
+    env.assertEqual(res['requires'], 'common@>=1.2')
+
+    env.expect('RG.FUNCTION', 'DEL', 'common').error().contains('library is used by the following libraries: orders')
+
+    # upgrading common reloads orders with the new exported functions
+    env.expect('RG.FUNCTION', 'LOAD', 'UPGRADE', """#!js name=common version=1.3.0
+redis.export_function('format', (id, amount) => {
+    return `new order ${id}: ${amount}`;
+});
+    """).equal('OK')
+    env.expect('RG.FCALL', 'orders', 'get_order', '0', 'x').equal('new order x: 10')
+
+    # new version must satisfy the dependents constraints
+    env.expect('RG.FUNCTION', 'LOAD', 'UPGRADE', 'FORCE', """#!js name=common version=1.0.0
+redis.export_function('format', (id, amount) => {
+    return 'foo';
+});
+    """).error().contains("Library orders requires library common with version '>=1.2'")
+
+    env.expect('debug', 'reload').equal('OK')
+    env.expect('RG.FCALL', 'orders', 'get_order', '0', 'x').equal('new order x: 10')
+
+    env.expect('RG.FUNCTION', 'DEL', 'orders').equal('OK')
+    env.expect('RG.FUNCTION', 'DEL', 'common').equal('OK')
+
+@gearsTest()
+def testLibraryDependencyFailedReload(env):
+    """#!js name=common version=1.0
+redis.export_function('format', (id) => {
+    return `order ${id}`;
+});
+    """
+    orders = """#!js name=orders requires=common
+var common = redis.import_library('common');
+common.format('validate');
+redis.register_function('get_order', (client, id) => {
+    return common.format(id);
+});
+    """
+    env.expect('RG.FUNCTION', 'LOAD', orders).equal('OK')
+    env.expect('RG.FCALL', 'orders', 'get_order', '0', 'x').equal('order x')
+
+    # orders fails to reload with the new version, it must not keep the old version alive
+    env.expect('RG.FUNCTION', 'LOAD', 'UPGRADE', """#!js name=common version=1.1
+redis.export_function('new_format', (id) => {
+    return `new order ${id}`;
+});
+    """).equal('OK')
+    env.expect('RG.FCALL', 'orders', 'get_order', '0', 'x').error().contains('Library common was upgraded or deleted, the library must be reloaded in order to use it')
+
+@gearsTest()
+def testLibraryDependenciesErrors(env):
+    """#!js name=common version=1.0
+redis.export_function('foo', () => {
+    return 'foo';
+});
+    """
+    env.expect('RG.FUNCTION', 'LOAD', "#!js name=lib requires=bar\nredis.register_function('test', () => {return 1;});").error().contains('Library lib requires library bar which does not exists')
+    env.expect('RG.FUNCTION', 'LOAD', "#!js name=lib requires=common@>=1.2\nredis.register_function('test', () => {return 1;});").error().contains("Library lib requires library common with version '>=1.2', found version '1.0'")
+    env.expect('RG.FUNCTION', 'LOAD', "#!js name=lib\nredis.import_library('common');\nredis.register_function('test', () => {return 1;});").error().contains("Library common was not declared on the library 'requires' property")
+    env.expect('RG.FUNCTION', 'LOAD', "#!js name=lib\nredis.export_function('test', async () => {return 1;});").error().contains('Exported function can not be async')
+
+@gearsTest()
+def testLibraryBundle(env):
+    bundle = {
+        'entry': 'main.js',
+        'modules': {
+            'main.js': "import { add } from './math.js';\nredis.register_function('add', (c, a, b) => add(parseInt(a), parseInt(b)));",
+            'math.js': "export function add(a, b) { return a + b; }",
+        }
+    }
+    env.expect('RG.FUNCTION', 'LOAD', '#!js name=lib type=bundle\n%s' % json.dumps(bundle)).equal('OK')
+    env.expect('RG.FCALL', 'lib', 'add', '0', '1', '2').equal(3)
+    res = toDictionary(env.cmd('RG.FUNCTION', 'LIST'), 6)[0]
+    env.assertEqual(res['type'], 'bundle')
+    env.expect('debug', 'reload').equal('OK')
+    env.expect('RG.FCALL', 'lib', 'add', '0', '1', '2').equal(3)
+
+@gearsTest(withReplicas=True)
+def testLibraryBundleReplicated(env):
+    bundle = {
+        'entry': 'main.js',
+        'modules': {
+            'main.js': "import { name } from 'name.js';\nredis.register_function('name', () => name, ['no-writes']);",
+            'name.js': "export const name = 'foo';",
+        }
+    }
+    env.expect('RG.FUNCTION', 'LOAD', '#!js name=lib type=bundle\n%s' % json.dumps(bundle)).equal('OK')
+    env.expect('WAIT', '1', '7000').equal(1)
+    slave_conn = env.getSlaveConnection()
+    res = toDictionary(slave_conn.execute_command('RG.FUNCTION', 'LIST'), 6)[0]
+    env.assertEqual(res['type'], 'bundle')
+    env.assertEqual(res['functions'], ['name'])
+
+@gearsTest()
+def testLibraryBundleErrors(env):
+    env.expect('RG.FUNCTION', 'LOAD', '#!js name=lib type=bundle\nfoo').error().contains('Failed parsing library bundle')
+    env.expect('RG.FUNCTION', 'LOAD', '#!js name=lib type=bundle\n%s' % json.dumps({'entry': 'main.js', 'modules': {}})).error().contains("Bundle entry module 'main.js' does not exists")
+    env.expect('RG.FUNCTION', 'LOAD', '#!js name=lib type=bundle\n%s' % json.dumps({'entry': 'main.js', 'modules': {'main.js': "import { foo } from './foo.js';"}})).error().contains("Module 'foo.js' was not found on the library bundle")
+    env.expect('RG.FUNCTION', 'LOAD', '#!js name=lib type=foo\nredis.register_function("test", () => 1);').error().contains("unknown library type 'foo'")
@@ -0,0 +1,220 @@
+from common import gearsTest
+from common import toDictionary
+from common import runUntil
+from common import runFor
+
+def getChannelConsumer(env, index=0):
+    res = env.cmd('RG.FUNCTION', 'LIST', 'vvv')
+    return toDictionary(res, 6)[0]['channel_consumers'][index]
+
+@gearsTest(skipOnCluster=True)
+def testChannelConsumer(env):
+    """#!js name=lib
+redis.register_channel_consumer("consumer", "orders", function(client, data) {
+    client.call('rpush', 'messages', data.channel + ':' + data.message);
+});
+    """
+    env.expect('PUBLISH', 'orders', 'order1').equal(0)
+    env.expect('PUBLISH', 'orders', 'order2').equal(0)
+    env.expect('PUBLISH', 'other', 'order3').equal(0)
+    runUntil(env, ['orders:order1', 'orders:order2'], lambda: env.cmd('LRANGE', 'messages', '0', '-1'))
+    res = getChannelConsumer(env)
+    env.assertEqual(res['name'], 'consumer')
+    env.assertEqual(res['channel'], 'orders')
+    env.assertEqual(res['run_on_replicas'], 'disabled')
+    env.assertEqual(res['num_triggered'], 2)
+    env.assertEqual(res['num_success'], 2)
+
+@gearsTest(skipOnCluster=True)
+def testChannelConsumerPattern(env):
+    """#!js name=lib
+redis.register_channel_consumer("consumer", {pattern: "orders:*"}, function(client, data) {
+    client.call('rpush', 'messages', data.channel);
+});
+    """
+    env.cmd('PUBLISH', 'orders:1', 'foo')
+    env.cmd('PUBLISH', 'other', 'foo')
+    env.cmd('PUBLISH', 'orders:2', 'foo')
+    runUntil(env, ['orders:1', 'orders:2'], lambda: env.cmd('LRANGE', 'messages', '0', '-1'))
+    env.assertEqual(getChannelConsumer(env)['pattern'], 'orders:*')
+
+@gearsTest(skipOnCluster=True)
+def testChannelConsumerSharded(env):
+    """#!js name=lib
+redis.register_channel_consumer("consumer", "orders", function(client, data) {
+    client.call('rpush', 'messages', data.message);
+});
+    """
+    env.cmd('SPUBLISH', 'orders', 'foo')
+    runUntil(env, ['foo'], lambda: env.cmd('LRANGE', 'messages', '0', '-1'))
+
+@gearsTest(skipOnCluster=True)
+def testChannelConsumerFromFunction(env):
+    """#!js name=lib
+redis.register_channel_consumer("consumer", "orders", function(client, data) {
+    client.call('rpush', 'messages', data.message);
+});
+redis.register_function("publish", function(client) {
+    return client.call('publish', 'orders', 'foo');
+});
+    """
+    env.expect('RG.FCALL', 'lib', 'publish', '0').equal(0)
+    runUntil(env, ['foo'], lambda: env.cmd('LRANGE', 'messages', '0', '-1'))
+
+@gearsTest(skipOnCluster=True)
+def testChannelConsumerAsync(env):
+    """#!js name=lib
+redis.register_channel_consumer("consumer", "orders", async function(client, data) {
+    client.block(function(c) {
+        c.call('rpush', 'messages', data.message);
+    });
+});
+    """
+    env.cmd('PUBLISH', 'orders', 'foo')
+    runUntil(env, ['foo'], lambda: env.cmd('LRANGE', 'messages', '0', '-1'))
+
+@gearsTest(skipOnCluster=True, decodeResponses=False)
+def testChannelConsumerBinaryMessage(env):
+    """#!js name=lib
+redis.register_channel_consumer("consumer", "orders", function(client, data) {
+    client.call('set', 'has_message', (data.message === undefined).toString());
+    client.call('set', 'raw_message', data.message_raw);
+});
+    """
+    env.cmd('PUBLISH', 'orders', b'\xaa')
+    runUntil(env, b'true', lambda: env.cmd('GET', 'has_message'))
+    env.expect('GET', 'raw_message').equal(b'\xaa')
+
+@gearsTest(skipOnCluster=True)
+def testChannelConsumerError(env):
+    """#!js name=lib
+redis.register_channel_consumer("consumer", "orders", function(client, data) {
+    throw 'Some error';
+});
+    """
+    env.cmd('PUBLISH', 'orders', 'foo')
+    runUntil(env, 1, lambda: getChannelConsumer(env)['num_failed'])
+    env.assertContains('Some error', getChannelConsumer(env)['last_error'])
+
+@gearsTest(skipOnCluster=True)
+def testChannelConsumerUpgrade(env):
+    code = """#!js name=lib
+redis.register_channel_consumer("consumer", "orders", function(client, data) {
+    client.call('set', '%s', data.message);
+});
+    """
+    env.expect('RG.FUNCTION', 'LOAD', code % 'x').equal('OK')
+    env.cmd('PUBLISH', 'orders', 'foo')
+    runUntil(env, 'foo', lambda: env.cmd('GET', 'x'))
+    env.expect('RG.FUNCTION', 'LOAD', 'UPGRADE', code % 'y').equal('OK')
+    env.cmd('PUBLISH', 'orders', 'bar')
+    runUntil(env, 'bar', lambda: env.cmd('GET', 'y'))
+    env.assertEqual(env.cmd('GET', 'x'), 'foo')
+    env.assertEqual(getChannelConsumer(env)['num_triggered'], 2)
+
+@gearsTest(skipOnCluster=True)
+def testChannelConsumerDelete(env):
+    """#!js name=lib
+redis.register_channel_consumer("consumer", "orders", function(client, data) {
+    client.call('incr', 'x');
+});
+    """
+    env.cmd('PUBLISH', 'orders', 'foo')
+    runUntil(env, '1', lambda: env.cmd('GET', 'x'))
+    env.expect('RG.FUNCTION', 'DELETE', 'lib').equal('OK')
+    env.cmd('PUBLISH', 'orders', 'foo')
+    runFor('1', lambda: env.cmd('GET', 'x'), timeout=0.5)
+
+@gearsTest(skipOnCluster=True, withReplicas=True)
+def testChannelConsumerOnReplica(env):
+    """#!js name=lib
+var primary_messages = 0;
+var replica_messages = 0;
+redis.register_channel_consumer("primary_consumer", "orders", function(client, data) {
+    primary_messages += 1;
+});
+redis.register_channel_consumer("replica_consumer", "orders", function(client, data) {
+    replica_messages += 1;
+}, {run_on_replicas: true});
+redis.register_function("messages", function(client) {
+    return [primary_messages, replica_messages];
+}, ['no-writes']);
+    """
+    replica = env.getSlaveConnection()
+    env.expect('WAIT', '1', '7000').equal(1)
+    replica.execute_command('PUBLISH', 'orders', 'foo')
+    runUntil(env, [0, 1], lambda: replica.execute_command('RG.FCALL', 'lib', 'messages', '0'))
+    runFor([0, 1], lambda: replica.execute_command('RG.FCALL', 'lib', 'messages', '0'), timeout=0.5)
+
+    # a message published on the primary is replicated to the replica but
+    # is not passed to the replica consumers
+    env.cmd('PUBLISH', 'orders', 'bar')
+    runUntil(env, [1, 1], lambda: env.cmd('RG.FCALL', 'lib', 'messages', '0'))
+    env.expect('WAIT', '1', '7000').equal(1)
+    runFor([0, 1], lambda: replica.execute_command('RG.FCALL', 'lib', 'messages', '0'), timeout=0.5)
+
+@gearsTest(skipOnCluster=True)
+def testChannelConsumerMultiDiscard(env):
+    """#!js name=lib
+redis.register_channel_consumer("consumer", "orders", function(client, data) {
+    client.call('rpush', 'messages', data.message);
+});
+    """
+    conn = env.getConnection()
+    conn.execute_command('MULTI')
+    conn.execute_command('PUBLISH', 'orders', 'discarded')
+    conn.execute_command('DISCARD')
+    conn.execute_command('MULTI')
+    conn.execute_command('PUBLISH', 'orders', 'executed')
+    env.assertEqual(conn.execute_command('EXEC'), [0])
+    runUntil(env, ['executed'], lambda: env.cmd('LRANGE', 'messages', '0', '-1'))
+    runFor(['executed'], lambda: env.cmd('LRANGE', 'messages', '0', '-1'), timeout=0.5)
+    env.assertEqual(getChannelConsumer(env)['num_triggered'], 1)
+
+@gearsTest(skipOnCluster=True)
+def testChannelConsumerAclReject(env):
+    """#!js name=lib
+redis.register_channel_consumer("consumer", "orders", function(client, data) {
+    client.call('rpush', 'messages', data.message);
+});
+    """
+    env.expect('ACL', 'SETUSER', 'alice', 'on', '>pass', '~*', '&*', '+@all', '-publish').equal('OK')
+    conn = env.getConnection()
+    conn.execute_command('AUTH', 'alice', 'pass')
+    env.expect('PUBLISH', 'orders', 'allowed').equal(0)
+    try:
+        conn.execute_command('PUBLISH', 'orders', 'rejected')
+        env.assertTrue(False, message='PUBLISH should have been rejected')
+    except Exception as e:
+        env.assertContains('NOPERM', str(e))
+    runUntil(env, ['allowed'], lambda: env.cmd('LRANGE', 'messages', '0', '-1'))
+    runFor(['allowed'], lambda: env.cmd('LRANGE', 'messages', '0', '-1'), timeout=0.5)
+
+@gearsTest(skipOnCluster=True)
+def testChannelConsumerErrors(env):
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_channel_consumer("consumer", "orders", "foo");
+    """).error().contains("Third argument to 'register_channel_consumer' must be a function")
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_channel_consumer("consumer", 1, function(){});
+    """).error().contains("Second argument to 'register_channel_consumer' must be a String or ArrayBuffer representing the channel or an object")
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_channel_consumer("consumer", {channel: "foo", pattern: "bar"}, function(){});
+    """).error().contains("Only one of 'channel' or 'pattern' can be given")
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_channel_consumer("consumer", {}, function(){});
+    """).error().contains("One of 'channel' or 'pattern' must be given")
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_channel_consumer("consumer", "orders", function(){}, {run_on_replicas: 1});
+    """).error().contains("'run_on_replicas' option must be a Boolean")
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_channel_consumer("consumer", "orders", function(){});
+redis.register_channel_consumer("consumer", "orders", function(){});
+    """).error().contains("Channel consumer consumer already exists")
+
+@gearsTest(cluster=True)
+def testChannelConsumerOnCluster(env):
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_channel_consumer("consumer", "orders", function(){});
+    """).error().contains("Channel consumers are not supported on a cluster")
+    env.expect('RG.FUNCTION', 'LIST').equal([])
@@ -173,3 +173,19 @@ redis.register_function("test", async (async_client) => {
     """
     cluster_conn.execute_command('set', 'z', '1')
     env.expect('RG.FCALL', 'foo', 'test', '1', 'z').error().contains('Timeout')
+
+@gearsTest(cluster=True, decodeResponses=False)
+def testRestoreOnAllShards(env, cluster_conn):
+    """#!js name=foo
+redis.register_function("test", () => {
+    return 1;
+});
+    """
+    conn = env.getConnection(shardId=1)
+    payload = conn.execute_command('RG.FUNCTION', 'DUMP')
+    conn.execute_command('RG.FUNCTION', 'DEL', 'foo')
+    for c in shardsConnections(env):
+        env.assertEqual(c.execute_command('RG.FUNCTION', 'LIST'), [])
+    env.assertEqual(conn.execute_command('RG.FUNCTION', 'RESTORE', payload), b'OK')
+    for c in shardsConnections(env):
+        env.assertEqual(c.execute_command('RG.FCALL_NO_KEYS', 'foo', 'test', '0'), 1)
@@ -0,0 +1,108 @@
+from common import gearsTest
+from common import toDictionary
+
+@gearsTest()
+def testArgumentsSchema(env):
+    """#!js name=lib
+redis.register_function("test", function(client, key, count, ratio, data) {
+    return [key, typeof count, count + 1, typeof ratio, ratio.toString(), data.foo];
+}, [], [
+    {name: 'key', type: 'string'},
+    {name: 'count', type: 'int'},
+    {name: 'ratio', type: 'float'},
+    {name: 'data', type: 'json'}
+]);
+    """
+    env.expect('RG.FCALL', 'lib', 'test', '1', 'x', '1', '1.5', '{"foo": "bar"}').equal(['x', 'number', 2, 'number', '1.5', 'bar'])
+    env.expect('RG.FCALL', 'lib', 'test', '1', 'x', 'foo', '1.5', '{}').error().contains("Argument 'count' must be an integer")
+    env.expect('RG.FCALL', 'lib', 'test', '1', 'x', '1', 'foo', '{}').error().contains("Argument 'ratio' must be a float")
+    env.expect('RG.FCALL', 'lib', 'test', '1', 'x', '1', '1.5', '{').error().contains("Argument 'data' must be a valid json")
+    env.expect('RG.FCALL', 'lib', 'test', '1', 'x', '1', '1.5').error().contains("Missing required argument 'data'")
+    env.expect('RG.FCALL', 'lib', 'test', '1', 'x', '1', '1.5', '{}', 'foo').error().contains("Too many arguments were given, expected at most 4 arguments, got 5 arguments.")
+
+@gearsTest()
+def testArgumentsSchemaOptionalAndVariadic(env):
+    """#!js name=lib
+redis.register_function("test", function(client, key, ...values) {
+    return [key].concat(values);
+}, [], [
+    {name: 'key', type: 'string'},
+    {name: 'values', type: 'int', optional: true, variadic: true}
+]);
+redis.register_function("test_required", function(client, ...values) {
+    return values;
+}, [], [
+    {name: 'values', type: 'string', variadic: true}
+]);
+    """
+    env.expect('RG.FCALL', 'lib', 'test', '1', 'x').equal(['x'])
+    env.expect('RG.FCALL', 'lib', 'test', '1', 'x', '1', '2', '3').equal(['x', 1, 2, 3])
+    env.expect('RG.FCALL', 'lib', 'test', '1', 'x', '1', 'foo').error().contains("Argument 'values' must be an integer")
+    env.expect('RG.FCALL', 'lib', 'test_required', '0', 'foo', 'bar').equal(['foo', 'bar'])
+    env.expect('RG.FCALL', 'lib', 'test_required', '0').error().contains("Missing required argument 'values'")
+
+@gearsTest(decodeResponses=False)
+def testArgumentsSchemaBinary(env):
+    """#!js name=lib
+redis.register_function("test", function(client, key, data) {
+    return [typeof key, data];
+}, [], [
+    {name: 'key', type: 'string'},
+    {name: 'data', type: 'binary'}
+]);
+    """
+    env.expect('RG.FCALL', 'lib', 'test', '1', 'x', b'\xaa').equal([b'string', b'\xaa'])
+    env.expect('RG.FCALL', 'lib', 'test', '1', b'\xaa', b'\xaa').error().contains("Argument 'key' must be a valid utf8 string")
+
+@gearsTest()
+def testArgumentsSchemaAsync(env):
+    """#!js name=lib
+redis.register_function("test", async function(client, count) {
+    return count + 1;
+}, [], [
+    {name: 'count', type: 'int'}
+]);
+    """
+    env.expect('RG.FCALL', 'lib', 'test', '0', '1').equal(2)
+    env.expect('RG.FCALL', 'lib', 'test', '0', 'foo').error().contains("Argument 'count' must be an integer")
+
+@gearsTest()
+def testArgumentsSchemaSignature(env):
+    """#!js name=lib
+redis.register_function("test", function(client) {
+    return 1;
+}, [], [
+    {name: 'key', type: 'string'},
+    {name: 'count', type: 'int'},
+    {name: 'ratio', type: 'float', optional: true},
+    {name: 'members', type: 'binary', optional: true, variadic: true}
+]);
+redis.register_function("no_schema", function(client) {
+    return 1;
+});
+    """
+    functions = toDictionary(env.cmd('RG.FUNCTION', 'LIST', 'v'), 6)[0]['functions']
+    signatures = {f['name']: f['signature'] for f in functions}
+    env.assertEqual(signatures['test'], 'key:string count:int [ratio:float] [members:binary]...')
+    env.assertEqual(signatures['no_schema'], None)
+
+@gearsTest()
+def testArgumentsSchemaErrors(env):
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_function("test", function(client) {}, [], [{name: 'foo', type: 'bar'}]);
+    """).error().contains("Failed parsing arguments schema, Unknown argument type 'bar'")
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_function("test", function(client) {}, [], [{type: 'int'}]);
+    """).error().contains("Failed parsing arguments schema, Argument 'name' must be a String")
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_function("test", function(client) {}, [], [{name: 'foo', type: 'int', optional: 1}]);
+    """).error().contains("Failed parsing arguments schema, 'optional' must be a Boolean")
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_function("test", function(client) {}, [], [{name: 'foo', type: 'int'}, {name: 'foo', type: 'int'}]);
+    """).error().contains("Invalid arguments schema for function test, Argument 'foo' was given more than once")
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_function("test", function(client) {}, [], [{name: 'foo', type: 'int', variadic: true}, {name: 'bar', type: 'int'}]);
+    """).error().contains("Invalid arguments schema for function test, Variadic argument 'foo' must be the last argument")
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_function("test", function(client) {}, [], [{name: 'foo', type: 'int', optional: true}, {name: 'bar', type: 'int'}]);
+    """).error().contains("Invalid arguments schema for function test, Required argument 'bar' can not follow optional argument 'foo'")
@@ -0,0 +1,189 @@
+from common import gearsTest
+from common import runUntil
+
+@gearsTest()
+def testOpenKeyString(env):
+    """#!js name=lib
+redis.register_function("set", function(client, key, val){
+    var k = client.open_key(key);
+    k.string_set(val);
+    return [k.type(), k.string_get(), k.value_length()];
+});
+redis.register_function("get", function(client, key){
+    return client.open_key(key).string_get();
+});
+redis.register_function("del", function(client, key){
+    return client.open_key(key).delete();
+});
+    """
+    env.expect('RG.FCALL', 'lib', 'set', '1', 'x', 'foo').equal(['string', 'foo', 3])
+    env.expect('GET', 'x').equal('foo')
+    env.expect('RG.FCALL', 'lib', 'get', '1', 'x').equal('foo')
+    env.expect('RG.FCALL', 'lib', 'get', '1', 'y').equal(None)
+    env.expect('RG.FCALL', 'lib', 'del', '1', 'x').equal(1)
+    env.expect('RG.FCALL', 'lib', 'del', '1', 'x').equal(0)
+    env.expect('EXISTS', 'x').equal(0)
+
+@gearsTest(decodeResponses=False)
+def testOpenKeyBinaryValues(env):
+    """#!js name=lib
+redis.register_function("get", function(client, key){
+    return client.open_key(key).string_get();
+});
+redis.register_function("get_raw", function(client, key){
+    return client.open_key(key).string_get_raw();
+});
+redis.register_function("hget_raw", function(client, key, field){
+    return client.open_key(key).hash_get_raw(field);
+});
+redis.register_function("lpop_raw", function(client, key){
+    return client.open_key(key).list_pop_head_raw();
+});
+    """
+    env.cmd('SET', 'x', b'\xaa')
+    env.cmd('HSET', 'h', 'foo', b'\xab')
+    env.cmd('RPUSH', 'l', b'\xac')
+    env.expect('RG.FCALL', 'lib', 'get', '1', 'x').error().contains('Could not decode value as string')
+    env.expect('RG.FCALL', 'lib', 'get_raw', '1', 'x').equal(b'\xaa')
+    env.expect('RG.FCALL', 'lib', 'get_raw', '1', 'y').equal(None)
+    env.expect('RG.FCALL', 'lib', 'hget_raw', '1', 'h', 'foo').equal(b'\xab')
+    env.expect('RG.FCALL', 'lib', 'lpop_raw', '1', 'l').equal(b'\xac')
+
+@gearsTest()
+def testOpenKeyHash(env):
+    """#!js name=lib
+redis.register_function("hincr_visits", function(client, key){
+    var k = client.open_key(key);
+    var visits = parseInt(k.hash_get('visits') || '0') + 1;
+    k.hash_set('visits', visits.toString());
+    return visits;
+});
+redis.register_function("hash", function(client, key){
+    var k = client.open_key(key);
+    return [k.hash_set('foo', 'bar'), k.hash_set('foo', 'baz'), k.hash_get('foo'), k.hash_get('bar'), k.value_length(), k.hash_del('foo'), k.hash_del('foo'), k.type()];
+});
+    """
+    env.expect('RG.FCALL', 'lib', 'hincr_visits', '1', 'h').equal(1)
+    env.expect('RG.FCALL', 'lib', 'hincr_visits', '1', 'h').equal(2)
+    env.expect('HGET', 'h', 'visits').equal('2')
+    env.expect('RG.FCALL', 'lib', 'hash', '1', 'h1').equal([1, 0, 'baz', None, 1, 1, 0, 'none'])
+
+@gearsTest()
+def testOpenKeyList(env):
+    """#!js name=lib
+redis.register_function("list", function(client, key){
+    var k = client.open_key(key);
+    k.list_push_tail('b');
+    k.list_push_head('a');
+    k.list_push_tail('c');
+    var res = [k.value_length(), k.list_pop_head(), k.list_pop_tail()];
+    return res;
+});
+    """
+    env.expect('RG.FCALL', 'lib', 'list', '1', 'l').equal([3, 'a', 'c'])
+    env.expect('LRANGE', 'l', '0', '-1').equal(['b'])
+
+@gearsTest()
+def testOpenKeyStream(env):
+    """#!js name=lib
+redis.register_function("xadd", function(client, key, val){
+    return client.open_key(key).stream_add({foo: val});
+});
+    """
+    id = env.cmd('RG.FCALL', 'lib', 'xadd', '1', 's', 'bar')
+    env.expect('XRANGE', 's', '-', '+').equal([[id, ['foo', 'bar']]])
+
+@gearsTest()
+def testOpenKeyErrors(env):
+    """#!js name=lib
+redis.register_function("hash_get", function(client, key){
+    return client.open_key(key).hash_get('foo');
+});
+redis.register_function("no_writes", function(client, key){
+    return client.open_key(key).string_set('foo');
+}, ['no-writes']);
+redis.register_function("strict", function(client, key){
+    return client.open_key(key).string_get();
+}, ['strict-keys']);
+var k;
+redis.register_function("keep_key", function(client, key){
+    k = client.open_key(key);
+    return 'OK';
+});
+redis.register_function("use_key", function(client){
+    return k.string_get();
+});
+    """
+    env.expect('SET', 'x', '1').equal(True)
+    env.expect('RG.FCALL', 'lib', 'hash_get', '1', 'x').error().contains('WRONGTYPE')
+    env.expect('RG.FCALL', 'lib', 'no_writes', '1', 'x').error().contains("Can not write to a key from a function with the 'no-writes' flag")
+    env.expect('RG.FCALL', 'lib', 'strict', '1', 'x').equal('1')
+    env.expect('RG.FCALL', 'lib', 'strict', '0', 'x').error().contains("Key 'x' was not declared by the function")
+    env.expect('RG.FCALL', 'lib', 'keep_key', '1', 'x').equal('OK')
+    env.expect('RG.FCALL', 'lib', 'use_key', '0').error().contains('Used on invalid client')
+
+@gearsTest()
+def testOpenKeyAcl(env):
+    """#!js name=lib
+redis.register_function("get", function(client, key){
+    return client.open_key(key).string_get();
+});
+redis.register_function("set", function(client, key, val){
+    return client.open_key(key).string_set(val);
+});
+    """
+    env.expect('SET', 'x', '1').equal(True)
+    env.expect('ACL', 'SETUSER', 'alice', 'on', '>pass', '~x', '%R~y', '+@all').equal('OK')
+    env.expect('AUTH', 'alice', 'pass').equal(True)
+    env.expect('RG.FCALL', 'lib', 'get', '1', 'x').equal('1')
+    env.expect('RG.FCALL', 'lib', 'get', '1', 'z').error().contains("User 'alice' has no permissions on key 'z'")
+    env.expect('RG.FCALL', 'lib', 'set', '1', 'y', '1').error().contains("User 'alice' has no permissions on key 'y'")
+
+@gearsTest()
+def testOpenKeyNotifications(env):
+    """#!js name=lib
+redis.register_function("set", function(client, key, val){
+    return client.open_key(key).string_set(val);
+});
+redis.register_notifications_consumer("consumer", "", function(client, data){
+    if (data.event == 'set' && data.key != 'count') {
+        client.call('incr', 'count');
+    }
+});
+    """
+    env.expect('RG.FCALL', 'lib', 'set', '1', 'x', '1').equal(None)
+    runUntil(env, '1', lambda: env.cmd('GET', 'count'))
+
+@gearsTest()
+def testOpenKeyDeleteNotifications(env):
+    """#!js name=lib
+redis.register_function("hdel", function(client, key, field){
+    return client.open_key(key).hash_del(field);
+});
+redis.register_function("lpop", function(client, key){
+    return client.open_key(key).list_pop_head();
+});
+redis.register_notifications_consumer("consumer", "", function(client, data){
+    if (['hdel', 'lpop', 'del'].includes(data.event)) {
+        client.call('rpush', 'events', data.event + ':' + data.key);
+    }
+});
+    """
+    env.cmd('HSET', 'h', 'foo', 'bar', 'baz', 'bar')
+    env.cmd('RPUSH', 'l', 'a')
+    env.expect('RG.FCALL', 'lib', 'hdel', '1', 'h', 'foo').equal(1)
+    env.expect('RG.FCALL', 'lib', 'hdel', '1', 'h', 'baz').equal(1)
+    env.expect('RG.FCALL', 'lib', 'lpop', '1', 'l').equal('a')
+    runUntil(env, ['hdel:h', 'hdel:h', 'del:h', 'lpop:l', 'del:l'], lambda: env.cmd('LRANGE', 'events', '0', '-1'))
+
+@gearsTest(withReplicas=True)
+def testOpenKeyReplication(env):
+    """#!js name=lib
+redis.register_function("hset", function(client, key, field, val){
+    return client.open_key(key).hash_set(field, val);
+});
+    """
+    env.expect('RG.FCALL', 'lib', 'hset', '1', 'h', 'foo', 'bar').equal(1)
+    replica = env.getSlaveConnection()
+    env.expect('WAIT', '1', '7000').equal(1)
+    runUntil(env, 'bar', lambda: replica.execute_command('HGET', 'h', 'foo'))
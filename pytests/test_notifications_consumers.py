@@ -234,3 +234,165 @@ redis.register_notifications_consumer("consumer", "", (client) => {
     """
     env.expect('SET', 'x', '1').equal(True)
     runUntil(env, 1, lambda: toDictionary(env.cmd('RG.FUNCTION', 'LIST', 'v'))[0]['notifications_consumers'][0]['num_success'])
+
+@gearsTest()
+def testNotificationsConsumerKeysMatching(env):
+    """#!js name=lib
+var keys = {};
+function register(name, registered_keys) {
+    keys[name] = [];
+    redis.register_notifications_consumer(name, registered_keys, function(client, data) {
+        keys[name].push(data.key);
+    });
+}
+register("prefix", "user:");
+register("prefixes", ["user:", "account:", "user:1"]);
+register("key", {key: "user:1:profile"});
+register("suffix", {suffix: ":profile"});
+register("pattern", {pattern: "user:[0-9]*:profile"});
+
+redis.register_function("keys", function(client, args){
+    return keys[args[0]];
+}, ['no-writes']);
+    """
+    env.cmd('SET', 'user:1:profile', '1')
+    env.cmd('SET', 'user:x:profile', '1')
+    env.cmd('SET', 'account:2', '1')
+    env.cmd('SET', 'foo:profile', '1')
+    env.cmd('SET', 'user:2:settings', '1')
+    env.expect('RG.FCALL', 'lib', 'keys', '1', 'prefix').equal(['user:1:profile', 'user:x:profile', 'user:2:settings'])
+    env.expect('RG.FCALL', 'lib', 'keys', '1', 'prefixes').equal(['user:1:profile', 'user:x:profile', 'account:2', 'user:2:settings'])
+    env.expect('RG.FCALL', 'lib', 'keys', '1', 'key').equal(['user:1:profile'])
+    env.expect('RG.FCALL', 'lib', 'keys', '1', 'suffix').equal(['user:1:profile', 'user:x:profile', 'foo:profile'])
+    env.expect('RG.FCALL', 'lib', 'keys', '1', 'pattern').equal(['user:1:profile'])
+
+@gearsTest()
+def testNotificationsConsumerKeysMatchingUpgrade(env):
+    code = """#!js name=lib
+var n_notifications = 0;
+redis.register_notifications_consumer("consumer", %s, function(client, data) {
+    n_notifications += 1;
+});
+redis.register_function("n_notifications", function(){
+    return n_notifications
+}, ['no-writes']);
+    """
+    env.expect('RG.FUNCTION', 'LOAD', code % '{pattern: "x*"}').equal('OK')
+    env.cmd('SET', 'x1', '1')
+    env.expect('RG.FCALL', 'lib', 'n_notifications', '0').equal(1)
+    env.expect('RG.FUNCTION', 'LOAD', 'UPGRADE', code % '{suffix: "y"}').equal('OK')
+    env.cmd('SET', 'x1', '1')
+    env.cmd('SET', 'xy', '1')
+    env.expect('RG.FCALL', 'lib', 'n_notifications', '0').equal(1)
+
+@gearsTest()
+def testNotificationsConsumerKeysMatchingErrors(env):
+    code = """#!js name=lib
+redis.register_notifications_consumer("consumer", %s, function(client, data) {});
+    """
+    env.expect('RG.FUNCTION', 'LOAD', code % '1').error().contains("must be a String or ArrayBuffer representing the prefix, an array of prefixes or an object")
+    env.expect('RG.FUNCTION', 'LOAD', code % '[]').error().contains("Prefixes list can not be empty")
+    env.expect('RG.FUNCTION', 'LOAD', code % '["x", 1]').error().contains("Prefixes list must contain only Strings or ArrayBuffers")
+    env.expect('RG.FUNCTION', 'LOAD', code % '{suffix: "x", pattern: "y"}').error().contains("Only one of 'key', 'prefix', 'suffix' or 'pattern' can be given")
+    env.expect('RG.FUNCTION', 'LOAD', code % '{}').error().contains("One of 'key', 'prefix', 'suffix' or 'pattern' must be given")
+    env.expect('RG.FUNCTION', 'LOAD', code % '{pattern: 1}').error().contains("'pattern' must be a String or ArrayBuffer")
+
+@gearsTest()
+def testNotificationsConsumerEventsFilter(env):
+    """#!js name=lib
+var events = [];
+redis.register_notifications_consumer("consumer", "", function(client, data) {
+    events.push(data.event);
+}, {events: ['del'], event_classes: ['hash']});
+
+redis.register_function("events", function(){
+    return events;
+}, ['no-writes']);
+    """
+    env.cmd('SET', 'x', '1')
+    env.cmd('HSET', 'h', 'foo', 'bar')
+    env.cmd('HDEL', 'h', 'foo')
+    env.cmd('DEL', 'x')
+    env.expect('RG.FCALL', 'lib', 'events', '0').equal(['hset', 'hdel', 'del', 'del'])
+    res = toDictionary(env.cmd('RG.FUNCTION', 'LIST', 'vvv'), 6)[0]['notifications_consumers'][0]
+    env.assertEqual(res['num_triggered'], 4)
+    env.assertEqual(res['num_filtered'], 1)
+
+@gearsTest()
+def testNotificationsConsumerEventsFilterUpgrade(env):
+    code = """#!js name=lib
+var n_notifications = 0;
+redis.register_notifications_consumer("consumer", "", function(client, data) {
+    n_notifications += 1;
+}, %s);
+redis.register_function("n_notifications", function(){
+    return n_notifications
+}, ['no-writes']);
+    """
+    env.expect('RG.FUNCTION', 'LOAD', code % "{events: ['set']}").equal('OK')
+    env.cmd('SET', 'x', '1')
+    env.cmd('DEL', 'x')
+    env.expect('RG.FCALL', 'lib', 'n_notifications', '0').equal(1)
+    env.expect('RG.FUNCTION', 'LOAD', 'UPGRADE', code % "{}").equal('OK')
+    env.cmd('SET', 'x', '1')
+    env.cmd('DEL', 'x')
+    env.expect('RG.FCALL', 'lib', 'n_notifications', '0').equal(2)
+
+@gearsTest()
+def testNotificationsConsumerEventsFilterErrors(env):
+    code = """#!js name=lib
+redis.register_notifications_consumer("consumer", "", function(client, data) {}, %s);
+    """
+    env.expect('RG.FUNCTION', 'LOAD', code % '1').error().contains("Fourth argument to 'register_notifications_consumer' must be an object")
+    env.expect('RG.FUNCTION', 'LOAD', code % "{events: 'del'}").error().contains("'events' must be an array of strings")
+    env.expect('RG.FUNCTION', 'LOAD', code % "{event_classes: ['foo']}").error().contains("Unknown event class 'foo'")
+
+@gearsTest()
+def testNotificationsConsumerCoalescing(env):
+    """#!js name=lib
+var notifications = [];
+redis.register_notifications_consumer("consumer", "", function(client, data) {
+    notifications.push([data.key, data.event, data.count, data.events]);
+}, {coalesce_ms: 200});
+
+redis.register_function("notifications", function(){
+    return notifications;
+}, ['no-writes']);
+    """
+    env.cmd('HSET', 'h', 'foo', 'bar')
+    env.cmd('HSET', 'h', 'foo', 'bar1')
+    env.cmd('HDEL', 'h', 'foo')
+    env.cmd('SET', 'x', '1')
+    env.expect('RG.FCALL', 'lib', 'notifications', '0').equal([])
+    runUntil(env, 2, lambda: len(env.cmd('RG.FCALL', 'lib', 'notifications', '0')))
+    env.expect('RG.FCALL', 'lib', 'notifications', '0').equal([['h', 'del', 4, ['hset', 'hdel', 'del']], ['x', 'set', 1, ['set']]])
+    res = toDictionary(env.cmd('RG.FUNCTION', 'LIST', 'vvv'), 6)[0]['notifications_consumers'][0]
+    env.assertEqual(res['num_triggered'], 2)
+    env.assertEqual(res['num_coalesced'], 3)
+
+@gearsTest()
+def testNotificationsConsumerCoalescingMostRecentEvent(env):
+    """#!js name=lib
+var notifications = [];
+redis.register_notifications_consumer("consumer", "", function(client, data) {
+    notifications.push([data.key, data.event, data.count, data.events]);
+}, {coalesce_ms: 200});
+
+redis.register_function("notifications", function(){
+    return notifications;
+}, ['no-writes']);
+    """
+    env.cmd('SET', 'x', '1')
+    env.cmd('DEL', 'x')
+    env.cmd('SET', 'x', '2')
+    runUntil(env, 1, lambda: len(env.cmd('RG.FCALL', 'lib', 'notifications', '0')))
+    # the event is the most recent one even if it first happened before other events
+    env.expect('RG.FCALL', 'lib', 'notifications', '0').equal([['x', 'set', 3, ['set', 'del']]])
+
+@gearsTest()
+def testNotificationsConsumerCoalescingErrors(env):
+    code = """#!js name=lib
+redis.register_notifications_consumer("consumer", "", function(client, data) {}, %s);
+    """
+    env.expect('RG.FUNCTION', 'LOAD', code % "{coalesce_ms: 'foo'}").error().contains("'coalesce_ms' must be a positive integer")
+    env.expect('RG.FUNCTION', 'LOAD', code % "{coalesce_ms: 0}").error().contains("'coalesce_ms' must be greater than 0")
@@ -0,0 +1,122 @@
+import socket
+from common import gearsTest
+from common import TimeLimit
+
+def rawCommands(env, commands, expected_suffix):
+    conn = env.getConnection()
+    kwargs = conn.connection_pool.connection_kwargs
+    with socket.create_connection((kwargs['host'], kwargs['port'])) as s:
+        for command in commands:
+            s.sendall(('*%d\r\n' % len(command) + ''.join(['$%d\r\n%s\r\n' % (len(a), a) for a in command])).encode())
+        res = b''
+        with TimeLimit(2, env, "Failed waiting for reply '%s'" % str(expected_suffix)):
+            while not res.endswith(expected_suffix):
+                data = s.recv(4096)
+                if not data:
+                    break
+                res += data
+        return res
+
+@gearsTest(skipWithTLS=True)
+def testResp3Replies(env):
+    """#!js name=lib
+redis.register_function("test", function(client){
+    var big_number = new String('12345678901234567890');
+    big_number.__reply_type = 'big_number';
+    var verbatim = new String('hello');
+    verbatim.__reply_type = 'verbatim';
+    verbatim.__ext = 'mkd';
+    return [true, false, {a: 1}, new Set(['foo']), big_number, verbatim, null, new Map([['b', 2], [3, 'c']])];
+});
+    """
+    expected = b'*8\r\n#t\r\n#f\r\n%1\r\n$1\r\na\r\n:1\r\n~1\r\n$3\r\nfoo\r\n(12345678901234567890\r\n=9\r\nmkd:hello\r\n_\r\n%2\r\n$1\r\nb\r\n:2\r\n:3\r\n$1\r\nc\r\n'
+    res = rawCommands(env, [['HELLO', '3'], ['RG.FCALL', 'lib', 'test', '0']], expected)
+    env.assertTrue(res.endswith(expected), message=str(res))
+
+@gearsTest()
+def testResp2Fallbacks(env):
+    """#!js name=lib
+redis.register_function("test", function(client){
+    var big_number = new String('12345678901234567890');
+    big_number.__reply_type = 'big_number';
+    var verbatim = new String('hello');
+    verbatim.__reply_type = 'verbatim';
+    verbatim.__ext = 'mkd';
+    return [true, false, {a: 1}, new Set(['foo']), big_number, verbatim, null, new Map([['b', 2], [3, 'c']])];
+});
+    """
+    env.expect('RG.FCALL', 'lib', 'test', '0').equal([1, 0, ['a', 1], ['foo'], '12345678901234567890', 'hello', None, ['b', 2, 3, 'c']])
+
+@gearsTest(enableGearsDebugCommands=True)
+def testCallReplyRoundTrip(env):
+    """#!js name=lib
+redis.register_function("test", function(client, type){
+    return client.call("debug", "protocol", type);
+});
+    """
+    env.expect('RG.FCALL', 'lib', 'test', '0', 'true').equal(1)
+    env.expect('RG.FCALL', 'lib', 'test', '0', 'false').equal(0)
+    env.expect('RG.FCALL', 'lib', 'test', '0', 'bignum').equal('1234567999999999999999999999999999999')
+
+@gearsTest()
+def testStreamingReply(env):
+    """#!js name=lib
+redis.register_function("range", function(client, count){
+    client.reply_array_begin();
+    for (var i = 0; i < parseInt(count); i++) {
+        client.reply_item(i);
+    }
+    client.reply_end();
+    return 'ignored';
+});
+redis.register_function("nested", function(client){
+    client.reply_array_begin();
+    client.reply_item('foo');
+    client.reply_array_begin();
+    client.reply_item({a: 1});
+    client.reply_item([1, 2]);
+    client.reply_end();
+    client.reply_array_begin();
+    // left open, closed when the function returns
+});
+    """
+    env.expect('RG.FCALL', 'lib', 'range', '0', '3').equal([0, 1, 2])
+    env.expect('RG.FCALL', 'lib', 'range', '0', '0').equal([])
+    env.assertEqual(len(env.cmd('RG.FCALL', 'lib', 'range', '0', '100000')), 100000)
+    env.expect('RG.FCALL', 'lib', 'nested', '0').equal(['foo', [['a', 1], [1, 2]], []])
+
+@gearsTest()
+def testStreamingReplyErrors(env):
+    """#!js name=lib
+redis.register_function("item_without_array", function(client){
+    client.reply_item(1);
+});
+redis.register_function("end_without_array", function(client){
+    client.reply_end();
+});
+redis.register_function("reply_twice", function(client){
+    client.reply_array_begin();
+    client.reply_end();
+    client.reply_array_begin();
+});
+redis.register_function("error_after_start", function(client){
+    client.reply_array_begin();
+    client.reply_item(1);
+    throw 'failure';
+});
+redis.register_function("async_streaming", async function(client){
+    client.block(function(c){
+        c.reply_array_begin();
+    });
+});
+    """
+    env.expect('RG.FCALL', 'lib', 'item_without_array', '0').error().contains("No reply array is open, use 'reply_array_begin' first")
+    env.expect('RG.FCALL', 'lib', 'end_without_array', '0').error().contains("No reply array is open, use 'reply_array_begin' first")
+    res = env.cmd('RG.FCALL', 'lib', 'reply_twice', '0')
+    env.assertEqual(len(res), 1)
+    env.assertContains('The reply was already sent', str(res[0]))
+    res = env.cmd('RG.FCALL', 'lib', 'error_after_start', '0')
+    env.assertEqual(res[0], 1)
+    env.assertContains('failure', str(res[1]))
+    env.expect('RG.FCALL', 'lib', 'async_streaming', '0').error().contains('Streaming replies can only be used while a synchronous function is running')
+    env.expect('PING').equal(True)
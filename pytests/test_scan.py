@@ -0,0 +1,127 @@
+from common import gearsTest
+
+@gearsTest(skipOnCluster=True)
+def testScanIter(env):
+    """#!js name=lib
+redis.register_function("scan", function(client, pattern, type, count){
+    var options = {};
+    if (pattern.length > 0) {
+        options.match = pattern;
+    }
+    if (type.length > 0) {
+        options.type = type;
+    }
+    if (count.length > 0) {
+        options.count = parseInt(count);
+    }
+    var keys = [];
+    for (const key of client.scan_iter(options)) {
+        keys.push(key);
+    }
+    return keys.sort();
+});
+    """
+    conn = env.getConnection()
+    for i in range(100):
+        conn.execute_command('set', 'x%d' % i, '1')
+    conn.execute_command('hset', 'h1', 'foo', 'bar')
+    conn.execute_command('hset', 'h2', 'foo', 'bar')
+    conn.execute_command('lpush', 'l1', 'foo')
+
+    env.assertEqual(len(env.cmd('RG.FCALL', 'lib', 'scan', '0', '', '', '')), 103)
+    env.expect('RG.FCALL', 'lib', 'scan', '0', 'h*', '', '').equal(['h1', 'h2'])
+    env.expect('RG.FCALL', 'lib', 'scan', '0', 'x1?', '', '').equal(sorted(['x1%d' % i for i in range(10)]))
+    env.expect('RG.FCALL', 'lib', 'scan', '0', 'x[1-2]', '', '').equal(['x1', 'x2'])
+    env.expect('RG.FCALL', 'lib', 'scan', '0', '', 'hash', '').equal(['h1', 'h2'])
+    env.expect('RG.FCALL', 'lib', 'scan', '0', '', 'list', '1').equal(['l1'])
+    env.expect('RG.FCALL', 'lib', 'scan', '0', 'x*', 'hash', '').equal([])
+    env.assertEqual(len(env.cmd('RG.FCALL', 'lib', 'scan', '0', 'x*', 'string', '1000')), 100)
+
+@gearsTest()
+def testScanIterErrors(env):
+    """#!js name=lib
+redis.register_function("bad_type", function(client){
+    return client.scan_iter({type: 'foo'});
+});
+redis.register_function("bad_count", function(client){
+    return client.scan_iter({count: 0});
+});
+redis.register_function("bad_options", function(client){
+    return client.scan_iter(1);
+});
+redis.register_function("strict", function(client){
+    return client.scan_iter();
+}, ['strict-keys']);
+var iter;
+redis.register_function("keep_iterator", function(client){
+    iter = client.scan_iter();
+    return 'OK';
+});
+redis.register_function("use_iterator", function(client){
+    return iter.next();
+});
+    """
+    env.expect('RG.FCALL', 'lib', 'bad_type', '0').error().contains("Unknown key type 'foo'")
+    env.expect('RG.FCALL', 'lib', 'bad_count', '0').error().contains("'count' option must be a positive integer")
+    env.expect('RG.FCALL', 'lib', 'bad_options', '0').error().contains("Argument to 'scan_iter' must be an object")
+    env.expect('RG.FCALL', 'lib', 'strict', '0').error().contains("Scanning the key space is not allowed on functions with the 'strict-keys' flag")
+    env.expect('RG.FCALL', 'lib', 'keep_iterator', '0').equal('OK')
+    env.expect('RG.FCALL', 'lib', 'use_iterator', '0').error().contains('Used on invalid client')
+
+@gearsTest(skipOnCluster=True)
+def testScanIterAsync(env):
+    """#!js name=lib
+redis.register_function("scan", async function(client){
+    var keys = [];
+    for (const key of client.scan_iter({match: 'x*', count: 5})) {
+        keys.push(key);
+    }
+    return keys.sort();
+});
+redis.register_function("scan_inside_block", async function(client){
+    return client.block(function(c){
+        var keys = [];
+        for (const key of c.scan_iter({match: 'x*'})) {
+            keys.push(key);
+        }
+        return keys.sort();
+    });
+});
+redis.register_function("background_scan_inside_block", async function(client){
+    return client.block(function(c){
+        return client.scan_iter().next();
+    });
+});
+    """
+    conn = env.getConnection()
+    for i in range(20):
+        conn.execute_command('set', 'x%d' % i, '1')
+    conn.execute_command('set', 'y', '1')
+    expected = sorted(['x%d' % i for i in range(20)])
+    env.expect('RG.FCALL', 'lib', 'scan', '0').equal(expected)
+    env.expect('RG.FCALL', 'lib', 'scan_inside_block', '0').equal(expected)
+    env.expect('RG.FCALL', 'lib', 'background_scan_inside_block', '0').error().contains('Main thread is already blocked')
+
+@gearsTest(decodeResponses=False, skipOnCluster=True)
+def testScanIterBinaryKeys(env):
+    """#!js name=lib
+redis.register_function("scan", function(client){
+    var res = [];
+    for (const key of client.scan_iter()) {
+        res.push(key);
+    }
+    return res;
+});
+redis.register_function("scan_raw", function(client){
+    var res = [];
+    for (const key of client.scan_iter_raw()) {
+        res.push(key);
+    }
+    return res;
+});
+    """
+    conn = env.getConnection()
+    conn.execute_command('set', b'\xaa', '1')
+    conn.execute_command('set', 'x', '1')
+    env.expect('RG.FCALL', 'lib', 'scan', '0').error().contains('Could not decode value as string')
+    env.assertEqual(sorted(env.cmd('RG.FCALL', 'lib', 'scan_raw', '0')), [b'x', b'\xaa'])
@@ -0,0 +1,132 @@
+from common import gearsTest
+from common import toDictionary
+from common import runUntil
+import os
+
+def getServerEventConsumer(env, index=0):
+    res = env.cmd('RG.FUNCTION', 'LIST', 'vvv')
+    return toDictionary(res, 6)[0]['server_event_consumers'][index]
+
+@gearsTest()
+def testServerEventFlush(env):
+    """#!js name=lib
+redis.register_server_event_consumer("flush", function(client, data) {
+    client.call('set', 'flushed', data.event);
+});
+    """
+    env.cmd('SET', 'x', '1')
+    env.cmd('FLUSHALL')
+    runUntil(env, 'flush', lambda: env.cmd('GET', 'flushed'))
+    env.assertEqual(env.cmd('GET', 'x'), None)
+    res = getServerEventConsumer(env)
+    env.assertEqual(res['event'], 'flush')
+    env.assertEqual(res['num_triggered'], 1)
+    env.assertEqual(res['num_success'], 1)
+
+@gearsTest()
+def testServerEventAsync(env):
+    """#!js name=lib
+redis.register_server_event_consumer("flush", async function(client, data) {
+    client.block(function(c) {
+        c.call('set', 'flushed', data.event);
+    });
+});
+    """
+    env.cmd('FLUSHALL')
+    runUntil(env, 'flush', lambda: env.cmd('GET', 'flushed'))
+
+@gearsTest()
+def testServerEventLoading(env):
+    """#!js name=lib
+var loaded = 0;
+redis.register_server_event_consumer("loading", function(client, data) {
+    loaded += 1;
+});
+redis.register_function("loaded", function(client) {
+    return loaded;
+}, ['no-writes']);
+    """
+    env.expect('RG.FCALL', 'lib', 'loaded', '0').equal(0)
+    env.expect('DEBUG', 'RELOAD').equal('OK')
+    runUntil(env, 1, lambda: env.cmd('RG.FCALL', 'lib', 'loaded', '0'))
+
+@gearsTest(withReplicas=True)
+def testServerEventRoleChange(env):
+    """#!js name=lib
+var role = null;
+redis.register_server_event_consumer("role_change", function(client, data) {
+    role = data.role;
+});
+redis.register_function("role", function(client) {
+    return role;
+}, ['no-writes']);
+    """
+    replica = env.getSlaveConnection()
+    env.expect('WAIT', '1', '7000').equal(1)
+    env.assertEqual(replica.execute_command('RG.FCALL', 'lib', 'role', '0'), None)
+    replica.execute_command('REPLICAOF', 'NO', 'ONE')
+    runUntil(env, 'primary', lambda: replica.execute_command('RG.FCALL', 'lib', 'role', '0'))
+
+@gearsTest()
+def testServerEventConfigChange(env):
+    """#!js name=lib
+redis.register_server_event_consumer("config_change", function(client, data) {
+    client.call('set', 'configs', data.configs.join(','));
+});
+    """
+    env.expect('CONFIG', 'SET', 'maxmemory-policy', 'allkeys-lru').equal('OK')
+    runUntil(env, 'maxmemory-policy', lambda: env.cmd('GET', 'configs'))
+    env.expect('CONFIG', 'SET', 'maxmemory-policy', 'noeviction', 'lazyfree-lazy-user-del', 'no').equal('OK')
+    runUntil(env, ['lazyfree-lazy-user-del', 'maxmemory-policy'], lambda: sorted(env.cmd('GET', 'configs').split(',')))
+
+@gearsTest()
+def testServerEventShutdown(env):
+    """#!js name=lib
+redis.register_server_event_consumer("shutdown", function(client, data) {
+    redis.log('shutdown consumer was invoked, event: ' + data.event);
+});
+    """
+    conn = env.getConnection()
+    log_file = os.path.join(conn.execute_command('CONFIG', 'GET', 'dir')[1], conn.execute_command('CONFIG', 'GET', 'logfile')[1])
+    try:
+        conn.execute_command('SHUTDOWN', 'NOSAVE')
+    except Exception:
+        pass
+
+    def shutdownLogged():
+        with open(log_file) as f:
+            return 'shutdown consumer was invoked, event: shutdown' in f.read()
+
+    runUntil(env, True, shutdownLogged)
+    # bring the server back up for the test teardown
+    env.start()
+
+@gearsTest()
+def testServerEventUpgrade(env):
+    code = """#!js name=lib
+redis.register_server_event_consumer("flush", function(client, data) {
+    client.call('set', '%s', '1');
+});
+    """
+    env.expect('RG.FUNCTION', 'LOAD', code % 'x').equal('OK')
+    env.cmd('FLUSHALL')
+    runUntil(env, '1', lambda: env.cmd('GET', 'x'))
+    env.expect('RG.FUNCTION', 'LOAD', 'UPGRADE', code % 'y').equal('OK')
+    env.cmd('FLUSHALL')
+    runUntil(env, '1', lambda: env.cmd('GET', 'y'))
+    env.assertEqual(env.cmd('GET', 'x'), None)
+    # stats are kept on upgrade
+    env.assertEqual(getServerEventConsumer(env)['num_triggered'], 2)
+
+@gearsTest()
+def testServerEventErrors(env):
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_server_event_consumer("foo", function(client, data) {});
+    """).error().contains("Unknown server event 'foo'")
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_server_event_consumer("flush", 1);
+    """).error().contains("Second argument to 'register_server_event_consumer' must be a function")
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_server_event_consumer("flush", function(client, data) {});
+redis.register_server_event_consumer("flush", function(client, data) {});
+    """).error().contains("Server event consumer for 'flush' already exists")
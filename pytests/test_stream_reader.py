@@ -481,3 +481,473 @@ redis.register_stream_consumer("consumer", "stream", 1, true, async function(cli
     env.assertEqual(2, res)
 
     env.cmd('slaveof', 'no', 'one')
+
+@gearsTest()
+def testDeadLetterStream(env):
+    """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, true, function(client, data) {
+    if (data.record[0][1] == 'fail') {
+        throw 'Failed processing record';
+    }
+}, {dead_letter: 'dead_letter'})
+    """
+    env.cmd('xadd', 'stream:1', '*', 'foo', 'bar')
+    failed_id = env.cmd('xadd', 'stream:1', '*', 'foo', 'fail')
+    env.expect('xlen', 'stream:1').equal(0)
+    res = env.cmd('xrange', 'dead_letter', '-', '+')
+    env.assertEqual(len(res), 1)
+    env.assertEqual(res[0][1], ['error', 'Failed processing record', 'consumer', 'lib.consumer', 'stream', 'stream:1', 'id', failed_id, 'foo', 'fail'])
+    res = toDictionary(env.cmd('RG.FUNCTION', 'LIST', 'v'), 6)[0]
+    env.assertEqual(res['stream_consumers'][0]['dead_letter'], 'dead_letter')
+
+@gearsTest()
+def testDeadLetterStreamAsync(env):
+    """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, true, async function(client, data) {
+    throw 'Failed processing record';
+}, {dead_letter: 'dead_letter'})
+    """
+    failed_id = env.cmd('xadd', 'stream:1', '*', 'foo', 'bar')
+    runUntil(env, 1, lambda: env.cmd('xlen', 'dead_letter'))
+    res = env.cmd('xrange', 'dead_letter', '-', '+')
+    env.assertEqual(res[0][1], ['error', 'Failed processing record', 'consumer', 'lib.consumer', 'stream', 'stream:1', 'id', failed_id, 'foo', 'bar'])
+    runUntil(env, 0, lambda: env.cmd('xlen', 'stream:1'))
+
+@gearsTest()
+def testDeadLetterStreamMatchConsumerPrefix(env):
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, true, function(client, data) {
+    throw 'Failed processing record';
+}, {dead_letter: 'stream:dead_letter'})
+    """).error().contains("Dead letter stream 'stream:dead_letter' can not match the consumer prefix")
+
+@gearsTest()
+def testStreamConsumerRetry(env):
+    """#!js name=lib
+var attempts = 0;
+redis.register_function("attempts", () => attempts, ['no-writes']);
+redis.register_stream_consumer("consumer", "stream", 1, true, function(client, data) {
+    attempts++;
+    if (attempts < 3) {
+        throw 'LOCKED key is locked';
+    }
+}, {retry: {max_attempts: 3, backoff: 'fixed', delay: 10, retryable_errors: ['LOCKED']}})
+    """
+    env.cmd('xadd', 'stream:1', '*', 'foo', 'bar')
+    runUntil(env, 3, lambda: env.cmd('RG.FCALL', 'lib', 'attempts', '0'))
+    runUntil(env, 0, lambda: env.cmd('xlen', 'stream:1'))
+    res = toDictionary(env.cmd('RG.FUNCTION', 'LIST', 'vv'), 6)[0]['stream_consumers'][0]
+    env.assertEqual(res['retry_policy']['max_attempts'], 3)
+    env.assertEqual(res['retry_policy']['backoff'], 'fixed')
+    env.assertEqual(res['retry_policy']['retryable_errors'], ['LOCKED'])
+    env.assertEqual(res['streams'][0]['total_retries'], 2)
+    env.assertEqual(res['streams'][0]['pending_retries'], 0)
+    env.assertEqual(res['streams'][0]['total_record_processed'], 1)
+
+@gearsTest()
+def testStreamConsumerRetryExhausted(env):
+    """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, true, function(client, data) {
+    if (data.record[0][1] == 'fatal') {
+        throw 'FATAL error';
+    }
+    throw 'LOCKED key is locked';
+}, {dead_letter: 'dead_letter', retry: {max_attempts: 3, backoff: 'exponential', delay: 1, max_delay: 10, retryable_errors: ['LOCKED']}})
+    """
+    env.cmd('xadd', 'stream:1', '*', 'foo', 'fatal')
+    env.cmd('xadd', 'stream:1', '*', 'foo', 'bar')
+    runUntil(env, 2, lambda: env.cmd('xlen', 'dead_letter'))
+    runUntil(env, 0, lambda: env.cmd('xlen', 'stream:1'))
+    res = env.cmd('xrange', 'dead_letter', '-', '+')
+    env.assertEqual(res[0][1][1], 'FATAL error')
+    env.assertEqual(res[1][1][1], 'LOCKED key is locked')
+    res = toDictionary(env.cmd('RG.FUNCTION', 'LIST', 'vv'), 6)[0]['stream_consumers'][0]
+    # only the retryable record was retried
+    env.assertEqual(res['streams'][0]['total_retries'], 2)
+
+@gearsTest()
+def testStreamConsumerRetryBadPolicy(env):
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, true, function(client, data) {
+}, {retry: {backoff: 'fixed'}})
+    """).error().contains("'max_attempts' must be given on 'retry' option")
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, true, function(client, data) {
+}, {retry: {max_attempts: 2, backoff: 'foo'}})
+    """).error().contains("Unknown backoff 'foo'")
+
+@gearsTest()
+def testStreamConsumerStartFromNow(env):
+    env.cmd('xadd', 'stream:1', '*', 'foo', 'old')
+    env.cmd('xadd', 'stream:1', '*', 'foo', 'old')
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+var processed = [];
+redis.register_function("processed", () => processed, ['no-writes']);
+redis.register_stream_consumer("consumer", "stream", 1, false, function(client, data) {
+    processed.push(data.stream_name + ':' + data.record[0][1]);
+}, {start_position: 'from_now'})
+    """).equal('OK')
+    env.cmd('xadd', 'stream:1', '*', 'foo', 'new')
+    env.cmd('xadd', 'stream:2', '*', 'foo', 'new')
+    runUntil(env, ['stream:1:new', 'stream:2:new'], lambda: sorted(env.cmd('RG.FCALL', 'lib', 'processed', '0')))
+    res = toDictionary(env.cmd('RG.FUNCTION', 'LIST', 'vv'), 6)[0]['stream_consumers'][0]
+    env.assertEqual(res['start_position'], 'from_now')
+
+@gearsTest()
+def testStreamConsumerStartFromId(env):
+    env.cmd('xadd', 'stream:1', '1-1', 'foo', 'bar1')
+    env.cmd('xadd', 'stream:1', '2-1', 'foo', 'bar2')
+    env.cmd('xadd', 'stream:1', '3-1', 'foo', 'bar3')
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+var processed = [];
+redis.register_function("processed", () => processed, ['no-writes']);
+redis.register_stream_consumer("consumer", "stream", 1, false, function(client, data) {
+    processed.push(data.record[0][1]);
+}, {start_position: '2-1'})
+    """).equal('OK')
+    runUntil(env, ['bar3'], lambda: env.cmd('RG.FCALL', 'lib', 'processed', '0'))
+    res = toDictionary(env.cmd('RG.FUNCTION', 'LIST', 'vv'), 6)[0]['stream_consumers'][0]
+    env.assertEqual(res['start_position'], '2-1')
+
+@gearsTest()
+def testStreamConsumerBadStartPosition(env):
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, false, function(client, data) {
+}, {start_position: 'foo'})
+    """).error().contains("Invalid stream id 'foo'")
+
+@gearsTest()
+def testStreamSetId(env):
+    """#!js name=lib
+var processed = [];
+redis.register_function("processed", () => processed, ['no-writes']);
+redis.register_stream_consumer("consumer", "stream", 1, false, function(client, data) {
+    processed.push(data.record[0][1]);
+})
+    """
+    env.cmd('xadd', 'stream:1', '1-1', 'foo', 'bar1')
+    env.cmd('xadd', 'stream:1', '2-1', 'foo', 'bar2')
+    runUntil(env, ['bar1', 'bar2'], lambda: env.cmd('RG.FCALL', 'lib', 'processed', '0'))
+    env.expect('RG.STREAM', 'SETID', 'lib', 'consumer', 'stream:1', '1-1').equal('OK')
+    runUntil(env, ['bar1', 'bar2', 'bar2'], lambda: env.cmd('RG.FCALL', 'lib', 'processed', '0'))
+    env.expect('RG.STREAM', 'SETID', 'lib', 'consumer', 'stream:1', 'from_beginning').equal('OK')
+    runUntil(env, ['bar1', 'bar2', 'bar2', 'bar1', 'bar2'], lambda: env.cmd('RG.FCALL', 'lib', 'processed', '0'))
+    env.expect('RG.STREAM', 'SETID', 'lib', 'consumer', 'stream:1', '$').equal('OK')
+    env.cmd('xadd', 'stream:1', '3-1', 'foo', 'bar3')
+    runUntil(env, ['bar1', 'bar2', 'bar2', 'bar1', 'bar2', 'bar3'], lambda: env.cmd('RG.FCALL', 'lib', 'processed', '0'))
+    res = toDictionary(env.cmd('RG.FUNCTION', 'LIST', 'vv'), 6)[0]['stream_consumers'][0]
+    env.assertEqual(res['streams'][0]['id_to_read_from'], '3-1')
+
+@gearsTest()
+def testStreamSetIdErrors(env):
+    """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, false, function(client, data) {
+})
+    """
+    env.cmd('xadd', 'stream:1', '*', 'foo', 'bar')
+    env.cmd('set', 'x', '1')
+    env.expect('RG.STREAM', 'SETID', 'lib1', 'consumer', 'stream:1', '0-0').error().contains("No such library 'lib1'")
+    env.expect('RG.STREAM', 'SETID', 'lib', 'consumer1', 'stream:1', '0-0').error().contains("No such consumer 'consumer1'")
+    env.expect('RG.STREAM', 'SETID', 'lib', 'consumer', 'x', '0-0').error().contains("No such stream 'x'")
+    env.expect('RG.STREAM', 'SETID', 'lib', 'consumer', 'stream:1', 'foo').error().contains("Invalid stream id 'foo'")
+    env.expect('RG.STREAM', 'FOO').error().contains("Unknown subcommand foo")
+
+@gearsTest()
+def testStreamSetIdKeys(env):
+    env.expect('COMMAND', 'GETKEYS', 'RG.STREAM', 'SETID', 'lib', 'consumer', 'stream:1', '0-0').equal(['stream:1'])
+
+@gearsTest()
+def testStreamConsumerBatch(env):
+    for i in range(5):
+        env.cmd('xadd', 'stream:1', '*', 'foo', 'bar%d' % i)
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+var batches = [];
+redis.register_function("batches", () => batches, ['no-writes']);
+redis.register_stream_consumer("consumer", "stream", 1, true, function(client, records) {
+    batches.push(records.map((r) => r.record[0][1]));
+}, {batch_size: 2})
+    """).equal('OK')
+    runUntil(env, [['bar0', 'bar1'], ['bar2', 'bar3'], ['bar4']], lambda: env.cmd('RG.FCALL', 'lib', 'batches', '0'))
+    runUntil(env, 0, lambda: env.cmd('xlen', 'stream:1'))
+    res = toDictionary(env.cmd('RG.FUNCTION', 'LIST', 'vv'), 6)[0]['stream_consumers'][0]
+    env.assertEqual(res['batch'], {'batch_size': 2, 'max_wait_ms': 0})
+    env.assertEqual(res['streams'][0]['total_record_processed'], 5)
+
+@gearsTest()
+def testStreamConsumerBatchMaxWait(env):
+    """#!js name=lib
+var batches = [];
+redis.register_function("batches", () => batches, ['no-writes']);
+redis.register_stream_consumer("consumer", "stream", 1, true, function(client, records) {
+    batches.push(records.map((r) => r.record[0][1]));
+}, {batch_size: 10, max_wait_ms: 500})
+    """
+    env.cmd('xadd', 'stream:1', '*', 'foo', 'bar1')
+    env.cmd('xadd', 'stream:1', '*', 'foo', 'bar2')
+    env.cmd('xadd', 'stream:1', '*', 'foo', 'bar3')
+    runUntil(env, [['bar1', 'bar2', 'bar3']], lambda: env.cmd('RG.FCALL', 'lib', 'batches', '0'))
+    runUntil(env, 0, lambda: env.cmd('xlen', 'stream:1'))
+
+@gearsTest()
+def testStreamConsumerBatchDeadLetter(env):
+    env.cmd('xadd', 'stream:1', '*', 'foo', 'bar1')
+    env.cmd('xadd', 'stream:1', '*', 'foo', 'bar2')
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, true, function(client, records) {
+    throw 'Failed processing batch';
+}, {batch_size: 2, dead_letter: 'dead_letter'})
+    """).equal('OK')
+    runUntil(env, 2, lambda: env.cmd('xlen', 'dead_letter'))
+    runUntil(env, 0, lambda: env.cmd('xlen', 'stream:1'))
+    res = env.cmd('xrange', 'dead_letter', '-', '+')
+    env.assertEqual(res[0][1][1], 'Failed processing batch')
+    env.assertEqual(res[0][1][-1], 'bar1')
+    env.assertEqual(res[1][1][-1], 'bar2')
+
+@gearsTest()
+def testStreamConsumerBatchErrors(env):
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, true, function(client, records) {
+}, {batch_size: 0})
+    """).error().contains("'batch_size' must be greater than 0")
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, true, function(client, records) {
+}, {max_wait_ms: 10})
+    """).error().contains("'max_wait_ms' option requires the 'batch_size' option")
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, true, function(client, data) {
+})
+    """).equal('OK')
+    env.expect('RG.FUNCTION', 'LOAD', 'UPGRADE', """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, true, function(client, records) {
+}, {batch_size: 10})
+    """).error().contains("Can not upgrade an existing consumer with different batch mode")
+
+@gearsTest(withReplicas=True)
+def testStreamConsumerPauseResume(env):
+    """#!js name=lib
+var processed = [];
+redis.register_function("processed", () => processed, ['no-writes']);
+redis.register_stream_consumer("consumer", "stream", 1, false, function(client, data) {
+    processed.push(data.record[0][1]);
+})
+    """
+    env.cmd('xadd', 'stream:1', '*', 'foo', 'bar1')
+    runUntil(env, ['bar1'], lambda: env.cmd('RG.FCALL', 'lib', 'processed', '0'))
+    env.expect('RG.STREAM', 'PAUSE', 'lib', 'consumer').equal('OK')
+    env.cmd('xadd', 'stream:1', '*', 'foo', 'bar2')
+    env.cmd('xadd', 'stream:2', '*', 'foo', 'bar3')
+    time.sleep(0.5)
+    env.expect('RG.FCALL', 'lib', 'processed', '0').equal(['bar1'])
+    res = toDictionary(env.cmd('RG.FUNCTION', 'LIST', 'vv'), 6)[0]['stream_consumers'][0]
+    env.assertEqual(res['state'], 'paused')
+
+    # paused state is replicated
+    env.expect('WAIT', '1', '7000').equal(1)
+    slave_conn = env.getSlaveConnection()
+    res = toDictionary(slave_conn.execute_command('RG.FUNCTION', 'LIST', 'vv'), 6)[0]['stream_consumers'][0]
+    env.assertEqual(res['state'], 'paused')
+
+    env.expect('RG.STREAM', 'RESUME', 'lib', 'consumer').equal('OK')
+    runUntil(env, ['bar1', 'bar2', 'bar3'], lambda: sorted(env.cmd('RG.FCALL', 'lib', 'processed', '0')))
+    res = toDictionary(env.cmd('RG.FUNCTION', 'LIST', 'vv'), 6)[0]['stream_consumers'][0]
+    env.assertEqual(res['state'], 'running')
+
+@gearsTest()
+def testStreamConsumerPausedStatePersisted(env):
+    """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, false, function(client, data) {
+})
+    """
+    env.expect('RG.STREAM', 'PAUSE', 'lib', 'consumer').equal('OK')
+    env.expect('debug', 'reload').equal('OK')
+    res = toDictionary(env.cmd('RG.FUNCTION', 'LIST', 'vv'), 6)[0]['stream_consumers'][0]
+    env.assertEqual(res['state'], 'paused')
+
+@gearsTest(decodeResponses=False)
+def testStreamConsumerPausedStateDumped(env):
+    """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, false, function(client, data) {
+})
+    """
+    env.expect('RG.STREAM', 'PAUSE', 'lib', 'consumer').equal(b'OK')
+    payload = env.cmd('RG.FUNCTION', 'DUMP')
+    env.expect('RG.STREAM', 'RESUME', 'lib', 'consumer').equal(b'OK')
+    env.expect('RG.FUNCTION', 'RESTORE', payload, 'REPLACE').equal(b'OK')
+    res = toDictionary(env.cmd('RG.FUNCTION', 'LIST', 'vv'), 6)[0][b'stream_consumers'][0]
+    env.assertEqual(res[b'state'], b'paused')
+
+@gearsTest()
+def testStreamConsumerRetryWhilePaused(env):
+    """#!js name=lib
+var attempts = 0;
+redis.register_function("attempts", () => attempts, ['no-writes']);
+redis.register_stream_consumer("consumer", "stream", 1, true, function(client, data) {
+    attempts++;
+    if (attempts < 2) {
+        throw 'LOCKED key is locked';
+    }
+}, {retry: {max_attempts: 3, backoff: 'fixed', delay: 500, retryable_errors: ['LOCKED']}})
+    """
+    env.cmd('xadd', 'stream:1', '*', 'foo', 'bar')
+    runUntil(env, 1, lambda: env.cmd('RG.FCALL', 'lib', 'attempts', '0'))
+    env.expect('RG.STREAM', 'PAUSE', 'lib', 'consumer').equal('OK')
+    time.sleep(1)
+    # the retry is not performed while the consumer is paused
+    env.expect('RG.FCALL', 'lib', 'attempts', '0').equal(1)
+    env.expect('xlen', 'stream:1').equal(1)
+    env.expect('RG.STREAM', 'RESUME', 'lib', 'consumer').equal('OK')
+    runUntil(env, 2, lambda: env.cmd('RG.FCALL', 'lib', 'attempts', '0'))
+    runUntil(env, 0, lambda: env.cmd('xlen', 'stream:1'))
+
+@gearsTest(withReplicas=True)
+def testStreamSetIdReplicated(env):
+    """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, false, function(client, data) {
+})
+    """
+    env.cmd('xadd', 'stream:1', '1-1', 'foo', 'bar1')
+    env.cmd('xadd', 'stream:1', '2-1', 'foo', 'bar2')
+    env.expect('RG.STREAM', 'PAUSE', 'lib', 'consumer').equal('OK')
+    env.expect('RG.STREAM', 'SETID', 'lib', 'consumer', 'stream:1', '$').equal('OK')
+    env.expect('WAIT', '1', '7000').equal(1)
+    slave_conn = env.getSlaveConnection()
+    res = toDictionary(slave_conn.execute_command('RG.FUNCTION', 'LIST', 'vv'), 6)[0]['stream_consumers'][0]
+    env.assertEqual(res['state'], 'paused')
+    env.assertEqual(res['streams'][0]['id_to_read_from'], '2-1')
+
+@gearsTest(withReplicas=True)
+def testStreamConsumerStateChangeOnReplica(env):
+    """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, false, function(client, data) {
+})
+    """
+    env.cmd('xadd', 'stream:1', '1-1', 'foo', 'bar1')
+    env.expect('WAIT', '1', '7000').equal(1)
+    slave_conn = env.getSlaveConnection()
+    for args in [['PAUSE', 'lib', 'consumer'],
+                 ['RESUME', 'lib', 'consumer'],
+                 ['SETID', 'lib', 'consumer', 'stream:1', '$']]:
+        try:
+            slave_conn.execute_command('RG.STREAM', *args)
+            env.assertTrue(False, message='Command succeeded on a replica')
+        except Exception as e:
+            env.assertContains('Can not change a stream consumer state on a replica', str(e))
+    res = toDictionary(slave_conn.execute_command('RG.FUNCTION', 'LIST', 'vv'), 6)[0]['stream_consumers'][0]
+    env.assertEqual(res['state'], 'running')
+
+@gearsTest()
+def testStreamConsumerPauseErrors(env):
+    """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, false, function(client, data) {
+})
+    """
+    env.expect('RG.STREAM', 'PAUSE', 'lib1', 'consumer').error().contains("No such library 'lib1'")
+    env.expect('RG.STREAM', 'RESUME', 'lib', 'consumer1').error().contains("No such consumer 'consumer1'")
+
+def getConsumerGroup(env, stream, group):
+    for g in env.cmd('XINFO', 'GROUPS', stream):
+        g = dict(zip(g[::2], g[1::2]))
+        if g['name'] == group:
+            return g
+    return None
+
+@gearsTest()
+def testStreamConsumerGroup(env):
+    """#!js name=lib
+var processed = [];
+redis.register_function("processed", () => processed, ['no-writes']);
+redis.register_stream_consumer("consumer", "stream", 1, false, function(client, data) {
+    processed.push(data.record[0][1]);
+}, {consumer_group: true})
+    """
+    env.cmd('xadd', 'stream:1', '1-1', 'foo', 'bar1')
+    env.cmd('xadd', 'stream:1', '2-1', 'foo', 'bar2')
+    runUntil(env, ['bar1', 'bar2'], lambda: env.cmd('RG.FCALL', 'lib', 'processed', '0'))
+    runUntil(env, '2-1', lambda: getConsumerGroup(env, 'stream:1', 'lib.consumer')['last-delivered-id'])
+    group = getConsumerGroup(env, 'stream:1', 'lib.consumer')
+    env.assertEqual(group['pending'], 0)
+    env.expect('XPENDING', 'stream:1', 'lib.consumer').equal([0, None, None, None])
+    res = toDictionary(env.cmd('RG.FUNCTION', 'LIST', 'vv'), 6)[0]['stream_consumers'][0]
+    env.assertEqual(res['consumer_group'], 'lib.consumer')
+
+@gearsTest()
+def testStreamConsumerGroupBatchedUpdates(env):
+    """#!js name=lib
+var processed = [];
+redis.register_function("processed", () => processed, ['no-writes']);
+redis.register_stream_consumer("consumer", "stream", 1, false, function(client, data) {
+    processed.push(data.record[0][1]);
+}, {consumer_group: true})
+    """
+    env.cmd('xadd', 'stream:1', '1-1', 'foo', 'bar1')
+    runUntil(env, '1-1', lambda: getConsumerGroup(env, 'stream:1', 'lib.consumer')['last-delivered-id'])
+    env.cmd('CONFIG', 'RESETSTAT')
+    conn = env.getConnection()
+    p = conn.pipeline(transaction=True)
+    for i in range(2, 7):
+        p.execute_command('xadd', 'stream:1', '%d-1' % i, 'foo', 'bar%d' % i)
+    p.execute()
+    runUntil(env, ['bar%d' % i for i in range(1, 7)], lambda: env.cmd('RG.FCALL', 'lib', 'processed', '0'))
+    runUntil(env, '6-1', lambda: getConsumerGroup(env, 'stream:1', 'lib.consumer')['last-delivered-id'])
+    env.expect('XPENDING', 'stream:1', 'lib.consumer').equal([0, None, None, None])
+    # the records processed on the same event loop iteration are reflected
+    # on the group with a single XREADGROUP and a single XACK.
+    stats = env.cmd('INFO', 'commandstats')
+    env.assertEqual(stats['cmdstat_xreadgroup']['calls'], 1)
+    env.assertEqual(stats['cmdstat_xack']['calls'], 1)
+    env.assertNotContains('cmdstat_xgroup|setid', stats)
+
+@gearsTest()
+def testStreamConsumerGroupPendingRecords(env):
+    """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, false, function(client, data) {
+    throw "Failed processing record";
+}, {consumer_group: true, retry: {max_attempts: 2, delay: 100000}})
+    """
+    env.cmd('xadd', 'stream:1', '1-1', 'foo', 'bar')
+    runUntil(env, 1, lambda: env.cmd('XPENDING', 'stream:1', 'lib.consumer')[0])
+    env.expect('XPENDING', 'stream:1', 'lib.consumer').equal([1, '1-1', '1-1', [['redisgears', '1']]])
+
+@gearsTest()
+def testStreamConsumerGroupExistingGroup(env):
+    env.cmd('xadd', 'stream:1', '1-1', 'foo', 'bar1')
+    env.cmd('xadd', 'stream:1', '2-1', 'foo', 'bar2')
+    env.cmd('xadd', 'stream:1', '3-1', 'foo', 'bar3')
+    env.cmd('XGROUP', 'CREATE', 'stream:1', 'lib.consumer', '2-1')
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+var processed = [];
+redis.register_function("processed", () => processed, ['no-writes']);
+redis.register_stream_consumer("consumer", "stream", 1, false, function(client, data) {
+    processed.push(data.record[0][1]);
+}, {consumer_group: true})
+    """).equal('OK')
+    runUntil(env, ['bar3'], lambda: env.cmd('RG.FCALL', 'lib', 'processed', '0'))
+    runUntil(env, '3-1', lambda: getConsumerGroup(env, 'stream:1', 'lib.consumer')['last-delivered-id'])
+
+@gearsTest()
+def testStreamConsumerGroupSetId(env):
+    """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, false, function(client, data) {
+}, {consumer_group: true})
+    """
+    env.cmd('xadd', 'stream:1', '1-1', 'foo', 'bar1')
+    env.cmd('xadd', 'stream:1', '2-1', 'foo', 'bar2')
+    runUntil(env, '2-1', lambda: getConsumerGroup(env, 'stream:1', 'lib.consumer')['last-delivered-id'])
+    env.expect('RG.STREAM', 'SETID', 'lib', 'consumer', 'stream:1', '5-1').equal('OK')
+    env.assertEqual(getConsumerGroup(env, 'stream:1', 'lib.consumer')['last-delivered-id'], '5-1')
+
+@gearsTest()
+def testStreamConsumerGroupUpgradeErrors(env):
+    """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, false, function(client, data) {
+}, {consumer_group: true})
+    """
+    env.expect('RG.FUNCTION', 'LOAD', 'UPGRADE', """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, false, function(client, data) {
+})
+    """).error().contains("Can not upgrade an existing consumer with different consumer group mode")
+    env.expect('RG.FUNCTION', 'LOAD', 'UPGRADE', """#!js name=lib
+redis.register_stream_consumer("consumer", "stream", 1, false, function(client, data) {
+}, {consumer_group: 'yes'})
+    """).error().contains("'consumer_group' option must be a Boolean")
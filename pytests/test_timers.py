@@ -0,0 +1,153 @@
+from common import gearsTest
+from common import toDictionary
+from common import runUntil
+from common import runFor
+import time
+
+def getTimer(env, conn=None, index=0):
+    res = (conn or env).execute_command('RG.FUNCTION', 'LIST', 'vvv')
+    return toDictionary(res, 6)[0]['timers'][index]
+
+@gearsTest()
+def testTimerInterval(env):
+    """#!js name=lib
+redis.register_timer("timer", 100, function(client) {
+    client.call('incr', 'x');
+});
+    """
+    runUntil(env, True, lambda: int(env.cmd('GET', 'x') or 0) >= 3, timeout=5)
+    res = getTimer(env)
+    env.assertEqual(res['name'], 'timer')
+    env.assertEqual(res['schedule'], 100)
+    env.assertGreaterEqual(res['num_runs'], 3)
+    env.assertEqual(res['num_failed'], 0)
+    env.assertNotEqual(res['last_run'], None)
+    env.assertGreater(res['next_run'], res['last_run'])
+
+@gearsTest()
+def testTimerAsync(env):
+    """#!js name=lib
+redis.register_timer("timer", 100, async function(client) {
+    client.block(function(c) {
+        c.call('incr', 'x');
+    });
+});
+    """
+    runUntil(env, True, lambda: int(env.cmd('GET', 'x') or 0) >= 2, timeout=5)
+    runUntil(env, True, lambda: getTimer(env)['num_success'] >= 2, timeout=5)
+
+@gearsTest(gearsConfig={'timer-run-timeout': '300'})
+def testTimerRunTimeout(env):
+    """#!js name=lib
+redis.register_timer("timer", 100, async function(client) {
+    client.block(function(c) {
+        c.call('incr', 'x');
+    });
+    return new Promise(function(resolve, reject) {});
+});
+    """
+    # a run that never finishes should not block the following runs forever
+    runUntil(env, True, lambda: int(env.cmd('GET', 'x') or 0) >= 2, timeout=5)
+    res = getTimer(env)
+    env.assertGreaterEqual(res['num_failed'], 1)
+    env.assertGreaterEqual(res['num_skipped'], 1)
+    env.assertEqual(res['num_success'], 0)
+    env.assertContains('Timer run timed out', res['last_error'])
+
+@gearsTest()
+def testTimerFailure(env):
+    """#!js name=lib
+redis.register_timer("timer", 100, function(client) {
+    client.call('incr', 'x');
+    throw 'timer failed';
+});
+    """
+    runUntil(env, True, lambda: int(env.cmd('GET', 'x') or 0) >= 2, timeout=5)
+    res = getTimer(env)
+    env.assertGreaterEqual(res['num_failed'], 1)
+    env.assertContains('timer failed', res['last_error'])
+
+@gearsTest()
+def testTimerCron(env):
+    """#!js name=lib
+redis.register_timer("timer", "*/5 * * * *", function(client) {});
+    """
+    res = getTimer(env)
+    env.assertEqual(res['schedule'], '*/5 * * * *')
+    env.assertEqual(res['num_runs'], 0)
+    env.assertEqual(res['next_run'] % (5 * 60 * 1000), 0)
+
+@gearsTest()
+def testTimerUpgrade(env):
+    code = """#!js name=lib
+redis.register_timer("timer", %s, function(client) {
+    client.call('incr', '%s');
+});
+    """
+    env.expect('RG.FUNCTION', 'LOAD', code % ('100', 'x')).equal('OK')
+    runUntil(env, True, lambda: int(env.cmd('GET', 'x') or 0) >= 2, timeout=5)
+    env.expect('RG.FUNCTION', 'LOAD', 'UPGRADE', code % ('100', 'y')).equal('OK')
+    runUntil(env, True, lambda: int(env.cmd('GET', 'y') or 0) >= 2, timeout=5)
+    x = int(env.cmd('GET', 'x'))
+    # stats are kept on upgrade
+    env.assertGreaterEqual(getTimer(env)['num_runs'], x + 2)
+    runFor(x, lambda: int(env.cmd('GET', 'x')), timeout=0.5)
+    # failed upgrade keeps the old timer
+    env.expect('RG.FUNCTION', 'LOAD', 'UPGRADE', (code % ('"foo"', 'z'))).error().contains("Invalid cron expression 'foo'")
+    y = int(env.cmd('GET', 'y'))
+    runUntil(env, True, lambda: int(env.cmd('GET', 'y')) > y, timeout=5)
+    env.assertEqual(env.cmd('GET', 'z'), None)
+
+@gearsTest()
+def testTimerStopsOnLibraryDelete(env):
+    """#!js name=lib
+redis.register_timer("timer", 100, function(client) {
+    client.call('incr', 'x');
+});
+    """
+    runUntil(env, True, lambda: int(env.cmd('GET', 'x') or 0) >= 1, timeout=5)
+    env.expect('RG.FUNCTION', 'DEL', 'lib').equal('OK')
+    x = int(env.cmd('GET', 'x'))
+    runFor(x, lambda: int(env.cmd('GET', 'x')), timeout=0.5)
+
+@gearsTest(withReplicas=True)
+def testTimerRunsOnlyOnPrimary(env):
+    """#!js name=lib
+var n_runs = 0;
+redis.register_timer("timer", 100, function(client) {
+    n_runs += 1;
+    client.call('incr', 'x');
+});
+redis.register_function("n_runs", function(client) {
+    return n_runs;
+}, ['no-writes']);
+    """
+    replica = env.getSlaveConnection()
+    runUntil(env, True, lambda: int(env.cmd('GET', 'x') or 0) >= 2, timeout=5)
+    env.expect('WAIT', '1', '7000').equal(1)
+    env.assertGreaterEqual(int(replica.execute_command('GET', 'x')), 2)
+    env.assertEqual(replica.execute_command('RG.FCALL', 'lib', 'n_runs', '0'), 0)
+    env.assertEqual(getTimer(env, replica)['next_run'], None)
+
+    # promote the replica, the timer should start running on it
+    replica.execute_command('REPLICAOF', 'NO', 'ONE')
+    runUntil(env, True, lambda: replica.execute_command('RG.FCALL', 'lib', 'n_runs', '0') >= 2, timeout=5)
+    env.assertNotEqual(getTimer(env, replica)['next_run'], None)
+
+@gearsTest()
+def testTimerErrors(env):
+    code = """#!js name=lib
+redis.register_timer("timer", %s, function(client) {});
+    """
+    env.expect('RG.FUNCTION', 'LOAD', code % '0').error().contains("Timer interval must be greater than 0")
+    env.expect('RG.FUNCTION', 'LOAD', code % '{}').error().contains("Second argument to 'register_timer' must be an interval in milliseconds or a cron expression")
+    env.expect('RG.FUNCTION', 'LOAD', code % '"* * *"').error().contains("Invalid cron expression '* * *', expected 5 fields")
+    env.expect('RG.FUNCTION', 'LOAD', code % '"60 * * * *"').error().contains("invalid value '60', expected a value between 0 and 59")
+    env.expect('RG.FUNCTION', 'LOAD', code % '"0 0 31 2 *"').error().contains("Cron expression '0 0 31 2 *' never matches")
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_timer("timer", 100, 1);
+    """).error().contains("Third argument to 'register_timer' must be a function")
+    env.expect('RG.FUNCTION', 'LOAD', """#!js name=lib
+redis.register_timer("timer", 100, function(client) {});
+redis.register_timer("timer", 100, function(client) {});
+    """).error().contains("Timer timer already exists")
@@ -0,0 +1,259 @@
+/*
+ * Copyright Redis Ltd. 2018 - present
+ * Licensed under your choice of the Redis Source Available License 2.0 (RSALv2) or
+ * the Server Side Public License v1 (SSPLv1).
+ */
+
+//! Atomic blocks of a background function. A block holds the Redis lock for its whole
+//! duration and records the state of each key right before the block first writes to it,
+//! so the effects of the block can be undone if the block fails. Redis propagates all
+//! the writes that were performed while it was locked, including the undo, to the
+//! replicas and the AOF wrapped with MULTI/EXEC.
+
+use redis_module::context::{thread_safe::ContextGuard, CallOptionsBuilder};
+use redis_module::{raw, RedisValue};
+
+use redisgears_plugin_api::redisgears_plugin_api::{
+    run_function_ctx::AtomicBlockCtxInterface, run_function_ctx::RedisClientCtxInterface,
+    GearsApiError,
+};
+
+use crate::declared_keys::get_command_keys_with_flags;
+use crate::run_ctx::{RedisClient, RedisClientCallOptions};
+use crate::{get_ctx, get_notification_blocker, GearsLibraryMetaData, NotificationBlocker};
+
+use std::collections::HashMap;
+use std::os::raw::{c_int, c_longlong};
+use std::sync::{Arc, Mutex};
+
+/// The state of a key before it was accessed by the block.
+struct KeySnapshot {
+    dump: Vec<u8>,
+    /// Absolute expiration time in milliseconds, 0 if the key has no expiration.
+    expire_at: c_longlong,
+}
+
+/// Returns the bytes of a string reply, `None` if the reply is not a string.
+fn reply_bytes(reply: RedisValue) -> Option<Vec<u8>> {
+    match reply {
+        RedisValue::SimpleString(s) | RedisValue::BulkString(s) => Some(s.into_bytes()),
+        RedisValue::BulkRedisString(s) => Some(s.as_slice().to_vec()),
+        RedisValue::StringBuffer(s) => Some(s),
+        _ => None,
+    }
+}
+
+/// Take a snapshot of the given key, returns `None` if the key does not exist.
+/// Fails if the snapshot can not be taken, in which case the key can not be
+/// restored and so it must not be written.
+fn snapshot_key(key: &[u8]) -> Result<Option<KeySnapshot>, GearsApiError> {
+    let ctx = get_ctx();
+    let call_options = CallOptionsBuilder::new().constract();
+    let args: &[&[u8]] = &[key];
+    let snapshot_error = |e: String| {
+        GearsApiError::new(format!(
+            "Failed taking a snapshot of key '{}' inside an atomic block, {}",
+            String::from_utf8_lossy(key),
+            e
+        ))
+    };
+    let dump = match ctx.call_ext("DUMP", &call_options, args) {
+        Ok(RedisValue::Null) => return Ok(None),
+        Ok(reply) => {
+            reply_bytes(reply).ok_or_else(|| snapshot_error("unexpected DUMP reply".to_string()))?
+        }
+        Err(e) => return Err(snapshot_error(e.to_string())),
+    };
+    let ttl = match ctx.call_ext("PTTL", &call_options, args) {
+        Ok(RedisValue::Integer(ttl)) => ttl,
+        Ok(_) => return Err(snapshot_error("unexpected PTTL reply".to_string())),
+        Err(e) => return Err(snapshot_error(e.to_string())),
+    };
+    let expire_at = if ttl > 0 {
+        let now = unsafe { raw::RedisModule_Milliseconds.unwrap()() };
+        now + ttl
+    } else {
+        0
+    };
+    Ok(Some(KeySnapshot { dump, expire_at }))
+}
+
+/// Bring the given key back to the state it had when the snapshot was taken.
+/// The restore is replicated so the replicas end up with the same state.
+fn restore_key(key: &[u8], snapshot: Option<&KeySnapshot>) {
+    let ctx = get_ctx();
+    let call_options = CallOptionsBuilder::new()
+        .replicate()
+        .errors_as_replies()
+        .constract();
+    let res = match snapshot {
+        Some(snapshot) => {
+            let expire_at = snapshot.expire_at.to_string();
+            let args: &[&[u8]] = &[
+                key,
+                expire_at.as_bytes(),
+                &snapshot.dump,
+                b"REPLACE",
+                b"ABSTTL",
+            ];
+            ctx.call_ext("RESTORE", &call_options, args)
+        }
+        None => {
+            let args: &[&[u8]] = &[key];
+            ctx.call_ext("DEL", &call_options, args)
+        }
+    };
+    if let Err(e) = res {
+        ctx.log_warning(&format!(
+            "Failed restoring key '{}' on atomic block abort, {}",
+            String::from_utf8_lossy(key),
+            e
+        ));
+    }
+}
+
+/// Returns true if the given command is a write command, according to `COMMAND INFO`.
+/// A command that does not exist or that is given with a wrong arity is not considered
+/// a write command, Redis will reject it when it is invoked.
+fn is_write_command(command: &str, num_args: usize) -> bool {
+    let ctx = get_ctx();
+    let call_options = CallOptionsBuilder::new().constract();
+    let args: &[&[u8]] = &[b"INFO", command.as_bytes()];
+    let info = match ctx.call_ext("COMMAND", &call_options, args) {
+        Ok(RedisValue::Array(mut infos)) if infos.len() == 1 => infos.pop().unwrap(),
+        _ => return false,
+    };
+    let info = match info {
+        RedisValue::Array(info) => info,
+        _ => return false,
+    };
+    let valid_arity = match info.get(1) {
+        Some(RedisValue::Integer(arity)) if *arity >= 0 => num_args + 1 == *arity as usize,
+        Some(RedisValue::Integer(arity)) => num_args + 1 >= arity.unsigned_abs() as usize,
+        _ => false,
+    };
+    let is_write = match info.get(2) {
+        Some(RedisValue::Array(flags)) => flags.iter().any(|f| {
+            matches!(f, RedisValue::SimpleString(s) | RedisValue::BulkString(s) if s == "write")
+        }),
+        _ => false,
+    };
+    valid_arity && is_write
+}
+
+/// Records the state of the keys an atomic block writes to. Keys are only
+/// recorded until the block ends, clients that outlive the block (for example
+/// a background client that was created inside the block) do not record anything.
+pub(crate) struct UndoLog {
+    snapshots: Mutex<Option<HashMap<Vec<u8>, Option<KeySnapshot>>>>,
+}
+
+impl UndoLog {
+    fn new() -> UndoLog {
+        UndoLog {
+            snapshots: Mutex::new(Some(HashMap::new())),
+        }
+    }
+
+    /// Record the state of the given key, unless it was already recorded.
+    /// Fails if the state of the key can not be recorded.
+    pub(crate) fn record_key(&self, key: &[u8]) -> Result<(), GearsApiError> {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        if let Some(snapshots) = snapshots.as_mut() {
+            if !snapshots.contains_key(key) {
+                snapshots.insert(key.to_vec(), snapshot_key(key)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Record the state of all the keys the given command is about to write. Write
+    /// commands that do not declare the keys they write to (like `FLUSHALL`) can not
+    /// be undone and so they are rejected.
+    pub(crate) fn record_command(&self, command: &str, args: &[&[u8]]) -> Result<(), String> {
+        if self.snapshots.lock().unwrap().is_none() {
+            return Ok(());
+        }
+        let write_flags = (raw::REDISMODULE_CMD_KEY_RW
+            | raw::REDISMODULE_CMD_KEY_OW
+            | raw::REDISMODULE_CMD_KEY_RM) as c_int;
+        let keys = get_command_keys_with_flags(command, args);
+        if keys.is_empty() && is_write_command(command, args.len()) {
+            return Err(format!(
+                "Write command '{}' does not declare the keys it writes to and can not be used inside an atomic block",
+                command
+            ));
+        }
+        keys.into_iter()
+            .filter(|(_key, flags)| flags & write_flags != 0)
+            .try_for_each(|(key, _flags)| self.record_key(&key))
+            .map_err(|e| e.get_msg().to_string())
+    }
+
+    /// Stop recording and restore all the recorded keys.
+    fn undo(&self) {
+        let snapshots = self.snapshots.lock().unwrap().take();
+        if let Some(snapshots) = snapshots {
+            snapshots
+                .iter()
+                .for_each(|(key, snapshot)| restore_key(key, snapshot.as_ref()));
+        }
+    }
+
+    /// Stop recording and keep the effects of the block.
+    fn finish(&self) {
+        self.snapshots.lock().unwrap().take();
+    }
+}
+
+pub(crate) struct GearsAtomicBlock {
+    _ctx_guard: ContextGuard,
+    call_options: RedisClientCallOptions,
+    user: Option<String>,
+    lib_meta_data: Arc<GearsLibraryMetaData>,
+    undo_log: Arc<UndoLog>,
+    _notification_blocker: NotificationBlocker,
+}
+
+unsafe impl Sync for GearsAtomicBlock {}
+unsafe impl Send for GearsAtomicBlock {}
+
+impl GearsAtomicBlock {
+    pub(crate) fn new(
+        ctx_guard: ContextGuard,
+        user: Option<String>,
+        lib_meta_data: &Arc<GearsLibraryMetaData>,
+        mut call_options: RedisClientCallOptions,
+    ) -> GearsAtomicBlock {
+        let undo_log = Arc::new(UndoLog::new());
+        call_options.undo_log = Some(Arc::clone(&undo_log));
+        GearsAtomicBlock {
+            _ctx_guard: ctx_guard,
+            call_options,
+            user,
+            lib_meta_data: Arc::clone(lib_meta_data),
+            undo_log,
+            _notification_blocker: get_notification_blocker(),
+        }
+    }
+}
+
+impl AtomicBlockCtxInterface for GearsAtomicBlock {
+    fn get_client(&self) -> Box<dyn RedisClientCtxInterface> {
+        Box::new(RedisClient::with_call_options(
+            Arc::clone(&self.lib_meta_data),
+            self.user.clone(),
+            self.call_options.clone(),
+        ))
+    }
+
+    fn abort(self: Box<Self>) {
+        self.undo_log.undo();
+    }
+}
+
+impl Drop for GearsAtomicBlock {
+    fn drop(&mut self) {
+        self.undo_log.finish();
+    }
+}
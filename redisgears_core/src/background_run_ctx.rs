@@ -6,17 +6,21 @@
 
 use redisgears_plugin_api::redisgears_plugin_api::load_library_ctx::FunctionFlags;
 use redisgears_plugin_api::redisgears_plugin_api::{
-    run_function_ctx::BackgroundRunFunctionCtxInterface, run_function_ctx::RedisClientCtxInterface,
-    run_function_ctx::RemoteFunctionData, GearsApiError,
+    run_function_ctx::AtomicBlockCtxInterface, run_function_ctx::BackgroundRunFunctionCtxInterface,
+    run_function_ctx::RedisClientCtxInterface, run_function_ctx::RemoteFunctionData,
+    run_function_ctx::ScanCursorInterface, run_function_ctx::ScanOptions, GearsApiError,
 };
 
+use crate::atomic_block::GearsAtomicBlock;
 use crate::background_run_scope_guard::BackgroundRunScopeGuardCtx;
+use crate::keys_scan::KeysScanCursor;
 use crate::run_ctx::RedisClientCallOptions;
 use crate::{
     get_globals, get_libraries, verify_ok_on_replica, verify_oom, Deserialize,
     GearsLibraryMetaData, Serialize,
 };
 
+use redis_module::context::thread_safe::ContextGuard;
 use redis_module::{RedisValue, ThreadSafeContext};
 
 use std::sync::Arc;
@@ -46,6 +50,21 @@ impl BackgroundRunCtx {
             call_options,
         }
     }
+
+    fn lock_redis(&self) -> Result<ContextGuard, GearsApiError> {
+        let ctx_guard = ThreadSafeContext::new().lock();
+        if !verify_ok_on_replica(self.call_options.flags) {
+            return Err(GearsApiError::new(
+                "Can not lock redis for write on replica".to_string(),
+            ));
+        }
+        if !verify_oom(self.call_options.flags) {
+            return Err(GearsApiError::new(
+                "OOM Can not lock redis for write".to_string(),
+            ));
+        }
+        Ok(ctx_guard)
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, BaseObject)]
@@ -154,17 +173,7 @@ impl RemoteTask for GearsRemoteTask {
 
 impl BackgroundRunFunctionCtxInterface for BackgroundRunCtx {
     fn lock(&self) -> Result<Box<dyn RedisClientCtxInterface>, GearsApiError> {
-        let ctx_guard = ThreadSafeContext::new().lock();
-        if !verify_ok_on_replica(self.call_options.flags) {
-            return Err(GearsApiError::new(
-                "Can not lock redis for write on replica".to_string(),
-            ));
-        }
-        if !verify_oom(self.call_options.flags) {
-            return Err(GearsApiError::new(
-                "OOM Can not lock redis for write".to_string(),
-            ));
-        }
+        let ctx_guard = self.lock_redis()?;
         Ok(Box::new(BackgroundRunScopeGuardCtx::new(
             ctx_guard,
             self.user.clone(),
@@ -173,6 +182,16 @@ impl BackgroundRunFunctionCtxInterface for BackgroundRunCtx {
         )))
     }
 
+    fn lock_atomic(&self) -> Result<Box<dyn AtomicBlockCtxInterface>, GearsApiError> {
+        let ctx_guard = self.lock_redis()?;
+        Ok(Box::new(GearsAtomicBlock::new(
+            ctx_guard,
+            self.user.clone(),
+            &self.lib_meta_data,
+            self.call_options.clone(),
+        )))
+    }
+
     fn run_on_key(
         &self,
         key: &[u8],
@@ -226,4 +245,12 @@ impl BackgroundRunFunctionCtxInterface for BackgroundRunCtx {
             get_globals().config.remote_task_default_timeout.timeout,
         )
     }
+
+    fn scan(&self, options: ScanOptions) -> Result<Box<dyn ScanCursorInterface>, GearsApiError> {
+        Ok(Box::new(KeysScanCursor::new(
+            options,
+            &self.call_options,
+            true,
+        )?))
+    }
 }
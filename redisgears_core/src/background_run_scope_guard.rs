@@ -9,11 +9,14 @@ use redis_module::context::thread_safe::ContextGuard;
 use redis_module::Status;
 
 use redisgears_plugin_api::redisgears_plugin_api::{
-    redisai_interface::AIModelInterface, redisai_interface::AIScriptInterface,
-    run_function_ctx::BackgroundRunFunctionCtxInterface, run_function_ctx::RedisClientCtxInterface,
-    CallResult, GearsApiError,
+    key_ctx::KeyCtxInterface, redisai_interface::AIModelInterface,
+    redisai_interface::AIScriptInterface, run_function_ctx::BackgroundRunFunctionCtxInterface,
+    run_function_ctx::RedisClientCtxInterface, run_function_ctx::ScanCursorInterface,
+    run_function_ctx::ScanOptions, CallResult, GearsApiError,
 };
 
+use crate::key_ctx::GearsKeyCtx;
+use crate::keys_scan::KeysScanCursor;
 use crate::run_ctx::RedisClientCallOptions;
 use crate::{
     background_run_ctx::BackgroundRunCtx, call_redis_command, get_notification_blocker,
@@ -61,7 +64,7 @@ impl RedisClientCtxInterface for BackgroundRunScopeGuardCtx {
             Some(u) => Some(u),
             None => Some(&self.lib_meta_data.user),
         };
-        call_redis_command(user, command, &self.call_options.call_options, args)
+        call_redis_command(user, command, &self.call_options, args)
     }
 
     fn get_background_redis_client(&self) -> Box<dyn BackgroundRunFunctionCtxInterface> {
@@ -113,4 +116,17 @@ impl RedisClientCtxInterface for BackgroundRunScopeGuardCtx {
             Err(e) => Err(GearsApiError::new(e)),
         }
     }
+
+    fn scan(&self, options: ScanOptions) -> Result<Box<dyn ScanCursorInterface>, GearsApiError> {
+        Ok(Box::new(KeysScanCursor::new(
+            options,
+            &self.call_options,
+            false,
+        )?))
+    }
+
+    fn open_key(&self, name: &[u8]) -> Result<Box<dyn KeyCtxInterface>, GearsApiError> {
+        let user = self.user.as_ref().unwrap_or(&self.lib_meta_data.user);
+        Ok(Box::new(GearsKeyCtx::new(name, user, &self.call_options)?))
+    }
 }
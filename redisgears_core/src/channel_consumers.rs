@@ -0,0 +1,345 @@
+/*
+ * Copyright Redis Ltd. 2018 - present
+ * Licensed under your choice of the Redis Source Available License 2.0 (RSALv2) or
+ * the Server Side Public License v1 (SSPLv1).
+ */
+
+//! Consumers of messages that are published on Pub/Sub channels. Modules can not
+//! subscribe to channels, so a command filter rewrites `PUBLISH` and `SPUBLISH` of
+//! a consumed channel into an internal command that publishes the message. The
+//! consumers only get the messages that were actually published, a command that
+//! was discarded (`MULTI`/`DISCARD`) or rejected (ACL) is never executed. The
+//! messages are passed to the consumers right after the command, from the event loop.
+//!
+//! Messages that arrive over the cluster bus are not executed as commands and can
+//! not be seen by the filter, so channel consumers are not supported on a cluster.
+//! The filter is only registered when the first channel consumer is added, so it
+//! does not add any overhead when channel consumers are not used.
+
+use redis_module::{
+    context::CallOptionsBuilder, raw, Context, RedisError, RedisResult, RedisString,
+};
+
+use redisgears_plugin_api::redisgears_plugin_api::{
+    channel_consumer_ctx::ChannelConsumerOptions, channel_consumer_ctx::RegisteredChannels,
+    GearsApiError, RefCellWrapper,
+};
+
+use crate::keys_notifications::glob_match;
+use crate::{get_ctx, get_globals, get_globals_mut};
+
+use std::cell::RefCell;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime};
+
+pub(crate) type ChannelConsumerCallback =
+    Box<dyn Fn(&[u8], &[u8], Box<dyn FnOnce(Result<(), GearsApiError>) + Send + Sync>)>;
+
+pub(crate) enum ConsumerChannel {
+    Channel(Vec<u8>),
+    Pattern(Vec<u8>),
+}
+
+impl From<RegisteredChannels<'_>> for ConsumerChannel {
+    fn from(channel: RegisteredChannels) -> Self {
+        match channel {
+            RegisteredChannels::Channel(c) => ConsumerChannel::Channel(c.to_vec()),
+            RegisteredChannels::Pattern(p) => ConsumerChannel::Pattern(p.to_vec()),
+        }
+    }
+}
+
+impl ConsumerChannel {
+    fn is_match(&self, channel: &[u8]) -> bool {
+        match self {
+            ConsumerChannel::Channel(c) => c == channel,
+            ConsumerChannel::Pattern(p) => glob_match(p, channel),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct ChannelConsumerStats {
+    pub(crate) num_trigger: usize,
+    pub(crate) num_success: usize,
+    pub(crate) num_failed: usize,
+    pub(crate) num_finished: usize,
+    pub(crate) last_error: Option<GearsApiError>,
+    pub(crate) last_execution_time: u128,
+    pub(crate) total_execution_time: u128,
+}
+
+pub(crate) struct ChannelConsumer {
+    channel: ConsumerChannel,
+    callback: Option<ChannelConsumerCallback>,
+    options: ChannelConsumerOptions,
+    stats: Arc<RefCellWrapper<ChannelConsumerStats>>,
+}
+
+impl ChannelConsumer {
+    pub(crate) fn set_callback(
+        &mut self,
+        callback: ChannelConsumerCallback,
+    ) -> ChannelConsumerCallback {
+        let old_callback = self.callback.take();
+        self.callback = Some(callback);
+        old_callback.unwrap()
+    }
+
+    pub(crate) fn set_channel(&mut self, channel: ConsumerChannel) -> ConsumerChannel {
+        std::mem::replace(&mut self.channel, channel)
+    }
+
+    pub(crate) fn set_options(
+        &mut self,
+        options: ChannelConsumerOptions,
+    ) -> ChannelConsumerOptions {
+        std::mem::replace(&mut self.options, options)
+    }
+
+    pub(crate) fn get_channel(&self) -> &ConsumerChannel {
+        &self.channel
+    }
+
+    pub(crate) fn get_options(&self) -> &ChannelConsumerOptions {
+        &self.options
+    }
+
+    pub(crate) fn get_stats(&self) -> ChannelConsumerStats {
+        self.stats.ref_cell.borrow().clone()
+    }
+}
+
+fn fire_message(consumer: &Arc<RefCell<ChannelConsumer>>, channel: &[u8], message: &[u8]) {
+    let c = consumer.borrow();
+    if !c.options.run_on_replicas && !get_ctx().is_primary() {
+        return;
+    }
+    {
+        let mut stats = c.stats.ref_cell.borrow_mut();
+        stats.num_trigger += 1;
+    }
+    let stats_ref = Arc::clone(&c.stats);
+    let start_time = SystemTime::now();
+
+    (c.callback.as_ref().unwrap())(
+        channel,
+        message,
+        Box::new(move |res| {
+            let duration = match SystemTime::now().duration_since(start_time) {
+                Ok(d) => d.as_millis(),
+                Err(_) => 0,
+            };
+            let mut stats = stats_ref.ref_cell.borrow_mut();
+            stats.num_finished += 1;
+            stats.last_execution_time = duration;
+            stats.total_execution_time += duration;
+            if let Err(e) = res {
+                stats.num_failed += 1;
+                stats.last_error = Some(e);
+            } else {
+                stats.num_success += 1;
+            }
+        }),
+    );
+}
+
+/// A published message along with the consumers it should be passed to.
+struct PendingMessage {
+    channel: Vec<u8>,
+    message: Vec<u8>,
+    consumers: Vec<Weak<RefCell<ChannelConsumer>>>,
+}
+
+pub(crate) struct ChannelConsumersCtx {
+    consumers: Vec<Weak<RefCell<ChannelConsumer>>>,
+    pending_messages: Vec<PendingMessage>,
+    /// Set while a message is published by the internal command, so the
+    /// command filter will not rewrite it again.
+    publishing: bool,
+    /// The `PUBLISH` command filter, null until the first consumer is added.
+    filter: *mut raw::RedisModuleCommandFilter,
+}
+
+impl ChannelConsumersCtx {
+    pub(crate) fn new() -> ChannelConsumersCtx {
+        ChannelConsumersCtx {
+            consumers: Vec::new(),
+            pending_messages: Vec::new(),
+            publishing: false,
+            filter: std::ptr::null_mut(),
+        }
+    }
+
+    pub(crate) fn add_consumer(
+        &mut self,
+        channel: ConsumerChannel,
+        callback: ChannelConsumerCallback,
+        options: ChannelConsumerOptions,
+    ) -> Arc<RefCell<ChannelConsumer>> {
+        let consumer = Arc::new(RefCell::new(ChannelConsumer {
+            channel,
+            callback: Some(callback),
+            options,
+            stats: Arc::new(RefCellWrapper {
+                ref_cell: RefCell::new(ChannelConsumerStats::default()),
+            }),
+        }));
+        self.consumers.retain(|c| c.strong_count() > 0);
+        self.consumers.push(Arc::downgrade(&consumer));
+        if self.filter.is_null() {
+            self.filter = unsafe {
+                raw::RedisModule_RegisterCommandFilter.unwrap()(
+                    get_ctx().ctx,
+                    Some(publish_command_filter),
+                    0,
+                )
+            };
+        }
+        consumer
+    }
+
+    fn is_consumed(&self, channel: &[u8]) -> bool {
+        self.consumers.iter().any(|c| {
+            c.upgrade()
+                .map_or(false, |c| c.borrow().channel.is_match(channel))
+        })
+    }
+
+    fn on_message_published(&mut self, channel: &[u8], message: &[u8]) {
+        let consumers = self
+            .consumers
+            .iter()
+            .filter(|c| {
+                c.upgrade()
+                    .map_or(false, |c| c.borrow().channel.is_match(channel))
+            })
+            .cloned()
+            .collect::<Vec<Weak<RefCell<ChannelConsumer>>>>();
+        if consumers.is_empty() {
+            return;
+        }
+        if self.pending_messages.is_empty() {
+            // first pending message, schedule the delivery of all the
+            // messages that will be published until the timer fires.
+            let job: Box<dyn FnOnce()> = Box::new(deliver_pending_messages);
+            get_ctx().create_timer(
+                Duration::from_millis(0),
+                |_ctx, job: Box<dyn FnOnce()>| job(),
+                job,
+            );
+        }
+        self.pending_messages.push(PendingMessage {
+            channel: channel.to_vec(),
+            message: message.to_vec(),
+            consumers,
+        });
+    }
+}
+
+fn deliver_pending_messages() {
+    let pending_messages =
+        std::mem::take(&mut get_globals_mut().channel_consumers_ctx.pending_messages);
+    for pending_message in pending_messages {
+        for consumer in pending_message.consumers.iter().filter_map(|c| c.upgrade()) {
+            fire_message(
+                &consumer,
+                &pending_message.channel,
+                &pending_message.message,
+            );
+        }
+    }
+}
+
+/// Channel consumers can not get the messages that are published on other shards,
+/// fail the registration on a cluster instead of missing those messages.
+pub(crate) fn verify_channel_consumers_supported() -> Result<(), GearsApiError> {
+    let flags = unsafe { raw::RedisModule_GetContextFlags.unwrap()(get_ctx().ctx) };
+    if flags & raw::REDISMODULE_CTX_FLAGS_CLUSTER as std::os::raw::c_int != 0 {
+        return Err(GearsApiError::new(
+            "Channel consumers are not supported on a cluster, messages published on other shards can not be consumed",
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the argument at the given position of the filtered command.
+///
+/// # Safety
+///
+/// The position must be smaller than the number of arguments and the returned
+/// slice must not be used after the filter callback returns.
+unsafe fn command_filter_arg<'a>(
+    fctx: *mut raw::RedisModuleCommandFilterCtx,
+    pos: i32,
+) -> &'a [u8] {
+    let arg = raw::RedisModule_CommandFilterArgGet.unwrap()(fctx, pos);
+    let mut len = 0;
+    let ptr = raw::RedisModule_StringPtrLen.unwrap()(arg, &mut len);
+    std::slice::from_raw_parts(ptr.cast::<u8>(), len)
+}
+
+/// A command filter that rewrites `PUBLISH` and `SPUBLISH` of a consumed channel
+/// into `_rg_internals.publish` and `_rg_internals.spublish`.
+extern "C" fn publish_command_filter(fctx: *mut raw::RedisModuleCommandFilterCtx) {
+    let channel_consumers_ctx = &get_globals().channel_consumers_ctx;
+    if channel_consumers_ctx.consumers.is_empty() || channel_consumers_ctx.publishing {
+        return;
+    }
+    unsafe {
+        if raw::RedisModule_CommandFilterArgsCount.unwrap()(fctx) != 3 {
+            return;
+        }
+        let command = command_filter_arg(fctx, 0);
+        let internal_command = if command.eq_ignore_ascii_case(b"publish") {
+            "_rg_internals.publish"
+        } else if command.eq_ignore_ascii_case(b"spublish") {
+            "_rg_internals.spublish"
+        } else {
+            return;
+        };
+        if !channel_consumers_ctx.is_consumed(command_filter_arg(fctx, 1)) {
+            return;
+        }
+        let internal_command = raw::RedisModule_CreateString.unwrap()(
+            std::ptr::null_mut(),
+            internal_command.as_ptr().cast(),
+            internal_command.len(),
+        );
+        raw::RedisModule_CommandFilterArgReplace.unwrap()(fctx, 0, internal_command);
+    }
+}
+
+/// Publish the message with the given command and pass it to the consumers. The message
+/// is not passed to the consumers if it was not published or if it was replicated from
+/// the primary (the consumers on the primary already got it).
+fn publish(ctx: &Context, command: &str, args: Vec<RedisString>) -> RedisResult {
+    if args.len() != 3 {
+        return Err(RedisError::WrongArity);
+    }
+    let call_options = CallOptionsBuilder::new()
+        .replicate()
+        .verify_acl()
+        .errors_as_replies()
+        .constract();
+    let call_args: &[&[u8]] = &[args[1].as_slice(), args[2].as_slice()];
+    get_globals_mut().channel_consumers_ctx.publishing = true;
+    let res = ctx.call_ext(command, &call_options, call_args);
+    get_globals_mut().channel_consumers_ctx.publishing = false;
+    let res = res?;
+    let flags = unsafe { raw::RedisModule_GetContextFlags.unwrap()(ctx.ctx) };
+    if flags & raw::REDISMODULE_CTX_FLAGS_REPLICATED as std::os::raw::c_int == 0 {
+        get_globals_mut()
+            .channel_consumers_ctx
+            .on_message_published(args[1].as_slice(), args[2].as_slice());
+    }
+    Ok(res)
+}
+
+pub(crate) fn publish_command(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    publish(ctx, "publish", args)
+}
+
+pub(crate) fn spublish_command(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    publish(ctx, "spublish", args)
+}
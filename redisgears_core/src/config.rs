@@ -188,6 +188,64 @@ impl RedisNumberConfigCtx for RemoteTaskDefaultTimeout {
     }
 }
 
+pub(crate) struct TimerRunTimeout {
+    pub(crate) timeout: usize,
+    flags: ConfigFlags,
+}
+
+impl TimerRunTimeout {
+    const OPTION_NAME: &'static str = "timer-run-timeout";
+
+    fn new() -> TimerRunTimeout {
+        TimerRunTimeout {
+            timeout: 300000, // 5 minutes
+            flags: ConfigFlags::new(),
+        }
+    }
+}
+
+impl fmt::Display for TimerRunTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.timeout)
+    }
+}
+
+impl RedisConfigCtx for TimerRunTimeout {
+    fn name(&self) -> &'static str {
+        Self::OPTION_NAME
+    }
+
+    fn apply(&self, _ctx: &Context) -> Result<(), RedisError> {
+        Ok(())
+    }
+
+    fn flags(&self) -> &ConfigFlags {
+        &self.flags
+    }
+}
+
+impl RedisNumberConfigCtx for TimerRunTimeout {
+    fn default(&self) -> i64 {
+        300000 // 5 minutes
+    }
+
+    fn min(&self) -> i64 {
+        1 // 1 ms
+    }
+    fn max(&self) -> i64 {
+        i64::MAX
+    }
+
+    fn get(&self, _name: &str) -> i64 {
+        self.timeout as i64
+    }
+
+    fn set(&mut self, _name: &str, value: i64) -> Result<(), RedisError> {
+        self.timeout = value as usize;
+        Ok(())
+    }
+}
+
 pub(crate) struct LibraryMaxMemory {
     pub(crate) size: usize,
     flags: ConfigFlags,
@@ -493,6 +551,7 @@ pub(crate) struct Config {
     pub(crate) lock_regis_timeout: LockRedisTimeout,
     pub(crate) enable_debug_command: EnableDebugCommand,
     pub(crate) remote_task_default_timeout: RemoteTaskDefaultTimeout,
+    pub(crate) timer_run_timeout: TimerRunTimeout,
     pub(crate) error_verbosity: ErrorVerbosity,
 }
 
@@ -506,6 +565,7 @@ impl Config {
             lock_regis_timeout: LockRedisTimeout::new(),
             enable_debug_command: EnableDebugCommand::new(),
             remote_task_default_timeout: RemoteTaskDefaultTimeout::new(),
+            timer_run_timeout: TimerRunTimeout::new(),
             error_verbosity: ErrorVerbosity::new(),
         }
     }
@@ -594,6 +654,9 @@ impl Config {
             x if x == self.remote_task_default_timeout.name() => {
                 Self::set_numeric_value(&mut self.remote_task_default_timeout, val)
             }
+            x if x == self.timer_run_timeout.name() => {
+                Self::set_numeric_value(&mut self.timer_run_timeout, val)
+            }
             x if x == self.error_verbosity.name() => {
                 Self::set_numeric_value(&mut self.error_verbosity, val)
             }
@@ -622,6 +685,7 @@ impl Config {
             RemoteTaskDefaultTimeout::OPTION_NAME => {
                 Self::is_immutable(&self.remote_task_default_timeout)
             }
+            TimerRunTimeout::OPTION_NAME => Self::is_immutable(&self.timer_run_timeout),
             EnableDebugCommand::OPTION_NAME => Self::is_immutable(&self.enable_debug_command),
             _ => {
                 return Err(RedisError::String(format!(
@@ -653,6 +717,7 @@ impl Config {
             x if x == self.remote_task_default_timeout.name() => {
                 Ok(format!("{}", self.remote_task_default_timeout))
             }
+            x if x == self.timer_run_timeout.name() => Ok(format!("{}", self.timer_run_timeout)),
             x if x == self.enable_debug_command.name() => {
                 Ok(format!("{}", self.enable_debug_command))
             }
@@ -0,0 +1,95 @@
+/*
+ * Copyright Redis Ltd. 2018 - present
+ * Licensed under your choice of the Redis Source Available License 2.0 (RSALv2) or
+ * the Server Side Public License v1 (SSPLv1).
+ */
+
+//! The run context given to the consumers that are invoked by Redis,
+//! keys notifications, timers, server events and channel consumers.
+
+use redisgears_plugin_api::redisgears_plugin_api::load_library_ctx::FunctionFlags;
+use redisgears_plugin_api::redisgears_plugin_api::{
+    channel_consumer_ctx::ChannelConsumerRunCtxInterface,
+    keys_notifications_consumer_ctx::NotificationRunCtxInterface,
+    run_function_ctx::BackgroundRunFunctionCtxInterface, run_function_ctx::RedisClientCtxInterface,
+    server_events_ctx::ServerEventRunCtxInterface, timer_ctx::TimerRunCtxInterface,
+};
+
+use crate::background_run_ctx::BackgroundRunCtx;
+use crate::run_ctx::{RedisClient, RedisClientCallOptions};
+use crate::GearsLibraryMetaData;
+
+use std::sync::Arc;
+
+pub(crate) struct ConsumerRunCtx {
+    lib_meta_data: Arc<GearsLibraryMetaData>,
+    flags: FunctionFlags,
+}
+
+impl ConsumerRunCtx {
+    pub(crate) fn new(
+        lib_meta_data: Arc<GearsLibraryMetaData>,
+        flags: FunctionFlags,
+    ) -> ConsumerRunCtx {
+        ConsumerRunCtx {
+            lib_meta_data,
+            flags,
+        }
+    }
+
+    fn redis_client(&self) -> Box<dyn RedisClientCtxInterface> {
+        Box::new(RedisClient::new(
+            self.lib_meta_data.clone(),
+            None,
+            self.flags,
+        ))
+    }
+
+    fn background_redis_client(&self) -> Box<dyn BackgroundRunFunctionCtxInterface> {
+        Box::new(BackgroundRunCtx::new(
+            None,
+            &self.lib_meta_data,
+            RedisClientCallOptions::new(self.flags),
+        ))
+    }
+}
+
+impl NotificationRunCtxInterface for ConsumerRunCtx {
+    fn get_redis_client(&self) -> Box<dyn RedisClientCtxInterface> {
+        self.redis_client()
+    }
+
+    fn get_background_redis_client(&self) -> Box<dyn BackgroundRunFunctionCtxInterface> {
+        self.background_redis_client()
+    }
+}
+
+impl TimerRunCtxInterface for ConsumerRunCtx {
+    fn get_redis_client(&self) -> Box<dyn RedisClientCtxInterface> {
+        self.redis_client()
+    }
+
+    fn get_background_redis_client(&self) -> Box<dyn BackgroundRunFunctionCtxInterface> {
+        self.background_redis_client()
+    }
+}
+
+impl ServerEventRunCtxInterface for ConsumerRunCtx {
+    fn get_redis_client(&self) -> Box<dyn RedisClientCtxInterface> {
+        self.redis_client()
+    }
+
+    fn get_background_redis_client(&self) -> Box<dyn BackgroundRunFunctionCtxInterface> {
+        self.background_redis_client()
+    }
+}
+
+impl ChannelConsumerRunCtxInterface for ConsumerRunCtx {
+    fn get_redis_client(&self) -> Box<dyn RedisClientCtxInterface> {
+        self.redis_client()
+    }
+
+    fn get_background_redis_client(&self) -> Box<dyn BackgroundRunFunctionCtxInterface> {
+        self.background_redis_client()
+    }
+}
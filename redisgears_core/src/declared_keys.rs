@@ -0,0 +1,170 @@
+/*
+ * Copyright Redis Ltd. 2018 - present
+ * Licensed under your choice of the Redis Source Available License 2.0 (RSALv2) or
+ * the Server Side Public License v1 (SSPLv1).
+ */
+
+//! Enforcement of the keys a function declared on `RG.FCALL`. A function with the
+//! `strict-keys` flag can only run commands that access the keys it declared and
+//! that do not span multiple cluster slots, so it is safe to run it on a cluster.
+
+use redis_module::{raw, RedisString};
+
+use crate::get_ctx;
+
+use std::collections::HashSet;
+use std::os::raw::c_int;
+
+const CLUSTER_SLOTS: u16 = 16384;
+
+/// Commands that access the entire key space without declaring any key,
+/// their result depends on keys that were not declared by the function.
+const KEYSPACE_COMMANDS: &[&str] = &[
+    "keys",
+    "scan",
+    "randomkey",
+    "dbsize",
+    "flushall",
+    "flushdb",
+    "swapdb",
+];
+
+/// CRC16 (XMODEM), the checksum Redis cluster uses to map keys to slots.
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, b| {
+        (0..8).fold(crc ^ ((*b as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// Returns the cluster slot of the given key, only the hash tag
+/// (the part between the first `{` and the following `}`) is hashed
+/// if the key has a non empty hash tag.
+fn key_hash_slot(key: &[u8]) -> u16 {
+    let hashed = key
+        .iter()
+        .position(|c| *c == b'{')
+        .and_then(|start| {
+            key[start + 1..]
+                .iter()
+                .position(|c| *c == b'}')
+                .filter(|len| *len > 0)
+                .map(|len| &key[start + 1..start + 1 + len])
+        })
+        .unwrap_or(key);
+    crc16(hashed) % CLUSTER_SLOTS
+}
+
+/// Returns the keys the given command accesses, according to the command key specs.
+pub(crate) fn get_command_keys(command: &str, args: &[&[u8]]) -> Vec<Vec<u8>> {
+    get_command_keys_with_flags(command, args)
+        .into_iter()
+        .map(|(key, _flags)| key)
+        .collect()
+}
+
+/// Returns the keys the given command accesses along with the key spec flags
+/// of each key (`REDISMODULE_CMD_KEY_*`), according to the command key specs.
+pub(crate) fn get_command_keys_with_flags(command: &str, args: &[&[u8]]) -> Vec<(Vec<u8>, c_int)> {
+    let ctx = get_ctx();
+    let argv = std::iter::once(ctx.create_string(command))
+        .chain(args.iter().map(|a| ctx.create_string_from_slice(a)))
+        .collect::<Vec<RedisString>>();
+    let mut argv_inner = argv
+        .iter()
+        .map(|a| a.inner)
+        .collect::<Vec<*mut raw::RedisModuleString>>();
+    let mut num_keys: c_int = 0;
+    let mut flags: *mut c_int = std::ptr::null_mut();
+    unsafe {
+        let positions = raw::RedisModule_GetCommandKeysWithFlags.unwrap()(
+            ctx.ctx,
+            argv_inner.as_mut_ptr(),
+            argv_inner.len() as c_int,
+            &mut num_keys,
+            &mut flags,
+        );
+        if positions.is_null() {
+            // the command does not exist, has a wrong arity or
+            // does not access any key.
+            return Vec::new();
+        }
+        let keys = std::slice::from_raw_parts(positions, num_keys as usize)
+            .iter()
+            .zip(std::slice::from_raw_parts(flags, num_keys as usize))
+            .map(|(pos, flags)| (argv[*pos as usize].as_slice().to_vec(), *flags))
+            .collect();
+        raw::RedisModule_Free.unwrap()(positions.cast());
+        raw::RedisModule_Free.unwrap()(flags.cast());
+        keys
+    }
+}
+
+/// The keys a function declared when it was invoked.
+pub(crate) struct DeclaredKeys {
+    keys: HashSet<Vec<u8>>,
+}
+
+impl DeclaredKeys {
+    /// Create the declared keys of a function invocation, fails if the keys
+    /// belong to different cluster slots.
+    pub(crate) fn new(keys: &[RedisString]) -> Result<DeclaredKeys, String> {
+        if let Some((first, rest)) = keys.split_first() {
+            let slot = key_hash_slot(first.as_slice());
+            if rest.iter().any(|k| key_hash_slot(k.as_slice()) != slot) {
+                return Err("Declared keys belong to different slots".to_string());
+            }
+        }
+        Ok(DeclaredKeys {
+            keys: keys.iter().map(|k| k.as_slice().to_vec()).collect(),
+        })
+    }
+
+    /// Verify that the given key was declared, used when a key is accessed directly.
+    pub(crate) fn verify_key(&self, key: &[u8]) -> Result<(), String> {
+        if self.keys.contains(key) {
+            return Ok(());
+        }
+        Err(format!(
+            "Key '{}' was not declared by the function",
+            String::from_utf8_lossy(key)
+        ))
+    }
+
+    /// Verify that the given command only accesses declared keys and
+    /// that all the keys it accesses belong to the same cluster slot.
+    pub(crate) fn verify_command(&self, command: &str, args: &[&[u8]]) -> Result<(), String> {
+        if KEYSPACE_COMMANDS
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(command))
+        {
+            return Err(format!(
+                "Command '{}' accesses the entire key space which is not allowed on functions with the 'strict-keys' flag",
+                command
+            ));
+        }
+        let keys = get_command_keys(command, args);
+        if let Some(key) = keys.iter().find(|k| !self.keys.contains(*k)) {
+            return Err(format!(
+                "Command '{}' accesses key '{}' which was not declared by the function",
+                command,
+                String::from_utf8_lossy(key)
+            ));
+        }
+        if let Some((first, rest)) = keys.split_first() {
+            let slot = key_hash_slot(first);
+            if rest.iter().any(|k| key_hash_slot(k) != slot) {
+                return Err(format!(
+                    "Command '{}' accesses keys that belong to different slots",
+                    command
+                ));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+/*
+ * Copyright Redis Ltd. 2018 - present
+ * Licensed under your choice of the Redis Source Available License 2.0 (RSALv2) or
+ * the Server Side Public License v1 (SSPLv1).
+ */
+
+//! Validation of the arguments given to a function against the
+//! arguments schema the function was registered with.
+
+use redis_module::RedisError;
+use redisgears_plugin_api::redisgears_plugin_api::function_ctx::{
+    FunctionArgument, FunctionArgumentType,
+};
+use redisgears_plugin_api::redisgears_plugin_api::GearsApiError;
+
+use std::collections::HashSet;
+
+/// Verify that the arguments schema is valid: argument names are unique,
+/// optional arguments are not followed by required arguments and only the
+/// last argument is variadic.
+pub(crate) fn verify_arguments_schema(schema: &[FunctionArgument]) -> Result<(), GearsApiError> {
+    let mut names = HashSet::new();
+    let mut optional_arg: Option<&str> = None;
+    for (i, arg) in schema.iter().enumerate() {
+        if arg.name.is_empty() {
+            return Err(GearsApiError::new("Argument name can not be empty"));
+        }
+        if !names.insert(arg.name.as_str()) {
+            return Err(GearsApiError::new(format!(
+                "Argument '{}' was given more than once",
+                arg.name
+            )));
+        }
+        if arg.variadic && i != schema.len() - 1 {
+            return Err(GearsApiError::new(format!(
+                "Variadic argument '{}' must be the last argument",
+                arg.name
+            )));
+        }
+        if let Some(optional_arg) = optional_arg {
+            if !arg.optional {
+                return Err(GearsApiError::new(format!(
+                    "Required argument '{}' can not follow optional argument '{}'",
+                    arg.name, optional_arg
+                )));
+            }
+        }
+        if arg.optional {
+            optional_arg = Some(&arg.name);
+        }
+    }
+    Ok(())
+}
+
+fn verify_argument(arg: &FunctionArgument, val: &[u8]) -> Result<(), RedisError> {
+    let (valid, expected) = match arg.arg_type {
+        FunctionArgumentType::Int => (
+            std::str::from_utf8(val).map_or(false, |v| v.parse::<i64>().is_ok()),
+            "an integer",
+        ),
+        FunctionArgumentType::Float => (
+            std::str::from_utf8(val).map_or(false, |v| v.parse::<f64>().is_ok()),
+            "a float",
+        ),
+        FunctionArgumentType::String => (std::str::from_utf8(val).is_ok(), "a valid utf8 string"),
+        FunctionArgumentType::Json => (
+            serde_json::from_slice::<serde_json::Value>(val).is_ok(),
+            "a valid json",
+        ),
+        FunctionArgumentType::Binary => return Ok(()),
+    };
+    if valid {
+        return Ok(());
+    }
+    Err(RedisError::String(format!(
+        "Argument '{}' must be {}",
+        arg.name, expected
+    )))
+}
+
+/// Verify the arguments given to a function (keys followed by the rest
+/// of the arguments, as the function gets them) against the function
+/// arguments schema.
+pub(crate) fn verify_arguments(
+    schema: &[FunctionArgument],
+    args: &[redis_module::RedisString],
+) -> Result<(), RedisError> {
+    let variadic = schema.last().map_or(false, |a| a.variadic);
+    if !variadic && args.len() > schema.len() {
+        return Err(RedisError::String(format!(
+            "Too many arguments were given, expected at most {} arguments, got {} arguments.",
+            schema.len(),
+            args.len()
+        )));
+    }
+    for (i, arg) in schema.iter().enumerate() {
+        let vals = if arg.variadic {
+            args.get(i..).unwrap_or_default()
+        } else {
+            args.get(i..i + 1).unwrap_or_default()
+        };
+        if vals.is_empty() && !arg.optional {
+            return Err(RedisError::String(format!(
+                "Missing required argument '{}'",
+                arg.name
+            )));
+        }
+        for val in vals {
+            verify_argument(arg, val.as_slice())?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns a human readable signature of the arguments schema, for example:
+/// `key:string count:int [ratio:float] members:binary...`
+pub(crate) fn arguments_signature(schema: &[FunctionArgument]) -> String {
+    schema
+        .iter()
+        .map(|arg| {
+            let mut res = format!("{}:{}", arg.name, arg.arg_type.as_str());
+            if arg.optional {
+                res = format!("[{}]", res);
+            }
+            if arg.variadic {
+                res.push_str("...");
+            }
+            res
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
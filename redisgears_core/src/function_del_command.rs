@@ -4,12 +4,15 @@
  * the Server Side Public License v1 (SSPLv1).
  */
 
-use redis_module::{Context, RedisError, RedisResult, RedisValue, ThreadSafeContext};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisValue, ThreadSafeContext};
 
+use std::collections::HashMap;
 use std::iter::Skip;
+use std::sync::Arc;
 use std::vec::IntoIter;
 
-use crate::{get_ctx, get_libraries, Deserialize, Serialize};
+use crate::function_load_command::library_dependents;
+use crate::{execute_on_pool, get_ctx, get_libraries, Deserialize, GearsLibrary, Serialize};
 
 use mr_derive::BaseObject;
 
@@ -60,6 +63,10 @@ impl RemoteTask for GearsFunctionDelRemoteTask {
     ) {
         let _ctx_guard = ThreadSafeContext::new().lock();
         let mut libraries = get_libraries();
+        if let Err(e) = verify_no_dependents(&r.lib_name, &libraries) {
+            on_done(Err(e));
+            return;
+        }
         let res = match libraries.remove(&r.lib_name) {
             Some(_) => {
                 redis_module::replicate_slices(
@@ -75,6 +82,21 @@ impl RemoteTask for GearsFunctionDelRemoteTask {
     }
 }
 
+/// A library can not be deleted while other libraries depends on it.
+fn verify_no_dependents(
+    lib_name: &str,
+    libraries: &HashMap<String, Arc<GearsLibrary>>,
+) -> Result<(), String> {
+    let dependents = library_dependents(lib_name, libraries);
+    if dependents.is_empty() {
+        return Ok(());
+    }
+    Err(format!(
+        "library is used by the following libraries: {}",
+        dependents.join(", ")
+    ))
+}
+
 pub(crate) fn function_del_command(
     ctx: &Context,
     mut args: Skip<IntoIter<redis_module::RedisString>>,
@@ -112,8 +134,132 @@ pub(crate) fn function_del_on_replica(
             s.try_as_str()
         })?;
     let mut libraries = get_libraries();
+    verify_no_dependents(lib_name, &libraries).map_err(RedisError::String)?;
     match libraries.remove(lib_name) {
         Some(_) => Ok(RedisValue::SimpleStringStatic("OK")),
         None => Err(RedisError::Str("library does not exists")),
     }
 }
+
+#[derive(Clone, Serialize, Deserialize, BaseObject)]
+pub(crate) struct GearsFunctionFlushInputRecord {
+    async_flush: bool,
+}
+
+impl LibMRRecord for GearsFunctionFlushInputRecord {
+    fn to_redis_value(&mut self) -> RedisValue {
+        RedisValue::Null
+    }
+
+    fn hash_slot(&self) -> usize {
+        1 // not relevant here
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, BaseObject)]
+pub(crate) struct GearsFunctionFlushOutputRecord;
+
+impl LibMRRecord for GearsFunctionFlushOutputRecord {
+    fn to_redis_value(&mut self) -> RedisValue {
+        RedisValue::Null
+    }
+
+    fn hash_slot(&self) -> usize {
+        1 // not relevant here
+    }
+}
+
+/// Libraries that were flushed and are released on a background thread.
+struct FlushedLibraries(Vec<Arc<GearsLibrary>>);
+
+/// The flushed libraries are no longer reachable from the main thread, the stream and
+/// notifications contexts only hold weak references to their consumers.
+unsafe impl Send for FlushedLibraries {}
+
+/// Remove all the libraries. Stream and notifications consumers are unregistered
+/// immediately (they are only weakly referenced by the stream and notifications
+/// contexts). With `async_flush`, the libraries resources (for example, the JS
+/// isolates) are released on a background thread instead of on the command path.
+/// A library that is still in use (a running function for example) is released
+/// by whoever holds the last reference to it.
+fn function_flush_internal(async_flush: bool) {
+    let libraries = FlushedLibraries(get_libraries().drain().map(|(_, l)| l).collect());
+    if async_flush {
+        execute_on_pool(move || drop(libraries));
+    }
+}
+
+fn get_flush_args(mut args: Skip<IntoIter<redis_module::RedisString>>) -> Result<bool, RedisError> {
+    let async_flush = match args.next_arg() {
+        Ok(arg) => {
+            let mode = arg.try_as_str()?.to_lowercase();
+            match mode.as_ref() {
+                "async" => true,
+                "sync" => false,
+                _ => return Err(RedisError::String(format!("Unknown flush mode '{}'", mode))),
+            }
+        }
+        Err(_) => false,
+    };
+    if args.next_arg().is_ok() {
+        return Err(RedisError::Str("Too many arguments were given"));
+    }
+    Ok(async_flush)
+}
+
+#[derive(Clone, Serialize, Deserialize, BaseObject)]
+pub(crate) struct GearsFunctionFlushRemoteTask;
+
+impl RemoteTask for GearsFunctionFlushRemoteTask {
+    type InRecord = GearsFunctionFlushInputRecord;
+    type OutRecord = GearsFunctionFlushOutputRecord;
+
+    fn task(
+        self,
+        r: Self::InRecord,
+        on_done: Box<dyn FnOnce(Result<Self::OutRecord, RustMRError>) + Send>,
+    ) {
+        let _ctx_guard = ThreadSafeContext::new().lock();
+        function_flush_internal(r.async_flush);
+        redis_module::replicate_slices(
+            get_ctx().ctx,
+            "_rg.function",
+            &[
+                "flush".as_bytes(),
+                if r.async_flush { "ASYNC" } else { "SYNC" }.as_bytes(),
+            ],
+        );
+        on_done(Ok(GearsFunctionFlushOutputRecord));
+    }
+}
+
+pub(crate) fn function_flush_command(
+    ctx: &Context,
+    args: Skip<IntoIter<redis_module::RedisString>>,
+) -> RedisResult {
+    let async_flush = get_flush_args(args)?;
+    let blocked_client = ctx.block_client();
+    run_on_all_shards(
+        GearsFunctionFlushRemoteTask,
+        GearsFunctionFlushInputRecord { async_flush },
+        |_results: Vec<GearsFunctionFlushOutputRecord>, mut errors| {
+            let thread_ctx = ThreadSafeContext::with_blocked_client(blocked_client);
+            if errors.is_empty() {
+                thread_ctx.reply(Ok(RedisValue::SimpleStringStatic("OK")));
+            } else {
+                thread_ctx.reply(Err(RedisError::String(errors.pop().unwrap())));
+            }
+        },
+        10000,
+    );
+    Ok(RedisValue::NoReply)
+}
+
+pub(crate) fn function_flush_on_replica(
+    _ctx: &Context,
+    args: Skip<IntoIter<redis_module::RedisString>>,
+) -> RedisResult {
+    let async_flush = get_flush_args(args)?;
+    function_flush_internal(async_flush);
+    Ok(RedisValue::SimpleStringStatic("OK"))
+}
@@ -0,0 +1,437 @@
+/*
+ * Copyright Redis Ltd. 2018 - present
+ * Licensed under your choice of the Redis Source Available License 2.0 (RSALv2) or
+ * the Server Side Public License v1 (SSPLv1).
+ */
+
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisValue, ThreadSafeContext};
+
+use std::collections::HashMap;
+use std::iter::Skip;
+use std::sync::Arc;
+use std::vec::IntoIter;
+
+use crate::function_load_command::{
+    function_load_intrernal, libraries_in_dependency_order, library_dependents,
+};
+use crate::gears_box::GearsBoxLibraryInfo;
+use crate::{get_ctx, get_globals_mut, get_libraries, Deserialize, GearsLibrary, Serialize};
+
+use mr_derive::BaseObject;
+
+use mr::libmr::{
+    record::Record as LibMRRecord, remote_task::run_on_all_shards, remote_task::RemoteTask,
+    RustMRError,
+};
+
+const DUMP_MAGIC: &[u8] = b"RGDUMP";
+const DUMP_VERSION: u32 = 2;
+const DUMP_CHECKSUM_LEN: usize = 64; // sha256 hex digest
+
+/// The last read id of a single stream consumed by a stream consumer.
+#[derive(Serialize, Deserialize)]
+struct DumpedStreamInfo {
+    stream: Vec<u8>,
+    ms: u64,
+    seq: u64,
+}
+
+/// A stream consumer and the streams it is consuming.
+#[derive(Serialize, Deserialize)]
+struct DumpedStreamConsumer {
+    name: String,
+    /// Available starting from version 2, consumers of older payloads are not paused.
+    #[serde(default)]
+    paused: bool,
+    streams: Vec<DumpedStreamInfo>,
+}
+
+/// A single library inside a dump payload, holds the same
+/// fields we are saving to the rdb.
+#[derive(Serialize, Deserialize)]
+struct DumpedLibrary {
+    name: String,
+    code: String,
+    user: String,
+    config: Option<String>,
+    gears_box_info: Option<GearsBoxLibraryInfo>,
+    stream_consumers: Vec<DumpedStreamConsumer>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+enum RestorePolicy {
+    Flush,
+    Append,
+    Replace,
+}
+
+impl RestorePolicy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RestorePolicy::Flush => "FLUSH",
+            RestorePolicy::Append => "APPEND",
+            RestorePolicy::Replace => "REPLACE",
+        }
+    }
+}
+
+fn dump_library(lib: &GearsLibrary) -> DumpedLibrary {
+    let meta_data = &lib.gears_lib_ctx.meta_data;
+    DumpedLibrary {
+        name: meta_data.name.clone(),
+        code: meta_data.code.clone(),
+        user: meta_data.user.clone(),
+        config: meta_data.config.clone(),
+        gears_box_info: lib.gears_box_lib.clone(),
+        stream_consumers: lib
+            .gears_lib_ctx
+            .stream_consumers
+            .iter()
+            .map(|(name, consumer)| {
+                let consumer = consumer.ref_cell.borrow();
+                DumpedStreamConsumer {
+                    name: name.clone(),
+                    paused: consumer.paused,
+                    streams: consumer
+                        .get_streams_info()
+                        .map(|(stream, ms, seq)| DumpedStreamInfo { stream, ms, seq })
+                        .collect(),
+                }
+            })
+            .collect(),
+    }
+}
+
+fn encode_dump_payload(libraries: &[DumpedLibrary]) -> Vec<u8> {
+    let body = serde_json::to_string(libraries).unwrap();
+    let checksum = sha256::digest(body.clone());
+    let mut payload = Vec::with_capacity(
+        DUMP_MAGIC.len() + std::mem::size_of::<u32>() + body.len() + checksum.len(),
+    );
+    payload.extend_from_slice(DUMP_MAGIC);
+    payload.extend_from_slice(&DUMP_VERSION.to_le_bytes());
+    payload.extend_from_slice(body.as_bytes());
+    payload.extend_from_slice(checksum.as_bytes());
+    payload
+}
+
+fn decode_dump_payload(payload: &[u8]) -> Result<Vec<DumpedLibrary>, RedisError> {
+    let header_len = DUMP_MAGIC.len() + std::mem::size_of::<u32>();
+    if payload.len() < header_len + DUMP_CHECKSUM_LEN || !payload.starts_with(DUMP_MAGIC) {
+        return Err(RedisError::Str("payload is not a valid RedisGears dump"));
+    }
+    let mut version = [0_u8; std::mem::size_of::<u32>()];
+    version.copy_from_slice(&payload[DUMP_MAGIC.len()..header_len]);
+    let version = u32::from_le_bytes(version);
+    if version > DUMP_VERSION {
+        return Err(RedisError::String(format!(
+            "Can not restore payload version '{}', max supported version '{}'",
+            version, DUMP_VERSION
+        )));
+    }
+    let (body, checksum) =
+        payload[header_len..].split_at(payload.len() - header_len - DUMP_CHECKSUM_LEN);
+    let body = std::str::from_utf8(body)
+        .map_err(|_| RedisError::Str("payload is not a valid RedisGears dump"))?;
+    if sha256::digest(body.to_string()).as_bytes() != checksum {
+        return Err(RedisError::Str("payload checksum mismatch"));
+    }
+    serde_json::from_str(body)
+        .map_err(|e| RedisError::String(format!("Failed deserializing dump payload, {}.", e)))
+}
+
+pub(crate) fn function_dump_command(
+    _ctx: &Context,
+    mut args: Skip<IntoIter<redis_module::RedisString>>,
+) -> RedisResult {
+    if let Ok(arg) = args.next_arg() {
+        return Err(RedisError::String(format!(
+            "Unknown argument '{}'",
+            arg.try_as_str().unwrap_or("[binary data]")
+        )));
+    }
+    let libraries = get_libraries();
+    // dump the libraries such that dependencies are restored before the libraries using them
+    let dumped_libraries = libraries_in_dependency_order(&libraries)
+        .into_iter()
+        .map(|l| dump_library(l.as_ref()))
+        .collect::<Vec<DumpedLibrary>>();
+    Ok(RedisValue::StringBuffer(encode_dump_payload(
+        &dumped_libraries,
+    )))
+}
+
+fn restore_library(lib: DumpedLibrary) -> Result<(), String> {
+    function_load_intrernal(
+        lib.user,
+        &lib.code,
+        lib.config,
+        false,
+        lib.gears_box_info,
+        true,
+    )?;
+
+    // library was load, we must be able to find it
+    let libraries = get_libraries();
+    let loaded_lib = libraries.get(&lib.name).ok_or_else(|| {
+        format!(
+            "Library name '{}' does not match the name on the library code",
+            lib.name
+        )
+    })?;
+    for consumer in lib.stream_consumers {
+        let stream_consumer = match loaded_lib
+            .gears_lib_ctx
+            .stream_consumers
+            .get(&consumer.name)
+        {
+            Some(c) => c,
+            None => continue, // consumer no longer registered by the library code
+        };
+        stream_consumer
+            .ref_cell
+            .borrow_mut()
+            .set_paused(consumer.paused);
+        for stream_info in consumer.streams {
+            get_globals_mut().stream_ctx.update_stream_for_consumer(
+                &stream_info.stream,
+                stream_consumer,
+                stream_info.ms,
+                stream_info.seq,
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Restore the given dumped libraries, on failure return to the state we had
+/// before the restore started. The libraries that are replaced by the restore
+/// (all of them with the FLUSH policy, the dumped ones with the REPLACE policy)
+/// are taken out and kept aside until the restore finishes.
+fn function_restore_internal(payload: &[u8], policy: RestorePolicy) -> Result<(), RedisError> {
+    let dumped_libraries = decode_dump_payload(payload)?;
+
+    let old_libraries: HashMap<String, Arc<GearsLibrary>> = {
+        let mut libraries = get_libraries();
+        match policy {
+            RestorePolicy::Flush => libraries.drain().collect(),
+            RestorePolicy::Append => {
+                if let Some(lib) = dumped_libraries
+                    .iter()
+                    .find(|l| libraries.contains_key(&l.name))
+                {
+                    return Err(RedisError::String(format!(
+                        "Library {} already exists",
+                        lib.name
+                    )));
+                }
+                HashMap::new()
+            }
+            RestorePolicy::Replace => dumped_libraries
+                .iter()
+                .filter_map(|l| libraries.remove_entry(&l.name))
+                .collect(),
+        }
+    };
+
+    let mut restored = Vec::new();
+    for lib in dumped_libraries {
+        let name = lib.name.clone();
+        if let Err(e) = restore_library(lib) {
+            function_restore_revert(&restored, old_libraries);
+            return Err(RedisError::String(format!(
+                "Failed restoring library '{}', {}",
+                name, e
+            )));
+        }
+        restored.push(name);
+    }
+
+    if let RestorePolicy::Replace = policy {
+        // libraries that were not part of the dump but depend on a replaced
+        // library are reloaded so they will use the restored version.
+        let dependents = {
+            let libraries = get_libraries();
+            let mut dependents = old_libraries
+                .keys()
+                .flat_map(|name| library_dependents(name, &libraries))
+                .filter(|name| !restored.contains(name))
+                .collect::<Vec<String>>();
+            dependents.sort();
+            dependents.dedup();
+            dependents
+                .into_iter()
+                .map(|name| Arc::clone(&libraries[&name]))
+                .collect::<Vec<Arc<GearsLibrary>>>()
+        };
+        for dependent in dependents {
+            let meta_data = &dependent.gears_lib_ctx.meta_data;
+            if let Err(e) = function_load_intrernal(
+                meta_data.user.clone(),
+                &meta_data.code,
+                meta_data.config.clone(),
+                true,
+                dependent.gears_box_lib.clone(),
+                true,
+            ) {
+                get_ctx().log_warning(&format!(
+                    "Failed reloading library {} after its dependency was restored, {}",
+                    meta_data.name, e
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Revert a failed restore, the libraries that were already restored are removed
+/// and the libraries that were replaced are put back. The replaced libraries were
+/// kept alive during the restore so their stream and notifications consumers are
+/// still registered and continue where they stopped.
+fn function_restore_revert(
+    restored: &[String],
+    replaced_libraries: HashMap<String, Arc<GearsLibrary>>,
+) {
+    let mut libraries = get_libraries();
+    for name in restored {
+        libraries.remove(name);
+    }
+    libraries.extend(replaced_libraries);
+}
+
+fn get_restore_args(
+    mut args: Skip<IntoIter<redis_module::RedisString>>,
+) -> Result<(redis_module::RedisString, RestorePolicy), RedisError> {
+    let payload = args
+        .next_arg()
+        .map_err(|_| RedisError::Str("payload was not given"))?;
+    let policy = match args.next_arg() {
+        Ok(arg) => {
+            let policy = arg
+                .try_as_str()
+                .map_err(|_| RedisError::Str("Binary option is not allowed"))?
+                .to_lowercase();
+            match policy.as_ref() {
+                "flush" => RestorePolicy::Flush,
+                "append" => RestorePolicy::Append,
+                "replace" => RestorePolicy::Replace,
+                _ => {
+                    return Err(RedisError::String(format!(
+                        "Unknown restore policy '{}'",
+                        policy
+                    )))
+                }
+            }
+        }
+        Err(_) => RestorePolicy::Append,
+    };
+    if args.next_arg().is_ok() {
+        return Err(RedisError::Str("Too many arguments were given"));
+    }
+    Ok((payload, policy))
+}
+
+#[derive(Clone, Serialize, Deserialize, BaseObject)]
+pub(crate) struct GearsFunctionRestoreInputRecord {
+    payload: Vec<u8>,
+    policy: RestorePolicy,
+}
+
+impl LibMRRecord for GearsFunctionRestoreInputRecord {
+    fn to_redis_value(&mut self) -> RedisValue {
+        RedisValue::Null
+    }
+
+    fn hash_slot(&self) -> usize {
+        1 // not relevant here
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, BaseObject)]
+pub(crate) struct GearsFunctionRestoreOutputRecord;
+
+impl LibMRRecord for GearsFunctionRestoreOutputRecord {
+    fn to_redis_value(&mut self) -> RedisValue {
+        RedisValue::Null
+    }
+
+    fn hash_slot(&self) -> usize {
+        1 // not relevant here
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, BaseObject)]
+pub(crate) struct GearsFunctionRestoreRemoteTask;
+
+impl RemoteTask for GearsFunctionRestoreRemoteTask {
+    type InRecord = GearsFunctionRestoreInputRecord;
+    type OutRecord = GearsFunctionRestoreOutputRecord;
+
+    fn task(
+        self,
+        r: Self::InRecord,
+        on_done: Box<dyn FnOnce(Result<Self::OutRecord, RustMRError>) + Send>,
+    ) {
+        let res = {
+            let _ctx_guard = ThreadSafeContext::new().lock();
+            let res = function_restore_internal(&r.payload, r.policy);
+            if res.is_ok() {
+                redis_module::replicate_slices(
+                    get_ctx().ctx,
+                    "_rg.function",
+                    &[
+                        "restore".as_bytes(),
+                        &r.payload,
+                        r.policy.as_str().as_bytes(),
+                    ],
+                );
+            }
+            res
+        };
+        on_done(
+            res.map(|_| GearsFunctionRestoreOutputRecord)
+                .map_err(|e| match e {
+                    RedisError::Str(s) => s.to_string(),
+                    RedisError::String(s) => s,
+                    RedisError::WrongArity => "Wrong arity".to_string(),
+                    RedisError::WrongType => "Wrong type".to_string(),
+                }),
+        );
+    }
+}
+
+pub(crate) fn function_restore_command(
+    ctx: &Context,
+    args: Skip<IntoIter<redis_module::RedisString>>,
+) -> RedisResult {
+    let (payload, policy) = get_restore_args(args)?;
+    // validate the payload before sending it to the shards
+    decode_dump_payload(payload.as_slice())?;
+    let blocked_client = ctx.block_client();
+    run_on_all_shards(
+        GearsFunctionRestoreRemoteTask,
+        GearsFunctionRestoreInputRecord {
+            payload: payload.as_slice().to_vec(),
+            policy,
+        },
+        |_results: Vec<GearsFunctionRestoreOutputRecord>, mut errors| {
+            let thread_ctx = ThreadSafeContext::with_blocked_client(blocked_client);
+            if errors.is_empty() {
+                thread_ctx.reply(Ok(RedisValue::SimpleStringStatic("OK")));
+            } else {
+                thread_ctx.reply(Err(RedisError::String(errors.pop().unwrap())));
+            }
+        },
+        10000,
+    );
+    Ok(RedisValue::NoReply)
+}
+
+pub(crate) fn function_restore_on_replica(
+    _ctx: &Context,
+    args: Skip<IntoIter<redis_module::RedisString>>,
+) -> RedisResult {
+    let (payload, policy) = get_restore_args(args)?;
+    function_restore_internal(payload.as_slice(), policy)?;
+    Ok(RedisValue::SimpleStringStatic("OK"))
+}
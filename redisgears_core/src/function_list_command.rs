@@ -6,11 +6,17 @@
 
 use redis_module::{Context, NextArg, RedisError, RedisResult, RedisValue};
 use redisgears_plugin_api::redisgears_plugin_api::load_library_ctx::FunctionFlags;
+use redisgears_plugin_api::redisgears_plugin_api::stream_ctx::{
+    StreamBatchOptions, StreamRetryBackoff, StreamRetryPolicy, StreamStartPosition,
+};
+use redisgears_plugin_api::redisgears_plugin_api::timer_ctx::TimerSchedule;
 
 use std::iter::Skip;
 use std::vec::IntoIter;
 
-use crate::{get_libraries, get_msg_verbose, json_to_redis_value};
+use crate::channel_consumers::ConsumerChannel;
+use crate::function_arguments::arguments_signature;
+use crate::{get_libraries, get_msg_verbose, json_to_redis_value, FunctionStats};
 
 fn function_list_command_flags(flags: FunctionFlags) -> RedisValue {
     let mut res = Vec::new();
@@ -23,9 +29,104 @@ fn function_list_command_flags(flags: FunctionFlags) -> RedisValue {
     if flags.contains(FunctionFlags::RAW_ARGUMENTS) {
         res.push(RedisValue::BulkString("raw-arguments".to_string()));
     }
+    if flags.contains(FunctionFlags::STRICT_KEYS) {
+        res.push(RedisValue::BulkString("strict-keys".to_string()));
+    }
     RedisValue::Array(res)
 }
 
+fn optional_string_to_redis_value(val: &Option<String>) -> RedisValue {
+    match val {
+        Some(v) => RedisValue::BulkString(v.to_string()),
+        None => RedisValue::Null,
+    }
+}
+
+fn timer_schedule_to_redis_value(schedule: &TimerSchedule) -> RedisValue {
+    match schedule {
+        TimerSchedule::Interval(ms) => RedisValue::Integer(*ms as i64),
+        TimerSchedule::Cron(expression) => RedisValue::BulkString(expression.to_string()),
+    }
+}
+
+fn optional_time_to_redis_value(time: Option<u128>) -> RedisValue {
+    match time {
+        Some(t) => RedisValue::Integer(t as i64),
+        None => RedisValue::Null,
+    }
+}
+
+fn stream_retry_policy_to_redis_value(retry_policy: Option<&StreamRetryPolicy>) -> RedisValue {
+    let retry_policy = match retry_policy {
+        Some(r) => r,
+        None => return RedisValue::Null,
+    };
+    let (backoff, delay, max_delay) = match retry_policy.backoff {
+        StreamRetryBackoff::Fixed(delay) => ("fixed", delay, delay),
+        StreamRetryBackoff::Exponential { initial, max } => ("exponential", initial, max),
+    };
+    RedisValue::Array(vec![
+        RedisValue::BulkString("max_attempts".to_string()),
+        RedisValue::Integer(retry_policy.max_attempts as i64),
+        RedisValue::BulkString("backoff".to_string()),
+        RedisValue::BulkString(backoff.to_string()),
+        RedisValue::BulkString("delay".to_string()),
+        RedisValue::Integer(delay as i64),
+        RedisValue::BulkString("max_delay".to_string()),
+        RedisValue::Integer(max_delay as i64),
+        RedisValue::BulkString("retryable_errors".to_string()),
+        match retry_policy.retryable_errors.as_ref() {
+            Some(errors) => RedisValue::Array(
+                errors
+                    .iter()
+                    .map(|e| RedisValue::BulkString(e.to_string()))
+                    .collect(),
+            ),
+            None => RedisValue::Null,
+        },
+    ])
+}
+
+fn stream_batch_options_to_redis_value(batch: Option<&StreamBatchOptions>) -> RedisValue {
+    match batch {
+        Some(batch) => RedisValue::Array(vec![
+            RedisValue::BulkString("batch_size".to_string()),
+            RedisValue::Integer(batch.batch_size as i64),
+            RedisValue::BulkString("max_wait_ms".to_string()),
+            RedisValue::Integer(batch.max_wait_ms as i64),
+        ]),
+        None => RedisValue::Null,
+    }
+}
+
+fn function_list_command_stats(stats: &FunctionStats) -> Vec<RedisValue> {
+    vec![
+        RedisValue::BulkString("num_calls".to_string()),
+        RedisValue::Integer(stats.num_calls as i64),
+        RedisValue::BulkString("num_finished".to_string()),
+        RedisValue::Integer(stats.num_finished as i64),
+        RedisValue::BulkString("num_errors".to_string()),
+        RedisValue::Integer(stats.num_errors as i64),
+        RedisValue::BulkString("num_async_calls".to_string()),
+        RedisValue::Integer(stats.num_async_calls as i64),
+        RedisValue::BulkString("last_error".to_string()),
+        RedisValue::BulkString(match &stats.last_error {
+            Some(e) => get_msg_verbose(e).to_string(),
+            None => "None".to_string(),
+        }),
+        RedisValue::BulkString("total_execution_time_us".to_string()),
+        RedisValue::Integer(stats.total_execution_time as i64),
+        RedisValue::BulkString("avg_execution_time_us".to_string()),
+        RedisValue::Float(if stats.num_finished > 0 {
+            stats.total_execution_time as f64 / stats.num_finished as f64
+        } else {
+            0.0
+        }),
+        RedisValue::BulkString("max_execution_time_us".to_string()),
+        RedisValue::Integer(stats.max_execution_time as i64),
+    ]
+}
+
 pub(crate) fn function_list_command(
     ctx: &Context,
     mut args: Skip<IntoIter<redis_module::RedisString>>,
@@ -83,6 +184,33 @@ pub(crate) fn function_list_command(
                             None => RedisValue::Null,
                         }
                     },
+                    RedisValue::BulkString("version".to_string()),
+                    optional_string_to_redis_value(&l.gears_lib_ctx.meta_data.version),
+                    RedisValue::BulkString("description".to_string()),
+                    optional_string_to_redis_value(&l.gears_lib_ctx.meta_data.description),
+                    RedisValue::BulkString("api_version".to_string()),
+                    optional_string_to_redis_value(&l.gears_lib_ctx.meta_data.api_version),
+                    RedisValue::BulkString("min_gears_version".to_string()),
+                    optional_string_to_redis_value(&l.gears_lib_ctx.meta_data.min_gears_version),
+                    RedisValue::BulkString("type".to_string()),
+                    RedisValue::BulkString(
+                        l.gears_lib_ctx
+                            .meta_data
+                            .library_type
+                            .as_deref()
+                            .unwrap_or("script")
+                            .to_string(),
+                    ),
+                    RedisValue::BulkString("requires".to_string()),
+                    optional_string_to_redis_value(&l.gears_lib_ctx.meta_data.requires),
+                    RedisValue::BulkString("exported_functions".to_string()),
+                    RedisValue::Array(
+                        l.gears_lib_ctx
+                            .exported_functions
+                            .keys()
+                            .map(|k| RedisValue::BulkString(k.to_string()))
+                            .collect::<Vec<RedisValue>>(),
+                    ),
                     RedisValue::BulkString("pending_jobs".to_string()),
                     RedisValue::Integer(l.compile_lib_internals.pending_jobs() as i64),
                     RedisValue::BulkString("functions".to_string()),
@@ -91,12 +219,25 @@ pub(crate) fn function_list_command(
                             .functions
                             .iter()
                             .map(|(k, v)| {
-                                RedisValue::Array(vec![
+                                let mut res = vec![
                                     RedisValue::BulkString("name".to_string()),
                                     RedisValue::BulkString(k.to_string()),
                                     RedisValue::BulkString("flags".to_string()),
                                     function_list_command_flags(v.flags),
-                                ])
+                                    RedisValue::BulkString("signature".to_string()),
+                                    match v.args_schema.as_ref() {
+                                        Some(schema) => {
+                                            RedisValue::BulkString(arguments_signature(schema))
+                                        }
+                                        None => RedisValue::Null,
+                                    },
+                                ];
+                                if verbosity > 1 {
+                                    res.extend(function_list_command_stats(
+                                        &v.stats.lock().unwrap(),
+                                    ));
+                                }
+                                RedisValue::Array(res)
                             })
                             .collect::<Vec<RedisValue>>()
                     } else {
@@ -136,6 +277,45 @@ pub(crate) fn function_list_command(
                                             (if v.trim { "enabled" } else { "disabled" })
                                                 .to_string(),
                                         ),
+                                        RedisValue::BulkString("state".to_string()),
+                                        RedisValue::BulkString(
+                                            (if v.paused { "paused" } else { "running" })
+                                                .to_string(),
+                                        ),
+                                        RedisValue::BulkString("dead_letter".to_string()),
+                                        match v.options.dead_letter.as_ref() {
+                                            Some(d) => RedisValue::BulkRedisString(
+                                                ctx.create_string_from_slice(d),
+                                            ),
+                                            None => RedisValue::Null,
+                                        },
+                                        RedisValue::BulkString("retry_policy".to_string()),
+                                        stream_retry_policy_to_redis_value(
+                                            v.options.retry_policy.as_ref(),
+                                        ),
+                                        RedisValue::BulkString("batch".to_string()),
+                                        stream_batch_options_to_redis_value(
+                                            v.options.batch.as_ref(),
+                                        ),
+                                        RedisValue::BulkString("start_position".to_string()),
+                                        RedisValue::BulkString(match v.options.start_position {
+                                            StreamStartPosition::Beginning => {
+                                                "from_beginning".to_string()
+                                            }
+                                            StreamStartPosition::Now => "from_now".to_string(),
+                                            StreamStartPosition::Id(ms, seq) => {
+                                                format!("{}-{}", ms, seq)
+                                            }
+                                        }),
+                                        RedisValue::BulkString("consumer_group".to_string()),
+                                        if v.options.consumer_group {
+                                            RedisValue::BulkString(format!(
+                                                "{}.{}",
+                                                l.gears_lib_ctx.meta_data.name, k
+                                            ))
+                                        } else {
+                                            RedisValue::Null
+                                        },
                                         RedisValue::BulkString("num_streams".to_string()),
                                         RedisValue::Integer(v.consumed_streams.len() as i64),
                                     ];
@@ -203,6 +383,16 @@ pub(crate) fn function_list_command(
                                                                 "None".to_string(),
                                                             ),
                                                         },
+                                                        RedisValue::BulkString(
+                                                            "total_retries".to_string(),
+                                                        ),
+                                                        RedisValue::Integer(v.total_retries as i64),
+                                                        RedisValue::BulkString(
+                                                            "pending_retries".to_string(),
+                                                        ),
+                                                        RedisValue::Integer(
+                                                            v.pending_retries.len() as i64,
+                                                        ),
                                                     ];
                                                     if verbosity > 2 {
                                                         res.push(RedisValue::BulkString(
@@ -253,6 +443,155 @@ pub(crate) fn function_list_command(
                                         RedisValue::Integer(stats.num_success as i64),
                                         RedisValue::BulkString("num_failed".to_string()),
                                         RedisValue::Integer(stats.num_failed as i64),
+                                        RedisValue::BulkString("num_filtered".to_string()),
+                                        RedisValue::Integer(stats.num_filtered as i64),
+                                        RedisValue::BulkString("num_coalesced".to_string()),
+                                        RedisValue::Integer(stats.num_coalesced as i64),
+                                        RedisValue::BulkString("last_error".to_string()),
+                                        RedisValue::BulkString(match stats.last_error {
+                                            Some(s) => get_msg_verbose(&s).to_string(),
+                                            None => "None".to_string(),
+                                        }),
+                                        RedisValue::BulkString("last_exection_time".to_string()),
+                                        RedisValue::Integer(stats.last_execution_time as i64),
+                                        RedisValue::BulkString("total_exection_time".to_string()),
+                                        RedisValue::Integer(stats.total_execution_time as i64),
+                                        RedisValue::BulkString("avg_exection_time".to_string()),
+                                        RedisValue::Float(
+                                            stats.total_execution_time as f64
+                                                / stats.num_finished as f64,
+                                        ),
+                                    ])
+                                }
+                            })
+                            .collect::<Vec<RedisValue>>(),
+                    ),
+                    RedisValue::BulkString("timers".to_string()),
+                    RedisValue::Array(
+                        l.gears_lib_ctx
+                            .timers
+                            .iter()
+                            .map(|(name, t)| {
+                                if verbosity == 0 {
+                                    RedisValue::BulkString(name.to_string())
+                                } else {
+                                    let t = t.borrow();
+                                    let stats = t.get_stats();
+                                    RedisValue::Array(vec![
+                                        RedisValue::BulkString("name".to_string()),
+                                        RedisValue::BulkString(name.to_string()),
+                                        RedisValue::BulkString("schedule".to_string()),
+                                        timer_schedule_to_redis_value(t.get_schedule()),
+                                        RedisValue::BulkString("num_runs".to_string()),
+                                        RedisValue::Integer(stats.num_runs as i64),
+                                        RedisValue::BulkString("num_finished".to_string()),
+                                        RedisValue::Integer(stats.num_finished as i64),
+                                        RedisValue::BulkString("num_success".to_string()),
+                                        RedisValue::Integer(stats.num_success as i64),
+                                        RedisValue::BulkString("num_failed".to_string()),
+                                        RedisValue::Integer(stats.num_failed as i64),
+                                        RedisValue::BulkString("num_skipped".to_string()),
+                                        RedisValue::Integer(stats.num_skipped as i64),
+                                        RedisValue::BulkString("last_run".to_string()),
+                                        optional_time_to_redis_value(stats.last_run),
+                                        RedisValue::BulkString("next_run".to_string()),
+                                        optional_time_to_redis_value(stats.next_run),
+                                        RedisValue::BulkString("last_error".to_string()),
+                                        RedisValue::BulkString(match stats.last_error {
+                                            Some(s) => get_msg_verbose(&s).to_string(),
+                                            None => "None".to_string(),
+                                        }),
+                                        RedisValue::BulkString("last_exection_time".to_string()),
+                                        RedisValue::Integer(stats.last_execution_time as i64),
+                                        RedisValue::BulkString("total_exection_time".to_string()),
+                                        RedisValue::Integer(stats.total_execution_time as i64),
+                                        RedisValue::BulkString("avg_exection_time".to_string()),
+                                        RedisValue::Float(
+                                            stats.total_execution_time as f64
+                                                / stats.num_finished as f64,
+                                        ),
+                                    ])
+                                }
+                            })
+                            .collect::<Vec<RedisValue>>(),
+                    ),
+                    RedisValue::BulkString("server_event_consumers".to_string()),
+                    RedisValue::Array(
+                        l.gears_lib_ctx
+                            .server_event_consumers
+                            .iter()
+                            .map(|(event, c)| {
+                                if verbosity == 0 {
+                                    RedisValue::BulkString(event.as_str().to_string())
+                                } else {
+                                    let stats = c.borrow().get_stats();
+                                    RedisValue::Array(vec![
+                                        RedisValue::BulkString("event".to_string()),
+                                        RedisValue::BulkString(event.as_str().to_string()),
+                                        RedisValue::BulkString("num_triggered".to_string()),
+                                        RedisValue::Integer(stats.num_trigger as i64),
+                                        RedisValue::BulkString("num_finished".to_string()),
+                                        RedisValue::Integer(stats.num_finished as i64),
+                                        RedisValue::BulkString("num_success".to_string()),
+                                        RedisValue::Integer(stats.num_success as i64),
+                                        RedisValue::BulkString("num_failed".to_string()),
+                                        RedisValue::Integer(stats.num_failed as i64),
+                                        RedisValue::BulkString("last_error".to_string()),
+                                        RedisValue::BulkString(match stats.last_error {
+                                            Some(s) => get_msg_verbose(&s).to_string(),
+                                            None => "None".to_string(),
+                                        }),
+                                        RedisValue::BulkString("last_exection_time".to_string()),
+                                        RedisValue::Integer(stats.last_execution_time as i64),
+                                        RedisValue::BulkString("total_exection_time".to_string()),
+                                        RedisValue::Integer(stats.total_execution_time as i64),
+                                        RedisValue::BulkString("avg_exection_time".to_string()),
+                                        RedisValue::Float(
+                                            stats.total_execution_time as f64
+                                                / stats.num_finished as f64,
+                                        ),
+                                    ])
+                                }
+                            })
+                            .collect::<Vec<RedisValue>>(),
+                    ),
+                    RedisValue::BulkString("channel_consumers".to_string()),
+                    RedisValue::Array(
+                        l.gears_lib_ctx
+                            .channel_consumers
+                            .iter()
+                            .map(|(name, c)| {
+                                if verbosity == 0 {
+                                    RedisValue::BulkString(name.to_string())
+                                } else {
+                                    let c = c.borrow();
+                                    let stats = c.get_stats();
+                                    let (channel_type, channel) = match c.get_channel() {
+                                        ConsumerChannel::Channel(c) => ("channel", c),
+                                        ConsumerChannel::Pattern(p) => ("pattern", p),
+                                    };
+                                    RedisValue::Array(vec![
+                                        RedisValue::BulkString("name".to_string()),
+                                        RedisValue::BulkString(name.to_string()),
+                                        RedisValue::BulkString(channel_type.to_string()),
+                                        RedisValue::StringBuffer(channel.clone()),
+                                        RedisValue::BulkString("run_on_replicas".to_string()),
+                                        RedisValue::BulkString(
+                                            (if c.get_options().run_on_replicas {
+                                                "enabled"
+                                            } else {
+                                                "disabled"
+                                            })
+                                            .to_string(),
+                                        ),
+                                        RedisValue::BulkString("num_triggered".to_string()),
+                                        RedisValue::Integer(stats.num_trigger as i64),
+                                        RedisValue::BulkString("num_finished".to_string()),
+                                        RedisValue::Integer(stats.num_finished as i64),
+                                        RedisValue::BulkString("num_success".to_string()),
+                                        RedisValue::Integer(stats.num_success as i64),
+                                        RedisValue::BulkString("num_failed".to_string()),
+                                        RedisValue::Integer(stats.num_failed as i64),
                                         RedisValue::BulkString("last_error".to_string()),
                                         RedisValue::BulkString(match stats.last_error {
                                             Some(s) => get_msg_verbose(&s).to_string(),
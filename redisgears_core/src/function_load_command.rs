@@ -6,12 +6,15 @@
 
 use redis_module::{Context, NextArg, RedisError, RedisResult, RedisValue, ThreadSafeContext};
 
+use redisgears_plugin_api::redisgears_plugin_api::backend_ctx::LibraryBundle;
+
 use crate::compiled_library_api::CompiledLibraryAPI;
 use crate::gears_box::GearsBoxLibraryInfo;
 use crate::{Deserialize, Serialize};
 
 use crate::{
-    get_backends_mut, get_ctx, get_libraries, GearsLibrary, GearsLibraryCtx, GearsLibraryMetaData,
+    get_backends_mut, get_ctx, get_globals_mut, get_libraries, GearsLibrary, GearsLibraryCtx,
+    GearsLibraryMetaData, VERSION_STR,
 };
 
 use mr::libmr::{
@@ -23,9 +26,10 @@ use std::iter::Skip;
 use std::vec::IntoIter;
 
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 
 use crate::gears_box::{do_http_get_text, gears_box_get_library};
+use crate::timers::schedule_timer;
 
 use crate::get_msg_verbose;
 
@@ -34,12 +38,304 @@ use mr_derive::BaseObject;
 #[derive(Clone, Serialize, Deserialize)]
 pub(crate) struct FunctionLoadArgs {
     upgrade: bool,
+    force: bool,
     config: Option<String>,
     code: String,
     gears_box: Option<GearsBoxLibraryInfo>,
     user: Option<String>,
 }
 
+/// The library api version supported by this build.
+const SUPPORTED_API_VERSION: &str = "1.0";
+
+/// A dot separated numeric version (i.e. `1.2.3`). Missing
+/// components are considered as zero, so `1.2` equals `1.2.0`.
+#[derive(Clone, Debug)]
+pub(crate) struct LibraryVersion(Vec<u64>);
+
+impl LibraryVersion {
+    pub(crate) fn parse(version: &str) -> Result<LibraryVersion, String> {
+        version
+            .split('.')
+            .map(|v| v.parse::<u64>())
+            .collect::<Result<Vec<u64>, _>>()
+            .map(LibraryVersion)
+            .map_err(|_| format!("Invalid version '{}'", version))
+    }
+}
+
+impl PartialEq for LibraryVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for LibraryVersion {}
+
+impl PartialOrd for LibraryVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for LibraryVersion {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        let len = self.0.len().max(other.0.len());
+        (0..len)
+            .map(|i| {
+                let a = self.0.get(i).unwrap_or(&0);
+                let b = other.0.get(i).unwrap_or(&0);
+                a.cmp(b)
+            })
+            .find(|o| *o != std::cmp::Ordering::Equal)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum VersionConstraint {
+    Eq,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+/// A single library requirement given on the `requires` property,
+/// i.e. `common@>=1.2`. A requirement without a version constraint
+/// (i.e. `common`) is satisfied by any version of the library.
+#[derive(Clone, Debug)]
+pub(crate) struct LibraryRequirement {
+    pub(crate) name: String,
+    constraint: Option<(VersionConstraint, LibraryVersion)>,
+}
+
+impl LibraryRequirement {
+    fn parse(requirement: &str) -> Result<LibraryRequirement, String> {
+        let (name, constraint) = match requirement.split_once('@') {
+            Some((name, constraint)) => {
+                let (op, version) = if let Some(v) = constraint.strip_prefix(">=") {
+                    (VersionConstraint::Ge, v)
+                } else if let Some(v) = constraint.strip_prefix("<=") {
+                    (VersionConstraint::Le, v)
+                } else if let Some(v) = constraint.strip_prefix('>') {
+                    (VersionConstraint::Gt, v)
+                } else if let Some(v) = constraint.strip_prefix('<') {
+                    (VersionConstraint::Lt, v)
+                } else if let Some(v) = constraint.strip_prefix('=') {
+                    (VersionConstraint::Eq, v)
+                } else {
+                    (VersionConstraint::Eq, constraint)
+                };
+                (name, Some((op, LibraryVersion::parse(version)?)))
+            }
+            None => (requirement, None),
+        };
+        if name.is_empty() {
+            return Err(format!("Invalid library requirement '{}'", requirement));
+        }
+        Ok(LibraryRequirement {
+            name: name.to_string(),
+            constraint,
+        })
+    }
+
+    /// Parse a comma separated list of requirements.
+    pub(crate) fn parse_list(requirements: &str) -> Result<Vec<LibraryRequirement>, String> {
+        requirements
+            .split(',')
+            .map(|r| LibraryRequirement::parse(r.trim()))
+            .collect()
+    }
+
+    fn constraint_str(&self) -> String {
+        match &self.constraint {
+            Some((op, version)) => format!(
+                "{}{}",
+                match op {
+                    VersionConstraint::Eq => "=",
+                    VersionConstraint::Gt => ">",
+                    VersionConstraint::Ge => ">=",
+                    VersionConstraint::Lt => "<",
+                    VersionConstraint::Le => "<=",
+                },
+                version
+                    .0
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<String>>()
+                    .join(".")
+            ),
+            None => "*".to_string(),
+        }
+    }
+
+    fn is_satisfied_by(&self, version: Option<&String>) -> bool {
+        let (op, required) = match &self.constraint {
+            Some(c) => c,
+            None => return true,
+        };
+        let version = match version {
+            Some(v) => LibraryVersion::parse(v).unwrap(),
+            None => return false,
+        };
+        match op {
+            VersionConstraint::Eq => version == *required,
+            VersionConstraint::Gt => version > *required,
+            VersionConstraint::Ge => version >= *required,
+            VersionConstraint::Lt => version < *required,
+            VersionConstraint::Le => version <= *required,
+        }
+    }
+}
+
+fn library_requirements(meta_data: &GearsLibraryMetaData) -> Vec<LibraryRequirement> {
+    // requirements were already validated when the metadata was extracted
+    meta_data
+        .requires
+        .as_ref()
+        .map(|r| LibraryRequirement::parse_list(r).unwrap())
+        .unwrap_or_default()
+}
+
+/// Resolve the libraries required by the given library. Also verify that the
+/// libraries that depends on the given library (if it already exists) are
+/// still satisfied by its new version.
+fn library_resolve_dependencies(
+    meta_data: &GearsLibraryMetaData,
+    libraries: &HashMap<String, Arc<GearsLibrary>>,
+) -> Result<HashMap<String, Weak<GearsLibrary>>, String> {
+    let mut dependencies = HashMap::new();
+    for requirement in library_requirements(meta_data) {
+        let dependency = libraries.get(&requirement.name).ok_or_else(|| {
+            format!(
+                "Library {} requires library {} which does not exists",
+                meta_data.name, requirement.name
+            )
+        })?;
+        if !requirement.is_satisfied_by(dependency.gears_lib_ctx.meta_data.version.as_ref()) {
+            return Err(format!(
+                "Library {} requires library {} with version '{}', found version '{}'",
+                meta_data.name,
+                requirement.name,
+                requirement.constraint_str(),
+                dependency
+                    .gears_lib_ctx
+                    .meta_data
+                    .version
+                    .as_deref()
+                    .unwrap_or("None")
+            ));
+        }
+        // a dependency must not (directly or indirectly) depends on the library itself
+        let mut to_check = vec![dependency];
+        while let Some(lib) = to_check.pop() {
+            if lib.gears_lib_ctx.dependencies.contains_key(&meta_data.name) {
+                return Err(format!(
+                    "Library {} has a circular dependency through library {}",
+                    meta_data.name, requirement.name
+                ));
+            }
+            to_check.extend(
+                lib.gears_lib_ctx
+                    .dependencies
+                    .keys()
+                    .filter_map(|name| libraries.get(name)),
+            );
+        }
+        dependencies.insert(requirement.name, Arc::downgrade(dependency));
+    }
+
+    for dependent in libraries.values() {
+        let requirement = library_requirements(&dependent.gears_lib_ctx.meta_data)
+            .into_iter()
+            .find(|r| r.name == meta_data.name);
+        if let Some(requirement) = requirement {
+            if !requirement.is_satisfied_by(meta_data.version.as_ref()) {
+                return Err(format!(
+                    "Library {} requires library {} with version '{}'",
+                    dependent.gears_lib_ctx.meta_data.name,
+                    meta_data.name,
+                    requirement.constraint_str()
+                ));
+            }
+        }
+    }
+    Ok(dependencies)
+}
+
+/// Return the libraries sorted such that each library comes after all the
+/// libraries it depends on, loading the libraries on that order is guaranteed
+/// to succeed in resolving the libraries dependencies.
+pub(crate) fn libraries_in_dependency_order(
+    libraries: &HashMap<String, Arc<GearsLibrary>>,
+) -> Vec<&Arc<GearsLibrary>> {
+    fn visit<'a>(
+        lib: &'a Arc<GearsLibrary>,
+        libraries: &'a HashMap<String, Arc<GearsLibrary>>,
+        res: &mut Vec<&'a Arc<GearsLibrary>>,
+    ) {
+        if res.iter().any(|l| Arc::ptr_eq(l, lib)) {
+            return;
+        }
+        for name in lib.gears_lib_ctx.dependencies.keys() {
+            if let Some(dependency) = libraries.get(name) {
+                visit(dependency, libraries, res);
+            }
+        }
+        res.push(lib);
+    }
+    let mut res = Vec::new();
+    for lib in libraries.values() {
+        visit(lib, libraries, &mut res);
+    }
+    res
+}
+
+/// Return the names of the libraries that depends on the given library.
+pub(crate) fn library_dependents(
+    name: &str,
+    libraries: &HashMap<String, Arc<GearsLibrary>>,
+) -> Vec<String> {
+    libraries
+        .values()
+        .filter(|l| l.gears_lib_ctx.dependencies.contains_key(name))
+        .map(|l| l.gears_lib_ctx.meta_data.name.clone())
+        .collect()
+}
+
+/// Split the shebang line into its properties, a property
+/// value might be quoted in order to contain spaces
+/// (i.e. `description="some description"`).
+fn split_shebang_properties(shebang: &str) -> Result<Vec<(&str, &str)>, RedisError> {
+    let mut res = Vec::new();
+    let mut rest = shebang.trim_start();
+    while !rest.is_empty() {
+        let (prop_name, val) = match rest.split_once('=') {
+            Some(v) => v,
+            None => return Err(RedisError::Str("could not extract property value")),
+        };
+        if prop_name.is_empty() || prop_name.contains(' ') {
+            return Err(RedisError::Str("could not extract property name"));
+        }
+        let (prop_val, next) = match val.strip_prefix('"') {
+            Some(val) => match val.split_once('"') {
+                Some(v) => v,
+                None => {
+                    return Err(RedisError::String(format!(
+                        "missing closing quote on property '{}'",
+                        prop_name
+                    )))
+                }
+            },
+            None => val.split_once(' ').unwrap_or((val, "")),
+        };
+        res.push((prop_name, prop_val));
+        rest = next.trim_start();
+    }
+    Ok(res)
+}
+
 fn library_extract_matadata(
     code: &str,
     config: Option<String>,
@@ -54,62 +350,183 @@ fn library_extract_matadata(
     }
 
     let shabeng = shabeng.strip_prefix("#!").unwrap();
-    let mut data = shabeng.split(' ');
-    let engine = match data.next() {
-        Some(s) => s,
-        None => return Err(RedisError::Str("could not extract engine name")),
-    };
+    let (engine, properties) = shabeng.split_once(' ').unwrap_or((shabeng, ""));
+    if engine.is_empty() {
+        return Err(RedisError::Str("could not extract engine name"));
+    }
 
-    let name = {
-        let d = match data.next() {
-            Some(s) => s,
-            None => return Err(RedisError::Str("Failed find 'name' property")),
-        };
-        let mut prop = d.split('=');
-        let prop_name = match prop.next() {
-            Some(s) => s,
-            None => return Err(RedisError::Str("could not extract property name")),
-        };
-        let prop_val = match prop.next() {
-            Some(s) => s,
-            None => return Err(RedisError::Str("could not extract property value")),
+    let mut name = None;
+    let mut version = None;
+    let mut description = None;
+    let mut api_version = None;
+    let mut min_gears_version = None;
+    let mut requires = None;
+    let mut library_type = None;
+    for (prop_name, prop_val) in split_shebang_properties(properties)? {
+        let prop = match prop_name.to_lowercase().as_ref() {
+            "name" => &mut name,
+            "version" => {
+                LibraryVersion::parse(prop_val).map_err(RedisError::String)?;
+                &mut version
+            }
+            "description" => &mut description,
+            "api_version" => {
+                LibraryVersion::parse(prop_val).map_err(RedisError::String)?;
+                &mut api_version
+            }
+            "min_gears_version" => {
+                LibraryVersion::parse(prop_val).map_err(RedisError::String)?;
+                &mut min_gears_version
+            }
+            "requires" => {
+                LibraryRequirement::parse_list(prop_val).map_err(RedisError::String)?;
+                &mut requires
+            }
+            "type" => {
+                if prop_val != "script" && prop_val != "bundle" {
+                    return Err(RedisError::String(format!(
+                        "unknown library type '{}'",
+                        prop_val
+                    )));
+                }
+                &mut library_type
+            }
+            _ => {
+                return Err(RedisError::String(format!(
+                    "unknown property '{}'",
+                    prop_name
+                )))
+            }
         };
-        if prop_name.to_lowercase() != "name" {
+        if prop.is_some() {
             return Err(RedisError::String(format!(
-                "unknown property '{}'",
+                "property '{}' was given more than once",
                 prop_name
             )));
         }
-        prop_val
-    };
+        *prop = Some(prop_val.to_string());
+    }
+
+    let name = name.ok_or(RedisError::Str("Failed find 'name' property"))?;
 
     Ok(GearsLibraryMetaData {
         engine: engine.to_string(),
-        name: name.to_string(),
+        name,
         code: code.to_string(),
         config,
         user,
+        version,
+        description,
+        api_version,
+        min_gears_version,
+        requires,
+        library_type,
     })
 }
 
+/// Extract the library bundle, the bundle is a JSON object that comes right
+/// after the library header, i.e. `{"entry": "main.js", "modules": {"main.js": "..."}}`.
+fn library_extract_bundle(code: &str) -> Result<LibraryBundle, String> {
+    let bundle = code.split_once('\n').map_or("", |(_, b)| b);
+    let bundle: LibraryBundle = serde_json::from_str(bundle)
+        .map_err(|e| format!("Failed parsing library bundle, {}", e))?;
+    if !bundle.modules.contains_key(&bundle.entry) {
+        return Err(format!(
+            "Bundle entry module '{}' does not exists",
+            bundle.entry
+        ));
+    }
+    Ok(bundle)
+}
+
+/// Verify that the library requirements (api version and
+/// RedisGears version) are satisfied by this build.
+fn library_verify_requirements(meta_data: &GearsLibraryMetaData) -> Result<(), String> {
+    if let Some(api_version) = &meta_data.api_version {
+        let requested = LibraryVersion::parse(api_version)?;
+        let supported = LibraryVersion::parse(SUPPORTED_API_VERSION).unwrap();
+        if requested.0.first() != supported.0.first() || requested > supported {
+            return Err(format!(
+                "Library requires api version '{}' which is not supported, supported api version is '{}'",
+                api_version, SUPPORTED_API_VERSION
+            ));
+        }
+    }
+    if let Some(min_gears_version) = &meta_data.min_gears_version {
+        let required = LibraryVersion::parse(min_gears_version)?;
+        let gears_version_str = VERSION_STR.unwrap_or_default();
+        let gears_version = LibraryVersion::parse(gears_version_str)?;
+        if gears_version < required {
+            return Err(format!(
+                "Library requires RedisGears version '{}' or above, current version is '{}'",
+                min_gears_version, gears_version_str
+            ));
+        }
+    }
+    Ok(())
+}
+
 pub(crate) fn function_load_revert(
     mut gears_library: GearsLibraryCtx,
     libraries: &mut HashMap<String, Arc<GearsLibrary>>,
 ) {
     if let Some(old_lib) = gears_library.old_lib.take() {
-        for (name, old_ctx, old_window, old_trim) in gears_library.revert_stream_consumers {
-            let stream_data = gears_library.stream_consumers.get(&name).unwrap();
+        for revert_data in gears_library.revert_stream_consumers {
+            let stream_data = gears_library
+                .stream_consumers
+                .get(&revert_data.name)
+                .unwrap();
             let mut s_d = stream_data.ref_cell.borrow_mut();
-            s_d.set_consumer(old_ctx);
-            s_d.set_window(old_window);
-            s_d.set_trim(old_trim);
+            s_d.set_consumer(revert_data.consumer);
+            s_d.set_window(revert_data.window);
+            s_d.set_trim(revert_data.trim);
+            s_d.set_options(revert_data.options);
+        }
+
+        for revert_data in gears_library.revert_notifications_consumers {
+            let notification_consumer = gears_library
+                .notifications_consumers
+                .get(&revert_data.name)
+                .unwrap();
+            get_globals_mut()
+                .notifications_ctx
+                .set_consumer_key(notification_consumer, revert_data.key);
+            let mut n_c = notification_consumer.borrow_mut();
+            let _ = n_c.set_callback(revert_data.callback);
+            n_c.set_options(revert_data.options);
+        }
+
+        for revert_data in gears_library.revert_server_event_consumers {
+            let _ = gears_library
+                .server_event_consumers
+                .get(&revert_data.event)
+                .unwrap()
+                .borrow_mut()
+                .set_callback(revert_data.callback);
         }
 
-        for (name, key, callback) in gears_library.revert_notifications_consumers {
-            let notification_consumer = gears_library.notifications_consumers.get(&name).unwrap();
-            let mut s_d = notification_consumer.borrow_mut();
-            s_d.set_key(key);
-            let _ = s_d.set_callback(callback);
+        for revert_data in gears_library.revert_channel_consumers {
+            let mut c = gears_library
+                .channel_consumers
+                .get(&revert_data.name)
+                .unwrap()
+                .borrow_mut();
+            let _ = c.set_channel(revert_data.channel);
+            let _ = c.set_callback(revert_data.callback);
+            let _ = c.set_options(revert_data.options);
+        }
+
+        for revert_data in gears_library.revert_timers {
+            let timer = gears_library.timers.get(&revert_data.name).unwrap();
+            let schedule = {
+                let mut t = timer.borrow_mut();
+                let _ = t.set_callback(revert_data.callback);
+                // the schedule was already validated when the timer was registered
+                t.set_schedule(revert_data.schedule).unwrap()
+            };
+            if *timer.borrow().get_schedule() != schedule && get_ctx().is_primary() {
+                schedule_timer(timer);
+            }
         }
 
         libraries.insert(gears_library.meta_data.name.clone(), old_lib);
@@ -122,8 +539,10 @@ pub(crate) fn function_load_intrernal(
     config: Option<String>,
     upgrade: bool,
     gears_box_lib: Option<GearsBoxLibraryInfo>,
+    force: bool,
 ) -> Result<(), String> {
     let meta_data = library_extract_matadata(code, config, user).map_err(|e| e.to_string())?;
+    library_verify_requirements(&meta_data)?;
     let backend_name = meta_data.engine.as_str();
     let backend = get_backends_mut().get_mut(backend_name);
     if backend.is_none() {
@@ -132,8 +551,17 @@ pub(crate) fn function_load_intrernal(
     let backend = backend.unwrap();
     let compile_lib_ctx = CompiledLibraryAPI::new();
     let compile_lib_internals = compile_lib_ctx.take_internals();
-    let lib_ctx =
-        backend.compile_library(code, meta_data.config.as_ref(), Box::new(compile_lib_ctx));
+    let lib_ctx = match meta_data.library_type.as_deref() {
+        Some("bundle") => {
+            let bundle = library_extract_bundle(code)?;
+            backend.compile_library_bundle(
+                &bundle,
+                meta_data.config.as_ref(),
+                Box::new(compile_lib_ctx),
+            )
+        }
+        _ => backend.compile_library(code, meta_data.config.as_ref(), Box::new(compile_lib_ctx)),
+    };
     let lib_ctx = match lib_ctx {
         Err(e) => return Err(format!("Failed library compilation {}", e.get_msg())),
         Ok(lib_ctx) => lib_ctx,
@@ -147,14 +575,53 @@ pub(crate) fn function_load_intrernal(
             return err;
         }
     }
+    if let Some(old_lib) = old_lib.as_ref().filter(|_| !force) {
+        let old_version = old_lib.gears_lib_ctx.meta_data.version.as_ref();
+        // versions were already validated when the libraries were loaded
+        let is_downgrade = match (old_version, meta_data.version.as_ref()) {
+            (Some(old_version), Some(new_version)) => {
+                LibraryVersion::parse(new_version).unwrap()
+                    < LibraryVersion::parse(old_version).unwrap()
+            }
+            _ => false,
+        };
+        if is_downgrade {
+            let err = Err(format!(
+                "Can not downgrade library {} from version '{}' to version '{}', use FORCE to allow downgrade",
+                meta_data.name,
+                old_version.unwrap(),
+                meta_data.version.as_ref().unwrap()
+            ));
+            libraries.insert(meta_data.name, Arc::clone(old_lib));
+            return err;
+        }
+    }
+    let dependencies = match library_resolve_dependencies(&meta_data, &libraries) {
+        Ok(d) => d,
+        Err(e) => {
+            if let Some(old_lib) = old_lib {
+                libraries.insert(meta_data.name, old_lib);
+            }
+            return Err(e);
+        }
+    };
+    let is_upgrade = old_lib.is_some();
     let mut gears_library = GearsLibraryCtx {
         meta_data: Arc::new(meta_data),
         functions: HashMap::new(),
         remote_functions: HashMap::new(),
         stream_consumers: HashMap::new(),
         notifications_consumers: HashMap::new(),
+        exported_functions: HashMap::new(),
+        dependencies,
         revert_stream_consumers: Vec::new(),
         revert_notifications_consumers: Vec::new(),
+        timers: HashMap::new(),
+        revert_timers: Vec::new(),
+        server_event_consumers: HashMap::new(),
+        revert_server_event_consumers: Vec::new(),
+        channel_consumers: HashMap::new(),
+        revert_channel_consumers: Vec::new(),
         old_lib,
     };
     let res = lib_ctx.load_library(&mut gears_library);
@@ -166,13 +633,18 @@ pub(crate) fn function_load_intrernal(
     if gears_library.functions.is_empty()
         && gears_library.stream_consumers.is_empty()
         && gears_library.notifications_consumers.is_empty()
+        && gears_library.timers.is_empty()
+        && gears_library.server_event_consumers.is_empty()
+        && gears_library.channel_consumers.is_empty()
+        && gears_library.exported_functions.is_empty()
     {
         function_load_revert(gears_library, &mut libraries);
         return Err("No function nor registrations was registered".to_string());
     }
     gears_library.old_lib = None;
+    let name = gears_library.meta_data.name.to_string();
     libraries.insert(
-        gears_library.meta_data.name.to_string(),
+        name.clone(),
         Arc::new(GearsLibrary {
             gears_lib_ctx: gears_library,
             _lib_ctx: lib_ctx,
@@ -180,6 +652,35 @@ pub(crate) fn function_load_intrernal(
             gears_box_lib,
         }),
     );
+    if is_upgrade {
+        // reload the libraries that depends on the upgraded library so they
+        // will use its new version, a library that fails to reload continues
+        // to use the old version.
+        let dependents = library_dependents(&name, &libraries)
+            .into_iter()
+            .map(|n| {
+                let l = &libraries[&n];
+                let meta_data = &l.gears_lib_ctx.meta_data;
+                (
+                    n,
+                    meta_data.user.clone(),
+                    meta_data.code.clone(),
+                    meta_data.config.clone(),
+                    l.gears_box_lib.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        drop(libraries);
+        for (dependent, user, code, config, gears_box_lib) in dependents {
+            if let Err(e) = function_load_intrernal(user, &code, config, true, gears_box_lib, true)
+            {
+                get_ctx().log_warning(&format!(
+                    "Failed reloading library {} after its dependency {} was upgraded, {}",
+                    dependent, name, e
+                ));
+            }
+        }
+    }
     Ok(())
 }
 
@@ -187,6 +688,7 @@ fn get_args_values(
     mut args: Skip<IntoIter<redis_module::RedisString>>,
 ) -> Result<FunctionLoadArgs, RedisError> {
     let mut upgrade = false;
+    let mut force = false;
     let mut config = None;
     let mut user = None;
     let last_arg = loop {
@@ -202,6 +704,7 @@ fn get_args_values(
         let arg_str = arg_str.to_lowercase();
         match arg_str.as_ref() {
             "upgrade" => upgrade = true,
+            "force" => force = true,
             "user" => {
                 let arg = args
                     .next_arg()
@@ -241,8 +744,12 @@ fn get_args_values(
         Err(_) => return Err(RedisError::Str("lib code must a valid string")),
     }
     .to_string();
+    if force && !upgrade {
+        return Err(RedisError::Str("FORCE can only be used with UPGRADE"));
+    }
     Ok(FunctionLoadArgs {
         upgrade,
+        force,
         config,
         code,
         user,
@@ -299,6 +806,7 @@ impl RemoteTask for GearsFunctionLoadRemoteTask {
                 r.args.config.clone(),
                 r.args.upgrade,
                 None,
+                r.args.force,
             );
             if res.is_ok() {
                 let mut replicate_args = Vec::new();
@@ -306,6 +814,9 @@ impl RemoteTask for GearsFunctionLoadRemoteTask {
                 if r.args.upgrade {
                     replicate_args.push("UPGRADE".as_bytes());
                 }
+                if r.args.force {
+                    replicate_args.push("FORCE".as_bytes());
+                }
                 if let Some(conf) = &r.args.config {
                     replicate_args.push("CONFIG".as_bytes());
                     replicate_args.push(conf.as_bytes());
@@ -365,6 +876,7 @@ pub(crate) fn function_load_on_replica(
         args.config,
         args.upgrade,
         None,
+        args.force,
     ) {
         Ok(_) => Ok(RedisValue::SimpleStringStatic("OK")),
         Err(e) => Err(RedisError::String(e)),
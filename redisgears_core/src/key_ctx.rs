@@ -0,0 +1,351 @@
+/*
+ * Copyright Redis Ltd. 2018 - present
+ * Licensed under your choice of the Redis Source Available License 2.0 (RSALv2) or
+ * the Server Side Public License v1 (SSPLv1).
+ */
+
+//! Direct access to keys from within a function using the Redis module key API,
+//! avoiding the command lookup and arguments building of running a command.
+//! Writes are replicated and fire keyspace notifications as the equivalent command would.
+
+use redis_module::context::AclPermissions;
+use redis_module::raw::{self, KeyType};
+use redis_module::{NotifyEvent, RedisString};
+
+use redisgears_plugin_api::redisgears_plugin_api::{
+    key_ctx::KeyCtxInterface, key_ctx::ListEnd, load_library_ctx::FunctionFlags, GearsApiError,
+};
+
+use crate::atomic_block::UndoLog;
+use crate::run_ctx::RedisClientCallOptions;
+use crate::{get_ctx, verify_oom};
+
+use std::os::raw::{c_int, c_long, c_void};
+use std::sync::Arc;
+
+const WRONG_TYPE_ERROR: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+/// Copy the content of a string returned by the key API and free it.
+fn take_module_string(s: *mut raw::RedisModuleString) -> Vec<u8> {
+    unsafe {
+        let mut len = 0;
+        let ptr = raw::RedisModule_StringPtrLen.unwrap()(s, &mut len);
+        let res = std::slice::from_raw_parts(ptr.cast::<u8>(), len).to_vec();
+        raw::RedisModule_FreeString.unwrap()(get_ctx().ctx, s);
+        res
+    }
+}
+
+/// A key that is open for the duration of a single operation.
+struct OpenKey {
+    key: *mut raw::RedisModuleKey,
+    name: RedisString,
+}
+
+impl OpenKey {
+    fn new(name: &[u8], write: bool) -> OpenKey {
+        let ctx = get_ctx();
+        let name = RedisString::create_from_slice(std::ptr::null_mut(), name);
+        let mode = if write {
+            raw::REDISMODULE_READ | raw::REDISMODULE_WRITE
+        } else {
+            raw::REDISMODULE_READ
+        };
+        let key = unsafe { raw::RedisModule_OpenKey.unwrap()(ctx.ctx, name.inner, mode as c_int) };
+        OpenKey {
+            key: key.cast(),
+            name,
+        }
+    }
+
+    fn key_type(&self) -> KeyType {
+        raw::key_type(self.key)
+    }
+
+    /// Verify that the key is either empty or of the expected type,
+    /// returns `false` if the key is empty.
+    fn verify_type(&self, expected: KeyType) -> Result<bool, GearsApiError> {
+        match self.key_type() {
+            KeyType::Empty => Ok(false),
+            t if t == expected => Ok(true),
+            _ => Err(GearsApiError::new(WRONG_TYPE_ERROR)),
+        }
+    }
+
+    /// Replicate the equivalent command and fire a keyspace notification.
+    fn on_write(&self, event_type: NotifyEvent, command: &str, args: &[&[u8]]) {
+        let ctx = get_ctx();
+        let mut replicate_args = vec![self.name.as_slice()];
+        replicate_args.extend_from_slice(args);
+        redis_module::replicate_slices(ctx.ctx, command, &replicate_args);
+        ctx.notify_keyspace_event(event_type, command, &self.name);
+    }
+
+    /// Fire a `del` keyspace notification if the last write removed the last
+    /// element and the key was deleted, like the equivalent command does. The
+    /// deletion is not replicated, the replicated command deletes the key.
+    fn on_emptied(&self) {
+        if self.key_type() == KeyType::Empty {
+            get_ctx().notify_keyspace_event(NotifyEvent::GENERIC, "del", &self.name);
+        }
+    }
+}
+
+impl Drop for OpenKey {
+    fn drop(&mut self) {
+        unsafe { raw::RedisModule_CloseKey.unwrap()(self.key) };
+    }
+}
+
+pub(crate) struct GearsKeyCtx {
+    name: Vec<u8>,
+    user: String,
+    flags: FunctionFlags,
+    undo_log: Option<Arc<UndoLog>>,
+}
+
+impl GearsKeyCtx {
+    pub(crate) fn new(
+        name: &[u8],
+        user: &str,
+        call_options: &RedisClientCallOptions,
+    ) -> Result<GearsKeyCtx, GearsApiError> {
+        if let Some(declared_keys) = call_options.declared_keys.as_ref() {
+            declared_keys.verify_key(name).map_err(GearsApiError::new)?;
+        }
+        let key = GearsKeyCtx {
+            name: name.to_vec(),
+            user: user.to_string(),
+            flags: call_options.flags,
+            undo_log: call_options.undo_log.clone(),
+        };
+        let mut permissions = AclPermissions::new();
+        permissions.add_access_permission();
+        key.verify_permissions(&permissions)?;
+        Ok(key)
+    }
+
+    fn verify_permissions(&self, permissions: &AclPermissions) -> Result<(), GearsApiError> {
+        let key_redis_str = RedisString::create_from_slice(std::ptr::null_mut(), &self.name);
+        get_ctx()
+            .acl_check_key_permission(&self.user, &key_redis_str, permissions)
+            .map_err(|e| {
+                GearsApiError::new(format!(
+                    "User '{}' has no permissions on key '{}', {}.",
+                    self.user,
+                    String::from_utf8_lossy(&self.name),
+                    e
+                ))
+            })
+    }
+
+    fn open_for_read(&self) -> OpenKey {
+        OpenKey::new(&self.name, false)
+    }
+
+    fn open_for_write(&self) -> Result<OpenKey, GearsApiError> {
+        if self.flags.contains(FunctionFlags::NO_WRITES) {
+            return Err(GearsApiError::new(
+                "Can not write to a key from a function with the 'no-writes' flag",
+            ));
+        }
+        if !verify_oom(self.flags) {
+            return Err(GearsApiError::new("OOM Can not write to a key"));
+        }
+        let mut permissions = AclPermissions::new();
+        permissions.add_full_permission();
+        self.verify_permissions(&permissions)?;
+        if let Some(undo_log) = self.undo_log.as_ref() {
+            undo_log.record_key(&self.name)?;
+        }
+        Ok(OpenKey::new(&self.name, true))
+    }
+}
+
+impl KeyCtxInterface for GearsKeyCtx {
+    fn key_type(&self) -> &'static str {
+        match self.open_for_read().key_type() {
+            KeyType::Empty => "none",
+            KeyType::String => "string",
+            KeyType::List => "list",
+            KeyType::Set => "set",
+            KeyType::ZSet => "zset",
+            KeyType::Hash => "hash",
+            KeyType::Stream => "stream",
+            KeyType::Module => "module",
+        }
+    }
+
+    fn delete(&self) -> Result<bool, GearsApiError> {
+        let key = self.open_for_write()?;
+        if key.key_type() == KeyType::Empty {
+            return Ok(false);
+        }
+        unsafe { raw::RedisModule_DeleteKey.unwrap()(key.key) };
+        key.on_write(NotifyEvent::GENERIC, "del", &[]);
+        Ok(true)
+    }
+
+    fn string_get(&self) -> Result<Option<Vec<u8>>, GearsApiError> {
+        let key = self.open_for_read();
+        if !key.verify_type(KeyType::String)? {
+            return Ok(None);
+        }
+        let mut len = 0;
+        let res = unsafe {
+            let ptr = raw::RedisModule_StringDMA.unwrap()(
+                key.key,
+                &mut len,
+                raw::REDISMODULE_READ as c_int,
+            );
+            std::slice::from_raw_parts(ptr.cast::<u8>(), len).to_vec()
+        };
+        Ok(Some(res))
+    }
+
+    fn string_set(&self, val: &[u8]) -> Result<(), GearsApiError> {
+        let key = self.open_for_write()?;
+        let val_redis_str = RedisString::create_from_slice(std::ptr::null_mut(), val);
+        unsafe { raw::RedisModule_StringSet.unwrap()(key.key, val_redis_str.inner) };
+        key.on_write(NotifyEvent::STRING, "set", &[val]);
+        Ok(())
+    }
+
+    fn hash_get(&self, field: &[u8]) -> Result<Option<Vec<u8>>, GearsApiError> {
+        let key = self.open_for_read();
+        if !key.verify_type(KeyType::Hash)? {
+            return Ok(None);
+        }
+        let field_redis_str = RedisString::create_from_slice(std::ptr::null_mut(), field);
+        let mut val: *mut raw::RedisModuleString = std::ptr::null_mut();
+        unsafe {
+            raw::RedisModule_HashGet.unwrap()(
+                key.key,
+                raw::REDISMODULE_HASH_NONE as c_int,
+                field_redis_str.inner,
+                &mut val as *mut *mut raw::RedisModuleString,
+                std::ptr::null_mut::<c_void>(),
+            )
+        };
+        if val.is_null() {
+            return Ok(None);
+        }
+        Ok(Some(take_module_string(val)))
+    }
+
+    fn hash_set(&self, field: &[u8], val: &[u8]) -> Result<bool, GearsApiError> {
+        let key = self.open_for_write()?;
+        key.verify_type(KeyType::Hash)?;
+        let field_redis_str = RedisString::create_from_slice(std::ptr::null_mut(), field);
+        let val_redis_str = RedisString::create_from_slice(std::ptr::null_mut(), val);
+        // returns the number of existing fields that were updated.
+        let updated = unsafe {
+            raw::RedisModule_HashSet.unwrap()(
+                key.key,
+                raw::REDISMODULE_HASH_NONE as c_int,
+                field_redis_str.inner,
+                val_redis_str.inner,
+                std::ptr::null_mut::<c_void>(),
+            )
+        };
+        key.on_write(NotifyEvent::HASH, "hset", &[field, val]);
+        Ok(updated == 0)
+    }
+
+    fn hash_del(&self, field: &[u8]) -> Result<bool, GearsApiError> {
+        let key = self.open_for_write()?;
+        if !key.verify_type(KeyType::Hash)? {
+            return Ok(false);
+        }
+        let field_redis_str = RedisString::create_from_slice(std::ptr::null_mut(), field);
+        // REDISMODULE_HASH_DELETE
+        let delete = std::ptr::null_mut::<u8>()
+            .wrapping_add(1)
+            .cast::<raw::RedisModuleString>();
+        let deleted = unsafe {
+            raw::RedisModule_HashSet.unwrap()(
+                key.key,
+                raw::REDISMODULE_HASH_NONE as c_int,
+                field_redis_str.inner,
+                delete,
+                std::ptr::null_mut::<c_void>(),
+            )
+        };
+        if deleted == 0 {
+            return Ok(false);
+        }
+        key.on_write(NotifyEvent::HASH, "hdel", &[field]);
+        key.on_emptied();
+        Ok(true)
+    }
+
+    fn list_push(&self, val: &[u8], end: ListEnd) -> Result<(), GearsApiError> {
+        let key = self.open_for_write()?;
+        key.verify_type(KeyType::List)?;
+        let val_redis_str = RedisString::create_from_slice(std::ptr::null_mut(), val);
+        let (list_end, command) = match end {
+            ListEnd::Head => (raw::REDISMODULE_LIST_HEAD, "lpush"),
+            ListEnd::Tail => (raw::REDISMODULE_LIST_TAIL, "rpush"),
+        };
+        unsafe {
+            raw::RedisModule_ListPush.unwrap()(key.key, list_end as c_int, val_redis_str.inner)
+        };
+        key.on_write(NotifyEvent::LIST, command, &[val]);
+        Ok(())
+    }
+
+    fn list_pop(&self, end: ListEnd) -> Result<Option<Vec<u8>>, GearsApiError> {
+        let key = self.open_for_write()?;
+        if !key.verify_type(KeyType::List)? {
+            return Ok(None);
+        }
+        let (list_end, command) = match end {
+            ListEnd::Head => (raw::REDISMODULE_LIST_HEAD, "lpop"),
+            ListEnd::Tail => (raw::REDISMODULE_LIST_TAIL, "rpop"),
+        };
+        let val = unsafe { raw::RedisModule_ListPop.unwrap()(key.key, list_end as c_int) };
+        if val.is_null() {
+            return Ok(None);
+        }
+        key.on_write(NotifyEvent::LIST, command, &[]);
+        key.on_emptied();
+        Ok(Some(take_module_string(val)))
+    }
+
+    fn value_length(&self) -> usize {
+        let key = self.open_for_read();
+        unsafe { raw::RedisModule_ValueLength.unwrap()(key.key) }
+    }
+
+    fn stream_add(&self, fields: &[(&[u8], &[u8])]) -> Result<String, GearsApiError> {
+        let key = self.open_for_write()?;
+        key.verify_type(KeyType::Stream)?;
+        let args = fields
+            .iter()
+            .flat_map(|(f, v)| [*f, *v])
+            .map(|a| RedisString::create_from_slice(std::ptr::null_mut(), a))
+            .collect::<Vec<RedisString>>();
+        let mut argv = args
+            .iter()
+            .map(|a| a.inner)
+            .collect::<Vec<*mut raw::RedisModuleString>>();
+        let mut id = raw::RedisModuleStreamID { ms: 0, seq: 0 };
+        let res = unsafe {
+            raw::RedisModule_StreamAdd.unwrap()(
+                key.key,
+                raw::REDISMODULE_STREAM_ADD_AUTOID as c_int,
+                &mut id,
+                argv.as_mut_ptr(),
+                fields.len() as c_long,
+            )
+        };
+        if res != raw::REDISMODULE_OK as c_int {
+            return Err(GearsApiError::new("Failed adding record to stream"));
+        }
+        let id = format!("{}-{}", id.ms, id.seq);
+        let mut replicate_args = vec![id.as_bytes()];
+        replicate_args.extend(fields.iter().flat_map(|(f, v)| [*f, *v]));
+        key.on_write(NotifyEvent::STREAM, "xadd", &replicate_args);
+        Ok(id)
+    }
+}
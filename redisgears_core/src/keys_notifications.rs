@@ -4,17 +4,254 @@
  * the Server Side Public License v1 (SSPLv1).
  */
 
-use redisgears_plugin_api::redisgears_plugin_api::{GearsApiError, RefCellWrapper};
+use redis_module::NotifyEvent;
+use redisgears_plugin_api::redisgears_plugin_api::{
+    keys_notifications_consumer_ctx::KeysNotificationsConsumerOptions,
+    keys_notifications_consumer_ctx::KeysNotificationsEventClasses,
+    load_library_ctx::RegisteredKeys, GearsApiError, RefCellWrapper,
+};
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::sync::{Arc, Weak};
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
-pub(crate) type NotificationCallback =
-    Box<dyn Fn(&str, &[u8], Box<dyn FnOnce(Result<(), GearsApiError>) + Send + Sync>)>;
+/// The events a notification is fired with.
+pub(crate) enum NotificationEvents<'a> {
+    Single(&'a str),
+    /// The distinct events of a key that were coalesced, the most recent event
+    /// and the total number of events.
+    Coalesced {
+        events: &'a [String],
+        last_event: &'a str,
+        count: usize,
+    },
+}
+
+pub(crate) type NotificationCallback = Box<
+    dyn Fn(NotificationEvents, &[u8], Box<dyn FnOnce(Result<(), GearsApiError>) + Send + Sync>),
+>;
+/// Run the given job after the given delay, used to fire coalesced events.
+pub(crate) type NotificationSchedulerCallback = dyn Fn(Duration, Box<dyn FnOnce()>);
 
 pub(crate) enum ConsumerKey {
     Key(Vec<u8>),
     Prefix(Vec<u8>),
+    Prefixes(Vec<Vec<u8>>),
+    Suffix(Vec<u8>),
+    Pattern(Vec<u8>),
+}
+
+impl From<RegisteredKeys<'_>> for ConsumerKey {
+    fn from(key: RegisteredKeys) -> Self {
+        match key {
+            RegisteredKeys::Key(k) => ConsumerKey::Key(k.to_vec()),
+            RegisteredKeys::Prefix(p) => ConsumerKey::Prefix(p.to_vec()),
+            RegisteredKeys::Prefixes(p) => {
+                ConsumerKey::Prefixes(p.iter().map(|p| p.to_vec()).collect())
+            }
+            RegisteredKeys::Suffix(s) => ConsumerKey::Suffix(s.to_vec()),
+            RegisteredKeys::Pattern(p) => ConsumerKey::Pattern(p.to_vec()),
+        }
+    }
+}
+
+/// Match the given key against a glob style pattern, using the same
+/// rules as the Redis `KEYS` command (`*`, `?`, `[...]` and `\` escaping).
+pub(crate) fn glob_match(pattern: &[u8], key: &[u8]) -> bool {
+    let mut p = 0;
+    let mut k = 0;
+    // the position after the last `*` and the key position it is currently matched to.
+    let mut star: Option<(usize, usize)> = None;
+    while k < key.len() {
+        let step = match pattern.get(p) {
+            Some(b'*') => {
+                p += 1;
+                star = Some((p, k));
+                continue;
+            }
+            Some(b'?') => Some(1),
+            Some(b'[') => glob_match_class(&pattern[p..], key[k]),
+            Some(b'\\') if p + 1 < pattern.len() => (pattern[p + 1] == key[k]).then_some(2),
+            Some(c) => (*c == key[k]).then_some(1),
+            None => None,
+        };
+        match (step, star) {
+            (Some(step), _) => {
+                p += step;
+                k += 1;
+            }
+            (None, Some((star_p, star_k))) => {
+                // let the last `*` match one more character.
+                p = star_p;
+                k = star_k + 1;
+                star = Some((star_p, k));
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+/// Match a single character against a `[...]` class at the start of the given
+/// pattern, returns the length of the class if the character matches.
+fn glob_match_class(class: &[u8], c: u8) -> Option<usize> {
+    let mut i = 1;
+    let negate = class.get(i) == Some(&b'^');
+    if negate {
+        i += 1;
+    }
+    let mut matched = false;
+    loop {
+        match class.get(i) {
+            None => break,
+            Some(b']') => {
+                i += 1;
+                break;
+            }
+            Some(b'\\') if i + 1 < class.len() => {
+                matched |= class[i + 1] == c;
+                i += 2;
+            }
+            Some(start) if class.get(i + 1) == Some(&b'-') && i + 2 < class.len() => {
+                let end = class[i + 2];
+                let (low, high) = if *start <= end {
+                    (*start, end)
+                } else {
+                    (end, *start)
+                };
+                matched |= low <= c && c <= high;
+                i += 3;
+            }
+            Some(x) => {
+                matched |= *x == c;
+                i += 1;
+            }
+        }
+    }
+    (matched != negate).then_some(i)
+}
+
+/// The part of the pattern that comes before the first special character,
+/// every key that matches the pattern starts with it.
+fn glob_literal_prefix(pattern: &[u8]) -> &[u8] {
+    let len = pattern
+        .iter()
+        .position(|c| matches!(c, b'*' | b'?' | b'[' | b'\\'))
+        .unwrap_or(pattern.len());
+    &pattern[..len]
+}
+
+/// A trie over the bytes of the registered keys, each node holds
+/// the values that were registered with the path leading to it.
+struct KeysTrie<V> {
+    values: Vec<V>,
+    children: HashMap<u8, KeysTrie<V>>,
+}
+
+impl<V> KeysTrie<V> {
+    fn new() -> KeysTrie<V> {
+        KeysTrie {
+            values: Vec::new(),
+            children: HashMap::new(),
+        }
+    }
+
+    fn insert<I: Iterator<Item = u8>>(&mut self, path: I, value: V) {
+        let node = path.fold(self, |node, c| {
+            node.children.entry(c).or_insert_with(KeysTrie::new)
+        });
+        node.values.push(value);
+    }
+
+    /// Call the given callback on all the values that were registered
+    /// with a path that is a prefix of the given path.
+    fn for_each_prefix<'a, I: Iterator<Item = u8>>(&'a self, path: I, f: &mut dyn FnMut(&'a V)) {
+        let mut node = self;
+        node.values.iter().for_each(&mut *f);
+        for c in path {
+            node = match node.children.get(&c) {
+                Some(n) => n,
+                None => return,
+            };
+            node.values.iter().for_each(&mut *f);
+        }
+    }
+}
+
+/// A consumer on the keys index, the id represents the registration order.
+struct IndexedConsumer {
+    id: usize,
+    consumer: Weak<RefCell<NotificationConsumer>>,
+}
+
+/// Index of the consumers by their registered keys so that finding the consumers
+/// of a key does not require going over all the consumers.
+struct KeysIndex {
+    keys: HashMap<Vec<u8>, Vec<IndexedConsumer>>,
+    prefixes: KeysTrie<IndexedConsumer>,
+    suffixes: KeysTrie<IndexedConsumer>,
+    // patterns are indexed by their literal prefix.
+    patterns: KeysTrie<(Vec<u8>, IndexedConsumer)>,
+}
+
+impl KeysIndex {
+    fn new() -> KeysIndex {
+        KeysIndex {
+            keys: HashMap::new(),
+            prefixes: KeysTrie::new(),
+            suffixes: KeysTrie::new(),
+            patterns: KeysTrie::new(),
+        }
+    }
+
+    fn add(&mut self, id: usize, consumer: &Arc<RefCell<NotificationConsumer>>) {
+        let indexed_consumer = || IndexedConsumer {
+            id,
+            consumer: Arc::downgrade(consumer),
+        };
+        match consumer.borrow().key.as_ref().unwrap() {
+            ConsumerKey::Key(k) => self
+                .keys
+                .entry(k.clone())
+                .or_default()
+                .push(indexed_consumer()),
+            ConsumerKey::Prefix(p) => self.prefixes.insert(p.iter().copied(), indexed_consumer()),
+            ConsumerKey::Prefixes(prefixes) => {
+                for p in prefixes {
+                    self.prefixes.insert(p.iter().copied(), indexed_consumer());
+                }
+            }
+            ConsumerKey::Suffix(s) => self
+                .suffixes
+                .insert(s.iter().rev().copied(), indexed_consumer()),
+            ConsumerKey::Pattern(p) => self.patterns.insert(
+                glob_literal_prefix(p).iter().copied(),
+                (p.clone(), indexed_consumer()),
+            ),
+        }
+    }
+
+    /// Returns the consumers that match the given key, by their registration order.
+    fn find(&self, key: &[u8]) -> Vec<&IndexedConsumer> {
+        let mut res = Vec::new();
+        if let Some(consumers) = self.keys.get(key) {
+            res.extend(consumers.iter());
+        }
+        self.prefixes
+            .for_each_prefix(key.iter().copied(), &mut |c| res.push(c));
+        self.suffixes
+            .for_each_prefix(key.iter().rev().copied(), &mut |c| res.push(c));
+        self.patterns
+            .for_each_prefix(key.iter().copied(), &mut |(pattern, c)| {
+                if glob_match(pattern, key) {
+                    res.push(c);
+                }
+            });
+        // a consumer with multiple prefixes might match more than once.
+        res.sort_by_key(|c| c.id);
+        res.dedup_by_key(|c| c.id);
+        res
+    }
 }
 
 #[derive(Clone)]
@@ -23,28 +260,47 @@ pub(crate) struct NotificationConsumerStats {
     pub(crate) num_success: usize,
     pub(crate) num_failed: usize,
     pub(crate) num_finished: usize,
+    pub(crate) num_filtered: usize, // events on matching keys that the consumer is not subscribed to
+    pub(crate) num_coalesced: usize, // events that were merged into a pending invocation
     pub(crate) last_error: Option<GearsApiError>,
     pub(crate) last_execution_time: u128,
     pub(crate) total_execution_time: u128,
 }
 
+/// The events of a key that wait to be fired together.
+struct CoalescedEvents {
+    events: Vec<String>, // distinct events by the order they first happened
+    last_event: String,
+    count: usize,
+}
+
 pub(crate) struct NotificationConsumer {
     key: Option<ConsumerKey>,
     callback: Option<NotificationCallback>,
+    options: KeysNotificationsConsumerOptions,
+    coalesced_events: HashMap<Vec<u8>, CoalescedEvents>,
     stats: Arc<RefCellWrapper<NotificationConsumerStats>>,
 }
 
 impl NotificationConsumer {
-    fn new(key: ConsumerKey, callback: NotificationCallback) -> NotificationConsumer {
+    fn new(
+        key: ConsumerKey,
+        callback: NotificationCallback,
+        options: KeysNotificationsConsumerOptions,
+    ) -> NotificationConsumer {
         NotificationConsumer {
             key: Some(key),
             callback: Some(callback),
+            options,
+            coalesced_events: HashMap::new(),
             stats: Arc::new(RefCellWrapper {
                 ref_cell: RefCell::new(NotificationConsumerStats {
                     num_trigger: 0,
                     num_success: 0,
                     num_failed: 0,
                     num_finished: 0,
+                    num_filtered: 0,
+                    num_coalesced: 0,
                     last_error: None,
                     last_execution_time: 0,
                     total_execution_time: 0,
@@ -59,18 +315,60 @@ impl NotificationConsumer {
         old_callback.unwrap()
     }
 
-    pub(crate) fn set_key(&mut self, key: ConsumerKey) -> ConsumerKey {
+    fn set_key(&mut self, key: ConsumerKey) -> ConsumerKey {
         let old_key = self.key.take();
         self.key = Some(key);
         old_key.unwrap()
     }
 
+    pub(crate) fn set_options(
+        &mut self,
+        options: KeysNotificationsConsumerOptions,
+    ) -> KeysNotificationsConsumerOptions {
+        std::mem::replace(&mut self.options, options)
+    }
+
+    /// Returns `true` if the consumer is not subscribed to the given event,
+    /// in which case the event is counted as filtered.
+    fn is_filtered(&self, event: &str, class: KeysNotificationsEventClasses) -> bool {
+        if self.options.is_subscribed(event, class) {
+            return false;
+        }
+        self.stats.ref_cell.borrow_mut().num_filtered += 1;
+        true
+    }
+
     pub(crate) fn get_stats(&self) -> NotificationConsumerStats {
         self.stats.ref_cell.borrow().clone()
     }
 }
 
-fn fire_event(consumer: &Arc<RefCell<NotificationConsumer>>, event: &str, key: &[u8]) {
+fn notify_event_to_class(event_type: NotifyEvent) -> KeysNotificationsEventClasses {
+    [
+        (NotifyEvent::GENERIC, KeysNotificationsEventClasses::GENERIC),
+        (NotifyEvent::STRING, KeysNotificationsEventClasses::STRING),
+        (NotifyEvent::LIST, KeysNotificationsEventClasses::LIST),
+        (NotifyEvent::SET, KeysNotificationsEventClasses::SET),
+        (NotifyEvent::HASH, KeysNotificationsEventClasses::HASH),
+        (NotifyEvent::ZSET, KeysNotificationsEventClasses::ZSET),
+        (NotifyEvent::EXPIRED, KeysNotificationsEventClasses::EXPIRED),
+        (NotifyEvent::EVICTED, KeysNotificationsEventClasses::EVICTED),
+        (NotifyEvent::STREAM, KeysNotificationsEventClasses::STREAM),
+        (NotifyEvent::MODULE, KeysNotificationsEventClasses::MODULE),
+        (NotifyEvent::MISSED, KeysNotificationsEventClasses::KEY_MISS),
+    ]
+    .into_iter()
+    .filter(|(event, _)| event_type.intersects(*event))
+    .fold(KeysNotificationsEventClasses::empty(), |res, (_, class)| {
+        res | class
+    })
+}
+
+fn fire_event(
+    consumer: &Arc<RefCell<NotificationConsumer>>,
+    events: NotificationEvents,
+    key: &[u8],
+) {
     let c = consumer.borrow();
     {
         let mut stats = c.stats.ref_cell.borrow_mut();
@@ -80,7 +378,7 @@ fn fire_event(consumer: &Arc<RefCell<NotificationConsumer>>, event: &str, key: &
     let start_time = SystemTime::now();
 
     (c.callback.as_ref().unwrap())(
-        event,
+        events,
         key,
         Box::new(move |res| {
             let duration = match SystemTime::now().duration_since(start_time) {
@@ -101,59 +399,136 @@ fn fire_event(consumer: &Arc<RefCell<NotificationConsumer>>, event: &str, key: &
     );
 }
 
+/// Fire the events of a key that were coalesced, if the consumer still exists.
+fn fire_coalesced_events(consumer: Weak<RefCell<NotificationConsumer>>, key: Vec<u8>) {
+    let consumer = match consumer.upgrade() {
+        Some(c) => c,
+        None => return,
+    };
+    let coalesced_events = consumer.borrow_mut().coalesced_events.remove(&key);
+    if let Some(coalesced_events) = coalesced_events {
+        fire_event(
+            &consumer,
+            NotificationEvents::Coalesced {
+                events: &coalesced_events.events,
+                last_event: &coalesced_events.last_event,
+                count: coalesced_events.count,
+            },
+            &key,
+        );
+    }
+}
+
 pub(crate) struct KeysNotificationsCtx {
     consumers: Vec<Weak<RefCell<NotificationConsumer>>>,
+    index: KeysIndex,
+    scheduler: Box<NotificationSchedulerCallback>,
 }
 
 impl KeysNotificationsCtx {
-    pub(crate) fn new() -> KeysNotificationsCtx {
+    pub(crate) fn new(scheduler: Box<NotificationSchedulerCallback>) -> KeysNotificationsCtx {
         KeysNotificationsCtx {
             consumers: Vec::new(),
+            index: KeysIndex::new(),
+            scheduler,
         }
     }
 
-    pub(crate) fn add_consumer_on_prefix(
+    pub(crate) fn add_consumer(
         &mut self,
-        prefix: &[u8],
+        key: ConsumerKey,
         callback: NotificationCallback,
+        options: KeysNotificationsConsumerOptions,
     ) -> Arc<RefCell<NotificationConsumer>> {
+        // consumers of removed libraries are only dropped from the index
+        // on rebuild, do it here so loading and removing libraries will not
+        // keep growing the consumers list.
+        if self.consumers.iter().any(|c| c.strong_count() == 0) {
+            self.rebuild_index();
+        }
         let consumer = Arc::new(RefCell::new(NotificationConsumer::new(
-            ConsumerKey::Prefix(prefix.to_vec()),
-            callback,
+            key, callback, options,
         )));
+        self.index.add(self.consumers.len(), &consumer);
         self.consumers.push(Arc::downgrade(&consumer));
         consumer
     }
 
-    pub(crate) fn add_consumer_on_key(
+    /// Set the key of an existing consumer, returns the old key.
+    pub(crate) fn set_consumer_key(
         &mut self,
-        key: &[u8],
-        callback: NotificationCallback,
-    ) -> Arc<RefCell<NotificationConsumer>> {
-        let consumer = Arc::new(RefCell::new(NotificationConsumer::new(
-            ConsumerKey::Key(key.to_vec()),
-            callback,
-        )));
-        self.consumers.push(Arc::downgrade(&consumer));
-        consumer
+        consumer: &Arc<RefCell<NotificationConsumer>>,
+        key: ConsumerKey,
+    ) -> ConsumerKey {
+        let old_key = consumer.borrow_mut().set_key(key);
+        self.rebuild_index();
+        old_key
+    }
+
+    fn rebuild_index(&mut self) {
+        self.consumers.retain(|c| c.strong_count() > 0);
+        self.index = KeysIndex::new();
+        for (id, consumer) in self.consumers.iter().enumerate() {
+            if let Some(consumer) = consumer.upgrade() {
+                self.index.add(id, &consumer);
+            }
+        }
     }
 
-    pub(crate) fn on_key_touched(&self, event: &str, key: &[u8]) {
-        for consumer in self.consumers.iter() {
-            let consumer = match consumer.upgrade() {
+    pub(crate) fn on_key_touched(&self, event_type: NotifyEvent, event: &str, key: &[u8]) {
+        let class = notify_event_to_class(event_type);
+        for c in self.index.find(key) {
+            let consumer = match c.consumer.upgrade() {
                 Some(c) => c,
                 None => continue,
             };
-            let res = {
-                let c = consumer.borrow_mut();
-                match c.key.as_ref().unwrap() {
-                    ConsumerKey::Key(k) => key == k,
-                    ConsumerKey::Prefix(prefix) => key.starts_with(prefix),
+            let coalesce_ms = {
+                let c = consumer.borrow();
+                if c.is_filtered(event, class) {
+                    continue;
                 }
+                c.options.coalesce_ms
             };
-            if res {
-                fire_event(&consumer, event, key);
+            match coalesce_ms {
+                Some(coalesce_ms) => self.coalesce_event(&consumer, event, key, coalesce_ms),
+                None => fire_event(&consumer, NotificationEvents::Single(event), key),
+            }
+        }
+    }
+
+    /// Add the event to the events of the key that wait to be fired, the first
+    /// event of the key schedules the firing after the given coalescing time.
+    fn coalesce_event(
+        &self,
+        consumer: &Arc<RefCell<NotificationConsumer>>,
+        event: &str,
+        key: &[u8],
+        coalesce_ms: u64,
+    ) {
+        let mut c_ref = consumer.borrow_mut();
+        let c = &mut *c_ref;
+        if let Some(coalesced_events) = c.coalesced_events.get_mut(key) {
+            if !coalesced_events.events.iter().any(|e| e == event) {
+                coalesced_events.events.push(event.to_string());
             }
+            coalesced_events.last_event = event.to_string();
+            coalesced_events.count += 1;
+            c.stats.ref_cell.borrow_mut().num_coalesced += 1;
+            return;
         }
+        c.coalesced_events.insert(
+            key.to_vec(),
+            CoalescedEvents {
+                events: vec![event.to_string()],
+                last_event: event.to_string(),
+                count: 1,
+            },
+        );
+        let consumer = Arc::downgrade(consumer);
+        let key = key.to_vec();
+        (self.scheduler)(
+            Duration::from_millis(coalesce_ms),
+            Box::new(move || fire_coalesced_events(consumer, key)),
+        );
     }
 }
@@ -0,0 +1,121 @@
+/*
+ * Copyright Redis Ltd. 2018 - present
+ * Licensed under your choice of the Redis Source Available License 2.0 (RSALv2) or
+ * the Server Side Public License v1 (SSPLv1).
+ */
+
+//! Scanning the key space from within a function, in batches, using a `KeysCursor`.
+
+use redis_module::context::keys_cursor::KeysCursor;
+use redis_module::{raw::KeyType, ThreadSafeContext};
+
+use redisgears_plugin_api::redisgears_plugin_api::{
+    run_function_ctx::ScanCursorInterface, run_function_ctx::ScanOptions, GearsApiError,
+};
+
+use crate::get_ctx;
+use crate::keys_notifications::glob_match;
+use crate::run_ctx::RedisClientCallOptions;
+
+use std::cell::RefCell;
+
+const DEFAULT_SCAN_COUNT: usize = 10;
+
+fn parse_key_type(key_type: &str) -> Result<KeyType, GearsApiError> {
+    match key_type.to_lowercase().as_str() {
+        "string" => Ok(KeyType::String),
+        "list" => Ok(KeyType::List),
+        "set" => Ok(KeyType::Set),
+        "zset" => Ok(KeyType::ZSet),
+        "hash" => Ok(KeyType::Hash),
+        "stream" => Ok(KeyType::Stream),
+        _ => Err(GearsApiError::new(format!(
+            "Unknown key type '{}'",
+            key_type
+        ))),
+    }
+}
+
+/// A cursor that scans the key space in batches. A cursor that was created by a background
+/// client locks Redis for each batch and releases the lock between batches, otherwise
+/// Redis is expected to be locked by the caller.
+pub(crate) struct KeysScanCursor {
+    cursor: KeysCursor,
+    pattern: Option<Vec<u8>>,
+    key_type: Option<KeyType>,
+    count: usize,
+    background: bool,
+    done: bool,
+}
+
+impl KeysScanCursor {
+    pub(crate) fn new(
+        options: ScanOptions,
+        call_options: &RedisClientCallOptions,
+        background: bool,
+    ) -> Result<KeysScanCursor, GearsApiError> {
+        if call_options.declared_keys.is_some() {
+            return Err(GearsApiError::new(
+                "Scanning the key space is not allowed on functions with the 'strict-keys' flag",
+            ));
+        }
+        let key_type = options
+            .key_type
+            .as_deref()
+            .map(parse_key_type)
+            .transpose()?;
+        let count = match options.count {
+            Some(0) => return Err(GearsApiError::new("Scan count must be positive")),
+            Some(count) => count,
+            None => DEFAULT_SCAN_COUNT,
+        };
+        Ok(KeysScanCursor {
+            cursor: KeysCursor::new(),
+            pattern: options.pattern,
+            key_type,
+            count,
+            background,
+            done: false,
+        })
+    }
+}
+
+impl ScanCursorInterface for KeysScanCursor {
+    fn next_batch(&mut self) -> Option<Vec<Vec<u8>>> {
+        if self.done {
+            return None;
+        }
+        let _guard = if self.background {
+            Some(ThreadSafeContext::new().lock())
+        } else {
+            None
+        };
+        let ctx = get_ctx();
+        let keys = RefCell::new(Vec::new());
+        while keys.borrow().len() < self.count {
+            let scanned = self.cursor.scan(ctx, &|ctx, key_name, key| {
+                let name = key_name.as_slice();
+                if let Some(pattern) = self.pattern.as_ref() {
+                    if !glob_match(pattern, name) {
+                        return;
+                    }
+                }
+                if let Some(key_type) = self.key_type.as_ref() {
+                    let curr_type = match key {
+                        Some(k) => k.key_type(),
+                        None => ctx.open_key(&key_name).key_type(),
+                    };
+                    if curr_type != *key_type {
+                        return;
+                    }
+                }
+                keys.borrow_mut().push(name.to_vec());
+            });
+            if !scanned {
+                self.done = true;
+                break;
+            }
+        }
+        Some(keys.into_inner())
+    }
+}
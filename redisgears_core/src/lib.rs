@@ -13,44 +13,65 @@
 use redisgears_plugin_api::redisgears_plugin_api::load_library_ctx::FunctionFlags;
 use serde::{Deserialize, Serialize};
 
-use redis_module::raw::{RedisModule_GetDetachedThreadSafeContext, RedisModule__Assert};
+use redis_module::raw::{
+    RedisModuleStreamID, RedisModule_GetDetachedThreadSafeContext, RedisModule__Assert,
+};
 use threadpool::ThreadPool;
 
 use redis_module::{
     context::keys_cursor::KeysCursor, context::server_events::FlushSubevent,
     context::server_events::LoadingSubevent, context::server_events::ServerEventData,
-    context::server_events::ServerRole, context::AclPermissions, context::CallOptions,
-    raw::KeyType::Stream, redis_command, redis_event_handler, Context, InfoContext, NextArg,
-    NotifyEvent, RedisError, RedisResult, RedisString, RedisValue, Status, ThreadSafeContext,
+    context::server_events::ServerRole, context::AclPermissions, raw::KeyType::Stream,
+    redis_command, redis_event_handler, Context, InfoContext, NextArg, NotifyEvent, RedisError,
+    RedisResult, RedisString, RedisValue, Status, ThreadSafeContext,
 };
 
 use redisgears_plugin_api::redisgears_plugin_api::{
-    backend_ctx::BackendCtx, backend_ctx::BackendCtxInterface, function_ctx::FunctionCtxInterface,
+    backend_ctx::BackendCtx, backend_ctx::BackendCtxInterface,
+    channel_consumer_ctx::ChannelConsumerCtxInterface,
+    channel_consumer_ctx::ChannelConsumerOptions, channel_consumer_ctx::RegisteredChannels,
+    function_ctx::FunctionArgument, function_ctx::FunctionCtxInterface,
     keys_notifications_consumer_ctx::KeysNotificationsConsumerCtxInterface,
+    keys_notifications_consumer_ctx::KeysNotificationsConsumerOptions,
+    load_library_ctx::ExportedFunctionCtx, load_library_ctx::ImportedLibraryCtxInterface,
     load_library_ctx::LibraryCtxInterface, load_library_ctx::LoadLibraryCtxInterface,
     load_library_ctx::RegisteredKeys, load_library_ctx::RemoteFunctionCtx,
-    stream_ctx::StreamCtxInterface, CallResult, GearsApiError,
+    run_function_ctx::RemoteFunctionData, server_events_ctx::ServerEvent,
+    server_events_ctx::ServerEventConsumerCtxInterface, server_events_ctx::ServerEventType,
+    stream_ctx::StreamConsumerOptions, stream_ctx::StreamCtxInterface,
+    stream_ctx::StreamStartPosition, timer_ctx::TimerCtxInterface, timer_ctx::TimerSchedule,
+    CallResult, FunctionCallResult, GearsApiError,
 };
 
 use redisgears_plugin_api::redisgears_plugin_api::RefCellWrapper;
 
-use crate::run_ctx::RunCtx;
+use crate::run_ctx::{FunctionCallTracker, RedisClientCallOptions, RunCtx};
 
 use libloading::{Library, Symbol};
 
 use std::collections::HashMap;
 
-use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
 
 use crate::stream_reader::{ConsumerData, StreamReaderCtx};
 use std::iter::Skip;
 use std::vec::IntoIter;
 
+use crate::channel_consumers::{
+    publish_command, spublish_command, ChannelConsumer, ChannelConsumerCallback,
+    ChannelConsumersCtx, ConsumerChannel,
+};
 use crate::compiled_library_api::CompiledLibraryInternals;
+use crate::consumer_run_ctx::ConsumerRunCtx;
+use crate::declared_keys::DeclaredKeys;
+use crate::function_arguments::{verify_arguments, verify_arguments_schema};
 use crate::gears_box::{gears_box_search, GearsBoxLibraryInfo};
-use crate::keys_notifications::{KeysNotificationsCtx, NotificationCallback, NotificationConsumer};
-use crate::keys_notifications_ctx::KeysNotificationsRunCtx;
+use crate::keys_notifications::{
+    KeysNotificationsCtx, NotificationCallback, NotificationConsumer, NotificationEvents,
+};
+use crate::server_events::{ServerEventCallback, ServerEventConsumer, ServerEventsCtx};
 use crate::stream_run_ctx::{GearsStreamConsumer, GearsStreamRecord};
+use crate::timers::{schedule_timer, stop_timer, GearsTimer, TimerCallback};
 
 use crate::config::Config;
 
@@ -62,20 +83,31 @@ use crate::keys_notifications::ConsumerKey;
 
 use mr::libmr::mr_init;
 
+mod atomic_block;
 mod background_run_ctx;
 mod background_run_scope_guard;
+mod channel_consumers;
 mod compiled_library_api;
 mod config;
+mod consumer_run_ctx;
+mod declared_keys;
+mod function_arguments;
 mod function_del_command;
+mod function_dump_command;
 mod function_list_command;
 mod function_load_command;
 mod gears_box;
+mod key_ctx;
 mod keys_notifications;
-mod keys_notifications_ctx;
+mod keys_scan;
 mod rdb;
 mod run_ctx;
+mod server_events;
+mod stream_command;
+mod stream_consumer_group;
 mod stream_reader;
 mod stream_run_ctx;
+mod timers;
 
 /// GIT commit hash used for this build.
 pub const GIT_SHA: Option<&str> = std::option_env!("GIT_SHA");
@@ -129,20 +161,93 @@ pub struct GearsLibraryMetaData {
     code: String,
     config: Option<String>,
     user: String,
+    version: Option<String>,
+    description: Option<String>,
+    api_version: Option<String>,
+    min_gears_version: Option<String>,
+    requires: Option<String>,
+    library_type: Option<String>,
+}
+
+/// Runtime statistics of a single function, execution
+/// times are measured in microseconds.
+#[derive(Clone, Default)]
+pub(crate) struct FunctionStats {
+    pub(crate) num_calls: usize,
+    pub(crate) num_finished: usize,
+    pub(crate) num_errors: usize,
+    pub(crate) num_async_calls: usize,
+    pub(crate) last_error: Option<GearsApiError>,
+    pub(crate) total_execution_time: u128,
+    pub(crate) max_execution_time: u128,
 }
 
 /// The context of a single gears function.
 struct GearsFunctionCtx {
     func: Box<dyn FunctionCtxInterface>,
     flags: FunctionFlags,
+    args_schema: Option<Vec<FunctionArgument>>,
+    stats: Arc<Mutex<FunctionStats>>,
 }
 
 impl GearsFunctionCtx {
-    fn new(func: Box<dyn FunctionCtxInterface>, flags: FunctionFlags) -> GearsFunctionCtx {
-        GearsFunctionCtx { func, flags }
+    fn new(
+        func: Box<dyn FunctionCtxInterface>,
+        flags: FunctionFlags,
+        args_schema: Option<Vec<FunctionArgument>>,
+    ) -> GearsFunctionCtx {
+        GearsFunctionCtx {
+            func,
+            flags,
+            args_schema,
+            stats: Arc::new(Mutex::new(FunctionStats::default())),
+        }
     }
 }
 
+/// The settings of an upgraded stream consumer from before the upgrade,
+/// used to revert the consumer if the library upgrade fails.
+struct StreamConsumerRevertData {
+    name: String,
+    consumer: GearsStreamConsumer,
+    window: usize,
+    trim: bool,
+    options: StreamConsumerOptions,
+}
+
+/// The settings of an upgraded notifications consumer from before the upgrade,
+/// used to revert the consumer if the library upgrade fails.
+struct NotificationConsumerRevertData {
+    name: String,
+    key: ConsumerKey,
+    callback: NotificationCallback,
+    options: KeysNotificationsConsumerOptions,
+}
+
+/// The callback of an upgraded server event consumer from before the upgrade,
+/// used to revert the consumer if the library upgrade fails.
+struct ServerEventConsumerRevertData {
+    event: ServerEventType,
+    callback: ServerEventCallback,
+}
+
+/// The settings of an upgraded channel consumer from before the upgrade,
+/// used to revert the consumer if the library upgrade fails.
+struct ChannelConsumerRevertData {
+    name: String,
+    channel: ConsumerChannel,
+    callback: ChannelConsumerCallback,
+    options: ChannelConsumerOptions,
+}
+
+/// The settings of an upgraded timer from before the upgrade,
+/// used to revert the timer if the library upgrade fails.
+struct TimerRevertData {
+    name: String,
+    schedule: TimerSchedule,
+    callback: TimerCallback,
+}
+
 /// The gears library runtime context. It contains the live "instance"
 /// of the global library state: all the functions registered and other
 /// state information.
@@ -152,9 +257,19 @@ struct GearsLibraryCtx {
     remote_functions: HashMap<String, RemoteFunctionCtx>,
     stream_consumers:
         HashMap<String, Arc<RefCellWrapper<ConsumerData<GearsStreamRecord, GearsStreamConsumer>>>>,
-    revert_stream_consumers: Vec<(String, GearsStreamConsumer, usize, bool)>,
+    revert_stream_consumers: Vec<StreamConsumerRevertData>,
     notifications_consumers: HashMap<String, Arc<RefCell<NotificationConsumer>>>,
-    revert_notifications_consumers: Vec<(String, ConsumerKey, NotificationCallback)>,
+    revert_notifications_consumers: Vec<NotificationConsumerRevertData>,
+    timers: HashMap<String, Arc<RefCell<GearsTimer>>>,
+    revert_timers: Vec<TimerRevertData>,
+    server_event_consumers: HashMap<ServerEventType, Arc<RefCell<ServerEventConsumer>>>,
+    revert_server_event_consumers: Vec<ServerEventConsumerRevertData>,
+    channel_consumers: HashMap<String, Arc<RefCell<ChannelConsumer>>>,
+    revert_channel_consumers: Vec<ChannelConsumerRevertData>,
+    exported_functions: HashMap<String, ExportedFunctionCtx>,
+    /// The libraries this library requires, weakly referenced so a dependency that was
+    /// upgraded or deleted is not kept alive by a library that failed to reload.
+    dependencies: HashMap<String, Weak<GearsLibrary>>,
     old_lib: Option<Arc<GearsLibrary>>,
 }
 
@@ -209,6 +324,7 @@ impl LoadLibraryCtxInterface for GearsLibraryCtx {
         name: &str,
         function_ctx: Box<dyn FunctionCtxInterface>,
         flags: FunctionFlags,
+        args_schema: Option<Vec<FunctionArgument>>,
     ) -> Result<(), GearsApiError> {
         if self.functions.contains_key(name) {
             return Err(GearsApiError::new(format!(
@@ -216,7 +332,16 @@ impl LoadLibraryCtxInterface for GearsLibraryCtx {
                 name
             )));
         }
-        let func_ctx = GearsFunctionCtx::new(function_ctx, flags);
+        if let Some(args_schema) = args_schema.as_ref() {
+            verify_arguments_schema(args_schema).map_err(|e| {
+                GearsApiError::new(format!(
+                    "Invalid arguments schema for function {}, {}",
+                    name,
+                    e.get_msg()
+                ))
+            })?;
+        }
+        let func_ctx = GearsFunctionCtx::new(function_ctx, flags, args_schema);
         self.functions.insert(name.to_string(), func_ctx);
         Ok(())
     }
@@ -246,6 +371,7 @@ impl LoadLibraryCtxInterface for GearsLibraryCtx {
         ctx: Box<dyn StreamCtxInterface>,
         window: usize,
         trim: bool,
+        options: StreamConsumerOptions,
     ) -> Result<(), GearsApiError> {
         if self.stream_consumers.contains_key(name) {
             return Err(GearsApiError::new(
@@ -253,6 +379,15 @@ impl LoadLibraryCtxInterface for GearsLibraryCtx {
             ));
         }
 
+        if let Some(dead_letter) = options.dead_letter.as_ref() {
+            if dead_letter.starts_with(prefix) {
+                return Err(GearsApiError::new(format!(
+                    "Dead letter stream '{}' can not match the consumer prefix",
+                    std::str::from_utf8(dead_letter).unwrap_or("[binary data]")
+                )));
+            }
+        }
+
         let stream_registration = if let Some(old_consumer) = self
             .old_lib
             .as_ref()
@@ -265,6 +400,18 @@ impl LoadLibraryCtxInterface for GearsLibraryCtx {
                     name, std::str::from_utf8(&o_c.prefix).unwrap_or("[binary data]"), std::str::from_utf8(prefix).unwrap_or("[binary data]"))
                 ));
             }
+            if o_c.options.batch.is_some() != options.batch.is_some() {
+                return Err(GearsApiError::new(format!(
+                    "Can not upgrade an existing consumer with different batch mode, consumer: '{}'.",
+                    name
+                )));
+            }
+            if o_c.options.consumer_group != options.consumer_group {
+                return Err(GearsApiError::new(format!(
+                    "Can not upgrade an existing consumer with different consumer group mode, consumer: '{}'.",
+                    name
+                )));
+            }
             let old_ctx = o_c.set_consumer(GearsStreamConsumer::new(
                 &self.meta_data,
                 FunctionFlags::empty(),
@@ -272,14 +419,31 @@ impl LoadLibraryCtxInterface for GearsLibraryCtx {
             ));
             let old_window = o_c.set_window(window);
             let old_trim = o_c.set_trim(trim);
-            self.revert_stream_consumers
-                .push((name.to_string(), old_ctx, old_window, old_trim));
+            let old_options = o_c.set_options(options);
+            self.revert_stream_consumers.push(StreamConsumerRevertData {
+                name: name.to_string(),
+                consumer: old_ctx,
+                window: old_window,
+                trim: old_trim,
+                options: old_options,
+            });
             Arc::clone(old_consumer)
         } else {
             let globals = get_globals_mut();
             let stream_ctx = &mut globals.stream_ctx;
             let lib_name = self.meta_data.name.clone();
             let consumer_name = name.to_string();
+            let failed_record_consumer_name = format!("{}.{}", lib_name, name);
+            let lib_user = self.meta_data.user.clone();
+            let start_from_now = options.start_position == StreamStartPosition::Now;
+            let consumer_group = if options.consumer_group {
+                Some(stream_consumer_group::consumer_group_callbacks(
+                    &lib_user,
+                    &failed_record_consumer_name,
+                ))
+            } else {
+                None
+            };
             let consumer = stream_ctx.add_consumer(
                 prefix,
                 GearsStreamConsumer::new(&self.meta_data, FunctionFlags::empty(), ctx),
@@ -298,7 +462,24 @@ impl LoadLibraryCtxInterface for GearsLibraryCtx {
                         ],
                     );
                 })),
+                options,
+                Some(Box::new(move |dead_letter, stream_name, id, error| {
+                    send_to_dead_letter(
+                        &lib_user,
+                        &failed_record_consumer_name,
+                        dead_letter,
+                        stream_name,
+                        id,
+                        error,
+                    );
+                })),
+                consumer_group,
             );
+            if start_from_now && get_ctx().is_primary() && !is_loading() {
+                // while loading the positions are taken from the rdb and
+                // replicas get them from the primary as records are processed.
+                set_consumer_position_to_streams_end(&consumer);
+            }
             if get_ctx().is_primary() {
                 // trigger a key scan
                 scan_key_space_for_streams();
@@ -316,6 +497,7 @@ impl LoadLibraryCtxInterface for GearsLibraryCtx {
         name: &str,
         key: RegisteredKeys,
         keys_notifications_consumer_ctx: Box<dyn KeysNotificationsConsumerCtxInterface>,
+        options: KeysNotificationsConsumerOptions,
     ) -> Result<(), GearsApiError> {
         if self.notifications_consumers.contains_key(name) {
             return Err(GearsApiError::new(
@@ -327,7 +509,7 @@ impl LoadLibraryCtxInterface for GearsLibraryCtx {
         let mut permissions = AclPermissions::new();
         permissions.add_full_permission();
         let fire_event_callback: NotificationCallback =
-            Box::new(move |event, key, done_callback| {
+            Box::new(move |events, key, done_callback| {
                 let key_redis_str = RedisString::create_from_slice(std::ptr::null_mut(), key);
                 if let Err(e) = get_ctx().acl_check_key_permission(
                     &meta_data.user,
@@ -343,17 +525,24 @@ impl LoadLibraryCtxInterface for GearsLibraryCtx {
                     return;
                 }
                 let _notification_blocker = get_notification_blocker();
-                let val = keys_notifications_consumer_ctx.on_notification_fired(
-                    event,
-                    key,
-                    Box::new(KeysNotificationsRunCtx::new(
-                        meta_data.clone(),
-                        FunctionFlags::empty(),
-                    )),
-                );
+                let run_ctx = Box::new(ConsumerRunCtx::new(
+                    meta_data.clone(),
+                    FunctionFlags::empty(),
+                ));
+                let val = match events {
+                    NotificationEvents::Single(event) => {
+                        keys_notifications_consumer_ctx.on_notification_fired(event, key, run_ctx)
+                    }
+                    NotificationEvents::Coalesced {
+                        events,
+                        last_event,
+                        count,
+                    } => keys_notifications_consumer_ctx
+                        .on_coalesced_notifications_fired(events, last_event, count, key, run_ctx),
+                };
                 keys_notifications_consumer_ctx.post_command_notification(
                     val,
-                    Box::new(KeysNotificationsRunCtx::new(
+                    Box::new(ConsumerRunCtx::new(
                         meta_data.clone(),
                         FunctionFlags::empty(),
                     )),
@@ -366,36 +555,272 @@ impl LoadLibraryCtxInterface for GearsLibraryCtx {
             .as_ref()
             .and_then(|v| v.gears_lib_ctx.notifications_consumers.get(name))
         {
-            let mut o_c = old_notification_consumer.borrow_mut();
-            let old_consumer_callback = o_c.set_callback(fire_event_callback);
-            let new_key = match key {
-                RegisteredKeys::Key(s) => ConsumerKey::Key(s.to_vec()),
-                RegisteredKeys::Prefix(s) => ConsumerKey::Prefix(s.to_vec()),
+            let (old_callback, old_options) = {
+                let mut o_c = old_notification_consumer.borrow_mut();
+                (
+                    o_c.set_callback(fire_event_callback),
+                    o_c.set_options(options),
+                )
             };
-            let old_key = o_c.set_key(new_key);
-            self.revert_notifications_consumers.push((
-                name.to_string(),
-                old_key,
-                old_consumer_callback,
-            ));
+            let old_key = get_globals_mut()
+                .notifications_ctx
+                .set_consumer_key(old_notification_consumer, ConsumerKey::from(key));
+            self.revert_notifications_consumers
+                .push(NotificationConsumerRevertData {
+                    name: name.to_string(),
+                    key: old_key,
+                    callback: old_callback,
+                    options: old_options,
+                });
             Arc::clone(old_notification_consumer)
         } else {
-            let globals = get_globals_mut();
-
-            match key {
-                RegisteredKeys::Key(k) => globals
-                    .notifications_ctx
-                    .add_consumer_on_key(k, fire_event_callback),
-                RegisteredKeys::Prefix(p) => globals
-                    .notifications_ctx
-                    .add_consumer_on_prefix(p, fire_event_callback),
-            }
+            get_globals_mut().notifications_ctx.add_consumer(
+                ConsumerKey::from(key),
+                fire_event_callback,
+                options,
+            )
         };
 
         self.notifications_consumers
             .insert(name.to_string(), consumer);
         Ok(())
     }
+
+    fn register_timer(
+        &mut self,
+        name: &str,
+        schedule: TimerSchedule,
+        timer_ctx: Box<dyn TimerCtxInterface>,
+    ) -> Result<(), GearsApiError> {
+        if self.timers.contains_key(name) {
+            return Err(GearsApiError::new(format!("Timer {} already exists", name)));
+        }
+
+        let meta_data = Arc::clone(&self.meta_data);
+        let callback: TimerCallback = Box::new(move |done_callback| {
+            timer_ctx.on_timer_fired(
+                Box::new(ConsumerRunCtx::new(
+                    Arc::clone(&meta_data),
+                    FunctionFlags::empty(),
+                )),
+                done_callback,
+            )
+        });
+
+        let (timer, reschedule) = if let Some(old_timer) = self
+            .old_lib
+            .as_ref()
+            .and_then(|v| v.gears_lib_ctx.timers.get(name))
+        {
+            let old_schedule = old_timer.borrow_mut().set_schedule(schedule)?;
+            let old_callback = old_timer.borrow_mut().set_callback(callback);
+            // keep the timer running on its current schedule if it was not changed
+            let reschedule = *old_timer.borrow().get_schedule() != old_schedule;
+            self.revert_timers.push(TimerRevertData {
+                name: name.to_string(),
+                schedule: old_schedule,
+                callback: old_callback,
+            });
+            (Arc::clone(old_timer), reschedule)
+        } else {
+            (
+                Arc::new(RefCell::new(GearsTimer::new(schedule, callback)?)),
+                true,
+            )
+        };
+
+        if reschedule && get_ctx().is_primary() {
+            schedule_timer(&timer);
+        }
+        self.timers.insert(name.to_string(), timer);
+        Ok(())
+    }
+
+    fn register_server_event_consumer(
+        &mut self,
+        event: ServerEventType,
+        server_event_consumer_ctx: Box<dyn ServerEventConsumerCtxInterface>,
+    ) -> Result<(), GearsApiError> {
+        if self.server_event_consumers.contains_key(&event) {
+            return Err(GearsApiError::new(format!(
+                "Server event consumer for '{}' already exists",
+                event.as_str()
+            )));
+        }
+
+        let meta_data = Arc::clone(&self.meta_data);
+        let callback: ServerEventCallback = Box::new(move |event, done_callback| {
+            server_event_consumer_ctx.on_server_event(
+                event,
+                Box::new(ConsumerRunCtx::new(
+                    Arc::clone(&meta_data),
+                    FunctionFlags::empty(),
+                )),
+                done_callback,
+            )
+        });
+
+        let consumer = if let Some(old_consumer) = self
+            .old_lib
+            .as_ref()
+            .and_then(|v| v.gears_lib_ctx.server_event_consumers.get(&event))
+        {
+            let old_callback = old_consumer.borrow_mut().set_callback(callback);
+            self.revert_server_event_consumers
+                .push(ServerEventConsumerRevertData {
+                    event,
+                    callback: old_callback,
+                });
+            Arc::clone(old_consumer)
+        } else {
+            get_globals_mut()
+                .server_events_ctx
+                .add_consumer(event, callback)
+        };
+
+        self.server_event_consumers.insert(event, consumer);
+        Ok(())
+    }
+
+    fn register_channel_consumer(
+        &mut self,
+        name: &str,
+        channel: RegisteredChannels,
+        channel_consumer_ctx: Box<dyn ChannelConsumerCtxInterface>,
+        options: ChannelConsumerOptions,
+    ) -> Result<(), GearsApiError> {
+        channel_consumers::verify_channel_consumers_supported()?;
+        if self.channel_consumers.contains_key(name) {
+            return Err(GearsApiError::new(format!(
+                "Channel consumer {} already exists",
+                name
+            )));
+        }
+
+        let meta_data = Arc::clone(&self.meta_data);
+        let callback: ChannelConsumerCallback = Box::new(move |channel, message, done_callback| {
+            channel_consumer_ctx.on_message(
+                channel,
+                message,
+                Box::new(ConsumerRunCtx::new(
+                    Arc::clone(&meta_data),
+                    FunctionFlags::empty(),
+                )),
+                done_callback,
+            )
+        });
+
+        let consumer = if let Some(old_consumer) = self
+            .old_lib
+            .as_ref()
+            .and_then(|v| v.gears_lib_ctx.channel_consumers.get(name))
+        {
+            let mut c = old_consumer.borrow_mut();
+            let old_channel = c.set_channel(channel.into());
+            let old_callback = c.set_callback(callback);
+            let old_options = c.set_options(options);
+            self.revert_channel_consumers
+                .push(ChannelConsumerRevertData {
+                    name: name.to_string(),
+                    channel: old_channel,
+                    callback: old_callback,
+                    options: old_options,
+                });
+            Arc::clone(old_consumer)
+        } else {
+            get_globals_mut()
+                .channel_consumers_ctx
+                .add_consumer(channel.into(), callback, options)
+        };
+
+        self.channel_consumers.insert(name.to_string(), consumer);
+        Ok(())
+    }
+
+    fn register_exported_function(
+        &mut self,
+        name: &str,
+        exported_function_ctx: ExportedFunctionCtx,
+    ) -> Result<(), GearsApiError> {
+        if self.exported_functions.contains_key(name) {
+            return Err(GearsApiError::new(format!(
+                "Exported function {} already exists",
+                name
+            )));
+        }
+        self.exported_functions
+            .insert(name.to_string(), exported_function_ctx);
+        Ok(())
+    }
+
+    fn import_library(
+        &mut self,
+        name: &str,
+    ) -> Result<Box<dyn ImportedLibraryCtxInterface>, GearsApiError> {
+        let lib = self.dependencies.get(name).ok_or_else(|| {
+            GearsApiError::new(format!(
+                "Library {} was not declared on the library 'requires' property",
+                name
+            ))
+        })?;
+        Ok(Box::new(GearsImportedLibrary {
+            name: name.to_string(),
+            lib: Weak::clone(lib),
+        }))
+    }
+}
+
+/// A library imported by another library, weakly references the imported library
+/// instance. When the imported library is upgraded, the importing library is reloaded
+/// to use the new version; if the reload fails, the importing library can no longer
+/// call the functions of the old version.
+struct GearsImportedLibrary {
+    name: String,
+    lib: Weak<GearsLibrary>,
+}
+
+impl GearsImportedLibrary {
+    fn get_lib(&self) -> Result<Arc<GearsLibrary>, GearsApiError> {
+        self.lib.upgrade().ok_or_else(|| {
+            GearsApiError::new(format!(
+                "Library {} was upgraded or deleted, the library must be reloaded in order to use it",
+                self.name
+            ))
+        })
+    }
+}
+
+impl ImportedLibraryCtxInterface for GearsImportedLibrary {
+    fn get_exported_functions(&self) -> Vec<String> {
+        self.get_lib()
+            .map(|lib| {
+                lib.gears_lib_ctx
+                    .exported_functions
+                    .keys()
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn call_exported_function(
+        &self,
+        name: &str,
+        args: Vec<RemoteFunctionData>,
+    ) -> Result<RemoteFunctionData, GearsApiError> {
+        let lib = self.get_lib()?;
+        let exported_function =
+            lib.gears_lib_ctx
+                .exported_functions
+                .get(name)
+                .ok_or_else(|| {
+                    GearsApiError::new(format!(
+                        "Library {} does not export a function named {}",
+                        self.name, name
+                    ))
+                })?;
+        exported_function(args)
+    }
 }
 
 struct GlobalCtx {
@@ -408,6 +833,8 @@ struct GlobalCtx {
     mgmt_pool: ThreadPool,
     stream_ctx: StreamReaderCtx<GearsStreamRecord, GearsStreamConsumer>,
     notifications_ctx: KeysNotificationsCtx,
+    server_events_ctx: ServerEventsCtx,
+    channel_consumers_ctx: ChannelConsumersCtx,
     config: Config,
     avoid_key_space_notifications: bool,
     allow_unsafe_redis_commands: bool,
@@ -482,9 +909,19 @@ pub(crate) fn execute_on_pool<F: FnOnce() + Send + 'static>(job: F) {
 pub(crate) fn call_redis_command(
     user: Option<&String>,
     command: &str,
-    call_options: &CallOptions,
+    call_options: &RedisClientCallOptions,
     args: &[&[u8]],
 ) -> CallResult {
+    if let Some(declared_keys) = call_options.declared_keys.as_ref() {
+        if let Err(e) = declared_keys.verify_command(command, args) {
+            return CallResult::Error(e);
+        }
+    }
+    if let Some(undo_log) = call_options.undo_log.as_ref() {
+        if let Err(e) = undo_log.record_command(command, args) {
+            return CallResult::Error(e);
+        }
+    }
     let ctx = match user {
         Some(u) => {
             let ctx = &get_globals().authenticated_redis_ctx;
@@ -495,7 +932,7 @@ pub(crate) fn call_redis_command(
         }
         None => get_ctx(),
     };
-    let res = ctx.call_ext(command, call_options, args);
+    let res = ctx.call_ext(command, &call_options.call_options, args);
     match res {
         Ok(r) => redis_value_to_call_reply(r),
         Err(e) => match e {
@@ -647,8 +1084,16 @@ fn js_init(ctx: &Context, args: &[RedisString]) -> Status {
                         );
                     }
                 }),
+                Box::new(|delay, job| {
+                    // scheduled jobs are executed on the main thread, same as the stream notifications.
+                    get_ctx().create_timer(delay, |_ctx, job: Box<dyn FnOnce()>| job(), job);
+                }),
             ),
-            notifications_ctx: KeysNotificationsCtx::new(),
+            notifications_ctx: KeysNotificationsCtx::new(Box::new(|delay, job| {
+                get_ctx().create_timer(delay, |_ctx, job: Box<dyn FnOnce()>| job(), job);
+            })),
+            server_events_ctx: ServerEventsCtx::new(),
+            channel_consumers_ctx: ChannelConsumersCtx::new(),
             config: Config::new(),
             avoid_key_space_notifications: false,
             allow_unsafe_redis_commands: false,
@@ -717,6 +1162,8 @@ fn js_init(ctx: &Context, args: &[RedisString]) -> Status {
         global_ctx.plugins.push(lib);
 
         GLOBALS = Some(global_ctx);
+
+        server_events::subscribe_to_server_events(ctx);
     }
     Status::Ok
 }
@@ -782,16 +1229,31 @@ fn function_call_command(
             args.len()
         )));
     }
+    if let Some(args_schema) = function.args_schema.as_ref() {
+        verify_arguments(args_schema, &args)?;
+    }
+    let declared_keys = if function.flags.contains(FunctionFlags::STRICT_KEYS) {
+        Some(Arc::new(
+            DeclaredKeys::new(&args[..num_keys]).map_err(RedisError::String)?,
+        ))
+    } else {
+        None
+    };
     let args_iter = args.iter();
 
     {
         let _notification_blocker = get_notification_blocker();
-        function.func.call(&mut RunCtx {
+        let res = function.func.call(&mut RunCtx {
             ctx,
             iter: args_iter,
             flags: function.flags,
             lib_meta_data: Arc::clone(&lib.gears_lib_ctx.meta_data),
+            call_tracker: FunctionCallTracker::new(&function.stats),
+            declared_keys,
         });
+        if let FunctionCallResult::Hold = res {
+            function.stats.lock().unwrap().num_async_calls += 1;
+        }
     }
 
     Ok(RedisValue::NoReply)
@@ -814,6 +1276,38 @@ fn function_call_result_to_redis_result(res: CallResult) -> RedisValue {
     }
 }
 
+fn function_stats_command(
+    _ctx: &Context,
+    mut args: Skip<IntoIter<redis_module::RedisString>>,
+) -> RedisResult {
+    let sub_command = args.next_arg()?.try_as_str()?.to_lowercase();
+    if sub_command != "reset" {
+        return Err(RedisError::String(format!(
+            "Unknown subcommand {}",
+            sub_command
+        )));
+    }
+    let library_name = match args.next_arg() {
+        Ok(n) => Some(n.try_as_str()?),
+        Err(_) => None,
+    };
+    let libraries = get_libraries();
+    if let Some(name) = library_name {
+        if !libraries.contains_key(name) {
+            return Err(RedisError::String(format!("Unknown library {}", name)));
+        }
+    }
+    libraries
+        .values()
+        .filter(|l| match library_name {
+            Some(name) => l.gears_lib_ctx.meta_data.name == name,
+            None => true,
+        })
+        .flat_map(|l| l.gears_lib_ctx.functions.values())
+        .for_each(|f| *f.stats.lock().unwrap() = FunctionStats::default());
+    Ok(RedisValue::SimpleStringStatic("OK"))
+}
+
 fn function_debug_command(
     _ctx: &Context,
     mut args: Skip<IntoIter<redis_module::RedisString>>,
@@ -921,6 +1415,8 @@ fn function_command_on_replica(ctx: &Context, args: Vec<RedisString>) -> RedisRe
     match sub_command.as_ref() {
         "load" => function_load_command::function_load_on_replica(ctx, args),
         "del" => function_del_command::function_del_on_replica(ctx, args),
+        "flush" => function_del_command::function_flush_on_replica(ctx, args),
+        "restore" => function_dump_command::function_restore_on_replica(ctx, args),
         _ => Err(RedisError::String(format!(
             "Unknown subcommand {}",
             sub_command
@@ -935,6 +1431,10 @@ fn function_command(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         "load" => function_load_command::function_load_command(ctx, args),
         "list" => function_list_command::function_list_command(ctx, args),
         "del" => function_del_command::function_del_command(ctx, args),
+        "flush" => function_del_command::function_flush_command(ctx, args),
+        "dump" => function_dump_command::function_dump_command(ctx, args),
+        "restore" => function_dump_command::function_restore_command(ctx, args),
+        "stats" => function_stats_command(ctx, args),
         "debug" => function_debug_command(ctx, args),
         _ => Err(RedisError::String(format!(
             "Unknown subcommand {}",
@@ -966,6 +1466,21 @@ fn config_command(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     }
 }
 
+fn stream_command(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let sub_command = args.next_arg()?.try_as_str()?.to_lowercase();
+    stream_command::verify_stream_state_change_allowed(ctx)?;
+    match sub_command.as_ref() {
+        "setid" => stream_command::stream_setid_command(ctx, args),
+        "pause" => stream_command::stream_pause_command(ctx, args),
+        "resume" => stream_command::stream_resume_command(ctx, args),
+        _ => Err(RedisError::String(format!(
+            "Unknown subcommand {}",
+            sub_command
+        ))),
+    }
+}
+
 fn gears_box_command(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     let mut args = args.into_iter().skip(1);
     let sub_command = args.next_arg()?.try_as_str()?.to_lowercase();
@@ -979,7 +1494,61 @@ fn gears_box_command(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     }
 }
 
+/// Add a record that failed processing to the consumer dead letter stream,
+/// alongside the error, the consumer name and the original stream and id.
+/// The record is added using the library user so ACL is verified.
+fn send_to_dead_letter(
+    user: &String,
+    consumer_name: &str,
+    dead_letter: &[u8],
+    stream_name: &[u8],
+    id: RedisModuleStreamID,
+    error: &GearsApiError,
+) {
+    let ctx = get_ctx();
+    let stream_name_redis_str = ctx.create_string_from_slice(stream_name);
+    let key = ctx.open_key(&stream_name_redis_str);
+    let record = key
+        .get_stream_range_iterator(Some(id), Some(id), false)
+        .ok()
+        .and_then(|mut iter| iter.next());
+    let record_id = format!("{}-{}", id.ms, id.seq);
+    let mut args: Vec<&[u8]> = vec![
+        dead_letter,
+        b"*",
+        b"error",
+        error.get_msg().as_bytes(),
+        b"consumer",
+        consumer_name.as_bytes(),
+        b"stream",
+        stream_name,
+        b"id",
+        record_id.as_bytes(),
+    ];
+    if let Some(record) = record.as_ref() {
+        for (field, val) in record.fields.iter() {
+            args.push(field.as_slice());
+            args.push(val.as_slice());
+        }
+    }
+    let call_options = RedisClientCallOptions::new(FunctionFlags::empty());
+    if let CallResult::Error(e) = call_redis_command(Some(user), "xadd", &call_options, &args) {
+        ctx.log_warning(&format!(
+            "Failed adding record {} of stream '{}' to dead letter stream '{}', {}",
+            record_id,
+            std::str::from_utf8(stream_name).unwrap_or("[binary data]"),
+            std::str::from_utf8(dead_letter).unwrap_or("[binary data]"),
+            e
+        ));
+    }
+}
+
 fn on_stream_touched(_ctx: &Context, _event_type: NotifyEvent, event: &str, key: &[u8]) {
+    if event.starts_with("xgroup-") || event == "xclaim" || event == "xautoclaim" {
+        // consumer groups changes do not add new records, those events are also
+        // raised by the consumers that reflect their progress on a consumer group.
+        return;
+    }
     if get_ctx().is_primary() {
         let stream_ctx = &mut get_globals_mut().stream_ctx;
         stream_ctx.on_stream_touched(event, key);
@@ -993,12 +1562,94 @@ fn generic_notification(_ctx: &Context, _event_type: NotifyEvent, event: &str, k
     }
 }
 
-fn key_space_notification(_ctx: &Context, _event_type: NotifyEvent, event: &str, key: &[u8]) {
+fn key_space_notification(_ctx: &Context, event_type: NotifyEvent, event: &str, key: &[u8]) {
     let globals = get_globals();
     if globals.avoid_key_space_notifications {
         return;
     }
-    globals.notifications_ctx.on_key_touched(event, key)
+    globals
+        .notifications_ctx
+        .on_key_touched(event_type, event, key)
+}
+
+/// Returns the id of the last record on the given stream,
+/// `None` if the stream does not exists or empty.
+pub(crate) fn get_stream_last_id(stream_name: &[u8]) -> Option<RedisModuleStreamID> {
+    let call_options = RedisClientCallOptions::new(FunctionFlags::empty());
+    let res = call_redis_command(
+        None,
+        "xrevrange",
+        &call_options,
+        &[stream_name, b"+", b"-", b"COUNT", b"1"],
+    );
+    match res {
+        CallResult::Array(records) => match records.first() {
+            Some(CallResult::Array(record)) => {
+                stream_consumer_group::call_result_to_stream_id(record.first()?)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns `true` if Redis is currently loading data (rdb, aof or replication).
+fn is_loading() -> bool {
+    let flags = unsafe { redis_module::raw::RedisModule_GetContextFlags.unwrap()(get_ctx().ctx) };
+    flags & redis_module::raw::REDISMODULE_CTX_FLAGS_LOADING as std::os::raw::c_int != 0
+}
+
+/// Set the position of a newly registered consumer to the last id of all
+/// the existing streams that match its prefix so only new records will be processed.
+fn set_consumer_position_to_streams_end(
+    consumer: &Arc<RefCellWrapper<ConsumerData<GearsStreamRecord, GearsStreamConsumer>>>,
+) {
+    let prefix = consumer.ref_cell.borrow().prefix.clone();
+    let cursor = KeysCursor::new();
+    let ctx = get_ctx();
+    loop {
+        let scanned = cursor.scan(ctx, &|ctx, key_name, key| {
+            let stream_name = key_name.as_slice();
+            if !stream_name.starts_with(&prefix) {
+                return;
+            }
+            let key_type = match key {
+                Some(k) => k.key_type(),
+                None => ctx.open_key(&key_name).key_type(),
+            };
+            if key_type != Stream {
+                return;
+            }
+            if let Some(id) = get_stream_last_id(stream_name) {
+                get_globals_mut().stream_ctx.update_stream_for_consumer(
+                    stream_name,
+                    consumer,
+                    id.ms,
+                    id.seq,
+                );
+            }
+        });
+        if !scanned {
+            break;
+        }
+    }
+}
+
+/// Find a stream consumer by its library and consumer name.
+pub(crate) fn get_stream_consumer<'a>(
+    libraries: &'a HashMap<String, Arc<GearsLibrary>>,
+    library_name: &str,
+    consumer_name: &str,
+) -> Result<&'a Arc<RefCellWrapper<ConsumerData<GearsStreamRecord, GearsStreamConsumer>>>, RedisError>
+{
+    let library = libraries
+        .get(library_name)
+        .ok_or_else(|| RedisError::String(format!("No such library '{}'", library_name)))?;
+    library
+        .gears_lib_ctx
+        .stream_consumers
+        .get(consumer_name)
+        .ok_or_else(|| RedisError::String(format!("No such consumer '{}'", consumer_name)))
 }
 
 fn update_stream_last_read_id(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
@@ -1010,22 +1661,7 @@ fn update_stream_last_read_id(ctx: &Context, args: Vec<RedisString>) -> RedisRes
     let ms = args.next_arg()?.try_as_str()?.parse::<u64>()?;
     let seq = args.next_arg()?.try_as_str()?.parse::<u64>()?;
     let libraries = get_libraries();
-    let library = libraries.get(library_name);
-    if library.is_none() {
-        return Err(RedisError::String(format!(
-            "No such library '{}'",
-            library_name
-        )));
-    }
-    let library = library.unwrap();
-    let consumer = library.gears_lib_ctx.stream_consumers.get(stream_consumer);
-    if consumer.is_none() {
-        return Err(RedisError::String(format!(
-            "No such consumer '{}'",
-            stream_consumer
-        )));
-    }
-    let consumer = consumer.unwrap();
+    let consumer = get_stream_consumer(&libraries, library_name, stream_consumer)?;
     get_globals_mut()
         .stream_ctx
         .update_stream_for_consumer(stream, consumer, ms, seq);
@@ -1066,7 +1702,21 @@ fn on_role_changed(ctx: &Context, event_data: ServerEventData) {
                     "Role changed to primary, initializing key scan to search for streams.",
                 );
                 scan_key_space_for_streams();
+                ctx.log_notice("Role changed to primary, starting timers.");
+                for lib in get_libraries().values() {
+                    lib.gears_lib_ctx.timers.values().for_each(schedule_timer);
+                }
+            } else {
+                ctx.log_notice("Role changed to replica, stopping timers.");
+                for lib in get_libraries().values() {
+                    lib.gears_lib_ctx.timers.values().for_each(stop_timer);
+                }
             }
+            get_globals()
+                .server_events_ctx
+                .on_server_event(ServerEvent::RoleChange {
+                    is_primary: matches!(role_changed.role, ServerRole::Primary),
+                });
         }
         _ => panic!("got unexpected sub event"),
     }
@@ -1085,7 +1735,15 @@ fn on_loading_event(ctx: &Context, event_data: ServerEventData) {
                     globals.libraries.lock().unwrap().clear();
                     globals.stream_ctx.clear();
                 }
-                _ => {}
+                LoadingSubevent::Ended => {
+                    // loading has finished, the libraries were loaded along with the data
+                    get_globals()
+                        .server_events_ctx
+                        .on_server_event(ServerEvent::Loading);
+                }
+                LoadingSubevent::Failed => {
+                    ctx.log_warning("Got a loading failed event.");
+                }
             }
         }
         _ => panic!("got unexpected sub event"),
@@ -1098,6 +1756,9 @@ fn on_module_change(ctx: &Context, _event_data: ServerEventData) {
         Ok(_) => ctx.log_notice("RedisAI API was loaded successfully."),
         Err(_) => ctx.log_notice("Failed loading RedisAI API."),
     }
+    get_globals()
+        .server_events_ctx
+        .on_server_event(ServerEvent::ModuleChange);
 }
 
 fn on_flush_event(ctx: &Context, event_data: ServerEventData) {
@@ -1113,6 +1774,10 @@ fn on_flush_event(ctx: &Context, event_data: ServerEventData) {
                     }
                 }
                 globals.stream_ctx.clear_tracked_streams();
+                // the consumers are invoked after the flush is done
+                globals
+                    .server_events_ctx
+                    .on_server_event(ServerEvent::Flush);
             }
         }
         _ => panic!("got unexpected sub event"),
@@ -1144,7 +1809,10 @@ mod gears_module {
             ["rg.fcall_no_keys", function_call, "may-replicate deny-script", 0,0,0],
             ["rg.box", gears_box_command, "may-replicate deny-script", 0,0,0],
             ["rg.config", config_command, "readonly deny-script", 0,0,0],
+            ["rg.stream", stream_command, "may-replicate deny-script", 4,4,1],
             ["_rg_internals.update_stream_last_read_id", update_stream_last_read_id, "readonly", 0,0,0],
+            ["_rg_internals.publish", publish_command, "pubsub may-replicate allow-loading allow-stale fast", 0,0,0],
+            ["_rg_internals.spublish", spublish_command, "pubsub may-replicate allow-loading allow-stale fast", 1,1,1],
         ],
         event_handlers: [
             [@STREAM: on_stream_touched],
@@ -1165,6 +1833,7 @@ mod gears_module {
             &get_globals().config.library_maxmemory,
             &get_globals().config.lock_regis_timeout,
             &get_globals().config.remote_task_default_timeout,
+            &get_globals().config.timer_run_timeout,
             &get_globals().config.error_verbosity,
         ],
         enum_configurations: [
@@ -5,7 +5,8 @@
  */
 
 use crate::{
-    function_load_command::function_load_intrernal, get_ctx, get_globals_mut, get_libraries,
+    function_load_command::function_load_intrernal,
+    function_load_command::libraries_in_dependency_order, get_ctx, get_globals_mut, get_libraries,
 };
 
 use redis_module::{
@@ -15,7 +16,7 @@ use redis_module::{
 
 use std::os::raw::c_int;
 
-pub(crate) static REDIS_GEARS_VERSION: i32 = 1;
+pub(crate) static REDIS_GEARS_VERSION: i32 = 2;
 pub(crate) static REDIS_GEARS_TYPE: RedisType = RedisType::new(
     "GearsType",
     REDIS_GEARS_VERSION,
@@ -52,7 +53,8 @@ extern "C" fn aux_save(rdb: *mut raw::RedisModuleIO, _when: c_int) {
     // save the number of libraries
     raw::save_unsigned(rdb, libraries.len() as u64);
 
-    for val in libraries.values() {
+    // save the libraries such that dependencies are loaded before the libraries using them
+    for val in libraries_in_dependency_order(&libraries) {
         raw::save_string(rdb, &val.gears_lib_ctx.meta_data.name);
         raw::save_string(rdb, &val.gears_lib_ctx.meta_data.code);
         raw::save_string(rdb, &val.gears_lib_ctx.meta_data.user);
@@ -73,9 +75,10 @@ extern "C" fn aux_save(rdb: *mut raw::RedisModuleIO, _when: c_int) {
         for (name, stream_consumer) in val.gears_lib_ctx.stream_consumers.iter() {
             // save the consumer name
             raw::save_string(rdb, name);
+            let stream_consumer = stream_consumer.ref_cell.borrow();
+            // save the consumer paused state
+            raw::save_unsigned(rdb, stream_consumer.paused as u64);
             let streams_info = stream_consumer
-                .ref_cell
-                .borrow()
                 .get_streams_info()
                 .collect::<Vec<(Vec<u8>, u64, u64)>>();
             // save the number of streams for this consumer
@@ -91,7 +94,7 @@ extern "C" fn aux_save(rdb: *mut raw::RedisModuleIO, _when: c_int) {
     }
 }
 
-fn aux_load_internals(rdb: *mut raw::RedisModuleIO) -> Result<(), Error> {
+fn aux_load_internals(rdb: *mut raw::RedisModuleIO, encver: c_int) -> Result<(), Error> {
     let num_of_libs = raw::load_unsigned(rdb)?;
 
     for _ in 0..num_of_libs {
@@ -156,7 +159,7 @@ fn aux_load_internals(rdb: *mut raw::RedisModuleIO) -> Result<(), Error> {
             None
         };
 
-        match function_load_intrernal(user, &code, config, false, gears_box_info) {
+        match function_load_intrernal(user, &code, config, false, gears_box_info, false) {
             Ok(_) => {}
             Err(e) => return Err(Error::generic(&format!("Failed loading librart, {}", e))),
         }
@@ -190,6 +193,16 @@ fn aux_load_internals(rdb: *mut raw::RedisModuleIO) -> Result<(), Error> {
                 .stream_consumers
                 .get(&consumer_name)
                 .unwrap();
+            // consumer paused state, available starting from version 2
+            if encver >= 2 {
+                let paused = raw::load_unsigned(rdb).map_err(|e| {
+                    Error::generic(&format!(
+                        "Failed loading paused state for a consumer '{}', {}.",
+                        consumer_name, e
+                    ))
+                })?;
+                consumer.ref_cell.borrow_mut().set_paused(paused > 0);
+            }
             // read the number of streams for this consumer
             let num_of_streams = raw::load_unsigned(rdb).map_err(|e| {
                 Error::generic(&format!(
@@ -238,7 +251,7 @@ unsafe extern "C" fn aux_load(rdb: *mut raw::RedisModuleIO, encver: c_int, _when
         return raw::REDISMODULE_ERR as i32;
     }
 
-    match aux_load_internals(rdb) {
+    match aux_load_internals(rdb, encver) {
         Ok(_) => raw::REDISMODULE_OK as i32,
         Err(e) => {
             get_ctx().log_warning(&format!("Failed loading functions from rdb, {}.", e));
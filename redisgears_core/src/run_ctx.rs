@@ -6,27 +6,38 @@
 
 use redis_module::{
     context::{CallOptions, CallOptionsBuilder},
-    Context, ThreadSafeContext,
+    raw, Context, ThreadSafeContext,
 };
 
 use redisgears_plugin_api::redisgears_plugin_api::{
-    load_library_ctx::FunctionFlags, redisai_interface::AIModelInterface,
+    key_ctx::KeyCtxInterface, load_library_ctx::FunctionFlags, redisai_interface::AIModelInterface,
     redisai_interface::AIScriptInterface, run_function_ctx::BackgroundRunFunctionCtxInterface,
     run_function_ctx::RedisClientCtxInterface, run_function_ctx::ReplyCtxInterface,
-    run_function_ctx::RunFunctionCtxInterface, CallResult, GearsApiError,
+    run_function_ctx::RunFunctionCtxInterface, run_function_ctx::ScanCursorInterface,
+    run_function_ctx::ScanOptions, CallResult, GearsApiError,
 };
 
 use redis_module::Status;
 
-use crate::{call_redis_command, get_globals, get_msg_verbose, GearsLibraryMetaData};
+use crate::{
+    call_redis_command, get_globals, get_msg_verbose, FunctionStats, GearsLibraryMetaData,
+};
 
+use std::ffi::CString;
+use std::os::raw::{c_int, c_long};
 use std::slice::Iter;
 
+use crate::atomic_block::UndoLog;
 use crate::background_run_ctx::BackgroundRunCtx;
+use crate::declared_keys::DeclaredKeys;
+use crate::key_ctx::GearsKeyCtx;
+use crate::keys_scan::KeysScanCursor;
 
 use crate::get_ctx;
 
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use redisai_rs::redisai::redisai_model::RedisAIModel;
 use redisai_rs::redisai::redisai_script::RedisAIScript;
@@ -35,6 +46,10 @@ use redisai_rs::redisai::redisai_script::RedisAIScript;
 pub(crate) struct RedisClientCallOptions {
     pub(crate) call_options: CallOptions,
     pub(crate) flags: FunctionFlags,
+    /// The keys the commands are allowed to access, `None` if any key can be accessed.
+    pub(crate) declared_keys: Option<Arc<DeclaredKeys>>,
+    /// Records the keys state before they are accessed, `None` if not inside an atomic block.
+    pub(crate) undo_log: Option<Arc<UndoLog>>,
 }
 
 impl RedisClientCallOptions {
@@ -58,6 +73,8 @@ impl RedisClientCallOptions {
         RedisClientCallOptions {
             call_options: call_options.constract(),
             flags,
+            declared_keys: None,
+            undo_log: None,
         }
     }
 }
@@ -83,12 +100,28 @@ impl RedisClient {
             user,
         }
     }
+
+    pub(crate) fn with_call_options(
+        lib_meta_data: Arc<GearsLibraryMetaData>,
+        user: Option<String>,
+        call_options: RedisClientCallOptions,
+    ) -> RedisClient {
+        RedisClient {
+            call_options,
+            lib_meta_data,
+            user,
+        }
+    }
+
+    pub(crate) fn set_declared_keys(&mut self, declared_keys: Option<Arc<DeclaredKeys>>) {
+        self.call_options.declared_keys = declared_keys;
+    }
 }
 
 impl RedisClientCtxInterface for RedisClient {
     fn call(&self, command: &str, args: &[&[u8]]) -> CallResult {
         let user = self.user.as_ref().or(Some(&self.lib_meta_data.user));
-        call_redis_command(user, command, &self.call_options.call_options, args)
+        call_redis_command(user, command, &self.call_options, args)
     }
 
     fn get_background_redis_client(&self) -> Box<dyn BackgroundRunFunctionCtxInterface> {
@@ -148,6 +181,116 @@ impl RedisClientCtxInterface for RedisClient {
             Err(e) => Err(GearsApiError::new(e)),
         }
     }
+
+    fn scan(&self, options: ScanOptions) -> Result<Box<dyn ScanCursorInterface>, GearsApiError> {
+        Ok(Box::new(KeysScanCursor::new(
+            options,
+            &self.call_options,
+            false,
+        )?))
+    }
+
+    fn open_key(&self, name: &[u8]) -> Result<Box<dyn KeyCtxInterface>, GearsApiError> {
+        let user = self.user.as_ref().unwrap_or(&self.lib_meta_data.user);
+        Ok(Box::new(GearsKeyCtx::new(name, user, &self.call_options)?))
+    }
+}
+
+/// Tracks a single function invocation. The invocation is considered finished
+/// when the last reference to the tracker is dropped, either when the function
+/// returns or, if the function was blocked, when the background client is freed.
+pub(crate) struct FunctionCallTracker {
+    stats: Arc<Mutex<FunctionStats>>,
+    start_time: Instant,
+    replied: AtomicBool,
+    error: Mutex<Option<GearsApiError>>,
+}
+
+impl FunctionCallTracker {
+    pub(crate) fn new(stats: &Arc<Mutex<FunctionStats>>) -> Arc<FunctionCallTracker> {
+        stats.lock().unwrap().num_calls += 1;
+        Arc::new(FunctionCallTracker {
+            stats: Arc::clone(stats),
+            start_time: Instant::now(),
+            replied: AtomicBool::new(false),
+            error: Mutex::new(None),
+        })
+    }
+
+    /// Called on each reply, only the first (top level) reply
+    /// decides whether or not the invocation failed.
+    fn on_reply(&self, error: Option<&GearsApiError>) {
+        if self.replied.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        if let Some(e) = error {
+            *self.error.lock().unwrap() = Some(e.clone());
+        }
+    }
+}
+
+impl Drop for FunctionCallTracker {
+    fn drop(&mut self) {
+        let duration = self.start_time.elapsed().as_micros();
+        let mut stats = self.stats.lock().unwrap();
+        stats.num_finished += 1;
+        stats.total_execution_time += duration;
+        if duration > stats.max_execution_time {
+            stats.max_execution_time = duration;
+        }
+        if let Some(e) = self.error.lock().unwrap().take() {
+            stats.num_errors += 1;
+            stats.last_error = Some(e);
+        }
+    }
+}
+
+/// Redis falls back to a flat array of keys and values on Resp2.
+fn reply_with_map(ctx: &Context, size: usize) {
+    unsafe { raw::RedisModule_ReplyWithMap.unwrap()(ctx.ctx, size as c_long) };
+}
+
+/// Redis falls back to an array on Resp2.
+fn reply_with_set(ctx: &Context, size: usize) {
+    unsafe { raw::RedisModule_ReplyWithSet.unwrap()(ctx.ctx, size as c_long) };
+}
+
+/// Redis falls back to an integer (1 or 0) on Resp2.
+fn reply_with_bool(ctx: &Context, val: bool) {
+    unsafe { raw::RedisModule_ReplyWithBool.unwrap()(ctx.ctx, val as c_int) };
+}
+
+/// Redis falls back to a bulk string on Resp2.
+fn reply_with_big_number(ctx: &Context, val: &str) {
+    unsafe {
+        raw::RedisModule_ReplyWithBigNumber.unwrap()(ctx.ctx, val.as_ptr().cast(), val.len())
+    };
+}
+
+/// `REDISMODULE_POSTPONED_LEN`, the array length is set later with `RedisModule_ReplySetArrayLength`.
+const POSTPONED_LEN: c_long = -1;
+
+fn reply_with_postponed_array(ctx: &Context) {
+    unsafe { raw::RedisModule_ReplyWithArray.unwrap()(ctx.ctx, POSTPONED_LEN) };
+}
+
+fn reply_set_array_length(ctx: &Context, len: usize) {
+    unsafe { raw::RedisModule_ReplySetArrayLength.unwrap()(ctx.ctx, len as c_long) };
+}
+
+/// Redis falls back to a bulk string on Resp2. The extension must be exactly
+/// 3 characters long, `txt` is used if the given extension is not valid.
+fn reply_with_verbatim(ctx: &Context, val: &str, ext: &str) {
+    let ext = if ext.len() == 3 { ext } else { "txt" };
+    let ext = CString::new(ext).unwrap_or_else(|_| CString::new("txt").unwrap());
+    unsafe {
+        raw::RedisModule_ReplyWithVerbatimStringType.unwrap()(
+            ctx.ctx,
+            val.as_ptr().cast(),
+            val.len(),
+            ext.as_ptr(),
+        )
+    };
 }
 
 pub(crate) struct RunCtx<'a> {
@@ -155,41 +298,85 @@ pub(crate) struct RunCtx<'a> {
     pub(crate) iter: Iter<'a, redis_module::RedisString>,
     pub(crate) flags: FunctionFlags,
     pub(crate) lib_meta_data: Arc<GearsLibraryMetaData>,
+    pub(crate) call_tracker: Arc<FunctionCallTracker>,
+    pub(crate) declared_keys: Option<Arc<DeclaredKeys>>,
 }
 
 impl<'a> ReplyCtxInterface for RunCtx<'a> {
     fn reply_with_simple_string(&self, val: &str) {
+        self.call_tracker.on_reply(None);
         self.ctx.reply_simple_string(val);
     }
 
     fn reply_with_error(&self, val: GearsApiError) {
+        self.call_tracker.on_reply(Some(&val));
         self.ctx.reply_error_string(get_msg_verbose(&val));
     }
 
     fn reply_with_long(&self, val: i64) {
+        self.call_tracker.on_reply(None);
         self.ctx.reply_long(val);
     }
 
     fn reply_with_double(&self, val: f64) {
+        self.call_tracker.on_reply(None);
         self.ctx.reply_double(val);
     }
 
     fn reply_with_bulk_string(&self, val: &str) {
+        self.call_tracker.on_reply(None);
         self.ctx.reply_bulk_string(val);
     }
 
     fn reply_with_array(&self, size: usize) {
+        self.call_tracker.on_reply(None);
         self.ctx.reply_array(size);
     }
 
     fn reply_with_slice(&self, val: &[u8]) {
+        self.call_tracker.on_reply(None);
         self.ctx.reply_bulk_slice(val);
     }
 
     fn reply_with_null(&self) {
+        self.call_tracker.on_reply(None);
         self.ctx.reply_null();
     }
 
+    fn reply_with_map(&self, size: usize) {
+        self.call_tracker.on_reply(None);
+        reply_with_map(self.ctx, size);
+    }
+
+    fn reply_with_set(&self, size: usize) {
+        self.call_tracker.on_reply(None);
+        reply_with_set(self.ctx, size);
+    }
+
+    fn reply_with_bool(&self, val: bool) {
+        self.call_tracker.on_reply(None);
+        reply_with_bool(self.ctx, val);
+    }
+
+    fn reply_with_big_number(&self, val: &str) {
+        self.call_tracker.on_reply(None);
+        reply_with_big_number(self.ctx, val);
+    }
+
+    fn reply_with_verbatim(&self, val: &str, ext: &str) {
+        self.call_tracker.on_reply(None);
+        reply_with_verbatim(self.ctx, val, ext);
+    }
+
+    fn reply_with_postponed_array(&self) {
+        self.call_tracker.on_reply(None);
+        reply_with_postponed_array(self.ctx);
+    }
+
+    fn reply_set_array_length(&self, len: usize) {
+        reply_set_array_length(self.ctx, len);
+    }
+
     fn as_client(&self) -> &dyn ReplyCtxInterface {
         self
     }
@@ -214,18 +401,25 @@ impl<'a> RunFunctionCtxInterface for RunCtx<'a> {
         let thread_ctx = ThreadSafeContext::with_blocked_client(blocked_client);
         let ctx = thread_ctx.get_ctx();
         Ok(Box::new(BackgroundClientCtx {
-            _thread_ctx: thread_ctx,
+            _owner: thread_ctx,
             ctx,
+            call_tracker: Arc::clone(&self.call_tracker),
         }))
     }
 
+    fn get_reply_client(&self) -> Box<dyn ReplyCtxInterface> {
+        Box::new(InvocationReplyCtx {
+            _owner: (),
+            ctx: Context::new(self.ctx.ctx),
+            call_tracker: Arc::clone(&self.call_tracker),
+        })
+    }
+
     fn get_redis_client(&self) -> Box<dyn RedisClientCtxInterface> {
         let user = self.ctx.get_current_user().ok();
-        Box::new(RedisClient::new(
-            self.lib_meta_data.clone(),
-            user,
-            self.flags,
-        ))
+        let mut client = RedisClient::new(self.lib_meta_data.clone(), user, self.flags);
+        client.set_declared_keys(self.declared_keys.clone());
+        Box::new(client)
     }
 
     fn allow_block(&self) -> bool {
@@ -233,47 +427,100 @@ impl<'a> RunFunctionCtxInterface for RunCtx<'a> {
     }
 }
 
-pub(crate) struct BackgroundClientCtx {
-    _thread_ctx: ThreadSafeContext<redis_module::BlockedClient>,
+/// Replies to a function invocation on the given context, `_owner` is
+/// whatever keeps the context valid for as long as the reply context lives.
+pub(crate) struct ContextReplyCtx<T> {
+    _owner: T,
     ctx: Context,
+    call_tracker: Arc<FunctionCallTracker>,
 }
 
-unsafe impl Sync for BackgroundClientCtx {}
-unsafe impl Send for BackgroundClientCtx {}
+/// Replies on a blocked client, the client is unblocked when dropped.
+pub(crate) type BackgroundClientCtx =
+    ContextReplyCtx<ThreadSafeContext<redis_module::BlockedClient>>;
+
+/// Replies directly on the context of the running function invocation,
+/// must not be used after the function returns.
+pub(crate) type InvocationReplyCtx = ContextReplyCtx<()>;
+
+unsafe impl<T> Sync for ContextReplyCtx<T> {}
+unsafe impl<T> Send for ContextReplyCtx<T> {}
 
-impl ReplyCtxInterface for BackgroundClientCtx {
+impl<T> ReplyCtxInterface for ContextReplyCtx<T> {
     fn reply_with_simple_string(&self, val: &str) {
+        self.call_tracker.on_reply(None);
         self.ctx.reply_simple_string(val);
     }
 
     fn reply_with_error(&self, val: GearsApiError) {
+        self.call_tracker.on_reply(Some(&val));
         self.ctx.reply_error_string(get_msg_verbose(&val));
     }
 
     fn reply_with_long(&self, val: i64) {
+        self.call_tracker.on_reply(None);
         self.ctx.reply_long(val);
     }
 
     fn reply_with_double(&self, val: f64) {
+        self.call_tracker.on_reply(None);
         self.ctx.reply_double(val);
     }
 
     fn reply_with_bulk_string(&self, val: &str) {
+        self.call_tracker.on_reply(None);
         self.ctx.reply_bulk_string(val);
     }
 
     fn reply_with_array(&self, size: usize) {
+        self.call_tracker.on_reply(None);
         self.ctx.reply_array(size);
     }
 
     fn reply_with_slice(&self, val: &[u8]) {
+        self.call_tracker.on_reply(None);
         self.ctx.reply_bulk_slice(val);
     }
 
     fn reply_with_null(&self) {
+        self.call_tracker.on_reply(None);
         self.ctx.reply_null();
     }
 
+    fn reply_with_map(&self, size: usize) {
+        self.call_tracker.on_reply(None);
+        reply_with_map(&self.ctx, size);
+    }
+
+    fn reply_with_set(&self, size: usize) {
+        self.call_tracker.on_reply(None);
+        reply_with_set(&self.ctx, size);
+    }
+
+    fn reply_with_bool(&self, val: bool) {
+        self.call_tracker.on_reply(None);
+        reply_with_bool(&self.ctx, val);
+    }
+
+    fn reply_with_big_number(&self, val: &str) {
+        self.call_tracker.on_reply(None);
+        reply_with_big_number(&self.ctx, val);
+    }
+
+    fn reply_with_verbatim(&self, val: &str, ext: &str) {
+        self.call_tracker.on_reply(None);
+        reply_with_verbatim(&self.ctx, val, ext);
+    }
+
+    fn reply_with_postponed_array(&self) {
+        self.call_tracker.on_reply(None);
+        reply_with_postponed_array(&self.ctx);
+    }
+
+    fn reply_set_array_length(&self, len: usize) {
+        reply_set_array_length(&self.ctx, len);
+    }
+
     fn as_client(&self) -> &dyn ReplyCtxInterface {
         self
     }
@@ -0,0 +1,210 @@
+/*
+ * Copyright Redis Ltd. 2018 - present
+ * Licensed under your choice of the Redis Source Available License 2.0 (RSALv2) or
+ * the Server Side Public License v1 (SSPLv1).
+ */
+
+use redis_module::{raw, Context};
+
+use redisgears_plugin_api::redisgears_plugin_api::{
+    server_events_ctx::ServerEvent, server_events_ctx::ServerEventType, GearsApiError,
+    RefCellWrapper,
+};
+
+use crate::{get_ctx, get_globals};
+
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::os::raw::c_void;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime};
+
+pub(crate) type ServerEventCallback =
+    Box<dyn Fn(&ServerEvent, Box<dyn FnOnce(Result<(), GearsApiError>) + Send + Sync>)>;
+
+#[derive(Clone, Default)]
+pub(crate) struct ServerEventConsumerStats {
+    pub(crate) num_trigger: usize,
+    pub(crate) num_success: usize,
+    pub(crate) num_failed: usize,
+    pub(crate) num_finished: usize,
+    pub(crate) last_error: Option<GearsApiError>,
+    pub(crate) last_execution_time: u128,
+    pub(crate) total_execution_time: u128,
+}
+
+pub(crate) struct ServerEventConsumer {
+    event: ServerEventType,
+    callback: Option<ServerEventCallback>,
+    stats: Arc<RefCellWrapper<ServerEventConsumerStats>>,
+}
+
+impl ServerEventConsumer {
+    pub(crate) fn set_callback(&mut self, callback: ServerEventCallback) -> ServerEventCallback {
+        let old_callback = self.callback.take();
+        self.callback = Some(callback);
+        old_callback.unwrap()
+    }
+
+    pub(crate) fn get_stats(&self) -> ServerEventConsumerStats {
+        self.stats.ref_cell.borrow().clone()
+    }
+}
+
+fn fire_event(consumer: &Arc<RefCell<ServerEventConsumer>>, event: &ServerEvent) {
+    let c = consumer.borrow();
+    {
+        let mut stats = c.stats.ref_cell.borrow_mut();
+        stats.num_trigger += 1;
+    }
+    let stats_ref = Arc::clone(&c.stats);
+    let start_time = SystemTime::now();
+
+    (c.callback.as_ref().unwrap())(
+        event,
+        Box::new(move |res| {
+            let duration = match SystemTime::now().duration_since(start_time) {
+                Ok(d) => d.as_millis(),
+                Err(_) => 0,
+            };
+            let mut stats = stats_ref.ref_cell.borrow_mut();
+            stats.num_finished += 1;
+            stats.last_execution_time = duration;
+            stats.total_execution_time += duration;
+            if let Err(e) = res {
+                stats.num_failed += 1;
+                stats.last_error = Some(e);
+            } else {
+                stats.num_success += 1;
+            }
+        }),
+    );
+}
+
+pub(crate) struct ServerEventsCtx {
+    consumers: Vec<Weak<RefCell<ServerEventConsumer>>>,
+}
+
+impl ServerEventsCtx {
+    pub(crate) fn new() -> ServerEventsCtx {
+        ServerEventsCtx {
+            consumers: Vec::new(),
+        }
+    }
+
+    pub(crate) fn add_consumer(
+        &mut self,
+        event: ServerEventType,
+        callback: ServerEventCallback,
+    ) -> Arc<RefCell<ServerEventConsumer>> {
+        let consumer = Arc::new(RefCell::new(ServerEventConsumer {
+            event,
+            callback: Some(callback),
+            stats: Arc::new(RefCellWrapper {
+                ref_cell: RefCell::new(ServerEventConsumerStats::default()),
+            }),
+        }));
+        self.consumers.retain(|c| c.strong_count() > 0);
+        self.consumers.push(Arc::downgrade(&consumer));
+        consumer
+    }
+
+    /// Fire the consumers that registered to the given event. The consumers are
+    /// not invoked from within the server event handler (where Redis is in the
+    /// middle of the operation that caused the event) but right after it, from
+    /// the event loop.
+    pub(crate) fn on_server_event(&self, event: ServerEvent) {
+        let consumers = self.get_consumers(&event);
+        if consumers.is_empty() {
+            return;
+        }
+        let job: Box<dyn FnOnce()> = Box::new(move || {
+            for consumer in consumers.iter().filter_map(|c| c.upgrade()) {
+                fire_event(&consumer, &event);
+            }
+        });
+        get_ctx().create_timer(
+            Duration::from_millis(0),
+            |_ctx, job: Box<dyn FnOnce()>| job(),
+            job,
+        );
+    }
+
+    /// Fire the consumers that registered to the given event right away, used
+    /// for events that are not followed by another iteration of the event loop.
+    pub(crate) fn on_server_event_now(&self, event: ServerEvent) {
+        for consumer in self
+            .get_consumers(&event)
+            .iter()
+            .filter_map(|c| c.upgrade())
+        {
+            fire_event(&consumer, &event);
+        }
+    }
+
+    fn get_consumers(&self, event: &ServerEvent) -> Vec<Weak<RefCell<ServerEventConsumer>>> {
+        self.consumers
+            .iter()
+            .filter(|c| {
+                c.upgrade()
+                    .map_or(false, |c| c.borrow().event == event.get_type())
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+extern "C" fn on_config_change_event(
+    _ctx: *mut raw::RedisModuleCtx,
+    _eid: raw::RedisModuleEvent,
+    _subevent: u64,
+    data: *mut c_void,
+) {
+    let data = unsafe { &*(data as *const raw::RedisModuleConfigChangeV1) };
+    let names = (0..data.num_changes as usize)
+        .map(|i| {
+            unsafe { CStr::from_ptr(*data.config_names.add(i)) }
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect();
+    get_globals()
+        .server_events_ctx
+        .on_server_event(ServerEvent::ConfigChange { names });
+}
+
+extern "C" fn on_shutdown_event(
+    _ctx: *mut raw::RedisModuleCtx,
+    _eid: raw::RedisModuleEvent,
+    _subevent: u64,
+    _data: *mut c_void,
+) {
+    // the server exits right after this event, there is no event loop
+    // iteration to fire the consumers on.
+    get_globals()
+        .server_events_ctx
+        .on_server_event_now(ServerEvent::Shutdown);
+}
+
+/// Subscribe to the server events that are not supported by `redis_module!`.
+pub(crate) fn subscribe_to_server_events(ctx: &Context) {
+    let events: [(u32, raw::RedisModuleEventCallback); 2] = [
+        (raw::REDISMODULE_EVENT_CONFIG, Some(on_config_change_event)),
+        (raw::REDISMODULE_EVENT_SHUTDOWN, Some(on_shutdown_event)),
+    ];
+    for (id, callback) in events {
+        let res = unsafe {
+            raw::RedisModule_SubscribeToServerEvent.unwrap()(
+                ctx.ctx,
+                raw::RedisModuleEvent {
+                    id: id as u64,
+                    dataver: 1,
+                },
+                callback,
+            )
+        };
+        if res != raw::REDISMODULE_OK as i32 {
+            ctx.log_warning(&format!("Failed subscribing to server event {}.", id));
+        }
+    }
+}
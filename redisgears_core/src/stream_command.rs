@@ -0,0 +1,151 @@
+/*
+ * Copyright Redis Ltd. 2018 - present
+ * Licensed under your choice of the Redis Source Available License 2.0 (RSALv2) or
+ * the Server Side Public License v1 (SSPLv1).
+ */
+
+use redis_module::{
+    raw, raw::KeyType::Stream, raw::RedisModuleStreamID, Context, NextArg, RedisError, RedisResult,
+    RedisString, RedisValue,
+};
+
+use redisgears_plugin_api::redisgears_plugin_api::stream_ctx::StreamStartPosition;
+
+use std::iter::Skip;
+use std::sync::Arc;
+use std::vec::IntoIter;
+
+use crate::{get_globals_mut, get_libraries, get_stream_consumer, get_stream_last_id};
+
+/// Replicate an `RG.STREAM` subcommand. All the subcommands are replicated the
+/// same way, as an `RG.STREAM` command with the arguments resolved on the primary.
+fn replicate_stream_command(ctx: &Context, sub_command: &str, args: &[&[u8]]) {
+    let mut replicated_args = vec![sub_command.as_bytes()];
+    replicated_args.extend_from_slice(args);
+    redis_module::replicate_slices(ctx.ctx, "rg.stream", &replicated_args);
+}
+
+/// The stream consumer state is owned by the primary, on a replica it can only
+/// be changed by the replication stream and not by an ordinary client.
+pub(crate) fn verify_stream_state_change_allowed(ctx: &Context) -> Result<(), RedisError> {
+    if ctx.is_primary() {
+        return Ok(());
+    }
+    let flags = unsafe { raw::RedisModule_GetContextFlags.unwrap()(ctx.ctx) };
+    if flags & raw::REDISMODULE_CTX_FLAGS_REPLICATED as std::os::raw::c_int == 0 {
+        return Err(RedisError::Str(
+            "Can not change a stream consumer state on a replica",
+        ));
+    }
+    Ok(())
+}
+
+/// Set the last read id of a stream consumer on a given stream, the consumer
+/// will continue processing records with id greater than the given id.
+/// Accepts a stream id, `from_beginning` or `$` (the last id on the stream).
+pub(crate) fn stream_setid_command(
+    ctx: &Context,
+    mut args: Skip<IntoIter<RedisString>>,
+) -> RedisResult {
+    let library_name = args.next_arg()?.try_as_str()?;
+    let consumer_name = args.next_arg()?.try_as_str()?;
+    let stream_arg = args.next_arg()?;
+    let stream = stream_arg.as_slice();
+    let id = args
+        .next_arg()?
+        .try_as_str()?
+        .parse::<StreamStartPosition>()
+        .map_err(|e| RedisError::String(e.get_msg().to_string()))?;
+
+    if ctx.open_key(&stream_arg).key_type() != Stream {
+        return Err(RedisError::String(format!(
+            "No such stream '{}'",
+            std::str::from_utf8(stream).unwrap_or("[binary data]")
+        )));
+    }
+
+    let (ms, seq) = match id {
+        StreamStartPosition::Beginning => (0, 0),
+        StreamStartPosition::Now => match get_stream_last_id(stream) {
+            Some(id) => (id.ms, id.seq),
+            None => (0, 0),
+        },
+        StreamStartPosition::Id(ms, seq) => (ms, seq),
+    };
+
+    let consumer = {
+        let libraries = get_libraries();
+        Arc::clone(get_stream_consumer(
+            &libraries,
+            library_name,
+            consumer_name,
+        )?)
+    };
+
+    let stream_ctx = &mut get_globals_mut().stream_ctx;
+    stream_ctx.update_stream_for_consumer(stream, &consumer, ms, seq);
+    replicate_stream_command(
+        ctx,
+        "setid",
+        &[
+            library_name.as_bytes(),
+            consumer_name.as_bytes(),
+            stream,
+            format!("{}-{}", ms, seq).as_bytes(),
+        ],
+    );
+    if ctx.is_primary() {
+        if let Some(group) = consumer.ref_cell.borrow().consumer_group.as_ref() {
+            (group.on_position_set)(stream, RedisModuleStreamID { ms, seq });
+        }
+        // continue processing from the new position
+        stream_ctx.on_stream_touched("setid", stream);
+    }
+    Ok(RedisValue::SimpleStringStatic("OK"))
+}
+
+fn stream_set_paused(
+    ctx: &Context,
+    mut args: Skip<IntoIter<RedisString>>,
+    paused: bool,
+) -> RedisResult {
+    let library_name = args.next_arg()?.try_as_str()?;
+    let consumer_name = args.next_arg()?.try_as_str()?;
+
+    let consumer = {
+        let libraries = get_libraries();
+        Arc::clone(get_stream_consumer(
+            &libraries,
+            library_name,
+            consumer_name,
+        )?)
+    };
+
+    let was_paused = consumer.ref_cell.borrow_mut().set_paused(paused);
+    replicate_stream_command(
+        ctx,
+        if paused { "pause" } else { "resume" },
+        &[library_name.as_bytes(), consumer_name.as_bytes()],
+    );
+    if was_paused && !paused && ctx.is_primary() {
+        get_globals_mut().stream_ctx.on_consumer_resumed(&consumer);
+    }
+    Ok(RedisValue::SimpleStringStatic("OK"))
+}
+
+/// Stop a stream consumer from reading new records, records that are
+/// already processed are not affected. The consumer position is kept.
+pub(crate) fn stream_pause_command(
+    ctx: &Context,
+    args: Skip<IntoIter<RedisString>>,
+) -> RedisResult {
+    stream_set_paused(ctx, args, true)
+}
+
+/// Continue reading new records on a paused stream consumer.
+pub(crate) fn stream_resume_command(
+    ctx: &Context,
+    args: Skip<IntoIter<RedisString>>,
+) -> RedisResult {
+    stream_set_paused(ctx, args, false)
+}
@@ -0,0 +1,396 @@
+/*
+ * Copyright Redis Ltd. 2018 - present
+ * Licensed under your choice of the Redis Source Available License 2.0 (RSALv2) or
+ * the Server Side Public License v1 (SSPLv1).
+ */
+
+//! Reflect the progress of a stream consumer on a Redis consumer group so it
+//! will be visible to external tools (`XINFO GROUPS`, `XPENDING`, ...).
+//! The consumer keeps reading the stream by itself, the group is updated so that
+//! its last delivered id is the consumer last read id and its pending entries
+//! are the records that are currently processed by the consumer. The group is
+//! updated in batches, once per event loop iteration.
+
+use redis_module::raw::RedisModuleStreamID;
+
+use redisgears_plugin_api::redisgears_plugin_api::{
+    load_library_ctx::FunctionFlags, stream_ctx::StreamStartPosition, CallResult, RefCellWrapper,
+};
+
+use crate::run_ctx::RedisClientCallOptions;
+use crate::stream_reader::ConsumerGroupCallbacks;
+use crate::{call_redis_command, get_ctx};
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The name of the consumer (inside the consumer group) that owns the pending records.
+const CONSUMER_GROUP_CONSUMER_NAME: &str = "redisgears";
+
+fn stream_id_to_string(id: RedisModuleStreamID) -> String {
+    format!("{}-{}", id.ms, id.seq)
+}
+
+/// Returns the id that comes right before the given id, `None` if there is no such id.
+fn stream_id_before(id: RedisModuleStreamID) -> Option<RedisModuleStreamID> {
+    if id.seq > 0 {
+        Some(RedisModuleStreamID {
+            ms: id.ms,
+            seq: id.seq - 1,
+        })
+    } else if id.ms > 0 {
+        Some(RedisModuleStreamID {
+            ms: id.ms - 1,
+            seq: u64::MAX,
+        })
+    } else {
+        None
+    }
+}
+
+/// Parse a stream id out of a command reply.
+pub(crate) fn call_result_to_stream_id(res: &CallResult) -> Option<RedisModuleStreamID> {
+    let id = match res {
+        CallResult::BulkStr(s) | CallResult::SimpleStr(s) => s.as_str(),
+        CallResult::StringBuffer(s) => std::str::from_utf8(s).ok()?,
+        _ => return None,
+    };
+    match id.parse::<StreamStartPosition>() {
+        Ok(StreamStartPosition::Id(ms, seq)) => Some(RedisModuleStreamID { ms, seq }),
+        _ => None,
+    }
+}
+
+/// Returns the value of the given field out of a map reply (or a flat array of
+/// field value pairs on RESP2).
+fn call_result_get_field<'a>(res: &'a CallResult, field: &str) -> Option<&'a CallResult> {
+    match res {
+        CallResult::Map(map) => map.get(field.as_bytes()),
+        CallResult::Array(arr) => arr.chunks(2).find_map(|pair| match pair {
+            [CallResult::BulkStr(f) | CallResult::SimpleStr(f), val] if f == field => Some(val),
+            _ => None,
+        }),
+        _ => None,
+    }
+}
+
+fn call_group_command(user: &String, command: &str, args: &[&[u8]]) -> CallResult {
+    let call_options = RedisClientCallOptions::new(FunctionFlags::empty());
+    call_redis_command(Some(user), command, &call_options, args)
+}
+
+fn log_group_error(group: &str, stream_name: &[u8], command: &str, err: &str) {
+    get_ctx().log_warning(&format!(
+        "Failed running {} for consumer group '{}' on stream '{}', {}",
+        command,
+        group,
+        std::str::from_utf8(stream_name).unwrap_or("[binary data]"),
+        err
+    ));
+}
+
+/// Returns the position of an existing consumer group, the position is right before
+/// the first record that was delivered and not yet acknowledged (so those records
+/// will be processed again) or the group last delivered id if there are no such records.
+fn get_group_position(
+    user: &String,
+    group: &str,
+    stream_name: &[u8],
+) -> Option<Option<RedisModuleStreamID>> {
+    if let CallResult::Array(summary) =
+        call_group_command(user, "xpending", &[stream_name, group.as_bytes()])
+    {
+        if let (Some(CallResult::Long(count)), Some(min_id)) = (summary.first(), summary.get(1)) {
+            if *count > 0 {
+                return call_result_to_stream_id(min_id).map(stream_id_before);
+            }
+        }
+    }
+    let groups = match call_group_command(user, "xinfo", &[b"GROUPS", stream_name]) {
+        CallResult::Array(groups) => groups,
+        _ => return None,
+    };
+    groups
+        .iter()
+        .find(|g| {
+            matches!(
+                call_result_get_field(g, "name"),
+                Some(CallResult::BulkStr(name) | CallResult::SimpleStr(name)) if name == group
+            )
+        })
+        .and_then(|g| call_result_get_field(g, "last-delivered-id"))
+        .and_then(call_result_to_stream_id)
+        .map(|id| {
+            if id.ms == 0 && id.seq == 0 {
+                None
+            } else {
+                Some(id)
+            }
+        })
+}
+
+/// Create the consumer group on the given stream. If the group already exists,
+/// returns its position so the consumer will continue from where the group stopped.
+fn init_group(
+    user: &String,
+    group: &str,
+    stream_name: &[u8],
+    start: Option<RedisModuleStreamID>,
+) -> Option<RedisModuleStreamID> {
+    if !get_ctx().is_primary() {
+        // the group is replicated from the primary.
+        return start;
+    }
+    let start_id = start.map_or_else(|| "0".to_string(), stream_id_to_string);
+    match call_group_command(
+        user,
+        "xgroup",
+        &[
+            b"CREATE",
+            stream_name,
+            group.as_bytes(),
+            start_id.as_bytes(),
+        ],
+    ) {
+        CallResult::Error(e) if e.starts_with("BUSYGROUP") => {
+            get_group_position(user, group, stream_name).unwrap_or(start)
+        }
+        CallResult::Error(e) => {
+            log_group_error(group, stream_name, "XGROUP CREATE", &e);
+            start
+        }
+        _ => start,
+    }
+}
+
+/// Set the consumer group last delivered id on the given stream,
+/// the group is created if it does not exist.
+fn set_group_position(
+    user: &String,
+    group: &str,
+    stream_name: &[u8],
+    id: Option<RedisModuleStreamID>,
+) -> bool {
+    let id = id.map_or_else(|| "0".to_string(), stream_id_to_string);
+    let res = call_group_command(
+        user,
+        "xgroup",
+        &[b"SETID", stream_name, group.as_bytes(), id.as_bytes()],
+    );
+    match res {
+        CallResult::Error(e) if e.starts_with("NOGROUP") => {
+            // the group was deleted, create it again.
+            if let CallResult::Error(e) = call_group_command(
+                user,
+                "xgroup",
+                &[b"CREATE", stream_name, group.as_bytes(), id.as_bytes()],
+            ) {
+                log_group_error(group, stream_name, "XGROUP CREATE", &e);
+                return false;
+            }
+            true
+        }
+        CallResult::Error(e) => {
+            log_group_error(group, stream_name, "XGROUP SETID", &e);
+            false
+        }
+        _ => true,
+    }
+}
+
+/// Records that were delivered to, or acknowledged by, the consumer
+/// and were not yet reflected on the consumer group.
+#[derive(Default)]
+struct GroupStreamUpdates {
+    delivered: Vec<RedisModuleStreamID>,
+    acked: Vec<RedisModuleStreamID>,
+}
+
+/// The consumer group of a stream consumer. Delivered and acknowledged records are
+/// collected and reflected on the group once per event loop iteration, using a single
+/// `XREADGROUP` and a single `XACK` for each stream. The consumer reads the stream in
+/// order, so as long as the group is in sync with the consumer, `XREADGROUP` delivers
+/// exactly the records the consumer processes and the group position is not set.
+struct ConsumerGroup {
+    user: String,
+    name: String,
+    updates: HashMap<Vec<u8>, GroupStreamUpdates>,
+    /// Streams on which the group position might differ from the consumer position.
+    out_of_sync: HashSet<Vec<u8>>,
+    flush_scheduled: bool,
+}
+
+impl ConsumerGroup {
+    fn get_updates(&mut self, stream_name: &[u8]) -> &mut GroupStreamUpdates {
+        self.updates.entry(stream_name.to_vec()).or_default()
+    }
+}
+
+/// Deliver the given records to the group consumer, if the group is not in sync
+/// with the consumer, its position is set right before the first record so exactly
+/// the given records will be read and added to the group pending entries.
+/// Returns `true` if the group is in sync with the consumer.
+fn deliver_records(
+    user: &String,
+    group: &str,
+    stream_name: &[u8],
+    ids: &[RedisModuleStreamID],
+    in_sync: bool,
+) -> bool {
+    if !in_sync && !set_group_position(user, group, stream_name, stream_id_before(ids[0])) {
+        return false;
+    }
+    let count = ids.len().to_string();
+    let read = || {
+        call_group_command(
+            user,
+            "xreadgroup",
+            &[
+                b"GROUP",
+                group.as_bytes(),
+                CONSUMER_GROUP_CONSUMER_NAME.as_bytes(),
+                b"COUNT",
+                count.as_bytes(),
+                b"STREAMS",
+                stream_name,
+                b">",
+            ],
+        )
+    };
+    let res = match read() {
+        CallResult::Error(e) if e.starts_with("NOGROUP") => {
+            // the group was deleted, create it again.
+            if !set_group_position(user, group, stream_name, stream_id_before(ids[0])) {
+                return false;
+            }
+            read()
+        }
+        res => res,
+    };
+    if let CallResult::Error(e) = res {
+        log_group_error(group, stream_name, "XREADGROUP", &e);
+        return false;
+    }
+    true
+}
+
+fn ack_records(user: &String, group: &str, stream_name: &[u8], ids: &[RedisModuleStreamID]) {
+    let ids = ids
+        .iter()
+        .map(|id| stream_id_to_string(*id))
+        .collect::<Vec<String>>();
+    let mut args: Vec<&[u8]> = vec![stream_name, group.as_bytes()];
+    args.extend(ids.iter().map(|id| id.as_bytes()));
+    if let CallResult::Error(e) = call_group_command(user, "xack", &args) {
+        log_group_error(group, stream_name, "XACK", &e);
+    }
+}
+
+/// Reflect the collected updates of the given streams (or all the streams if `None`)
+/// on the consumer group.
+fn flush_group_updates(group: &RefCellWrapper<ConsumerGroup>, stream_name: Option<&[u8]>) {
+    let (user, name, updates) = {
+        let mut g = group.ref_cell.borrow_mut();
+        let updates = match stream_name {
+            Some(stream_name) => g
+                .updates
+                .remove(stream_name)
+                .map(|u| vec![(stream_name.to_vec(), u)])
+                .unwrap_or_default(),
+            None => {
+                g.flush_scheduled = false;
+                g.updates.drain().collect::<Vec<_>>()
+            }
+        };
+        (g.user.clone(), g.name.clone(), updates)
+    };
+    for (stream_name, updates) in updates {
+        if !updates.delivered.is_empty() {
+            let in_sync = !group.ref_cell.borrow().out_of_sync.contains(&stream_name);
+            let in_sync = deliver_records(&user, &name, &stream_name, &updates.delivered, in_sync);
+            let mut g = group.ref_cell.borrow_mut();
+            if in_sync {
+                g.out_of_sync.remove(&stream_name);
+            } else {
+                g.out_of_sync.insert(stream_name.clone());
+            }
+        }
+        if !updates.acked.is_empty() {
+            ack_records(&user, &name, &stream_name, &updates.acked);
+        }
+    }
+}
+
+/// Flush the collected updates on the next event loop iteration, unless already scheduled.
+fn schedule_group_flush(group: &Arc<RefCellWrapper<ConsumerGroup>>) {
+    {
+        let mut g = group.ref_cell.borrow_mut();
+        if g.flush_scheduled {
+            return;
+        }
+        g.flush_scheduled = true;
+    }
+    get_ctx().create_timer(
+        Duration::from_millis(0),
+        |_ctx, group: Arc<RefCellWrapper<ConsumerGroup>>| flush_group_updates(&group, None),
+        Arc::clone(group),
+    );
+}
+
+/// Create the callbacks that reflect the consumer progress on the given consumer group.
+/// The group commands are invoked using the library user so ACL is verified.
+pub(crate) fn consumer_group_callbacks(user: &str, group: &str) -> ConsumerGroupCallbacks {
+    let consumer_group = Arc::new(RefCellWrapper {
+        ref_cell: RefCell::new(ConsumerGroup {
+            user: user.to_string(),
+            name: group.to_string(),
+            updates: HashMap::new(),
+            out_of_sync: HashSet::new(),
+            flush_scheduled: false,
+        }),
+    });
+    let (init_user, init_group_name) = (user.to_string(), group.to_string());
+    let deliver_group = Arc::clone(&consumer_group);
+    let ack_group = Arc::clone(&consumer_group);
+    let set_id_group = consumer_group;
+    ConsumerGroupCallbacks {
+        on_stream_init: Box::new(move |stream_name, start| {
+            init_group(&init_user, &init_group_name, stream_name, start)
+        }),
+        on_records_delivered: Box::new(move |stream_name, ids| {
+            deliver_group
+                .ref_cell
+                .borrow_mut()
+                .get_updates(stream_name)
+                .delivered
+                .extend_from_slice(ids);
+            schedule_group_flush(&deliver_group);
+        }),
+        on_records_acked: Box::new(move |stream_name, ids| {
+            ack_group
+                .ref_cell
+                .borrow_mut()
+                .get_updates(stream_name)
+                .acked
+                .extend_from_slice(ids);
+            schedule_group_flush(&ack_group);
+        }),
+        on_position_set: Box::new(move |stream_name, id| {
+            // updates that were collected before the position was changed must be reflected first.
+            flush_group_updates(&set_id_group, Some(stream_name));
+            let (user, name) = {
+                let g = set_id_group.ref_cell.borrow();
+                (g.user.clone(), g.name.clone())
+            };
+            let in_sync = set_group_position(&user, &name, stream_name, Some(id));
+            let mut g = set_id_group.ref_cell.borrow_mut();
+            if in_sync {
+                g.out_of_sync.remove(stream_name);
+            } else {
+                g.out_of_sync.insert(stream_name.to_vec());
+            }
+        }),
+    }
+}
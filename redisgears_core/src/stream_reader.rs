@@ -5,7 +5,10 @@
  */
 
 use redis_module::raw::RedisModuleStreamID;
-use redisgears_plugin_api::redisgears_plugin_api::GearsApiError;
+use redisgears_plugin_api::redisgears_plugin_api::{
+    stream_ctx::StreamConsumerOptions, stream_ctx::StreamRetryBackoff,
+    stream_ctx::StreamStartPosition, GearsApiError,
+};
 
 use std::collections::HashMap;
 
@@ -13,14 +16,36 @@ use std::cell::RefCell;
 use std::collections::LinkedList;
 use std::sync::{Arc, Weak};
 
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use crate::RefCellWrapper;
 
 pub type RecordAcknowledgeCallback = dyn Fn(&[u8], u64, u64);
+/// Called with the dead letter target, the stream name, the record id and the
+/// error when a record failed processing and the consumer has a dead letter target.
+pub type RecordFailedCallback = dyn Fn(&[u8], &[u8], RedisModuleStreamID, &GearsApiError);
 pub type StreamReaderCallback<T> =
     dyn Fn(&[u8], Option<RedisModuleStreamID>, bool) -> Result<Option<T>, String> + Sync + Send;
 pub type StreamTrimmerCallback = dyn Fn(&[u8], RedisModuleStreamID) + Sync + Send;
+/// Run the given job after the given delay, used to retry failed records
+/// and to flush batches that did not fill up.
+pub type StreamSchedulerCallback = dyn Fn(Duration, Box<dyn FnOnce()>) + Sync + Send;
+/// Called with the stream name and the consumer start position when a consumer starts
+/// reading a stream, returns the position from which the consumer should continue.
+pub type StreamInitCallback =
+    dyn Fn(&[u8], Option<RedisModuleStreamID>) -> Option<RedisModuleStreamID>;
+/// Called with the stream name and the ids of the records.
+pub type RecordsCallback = dyn Fn(&[u8], &[RedisModuleStreamID]);
+/// Called with the stream name and the new consumer position.
+pub type StreamPositionCallback = dyn Fn(&[u8], RedisModuleStreamID);
+
+/// Callbacks used to reflect the consumer progress on a consumer group.
+pub(crate) struct ConsumerGroupCallbacks {
+    pub(crate) on_stream_init: Box<StreamInitCallback>,
+    pub(crate) on_records_delivered: Box<RecordsCallback>,
+    pub(crate) on_records_acked: Box<RecordsCallback>,
+    pub(crate) on_position_set: Box<StreamPositionCallback>,
+}
 
 pub(crate) trait StreamReaderRecord {
     fn get_id(&self) -> RedisModuleStreamID;
@@ -45,12 +70,20 @@ pub(crate) trait StreamConsumer<T: StreamReaderRecord> {
         record: T,
         ack_callback: Box<dyn FnOnce(StreamReaderAck) + Send>,
     ) -> Option<StreamReaderAck>;
+
+    fn new_batch(
+        &self,
+        stream_name: &[u8],
+        records: Vec<T>,
+        ack_callback: Box<dyn FnOnce(StreamReaderAck) + Send>,
+    ) -> Option<StreamReaderAck>;
 }
 
 pub(crate) struct TrackedStream {
     name: Vec<u8>,
     consumers_data: Vec<Weak<RefCellWrapper<ConsumerInfo>>>,
     stream_trimmer: Arc<Box<StreamTrimmerCallback>>,
+    scheduler: Arc<Box<StreamSchedulerCallback>>,
 }
 
 impl TrackedStream {
@@ -114,11 +147,15 @@ pub(crate) struct ConsumerInfo {
     pub(crate) pending_ids: LinkedList<RedisModuleStreamID>,
     pub(crate) last_read_id: Option<RedisModuleStreamID>,
     pub(crate) last_error: Option<GearsApiError>,
+    pub(crate) pending_retries: HashMap<(u64, u64), usize>, // failed attempts of records waiting for retry
+    pub(crate) total_retries: usize,
+    pub(crate) batch_flush_scheduled: bool,
+    pub(crate) paused_retries: Vec<Vec<RedisModuleStreamID>>, // retries that are delayed until the consumer is resumed
 }
 
 impl ConsumerInfo {
-    fn ack_id(&mut self, id: RedisModuleStreamID, start_time: u128) -> bool {
-        self.records_processed += 1;
+    fn ack_id(&mut self, id: RedisModuleStreamID, records: usize, start_time: u128) -> bool {
+        self.records_processed += records;
         let since_the_epoch = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
@@ -151,6 +188,10 @@ pub(crate) struct ConsumerData<T: StreamReaderRecord, C: StreamConsumer<T>> {
     pub(crate) window: usize, // represent the max amount of elements that can be processed at the same time
     pub(crate) trim: bool,
     pub(crate) on_record_acked: Option<Box<RecordAcknowledgeCallback>>,
+    pub(crate) on_record_failed: Option<Box<RecordFailedCallback>>,
+    pub(crate) options: StreamConsumerOptions,
+    pub(crate) paused: bool, // paused consumer does not read new records
+    pub(crate) consumer_group: Option<ConsumerGroupCallbacks>,
     phantom: std::marker::PhantomData<T>,
 }
 
@@ -177,16 +218,80 @@ where
         old_trim
     }
 
+    pub(crate) fn set_paused(&mut self, paused: bool) -> bool {
+        std::mem::replace(&mut self.paused, paused)
+    }
+
+    pub(crate) fn set_options(&mut self, options: StreamConsumerOptions) -> StreamConsumerOptions {
+        std::mem::replace(&mut self.options, options)
+    }
+
+    /// Decide whether a failed record should be retried according to the consumer
+    /// retry policy. Returns the delay before the next attempt or `None` if the
+    /// record should not be retried.
+    fn next_retry_delay(
+        &self,
+        consumer_info: &mut ConsumerInfo,
+        id: RedisModuleStreamID,
+        error: &GearsApiError,
+    ) -> Option<Duration> {
+        let key = (id.ms, id.seq);
+        let failed_attempts = consumer_info.pending_retries.remove(&key).unwrap_or(0) + 1;
+        let retry_policy = self.options.retry_policy.as_ref()?;
+        if failed_attempts >= retry_policy.max_attempts {
+            return None;
+        }
+        let is_retryable = match retry_policy.retryable_errors.as_ref() {
+            Some(errors) => errors.iter().any(|e| error.get_msg().contains(e.as_str())),
+            None => true,
+        };
+        if !is_retryable {
+            return None;
+        }
+        consumer_info.pending_retries.insert(key, failed_attempts);
+        consumer_info.total_retries += 1;
+        let delay = match retry_policy.backoff {
+            StreamRetryBackoff::Fixed(delay) => delay,
+            StreamRetryBackoff::Exponential { initial, max } => initial
+                .saturating_mul(1_u64 << (failed_attempts - 1).min(63))
+                .min(max),
+        };
+        Some(Duration::from_millis(delay))
+    }
+
+    /// Send a failed record to the dead letter target (if the consumer has one).
+    /// Must be called before the record is acknowledged so it will not be trimmed.
+    fn record_failed(&self, stream_name: &[u8], id: RedisModuleStreamID, error: &GearsApiError) {
+        if let (Some(dead_letter), Some(on_record_failed)) = (
+            self.options.dead_letter.as_ref(),
+            self.on_record_failed.as_ref(),
+        ) {
+            on_record_failed(dead_letter, stream_name, id, error);
+        }
+    }
+
     pub(crate) fn get_or_create_consumed_stream(
         &mut self,
         name: &[u8],
     ) -> (Arc<RefCellWrapper<ConsumerInfo>>, bool) {
         let mut is_new = false;
+        // streams that exists when the consumer is registered with `StreamStartPosition::Now`
+        // are updated on registration, all the rest are read from the start position.
+        let last_read_id = match self.options.start_position {
+            StreamStartPosition::Id(ms, seq) => Some(RedisModuleStreamID { ms, seq }),
+            StreamStartPosition::Beginning | StreamStartPosition::Now => None,
+        };
+        let consumer_group = self.consumer_group.as_ref();
         let res = self
             .consumed_streams
             .entry(name.to_vec())
             .or_insert_with(|| {
                 is_new = true;
+                // an existing consumer group knows where we stopped reading.
+                let last_read_id = match consumer_group {
+                    Some(group) => (group.on_stream_init)(name, last_read_id),
+                    None => last_read_id,
+                };
                 Arc::new(RefCellWrapper {
                     ref_cell: RefCell::new(ConsumerInfo {
                         last_processed_time: 0,
@@ -196,7 +301,11 @@ where
                         records_processed: 0,
                         pending_ids: LinkedList::new(),
                         last_error: None,
-                        last_read_id: None,
+                        last_read_id,
+                        pending_retries: HashMap::new(),
+                        total_retries: 0,
+                        batch_flush_scheduled: false,
+                        paused_retries: Vec::new(),
                     }),
                 })
             });
@@ -234,176 +343,407 @@ where
     consumers: Vec<Weak<RefCellWrapper<ConsumerData<T, C>>>>,
     stream_reader: Arc<Box<StreamReaderCallback<T>>>,
     stream_trimmer: Arc<Box<StreamTrimmerCallback>>,
+    scheduler: Arc<Box<StreamSchedulerCallback>>,
     tracked_streams: HashMap<Vec<u8>, Arc<RefCellWrapper<TrackedStream>>>,
 }
 
-fn read_next_data<T: StreamReaderRecord>(
-    name: &[u8],
-    id: Option<RedisModuleStreamID>,
-    include_id: bool,
+/// Read the next records to process, up to the consumer batch size (or a single
+/// record if the consumer does not process batches). A partial batch is only
+/// returned if `force` is true or the consumer does not wait for batches to fill up,
+/// otherwise a flush of the batch is scheduled and no records are returned.
+/// A paused consumer does not read any records.
+fn read_next_records<T: StreamReaderRecord + 'static, C: StreamConsumer<T> + 'static>(
+    stream: &Arc<RefCellWrapper<TrackedStream>>,
+    consumer_weak: &Weak<RefCellWrapper<ConsumerData<T, C>>>,
     consumer_info: &Arc<RefCellWrapper<ConsumerInfo>>,
     stream_reader: &Arc<Box<StreamReaderCallback<T>>>,
-) -> Result<Option<T>, String> {
-    let r = stream_reader(name, id, include_id);
-    r.as_ref()?;
-    let record = r.as_ref().unwrap();
-    if record.is_none() {
-        return r;
-    }
-    let record = record.as_ref().unwrap();
-    let new_id = record.get_id();
-    let mut c_i = consumer_info.ref_cell.borrow_mut();
-    c_i.last_read_id = Some(new_id);
-    r
+    force: bool,
+) -> Result<Vec<T>, String> {
+    let batch = match consumer_weak.upgrade() {
+        Some(c) => {
+            let c = c.ref_cell.borrow();
+            if c.paused {
+                return Ok(Vec::new());
+            }
+            c.options.batch.clone()
+        }
+        None => return Ok(Vec::new()),
+    };
+    let batch_size = batch.as_ref().map_or(1, |b| b.batch_size);
+    let name = stream.ref_cell.borrow().name.clone();
+    let mut last_read_id = consumer_info.ref_cell.borrow().last_read_id;
+    let mut records = Vec::new();
+    while records.len() < batch_size {
+        match stream_reader(&name, last_read_id, false)? {
+            Some(record) => {
+                last_read_id = Some(record.get_id());
+                records.push(record);
+            }
+            None => break,
+        }
+    }
+    if let Some(batch) = batch {
+        if !force && batch.max_wait_ms > 0 && !records.is_empty() && records.len() < batch_size {
+            schedule_batch_flush(
+                stream,
+                consumer_weak,
+                consumer_info,
+                stream_reader,
+                batch.max_wait_ms,
+            );
+            return Ok(Vec::new());
+        }
+    }
+    if !records.is_empty() {
+        consumer_info.ref_cell.borrow_mut().last_read_id = last_read_id;
+    }
+    Ok(records)
 }
 
-fn send_new_data<T: StreamReaderRecord + 'static, C: StreamConsumer<T> + 'static>(
+fn schedule_batch_flush<T: StreamReaderRecord + 'static, C: StreamConsumer<T> + 'static>(
+    stream: &Arc<RefCellWrapper<TrackedStream>>,
+    consumer_weak: &Weak<RefCellWrapper<ConsumerData<T, C>>>,
+    consumer_info: &Arc<RefCellWrapper<ConsumerInfo>>,
+    stream_reader: &Arc<Box<StreamReaderCallback<T>>>,
+    max_wait_ms: u64,
+) {
+    {
+        let mut c_i = consumer_info.ref_cell.borrow_mut();
+        if c_i.batch_flush_scheduled {
+            return;
+        }
+        c_i.batch_flush_scheduled = true;
+    }
+    let scheduler = Arc::clone(&stream.ref_cell.borrow().scheduler);
+    let stream = Arc::clone(stream);
+    let consumer_weak = Weak::clone(consumer_weak);
+    let consumer_info = Arc::downgrade(consumer_info);
+    let stream_reader = Arc::clone(stream_reader);
+    scheduler(
+        Duration::from_millis(max_wait_ms),
+        Box::new(move || flush_batch(stream, consumer_weak, consumer_info, stream_reader)),
+    );
+}
+
+/// Process the partial batch that waits for more records, if the window allows it.
+fn flush_batch<T: StreamReaderRecord + 'static, C: StreamConsumer<T> + 'static>(
     stream: Arc<RefCellWrapper<TrackedStream>>,
     consumer_weak: Weak<RefCellWrapper<ConsumerData<T, C>>>,
-    mut actual_record: Result<Option<T>, String>,
-    consumer_info: Arc<RefCellWrapper<ConsumerInfo>>,
+    consumer_info: Weak<RefCellWrapper<ConsumerInfo>>,
     stream_reader: Arc<Box<StreamReaderCallback<T>>>,
 ) {
-    let consumer = match consumer_weak.upgrade() {
-        Some(c) => c,
+    // if weak ref returns None it means that stream was deleted
+    let consumer_info = match consumer_info.upgrade() {
+        Some(c_i) => c_i,
         None => return,
     };
-    let trim = { consumer.ref_cell.borrow().trim };
-    loop {
-        let (id, record) = {
+    consumer_info.ref_cell.borrow_mut().batch_flush_scheduled = false;
+    let window = match consumer_weak.upgrade() {
+        Some(c) => c.ref_cell.borrow().window,
+        None => return,
+    };
+    if consumer_info.ref_cell.borrow().pending_ids.len() >= window {
+        // the batch will be read when one of the pending batches will be acknowledged.
+        return;
+    }
+    let records = read_next_records(
+        &stream,
+        &consumer_weak,
+        &consumer_info,
+        &stream_reader,
+        true,
+    );
+    send_new_data(stream, consumer_weak, records, consumer_info, stream_reader);
+}
+
+/// Handle the processing result of a batch of records (a single record if the
+/// consumer does not process batches). If the batch failed and the consumer
+/// retry policy allows it, a retry is scheduled and the batch is kept on the pending
+/// ids, in this case `false` is returned. Otherwise the batch is acknowledged and
+/// `true` is returned.
+fn handle_record_ack<T: StreamReaderRecord + 'static, C: StreamConsumer<T> + 'static>(
+    stream: &Arc<RefCellWrapper<TrackedStream>>,
+    consumer_weak: &Weak<RefCellWrapper<ConsumerData<T, C>>>,
+    consumer_info: &Arc<RefCellWrapper<ConsumerInfo>>,
+    stream_reader: &Arc<Box<StreamReaderCallback<T>>>,
+    ids: &[RedisModuleStreamID],
+    start_time: u128,
+    ack: StreamReaderAck,
+) -> bool {
+    // the batch is tracked on the pending ids by its first id
+    let first_id = ids[0];
+    let last_id = ids[ids.len() - 1];
+    let consumer = consumer_weak.upgrade();
+    if let (StreamReaderAck::Nack(error), Some(c)) = (&ack, consumer.as_ref()) {
+        let c = c.ref_cell.borrow();
+        let retry_delay = {
             let mut c_i = consumer_info.ref_cell.borrow_mut();
-            if actual_record.is_err() {
-                return;
-            }
-            let record = actual_record.unwrap();
-            if record.is_none() {
-                return;
+            let retry_delay = c.next_retry_delay(&mut c_i, first_id, error);
+            if retry_delay.is_some() {
+                c_i.last_error = Some(error.clone());
             }
-            let record = record.unwrap();
-            let id = record.get_id();
-            c_i.pending_ids.push_back(id);
-            (id, record)
+            retry_delay
         };
-        let start_time = SystemTime::now()
+        if let Some(retry_delay) = retry_delay {
+            let scheduler = Arc::clone(&stream.ref_cell.borrow().scheduler);
+            let stream = Arc::clone(stream);
+            let consumer_weak = Weak::clone(consumer_weak);
+            let consumer_info = Arc::downgrade(consumer_info);
+            let stream_reader = Arc::clone(stream_reader);
+            let ids = ids.to_vec();
+            scheduler(
+                retry_delay,
+                Box::new(move || {
+                    retry_record(stream, consumer_weak, consumer_info, stream_reader, ids)
+                }),
+            );
+            return false;
+        }
+        let name = stream.ref_cell.borrow().name.clone();
+        for id in ids {
+            c.record_failed(&name, *id, error);
+        }
+    }
+
+    let mut t_s = stream.ref_cell.borrow_mut();
+    let mut c_i = consumer_info.ref_cell.borrow_mut();
+    let mut trimmed_first = c_i.ack_id(first_id, ids.len(), start_time);
+    c_i.pending_retries.remove(&(first_id.ms, first_id.seq));
+    let trim = if let Some(c) = consumer.as_ref() {
+        // consumer is still allive, fire the on acked event.
+        let c = c.ref_cell.borrow();
+        if let Some(group) = c.consumer_group.as_ref() {
+            (group.on_records_acked)(&t_s.name, ids);
+        }
+        if trimmed_first {
+            // only if we trimmed the first element we
+            // can fire the acked callback to notify
+            // that it is safe to continue from this ID
+            // in case of a crash.
+            if let Some(on_record_acked) = c.on_record_acked.as_ref() {
+                on_record_acked(&t_s.name, last_id.ms, last_id.seq);
+            }
+        }
+        c.trim
+    } else {
+        // consumer is dead, lets not trim the stream.
+        trimmed_first = false;
+        false
+    };
+    if let StreamReaderAck::Nack(msg) = ack {
+        c_i.last_error = Some(msg);
+    }
+    drop(c_i);
+    if trimmed_first && trim {
+        t_s.trim();
+    }
+    true
+}
+
+/// Process again a batch of records that failed processing, the batch is still on the
+/// consumer pending ids so its records were not trimmed from the stream. If the consumer
+/// is paused, the retry is delayed until the consumer is resumed.
+fn retry_record<T: StreamReaderRecord + 'static, C: StreamConsumer<T> + 'static>(
+    stream: Arc<RefCellWrapper<TrackedStream>>,
+    consumer_weak: Weak<RefCellWrapper<ConsumerData<T, C>>>,
+    consumer_info: Weak<RefCellWrapper<ConsumerInfo>>,
+    stream_reader: Arc<Box<StreamReaderCallback<T>>>,
+    ids: Vec<RedisModuleStreamID>,
+) {
+    // if weak ref returns None it means that stream was deleted
+    let consumer_info = match consumer_info.upgrade() {
+        Some(c_i) => c_i,
+        None => return,
+    };
+    if consumer_weak
+        .upgrade()
+        .map_or(false, |c| c.ref_cell.borrow().paused)
+    {
+        consumer_info.ref_cell.borrow_mut().paused_retries.push(ids);
+        return;
+    }
+    let name = stream.ref_cell.borrow().name.clone();
+    let records = ids
+        .iter()
+        .map(|id| {
+            stream_reader(&name, Some(*id), true).map(|r| {
+                r.filter(|r| {
+                    let r_id = r.get_id();
+                    r_id.ms == id.ms && r_id.seq == id.seq
+                })
+            })
+        })
+        .collect::<Result<Vec<Option<T>>, String>>();
+    let records = match records {
+        Ok(records) => records.into_iter().flatten().collect::<Vec<T>>(),
+        Err(_) => return,
+    };
+    let can_read_next = if records.is_empty() {
+        // records were deleted from the stream, nothing to retry.
+        let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_millis();
-        let res = {
-            let t_s = stream.ref_cell.borrow();
-            let c = consumer.ref_cell.borrow();
-            let clone_consumer_weak = Weak::clone(&consumer_weak);
-            let clone_consumer_info = Arc::downgrade(&consumer_info);
-            let clone_stream = Arc::clone(&stream);
-            let clone_stream_reader = Arc::clone(&stream_reader);
-            c.consumer.as_ref().unwrap().new_data(
-                &t_s.name,
-                record,
-                Box::new(move |ack| {
-                    // if weak ref returns None it means that stream was deleted
-                    if let Some(clone_consumer_info) = clone_consumer_info.upgrade() {
-                        let record = {
-                            let mut t_s = clone_stream.ref_cell.borrow_mut();
-                            let last_read_id = {
-                                let (trimmed_first, last_read_id) = {
-                                    let mut c_i = clone_consumer_info.ref_cell.borrow_mut();
-                                    let mut trimmed_first = c_i.ack_id(id, start_time);
-                                    if let Some(c) = clone_consumer_weak.upgrade() {
-                                        // consumer is still allive, fire the on acked event.
-                                        if trimmed_first {
-                                            // only if we trimmed the first element we
-                                            // can fire the acked callback to notify
-                                            // that it is safe to continue from this ID
-                                            // in case of a crash.
-                                            if let Some(on_record_acked) =
-                                                c.ref_cell.borrow().on_record_acked.as_ref()
-                                            {
-                                                on_record_acked(&t_s.name, id.ms, id.seq);
-                                            }
-                                        }
-                                    } else {
-                                        // consumer is dead, lets not trim the stream.
-                                        trimmed_first = false;
-                                    }
-                                    match ack {
-                                        StreamReaderAck::Ack => {}
-                                        StreamReaderAck::Nack(msg) => c_i.last_error = Some(msg),
-                                    }
-                                    (trimmed_first, c_i.last_read_id)
-                                };
-                                if trimmed_first && trim {
-                                    t_s.trim();
-                                }
-                                last_read_id
-                            };
-                            read_next_data(
-                                &t_s.name,
-                                last_read_id,
-                                false,
-                                &clone_consumer_info,
-                                &clone_stream_reader,
-                            )
-                        };
-                        send_new_data(
-                            clone_stream,
-                            clone_consumer_weak,
-                            record,
-                            clone_consumer_info,
-                            clone_stream_reader,
-                        );
-                    }
-                }),
-            )
-        };
+        handle_record_ack(
+            &stream,
+            &consumer_weak,
+            &consumer_info,
+            &stream_reader,
+            &ids,
+            now,
+            StreamReaderAck::Ack,
+        )
+    } else {
+        process_records(
+            &stream,
+            &consumer_weak,
+            &consumer_info,
+            &stream_reader,
+            ids,
+            records,
+        )
+    };
+    if can_read_next {
+        let records = read_next_records(
+            &stream,
+            &consumer_weak,
+            &consumer_info,
+            &stream_reader,
+            false,
+        );
+        send_new_data(stream, consumer_weak, records, consumer_info, stream_reader);
+    }
+}
 
-        let mut t_s = stream.ref_cell.borrow_mut();
-        let last_read_id = match res {
-            Some(r) => {
-                let (trimmed_first, last_read_id) = {
-                    let mut c_i = consumer_info.ref_cell.borrow_mut();
-                    let mut trimmed_first = c_i.ack_id(id, start_time);
-                    if let Some(c) = consumer_weak.upgrade() {
-                        // consumer is still allive, fire the on acked event.
-                        if trimmed_first {
-                            // only if we trimmed the first element we
-                            // can fire the acked callback to notify
-                            // that it is safe to continue from this ID
-                            // in case of a crash.
-                            if let Some(on_record_acked) =
-                                c.ref_cell.borrow().on_record_acked.as_ref()
-                            {
-                                on_record_acked(&t_s.name, id.ms, id.seq);
-                            }
-                        }
-                    } else {
-                        // consumer is dead, lets not trim the stream.
-                        trimmed_first = false;
-                    }
-                    match r {
-                        StreamReaderAck::Ack => {}
-                        StreamReaderAck::Nack(msg) => c_i.last_error = Some(msg),
-                    }
-                    (trimmed_first, c_i.last_read_id)
-                };
-                if trimmed_first && trim {
-                    t_s.trim();
-                }
-                last_read_id
-            }
-            None => {
-                let window = { consumer.ref_cell.borrow().window };
-                let c_i = consumer_info.ref_cell.borrow();
-                if c_i.pending_ids.len() >= window {
-                    return;
+/// Send a batch of records (which is already on the pending ids) to the consumer,
+/// `ids` are the ids of the batch records. Returns `true` if the next batch can be read.
+fn process_records<T: StreamReaderRecord + 'static, C: StreamConsumer<T> + 'static>(
+    stream: &Arc<RefCellWrapper<TrackedStream>>,
+    consumer_weak: &Weak<RefCellWrapper<ConsumerData<T, C>>>,
+    consumer_info: &Arc<RefCellWrapper<ConsumerInfo>>,
+    stream_reader: &Arc<Box<StreamReaderCallback<T>>>,
+    ids: Vec<RedisModuleStreamID>,
+    records: Vec<T>,
+) -> bool {
+    let consumer = match consumer_weak.upgrade() {
+        Some(c) => c,
+        None => return false,
+    };
+    let start_time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    let res = {
+        let t_s = stream.ref_cell.borrow();
+        let c = consumer.ref_cell.borrow();
+        let clone_consumer_weak = Weak::clone(consumer_weak);
+        let clone_consumer_info = Arc::downgrade(consumer_info);
+        let clone_stream = Arc::clone(stream);
+        let clone_stream_reader = Arc::clone(stream_reader);
+        let clone_ids = ids.clone();
+        let ack_callback = Box::new(move |ack: StreamReaderAck| {
+            // if weak ref returns None it means that stream was deleted
+            if let Some(clone_consumer_info) = clone_consumer_info.upgrade() {
+                if handle_record_ack(
+                    &clone_stream,
+                    &clone_consumer_weak,
+                    &clone_consumer_info,
+                    &clone_stream_reader,
+                    &clone_ids,
+                    start_time,
+                    ack,
+                ) {
+                    let records = read_next_records(
+                        &clone_stream,
+                        &clone_consumer_weak,
+                        &clone_consumer_info,
+                        &clone_stream_reader,
+                        false,
+                    );
+                    send_new_data(
+                        clone_stream,
+                        clone_consumer_weak,
+                        records,
+                        clone_consumer_info,
+                        clone_stream_reader,
+                    );
                 }
-                c_i.last_read_id
             }
+        });
+        let stream_consumer = c.consumer.as_ref().unwrap();
+        if c.options.batch.is_some() {
+            stream_consumer.new_batch(&t_s.name, records, ack_callback)
+        } else {
+            // without batches the records always contains a single record
+            let record = records.into_iter().next().unwrap();
+            stream_consumer.new_data(&t_s.name, record, ack_callback)
+        }
+    };
+
+    if let Some(ack) = res {
+        if handle_record_ack(
+            stream,
+            consumer_weak,
+            consumer_info,
+            stream_reader,
+            &ids,
+            start_time,
+            ack,
+        ) {
+            return true;
+        }
+    }
+    // the batch is still pending, continue only if the window allows it.
+    let window = { consumer.ref_cell.borrow().window };
+    let c_i = consumer_info.ref_cell.borrow();
+    c_i.pending_ids.len() < window
+}
+
+fn send_new_data<T: StreamReaderRecord + 'static, C: StreamConsumer<T> + 'static>(
+    stream: Arc<RefCellWrapper<TrackedStream>>,
+    consumer_weak: Weak<RefCellWrapper<ConsumerData<T, C>>>,
+    mut actual_records: Result<Vec<T>, String>,
+    consumer_info: Arc<RefCellWrapper<ConsumerInfo>>,
+    stream_reader: Arc<Box<StreamReaderCallback<T>>>,
+) {
+    if consumer_weak.strong_count() == 0 {
+        return;
+    }
+    loop {
+        let records = match actual_records {
+            Ok(records) if !records.is_empty() => records,
+            _ => return,
         };
-        actual_record = read_next_data(
-            &t_s.name,
-            last_read_id,
-            false,
+        let ids = records
+            .iter()
+            .map(|r| r.get_id())
+            .collect::<Vec<RedisModuleStreamID>>();
+        consumer_info
+            .ref_cell
+            .borrow_mut()
+            .pending_ids
+            .push_back(ids[0]);
+        if let Some(c) = consumer_weak.upgrade() {
+            if let Some(group) = c.ref_cell.borrow().consumer_group.as_ref() {
+                (group.on_records_delivered)(&stream.ref_cell.borrow().name, &ids);
+            }
+        }
+        if !process_records(
+            &stream,
+            &consumer_weak,
             &consumer_info,
             &stream_reader,
+            ids,
+            records,
+        ) {
+            return;
+        }
+        actual_records = read_next_records(
+            &stream,
+            &consumer_weak,
+            &consumer_info,
+            &stream_reader,
+            false,
         );
     }
 }
@@ -425,11 +765,13 @@ where
     pub(crate) fn new(
         stream_reader: Box<StreamReaderCallback<T>>,
         stream_trimmer: Box<StreamTrimmerCallback>,
+        scheduler: Box<StreamSchedulerCallback>,
     ) -> Self {
         StreamReaderCtx {
             consumers: Vec::new(),
             stream_reader: Arc::new(stream_reader),
             stream_trimmer: Arc::new(stream_trimmer),
+            scheduler: Arc::new(scheduler),
             tracked_streams: HashMap::new(),
         }
     }
@@ -439,6 +781,7 @@ where
         self.tracked_streams.clear();
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn add_consumer(
         &'static mut self,
         prefix: &[u8],
@@ -446,6 +789,9 @@ where
         window: usize,
         trim: bool,
         on_record_acked: Option<Box<RecordAcknowledgeCallback>>,
+        options: StreamConsumerOptions,
+        on_record_failed: Option<Box<RecordFailedCallback>>,
+        consumer_group: Option<ConsumerGroupCallbacks>,
     ) -> Arc<RefCellWrapper<ConsumerData<T, C>>> {
         let consumer_data = Arc::new(RefCellWrapper {
             ref_cell: RefCell::new(ConsumerData {
@@ -456,6 +802,10 @@ where
                 window,
                 trim,
                 on_record_acked,
+                on_record_failed,
+                options,
+                paused: false,
+                consumer_group,
             }),
         });
         self.consumers.push(Arc::downgrade(&consumer_data));
@@ -488,6 +838,7 @@ where
                     name: name.to_vec(),
                     consumers_data: Vec::new(),
                     stream_trimmer: Arc::clone(&self.stream_trimmer),
+                    scheduler: Arc::clone(&self.scheduler),
                 }),
             }))
     }
@@ -511,6 +862,38 @@ where
         stream_info.ref_cell.borrow_mut().last_read_id = Some(RedisModuleStreamID { ms, seq });
     }
 
+    /// Continue reading the streams of a consumer that was paused,
+    /// retries that were delayed while the consumer was paused are performed first.
+    pub(crate) fn on_consumer_resumed(
+        &mut self,
+        consumer_data: &Arc<RefCellWrapper<ConsumerData<T, C>>>,
+    ) {
+        let streams = consumer_data
+            .ref_cell
+            .borrow()
+            .consumed_streams
+            .iter()
+            .map(|(name, consumer_info)| (name.clone(), Arc::clone(consumer_info)))
+            .collect::<Vec<(Vec<u8>, Arc<RefCellWrapper<ConsumerInfo>>)>>();
+        for (stream, consumer_info) in streams {
+            let paused_retries =
+                std::mem::take(&mut consumer_info.ref_cell.borrow_mut().paused_retries);
+            if !paused_retries.is_empty() {
+                let tracked_stream = Arc::clone(self.get_or_create_tracked_stream(&stream));
+                for ids in paused_retries {
+                    retry_record(
+                        Arc::clone(&tracked_stream),
+                        Arc::downgrade(consumer_data),
+                        Arc::downgrade(&consumer_info),
+                        Arc::clone(&self.stream_reader),
+                        ids,
+                    );
+                }
+            }
+            self.on_stream_touched("resumed", &stream);
+        }
+    }
+
     pub(crate) fn clear_tracked_streams(&mut self) {
         self.tracked_streams.clear();
     }
@@ -536,49 +919,41 @@ where
             })
             .map(|(_, v)| {
                 let consumer = v.upgrade().unwrap();
-                let (record, consumer_info) = {
+                let consumer_info = {
                     let mut c = consumer.ref_cell.borrow_mut();
                     let (consumer_info, is_new) = c.get_or_create_consumed_stream(key);
                     if is_new {
                         let mut t_s = tracked_stream.ref_cell.borrow_mut();
                         t_s.consumers_data.push(Arc::downgrade(&consumer_info));
                     }
-                    let last_read_id = {
-                        let c_i = consumer_info.ref_cell.borrow();
-                        if c_i.pending_ids.len() >= c.window {
-                            return None;
-                        }
-                        c_i.last_read_id
-                    };
-
-                    (
-                        read_next_data(
-                            key,
-                            last_read_id,
-                            false,
-                            &consumer_info,
-                            &self.stream_reader,
-                        ),
-                        Arc::clone(&consumer_info),
-                    )
+                    if consumer_info.ref_cell.borrow().pending_ids.len() >= c.window {
+                        return None;
+                    }
+                    consumer_info
                 };
-                let res = (Weak::clone(v), record, consumer_info);
-                Some(res)
+                let records = read_next_records(
+                    &tracked_stream,
+                    v,
+                    &consumer_info,
+                    &self.stream_reader,
+                    false,
+                );
+                Some((Weak::clone(v), records, consumer_info))
             })
             .collect::<Vec<
                 Option<(
                     Weak<RefCellWrapper<ConsumerData<T, C>>>,
-                    Result<Option<T>, String>,
+                    Result<Vec<T>, String>,
                     Arc<RefCellWrapper<ConsumerInfo>>,
                 )>,
             >>()
             .into_iter()
             .map(|res| {
-                if let Some((consumer_weak, record, consumer_info)) = res {
+                if let Some((consumer_weak, records, consumer_info)) = res {
                     send_new_data(
                         Arc::clone(&tracked_stream),
                         consumer_weak,
-                        record,
+                        records,
                         consumer_info,
                         Arc::clone(&self.stream_reader),
                     );
@@ -114,6 +114,39 @@ impl GearsStreamConsumer {
             permissions,
         }
     }
+
+    fn verify_key_permissions(&self, stream_name: &[u8]) -> Result<(), StreamReaderAck> {
+        let user = &self.lib_meta_data.user;
+        let key_redis_str = RedisString::create_from_slice(std::ptr::null_mut(), stream_name);
+        get_ctx()
+            .acl_check_key_permission(user, &key_redis_str, &self.permissions)
+            .map_err(|e| {
+                StreamReaderAck::Nack(GearsApiError::new(format!(
+                    "User '{}' has no permissions on key '{}', {}.",
+                    user,
+                    std::str::from_utf8(stream_name).unwrap_or("[binary data]"),
+                    e
+                )))
+            })
+    }
+}
+
+fn to_stream_reader_ack(ack: StreamRecordAck) -> StreamReaderAck {
+    match ack {
+        StreamRecordAck::Ack => StreamReaderAck::Ack,
+        StreamRecordAck::Nack(msg) => StreamReaderAck::Nack(msg),
+    }
+}
+
+fn wrap_ack_callback(
+    ack_callback: Box<dyn FnOnce(StreamReaderAck) + Send>,
+) -> Box<dyn FnOnce(StreamRecordAck) + Send> {
+    Box::new(|ack| {
+        // here we must take the redis lock
+        let ctx = ThreadSafeContext::new();
+        let _gaurd = ctx.lock();
+        ack_callback(to_stream_reader_ack(ack))
+    })
 }
 
 impl StreamConsumer<GearsStreamRecord> for GearsStreamConsumer {
@@ -123,16 +156,8 @@ impl StreamConsumer<GearsStreamRecord> for GearsStreamConsumer {
         record: GearsStreamRecord,
         ack_callback: Box<dyn FnOnce(StreamReaderAck) + Send>,
     ) -> Option<StreamReaderAck> {
-        let user = &self.lib_meta_data.user;
-        let key_redis_str = RedisString::create_from_slice(std::ptr::null_mut(), stream_name);
-        if let Err(e) = get_ctx().acl_check_key_permission(user, &key_redis_str, &self.permissions)
-        {
-            return Some(StreamReaderAck::Nack(GearsApiError::new(format!(
-                "User '{}' has no permissions on key '{}', {}.",
-                user,
-                std::str::from_utf8(stream_name).unwrap_or("[binary data]"),
-                e
-            ))));
+        if let Err(ack) = self.verify_key_permissions(stream_name) {
+            return Some(ack);
         }
 
         let res = {
@@ -141,20 +166,35 @@ impl StreamConsumer<GearsStreamRecord> for GearsStreamConsumer {
                 stream_name,
                 Box::new(record),
                 &StreamRunCtx::new(&self.lib_meta_data, self.flags),
-                Box::new(|ack| {
-                    // here we must take the redis lock
-                    let ctx = ThreadSafeContext::new();
-                    let _gaurd = ctx.lock();
-                    ack_callback(match ack {
-                        StreamRecordAck::Ack => StreamReaderAck::Ack,
-                        StreamRecordAck::Nack(msg) => StreamReaderAck::Nack(msg),
-                    })
-                }),
+                wrap_ack_callback(ack_callback),
+            )
+        };
+        res.map(to_stream_reader_ack)
+    }
+
+    fn new_batch(
+        &self,
+        stream_name: &[u8],
+        records: Vec<GearsStreamRecord>,
+        ack_callback: Box<dyn FnOnce(StreamReaderAck) + Send>,
+    ) -> Option<StreamReaderAck> {
+        if let Err(ack) = self.verify_key_permissions(stream_name) {
+            return Some(ack);
+        }
+
+        let records = records
+            .into_iter()
+            .map(|r| Box::new(r) as Box<dyn StreamRecordInterface + Send>)
+            .collect();
+        let res = {
+            let _notification_blocker = get_notification_blocker();
+            self.ctx.process_batch(
+                stream_name,
+                records,
+                &StreamRunCtx::new(&self.lib_meta_data, self.flags),
+                wrap_ack_callback(ack_callback),
             )
         };
-        res.map(|r| match r {
-            StreamRecordAck::Ack => StreamReaderAck::Ack,
-            StreamRecordAck::Nack(msg) => StreamReaderAck::Nack(msg),
-        })
+        res.map(to_stream_reader_ack)
     }
 }
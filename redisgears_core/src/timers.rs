@@ -0,0 +1,389 @@
+/*
+ * Copyright Redis Ltd. 2018 - present
+ * Licensed under your choice of the Redis Source Available License 2.0 (RSALv2) or
+ * the Server Side Public License v1 (SSPLv1).
+ */
+
+//! Timers registered by libraries. A timer runs its callback periodically,
+//! either every fixed interval or according to a cron expression. Timers only
+//! run on the primary, they are started when the library is loaded on a primary
+//! (or when a replica is promoted) and stopped when the primary becomes a replica.
+
+use redisgears_plugin_api::redisgears_plugin_api::{
+    timer_ctx::TimerSchedule, GearsApiError, RefCellWrapper,
+};
+
+use crate::{get_ctx, get_globals};
+
+use std::cell::RefCell;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub(crate) type TimerCallback =
+    Box<dyn Fn(Box<dyn FnOnce(Result<(), GearsApiError>) + Send + Sync>)>;
+
+/// Cron expressions are looked up to this amount of days ahead, an expression
+/// that does not match any time within this range is considered as never matching.
+const CRON_MAX_LOOKAHEAD_DAYS: u64 = 366 * 8;
+
+/// A parsed cron expression, each field holds the allowed values as a bit set.
+#[derive(Clone, Debug)]
+struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    days_of_month_restricted: bool,
+    days_of_week_restricted: bool,
+}
+
+/// Parse a single cron field, accepts a comma separated list of `*`, `<n>`,
+/// `<a>-<b>`, each optionally followed by `/<step>`.
+fn parse_cron_field(field: &str, min: u64, max: u64) -> Result<u64, String> {
+    let mut res = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u64>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("invalid step '{}'", step)),
+            },
+            None => (part, 1),
+        };
+        let parse_value = |v: &str| match v.parse::<u64>() {
+            Ok(v) if (min..=max).contains(&v) => Ok(v),
+            _ => Err(format!(
+                "invalid value '{}', expected a value between {} and {}",
+                v, min, max
+            )),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (parse_value(start)?, parse_value(end)?),
+                None => {
+                    let start = parse_value(range)?;
+                    // `<n>/<step>` means from n to the end of the range
+                    (start, if part.contains('/') { max } else { start })
+                }
+            },
+        };
+        if start > end {
+            return Err(format!("invalid range '{}'", range));
+        }
+        for v in (start..=end).step_by(step as usize) {
+            res |= 1 << v;
+        }
+    }
+    Ok(res)
+}
+
+/// Returns the month (1-12) and the day of the month (1-31) of the given
+/// amount of days since the epoch.
+fn month_and_day_from_days(days: u64) -> (u64, u64) {
+    let z = days + 719468;
+    let doe = z % 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (month, day)
+}
+
+impl CronSchedule {
+    fn parse(expression: &str) -> Result<CronSchedule, String> {
+        let expression = match expression.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            e => e,
+        };
+        let fields = expression.split_whitespace().collect::<Vec<&str>>();
+        if fields.len() != 5 {
+            return Err(
+                "expected 5 fields (minute, hour, day of month, month, day of week)".into(),
+            );
+        }
+        let mut days_of_week = parse_cron_field(fields[4], 0, 7)?;
+        // both 0 and 7 stands for Sunday
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week |= 1;
+        }
+        Ok(CronSchedule {
+            minutes: parse_cron_field(fields[0], 0, 59)?,
+            hours: parse_cron_field(fields[1], 0, 23)?,
+            days_of_month: parse_cron_field(fields[2], 1, 31)?,
+            months: parse_cron_field(fields[3], 1, 12)?,
+            days_of_week,
+            days_of_month_restricted: !fields[2].starts_with('*'),
+            days_of_week_restricted: !fields[4].starts_with('*'),
+        })
+    }
+
+    fn is_day_matched(&self, days: u64) -> bool {
+        let (month, day) = month_and_day_from_days(days);
+        if self.months & (1 << month) == 0 {
+            return false;
+        }
+        // the epoch was on Thursday
+        let weekday = (days + 4) % 7;
+        let day_of_month_matched = self.days_of_month & (1 << day) != 0;
+        let day_of_week_matched = self.days_of_week & (1 << weekday) != 0;
+        // same as the standard cron, if both days fields are restricted
+        // it is enough for one of them to match.
+        if self.days_of_month_restricted && self.days_of_week_restricted {
+            day_of_month_matched || day_of_week_matched
+        } else {
+            day_of_month_matched && day_of_week_matched
+        }
+    }
+
+    /// Returns the first time (in seconds since the epoch, UTC) that matches
+    /// the expression and comes after the given time.
+    fn next_after(&self, now: u64) -> Option<u64> {
+        let mut t = (now / 60 + 1) * 60;
+        let max_days = now / 86400 + CRON_MAX_LOOKAHEAD_DAYS;
+        while t / 86400 <= max_days {
+            if !self.is_day_matched(t / 86400) {
+                t = (t / 86400 + 1) * 86400;
+                continue;
+            }
+            if self.hours & (1 << ((t % 86400) / 3600)) == 0 {
+                t = (t / 3600 + 1) * 3600;
+                continue;
+            }
+            if self.minutes & (1 << ((t % 3600) / 60)) == 0 {
+                t += 60;
+                continue;
+            }
+            return Some(t);
+        }
+        None
+    }
+}
+
+#[derive(Clone, Debug)]
+enum ParsedTimerSchedule {
+    Interval(Duration),
+    Cron(CronSchedule),
+}
+
+impl ParsedTimerSchedule {
+    fn parse(schedule: &TimerSchedule) -> Result<ParsedTimerSchedule, GearsApiError> {
+        match schedule {
+            TimerSchedule::Interval(0) => {
+                Err(GearsApiError::new("Timer interval must be greater than 0"))
+            }
+            TimerSchedule::Interval(ms) => {
+                Ok(ParsedTimerSchedule::Interval(Duration::from_millis(*ms)))
+            }
+            TimerSchedule::Cron(expression) => {
+                let cron = CronSchedule::parse(expression).map_err(|e| {
+                    GearsApiError::new(format!("Invalid cron expression '{}', {}", expression, e))
+                })?;
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                if cron.next_after(now).is_none() {
+                    return Err(GearsApiError::new(format!(
+                        "Cron expression '{}' never matches",
+                        expression
+                    )));
+                }
+                Ok(ParsedTimerSchedule::Cron(cron))
+            }
+        }
+    }
+
+    /// Returns the next run time (since the epoch) that comes after the given time.
+    fn next_run(&self, now: Duration) -> Option<Duration> {
+        match self {
+            ParsedTimerSchedule::Interval(interval) => Some(now + *interval),
+            ParsedTimerSchedule::Cron(cron) => {
+                cron.next_after(now.as_secs()).map(Duration::from_secs)
+            }
+        }
+    }
+}
+
+/// Runtime statistics of a timer, times are in milliseconds since the epoch.
+#[derive(Clone, Default)]
+pub(crate) struct TimerStats {
+    pub(crate) num_runs: usize,
+    pub(crate) num_finished: usize,
+    pub(crate) num_success: usize,
+    pub(crate) num_failed: usize,
+    pub(crate) num_skipped: usize, // runs that were skipped because the previous run did not finish
+    pub(crate) running: bool,
+    pub(crate) last_run: Option<u128>,
+    pub(crate) next_run: Option<u128>,
+    pub(crate) last_error: Option<GearsApiError>,
+    pub(crate) last_execution_time: u128,
+    pub(crate) total_execution_time: u128,
+    /// Identifies the current run, an ack of a run that already timed out is ignored.
+    run_id: usize,
+}
+
+impl TimerStats {
+    fn finish_run(&mut self, start_time: SystemTime, res: Result<(), GearsApiError>) {
+        let duration = match SystemTime::now().duration_since(start_time) {
+            Ok(d) => d.as_millis(),
+            Err(_) => 0,
+        };
+        self.running = false;
+        self.num_finished += 1;
+        self.last_execution_time = duration;
+        self.total_execution_time += duration;
+        if let Err(e) = res {
+            self.num_failed += 1;
+            self.last_error = Some(e);
+        } else {
+            self.num_success += 1;
+        }
+    }
+}
+
+pub(crate) struct GearsTimer {
+    schedule: TimerSchedule,
+    parsed_schedule: ParsedTimerSchedule,
+    callback: Option<TimerCallback>,
+    /// Increased each time the timer is scheduled or stopped, a fired
+    /// Redis timer that belongs to an older generation is ignored.
+    generation: usize,
+    stats: Arc<RefCellWrapper<TimerStats>>,
+}
+
+impl GearsTimer {
+    pub(crate) fn new(
+        schedule: TimerSchedule,
+        callback: TimerCallback,
+    ) -> Result<GearsTimer, GearsApiError> {
+        Ok(GearsTimer {
+            parsed_schedule: ParsedTimerSchedule::parse(&schedule)?,
+            schedule,
+            callback: Some(callback),
+            generation: 0,
+            stats: Arc::new(RefCellWrapper {
+                ref_cell: RefCell::new(TimerStats::default()),
+            }),
+        })
+    }
+
+    pub(crate) fn set_callback(&mut self, callback: TimerCallback) -> TimerCallback {
+        let old_callback = self.callback.take();
+        self.callback = Some(callback);
+        old_callback.unwrap()
+    }
+
+    /// Set the timer schedule, returns the old schedule. The new schedule
+    /// takes effect the next time the timer is scheduled.
+    pub(crate) fn set_schedule(
+        &mut self,
+        schedule: TimerSchedule,
+    ) -> Result<TimerSchedule, GearsApiError> {
+        self.parsed_schedule = ParsedTimerSchedule::parse(&schedule)?;
+        Ok(std::mem::replace(&mut self.schedule, schedule))
+    }
+
+    pub(crate) fn get_schedule(&self) -> &TimerSchedule {
+        &self.schedule
+    }
+
+    pub(crate) fn get_stats(&self) -> TimerStats {
+        self.stats.ref_cell.borrow().clone()
+    }
+}
+
+fn run_timer(timer: &Arc<RefCell<GearsTimer>>) {
+    let t = timer.borrow();
+    let start_time = SystemTime::now();
+    let run_id = {
+        let mut stats = t.stats.ref_cell.borrow_mut();
+        if stats.running {
+            let timeout =
+                Duration::from_millis(get_globals().config.timer_run_timeout.timeout as u64);
+            let last_run = Duration::from_millis(stats.last_run.unwrap_or_default() as u64);
+            let last_start_time = UNIX_EPOCH + last_run;
+            if start_time
+                .duration_since(last_start_time)
+                .unwrap_or_default()
+                < timeout
+            {
+                stats.num_skipped += 1;
+                return;
+            }
+            // the previous run did not finish in time, consider it as failed so it
+            // will not block the timer forever. A late ack of this run is ignored.
+            get_ctx().log_warning(&format!(
+                "Timer run did not finish within {}ms, considering it as failed.",
+                timeout.as_millis()
+            ));
+            stats.finish_run(
+                last_start_time,
+                Err(GearsApiError::new("Timer run timed out")),
+            );
+        }
+        stats.running = true;
+        stats.num_runs += 1;
+        stats.run_id += 1;
+        stats.last_run = start_time
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_millis());
+        stats.run_id
+    };
+    let stats_ref = Arc::clone(&t.stats);
+
+    (t.callback.as_ref().unwrap())(Box::new(move |res| {
+        let mut stats = stats_ref.ref_cell.borrow_mut();
+        if stats.run_id != run_id {
+            return;
+        }
+        stats.finish_run(start_time, res);
+    }));
+}
+
+fn on_timer_fired(timer: Weak<RefCell<GearsTimer>>, generation: usize) {
+    let timer = match timer.upgrade() {
+        Some(t) => t,
+        None => return,
+    };
+    if timer.borrow().generation != generation {
+        return;
+    }
+    // schedule the next run first so a failing run will not stop the timer.
+    schedule_timer(&timer);
+    run_timer(&timer);
+}
+
+/// Schedule the next run of the timer, a previously scheduled run is cancelled.
+pub(crate) fn schedule_timer(timer: &Arc<RefCell<GearsTimer>>) {
+    let mut t = timer.borrow_mut();
+    t.generation += 1;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let next_run = t.parsed_schedule.next_run(now);
+    t.stats.ref_cell.borrow_mut().next_run = next_run.map(|v| v.as_millis());
+    if let Some(next_run) = next_run {
+        let weak_timer = Arc::downgrade(timer);
+        let generation = t.generation;
+        let job: Box<dyn FnOnce()> = Box::new(move || on_timer_fired(weak_timer, generation));
+        get_ctx().create_timer(
+            next_run.saturating_sub(now),
+            |_ctx, job: Box<dyn FnOnce()>| job(),
+            job,
+        );
+    }
+}
+
+/// Cancel the next run of the timer, a run that is already in progress is not affected.
+pub(crate) fn stop_timer(timer: &Arc<RefCell<GearsTimer>>) {
+    let mut t = timer.borrow_mut();
+    t.generation += 1;
+    t.stats.ref_cell.borrow_mut().next_run = None;
+}
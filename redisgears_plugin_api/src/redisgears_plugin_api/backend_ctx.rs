@@ -8,7 +8,9 @@ use crate::redisgears_plugin_api::load_library_ctx::LibraryCtxInterface;
 use crate::redisgears_plugin_api::redisai_interface::AITensorInterface;
 use crate::redisgears_plugin_api::CallResult;
 use crate::redisgears_plugin_api::GearsApiError;
+use crate::{Deserialize, Serialize};
 use std::alloc::GlobalAlloc;
+use std::collections::HashMap;
 
 pub trait CompiledLibraryInterface {
     fn log(&self, msg: &str);
@@ -35,6 +37,15 @@ pub struct BackendCtx {
     pub get_lock_timeout: Box<dyn Fn() -> u128 + 'static>,
 }
 
+/// A library built out of multiple named modules. The modules are loaded
+/// as ES modules starting from the entry module, imports are resolved
+/// against the modules of the bundle.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LibraryBundle {
+    pub entry: String,
+    pub modules: HashMap<String, String>,
+}
+
 pub trait BackendCtxInterface {
     fn get_name(&self) -> &'static str;
     fn initialize(&self, backend_ctx: BackendCtx) -> Result<(), GearsApiError>;
@@ -44,5 +55,16 @@ pub trait BackendCtxInterface {
         config: Option<&String>,
         compiled_library_api: Box<dyn CompiledLibraryInterface + Send + Sync>,
     ) -> Result<Box<dyn LibraryCtxInterface>, GearsApiError>;
+    fn compile_library_bundle(
+        &mut self,
+        _bundle: &LibraryBundle,
+        _config: Option<&String>,
+        _compiled_library_api: Box<dyn CompiledLibraryInterface + Send + Sync>,
+    ) -> Result<Box<dyn LibraryCtxInterface>, GearsApiError> {
+        Err(GearsApiError::new(format!(
+            "Backend {} does not support library bundles",
+            self.get_name()
+        )))
+    }
     fn debug(&mut self, args: &[&str]) -> Result<CallResult, GearsApiError>;
 }
@@ -0,0 +1,38 @@
+/*
+ * Copyright Redis Ltd. 2018 - present
+ * Licensed under your choice of the Redis Source Available License 2.0 (RSALv2) or
+ * the Server Side Public License v1 (SSPLv1).
+ */
+
+use crate::redisgears_plugin_api::run_function_ctx::BackgroundRunFunctionCtxInterface;
+use crate::redisgears_plugin_api::run_function_ctx::RedisClientCtxInterface;
+
+use super::GearsApiError;
+
+pub enum RegisteredChannels<'a> {
+    Channel(&'a [u8]),
+    /// A glob style pattern, same as the pattern given to the Redis `PSUBSCRIBE` command.
+    Pattern(&'a [u8]),
+}
+
+/// Optional settings of a channel consumer.
+#[derive(Clone, Debug, Default)]
+pub struct ChannelConsumerOptions {
+    /// Consume messages on replicas as well, by default messages are only consumed on the primary.
+    pub run_on_replicas: bool,
+}
+
+pub trait ChannelConsumerRunCtxInterface {
+    fn get_redis_client(&self) -> Box<dyn RedisClientCtxInterface>;
+    fn get_background_redis_client(&self) -> Box<dyn BackgroundRunFunctionCtxInterface>;
+}
+
+pub trait ChannelConsumerCtxInterface {
+    fn on_message(
+        &self,
+        channel: &[u8],
+        message: &[u8],
+        run_ctx: Box<dyn ChannelConsumerRunCtxInterface>,
+        ack_callback: Box<dyn FnOnce(Result<(), GearsApiError>) + Send + Sync>,
+    );
+}
@@ -7,6 +7,64 @@
 use crate::redisgears_plugin_api::run_function_ctx::RunFunctionCtxInterface;
 use crate::redisgears_plugin_api::FunctionCallResult;
 
+use super::GearsApiError;
+
+use std::str::FromStr;
+
+/// The type of a function argument, the argument is validated
+/// to be of the given type before the function is invoked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FunctionArgumentType {
+    /// A 64 bit signed integer.
+    Int,
+    /// A double precision floating point number.
+    Float,
+    /// A valid utf8 string.
+    String,
+    /// A valid json.
+    Json,
+    /// Any binary data.
+    Binary,
+}
+
+impl FunctionArgumentType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FunctionArgumentType::Int => "int",
+            FunctionArgumentType::Float => "float",
+            FunctionArgumentType::String => "string",
+            FunctionArgumentType::Json => "json",
+            FunctionArgumentType::Binary => "binary",
+        }
+    }
+}
+
+impl FromStr for FunctionArgumentType {
+    type Err = GearsApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "int" => Ok(FunctionArgumentType::Int),
+            "float" => Ok(FunctionArgumentType::Float),
+            "string" => Ok(FunctionArgumentType::String),
+            "json" => Ok(FunctionArgumentType::Json),
+            "binary" => Ok(FunctionArgumentType::Binary),
+            _ => Err(GearsApiError::new(format!("Unknown argument type '{}'", s))),
+        }
+    }
+}
+
+/// A single entry on a function arguments schema.
+#[derive(Clone, Debug)]
+pub struct FunctionArgument {
+    pub name: String,
+    pub arg_type: FunctionArgumentType,
+    /// The argument can be omitted, only the last arguments of the schema can be optional.
+    pub optional: bool,
+    /// The argument can be repeated, only the last argument of the schema can be variadic.
+    pub variadic: bool,
+}
+
 pub trait FunctionCtxInterface {
     fn call(&self, run_ctx: &mut dyn RunFunctionCtxInterface) -> FunctionCallResult;
 }
@@ -0,0 +1,37 @@
+/*
+ * Copyright Redis Ltd. 2018 - present
+ * Licensed under your choice of the Redis Source Available License 2.0 (RSALv2) or
+ * the Server Side Public License v1 (SSPLv1).
+ */
+
+use super::GearsApiError;
+
+/// The end of a list to push to or pop from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListEnd {
+    Head,
+    Tail,
+}
+
+/// A key that was opened by a function in order to access it directly, without
+/// running a command. Operations are only valid while Redis is locked.
+pub trait KeyCtxInterface {
+    /// Returns the key type: `none`, `string`, `list`, `set`, `zset`, `hash`, `stream` or `module`.
+    fn key_type(&self) -> &'static str;
+    /// Delete the key, returns `true` if the key existed.
+    fn delete(&self) -> Result<bool, GearsApiError>;
+    fn string_get(&self) -> Result<Option<Vec<u8>>, GearsApiError>;
+    fn string_set(&self, val: &[u8]) -> Result<(), GearsApiError>;
+    fn hash_get(&self, field: &[u8]) -> Result<Option<Vec<u8>>, GearsApiError>;
+    /// Set a hash field, returns `true` if the field is new.
+    fn hash_set(&self, field: &[u8], val: &[u8]) -> Result<bool, GearsApiError>;
+    /// Delete a hash field, returns `true` if the field existed.
+    fn hash_del(&self, field: &[u8]) -> Result<bool, GearsApiError>;
+    fn list_push(&self, val: &[u8], end: ListEnd) -> Result<(), GearsApiError>;
+    fn list_pop(&self, end: ListEnd) -> Result<Option<Vec<u8>>, GearsApiError>;
+    /// Returns the length of the value, i.e. the number of elements on a list or
+    /// the number of fields on a hash.
+    fn value_length(&self) -> usize;
+    /// Add a record with an auto generated id to a stream, returns the id of the new record.
+    fn stream_add(&self, fields: &[(&[u8], &[u8])]) -> Result<String, GearsApiError>;
+}
@@ -7,9 +7,71 @@
 use crate::redisgears_plugin_api::run_function_ctx::BackgroundRunFunctionCtxInterface;
 use crate::redisgears_plugin_api::run_function_ctx::RedisClientCtxInterface;
 use std::any::Any;
+use std::str::FromStr;
 
 use super::GearsApiError;
 
+bitflags::bitflags! {
+    /// Classes of keyspace events, same as the classes that can be
+    /// set on the Redis `notify-keyspace-events` configuration.
+    #[derive(Default)]
+    pub struct KeysNotificationsEventClasses: u16 {
+        const GENERIC = 0x0001;
+        const STRING = 0x0002;
+        const LIST = 0x0004;
+        const SET = 0x0008;
+        const HASH = 0x0010;
+        const ZSET = 0x0020;
+        const EXPIRED = 0x0040;
+        const EVICTED = 0x0080;
+        const STREAM = 0x0100;
+        const MODULE = 0x0200;
+        const KEY_MISS = 0x0400;
+    }
+}
+
+impl FromStr for KeysNotificationsEventClasses {
+    type Err = GearsApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "generic" => Ok(Self::GENERIC),
+            "string" => Ok(Self::STRING),
+            "list" => Ok(Self::LIST),
+            "set" => Ok(Self::SET),
+            "hash" => Ok(Self::HASH),
+            "zset" => Ok(Self::ZSET),
+            "expired" => Ok(Self::EXPIRED),
+            "evicted" => Ok(Self::EVICTED),
+            "stream" => Ok(Self::STREAM),
+            "module" => Ok(Self::MODULE),
+            "key_miss" => Ok(Self::KEY_MISS),
+            _ => Err(GearsApiError::new(format!("Unknown event class '{}'", s))),
+        }
+    }
+}
+
+/// Optional settings of a notifications consumer.
+#[derive(Clone, Debug, Default)]
+pub struct KeysNotificationsConsumerOptions {
+    /// Only fire the consumer on the given events and on the events of the given
+    /// classes. If both are empty the consumer is fired on all events.
+    pub events: Vec<String>,
+    pub event_classes: KeysNotificationsEventClasses,
+    /// Coalesce the events of each key that happen within the given time (in milliseconds)
+    /// into a single invocation, `None` means each event is fired separately.
+    pub coalesce_ms: Option<u64>,
+}
+
+impl KeysNotificationsConsumerOptions {
+    pub fn is_subscribed(&self, event: &str, class: KeysNotificationsEventClasses) -> bool {
+        if self.events.is_empty() && self.event_classes.is_empty() {
+            return true;
+        }
+        self.event_classes.intersects(class) || self.events.iter().any(|e| e == event)
+    }
+}
+
 pub trait NotificationFiredDataInterface {}
 
 pub trait NotificationRunCtxInterface {
@@ -25,6 +87,18 @@ pub trait KeysNotificationsConsumerCtxInterface {
         notification_ctx: Box<dyn NotificationRunCtxInterface>,
     ) -> Option<Box<dyn Any>>;
 
+    /// Called when coalesced events of a key are fired, `events` are the distinct
+    /// events by the order they first happened, `last_event` is the most recent event
+    /// and `count` is the total number of events.
+    fn on_coalesced_notifications_fired(
+        &self,
+        events: &[String],
+        last_event: &str,
+        count: usize,
+        key: &[u8],
+        notification_ctx: Box<dyn NotificationRunCtxInterface>,
+    ) -> Option<Box<dyn Any>>;
+
     fn post_command_notification(
         &self,
         notificaion_data: Option<Box<dyn Any>>,
@@ -4,11 +4,21 @@
  * the Server Side Public License v1 (SSPLv1).
  */
 
-use crate::redisgears_plugin_api::function_ctx::FunctionCtxInterface;
-use crate::redisgears_plugin_api::keys_notifications_consumer_ctx::KeysNotificationsConsumerCtxInterface;
+use crate::redisgears_plugin_api::channel_consumer_ctx::{
+    ChannelConsumerCtxInterface, ChannelConsumerOptions, RegisteredChannels,
+};
+use crate::redisgears_plugin_api::function_ctx::{FunctionArgument, FunctionCtxInterface};
+use crate::redisgears_plugin_api::keys_notifications_consumer_ctx::{
+    KeysNotificationsConsumerCtxInterface, KeysNotificationsConsumerOptions,
+};
 use crate::redisgears_plugin_api::run_function_ctx::BackgroundRunFunctionCtxInterface;
 use crate::redisgears_plugin_api::run_function_ctx::RemoteFunctionData;
+use crate::redisgears_plugin_api::server_events_ctx::{
+    ServerEventConsumerCtxInterface, ServerEventType,
+};
+use crate::redisgears_plugin_api::stream_ctx::StreamConsumerOptions;
 use crate::redisgears_plugin_api::stream_ctx::StreamCtxInterface;
+use crate::redisgears_plugin_api::timer_ctx::{TimerCtxInterface, TimerSchedule};
 use crate::redisgears_plugin_api::GearsApiError;
 
 pub trait LibraryCtxInterface {
@@ -21,6 +31,11 @@ pub trait LibraryCtxInterface {
 pub enum RegisteredKeys<'a> {
     Key(&'a [u8]),
     Prefix(&'a [u8]),
+    /// Match keys that starts with any of the given prefixes.
+    Prefixes(Vec<&'a [u8]>),
+    Suffix(&'a [u8]),
+    /// A glob style pattern, same as the pattern given to the Redis `KEYS` command.
+    Pattern(&'a [u8]),
 }
 
 bitflags::bitflags! {
@@ -34,6 +49,9 @@ bitflags::bitflags! {
         const ALLOW_OOM = 0x02;
         /// TODO
         const RAW_ARGUMENTS = 0x04;
+        /// The function can only access the keys it declared, and a single
+        /// command can not access keys that belong to different slots.
+        const STRICT_KEYS = 0x08;
     }
 }
 
@@ -45,12 +63,27 @@ pub type RemoteFunctionCtx = Box<
     ),
 >;
 
+pub type ExportedFunctionCtx =
+    Box<dyn Fn(Vec<RemoteFunctionData>) -> Result<RemoteFunctionData, GearsApiError>>;
+
+/// A library that was imported by another library, gives access
+/// to the functions exported by the imported library.
+pub trait ImportedLibraryCtxInterface {
+    fn get_exported_functions(&self) -> Vec<String>;
+    fn call_exported_function(
+        &self,
+        name: &str,
+        args: Vec<RemoteFunctionData>,
+    ) -> Result<RemoteFunctionData, GearsApiError>;
+}
+
 pub trait LoadLibraryCtxInterface {
     fn register_function(
         &mut self,
         name: &str,
         function_ctx: Box<dyn FunctionCtxInterface>,
         flags: FunctionFlags,
+        args_schema: Option<Vec<FunctionArgument>>,
     ) -> Result<(), GearsApiError>;
     fn register_remote_task(
         &mut self,
@@ -64,11 +97,40 @@ pub trait LoadLibraryCtxInterface {
         stream_ctx: Box<dyn StreamCtxInterface>,
         window: usize,
         trim: bool,
+        options: StreamConsumerOptions,
     ) -> Result<(), GearsApiError>;
     fn register_key_space_notification_consumer(
         &mut self,
         name: &str,
         key: RegisteredKeys,
         keys_notifications_consumer_ctx: Box<dyn KeysNotificationsConsumerCtxInterface>,
+        options: KeysNotificationsConsumerOptions,
+    ) -> Result<(), GearsApiError>;
+    fn register_timer(
+        &mut self,
+        name: &str,
+        schedule: TimerSchedule,
+        timer_ctx: Box<dyn TimerCtxInterface>,
+    ) -> Result<(), GearsApiError>;
+    fn register_server_event_consumer(
+        &mut self,
+        event: ServerEventType,
+        server_event_consumer_ctx: Box<dyn ServerEventConsumerCtxInterface>,
     ) -> Result<(), GearsApiError>;
+    fn register_channel_consumer(
+        &mut self,
+        name: &str,
+        channel: RegisteredChannels,
+        channel_consumer_ctx: Box<dyn ChannelConsumerCtxInterface>,
+        options: ChannelConsumerOptions,
+    ) -> Result<(), GearsApiError>;
+    fn register_exported_function(
+        &mut self,
+        name: &str,
+        exported_function_ctx: ExportedFunctionCtx,
+    ) -> Result<(), GearsApiError>;
+    fn import_library(
+        &mut self,
+        name: &str,
+    ) -> Result<Box<dyn ImportedLibraryCtxInterface>, GearsApiError>;
 }
@@ -8,12 +8,16 @@ use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 
 pub mod backend_ctx;
+pub mod channel_consumer_ctx;
 pub mod function_ctx;
+pub mod key_ctx;
 pub mod keys_notifications_consumer_ctx;
 pub mod load_library_ctx;
 pub mod redisai_interface;
 pub mod run_function_ctx;
+pub mod server_events_ctx;
 pub mod stream_ctx;
+pub mod timer_ctx;
 
 #[derive(Clone)]
 pub struct GearsApiError {
@@ -6,15 +6,36 @@
 
 use crate::{Deserialize, Serialize};
 
+use crate::redisgears_plugin_api::key_ctx::KeyCtxInterface;
 use crate::redisgears_plugin_api::redisai_interface::{AIModelInterface, AIScriptInterface};
 use crate::redisgears_plugin_api::CallResult;
 use crate::redisgears_plugin_api::GearsApiError;
 
+/// Options of a key space scan.
+#[derive(Clone, Debug, Default)]
+pub struct ScanOptions {
+    /// Only return keys that match the given glob style pattern.
+    pub pattern: Option<Vec<u8>>,
+    /// Only return keys of the given type (`string`, `list`, `set`, `zset`, `hash` or `stream`).
+    pub key_type: Option<String>,
+    /// The minimal amount of keys on each batch (except of the last one).
+    pub count: Option<usize>,
+}
+
+pub trait ScanCursorInterface {
+    /// Returns the next batch of keys, or `None` if the scan is done.
+    fn next_batch(&mut self) -> Option<Vec<Vec<u8>>>;
+}
+
 pub trait RedisClientCtxInterface: Send + Sync {
     fn call(&self, command: &str, args: &[&[u8]]) -> CallResult;
     fn get_background_redis_client(&self) -> Box<dyn BackgroundRunFunctionCtxInterface>;
     fn open_ai_model(&self, name: &str) -> Result<Box<dyn AIModelInterface>, GearsApiError>;
     fn open_ai_script(&self, name: &str) -> Result<Box<dyn AIScriptInterface>, GearsApiError>;
+    /// Scan the key space, the cursor must only be used while Redis is locked.
+    fn scan(&self, options: ScanOptions) -> Result<Box<dyn ScanCursorInterface>, GearsApiError>;
+    /// Open a key for direct access, the key must only be used while Redis is locked.
+    fn open_key(&self, name: &[u8]) -> Result<Box<dyn KeyCtxInterface>, GearsApiError>;
 }
 
 pub trait ReplyCtxInterface: Send + Sync {
@@ -26,6 +47,25 @@ pub trait ReplyCtxInterface: Send + Sync {
     fn reply_with_slice(&self, val: &[u8]);
     fn reply_with_array(&self, size: usize);
     fn reply_with_null(&self);
+    /// Start a map reply with the given amount of entries, each entry is
+    /// followed by 2 replies, the key and the value.
+    /// Resp2 clients get a flat array of keys and values.
+    fn reply_with_map(&self, size: usize);
+    /// Start a set reply with the given amount of elements.
+    /// Resp2 clients get an array.
+    fn reply_with_set(&self, size: usize);
+    /// Reply with a boolean, Resp2 clients get an integer (1 or 0).
+    fn reply_with_bool(&self, val: bool);
+    /// Reply with a big number, Resp2 clients get a bulk string.
+    fn reply_with_big_number(&self, val: &str);
+    /// Reply with a verbatim string of the given 3 characters extension (`txt`, `mkd`, ...),
+    /// Resp2 clients get a bulk string.
+    fn reply_with_verbatim(&self, val: &str, ext: &str);
+    /// Start an array reply whose length is not yet known, the length
+    /// must be set later using `reply_set_array_length`.
+    fn reply_with_postponed_array(&self);
+    /// Set the length of the latest postponed array.
+    fn reply_set_array_length(&self, len: usize);
     fn as_client(&self) -> &dyn ReplyCtxInterface;
 }
 
@@ -35,8 +75,21 @@ pub enum RemoteFunctionData {
     String(String),
 }
 
+/// An atomic block, Redis is locked as long as the block is alive. The writes that
+/// were performed inside the block are replicated as a single transaction and are
+/// kept when the block is dropped, unless the block is aborted.
+pub trait AtomicBlockCtxInterface {
+    /// Returns a client to run commands inside the block, the
+    /// client must not be used after the block is dropped.
+    fn get_client(&self) -> Box<dyn RedisClientCtxInterface>;
+    /// Undo the effects the block had on the keys it accessed and release the lock.
+    fn abort(self: Box<Self>);
+}
+
 pub trait BackgroundRunFunctionCtxInterface: Send + Sync {
     fn lock(&self) -> Result<Box<dyn RedisClientCtxInterface>, GearsApiError>;
+    /// Lock Redis and start an atomic block.
+    fn lock_atomic(&self) -> Result<Box<dyn AtomicBlockCtxInterface>, GearsApiError>;
     fn run_on_key(
         &self,
         key: &[u8],
@@ -50,11 +103,16 @@ pub trait BackgroundRunFunctionCtxInterface: Send + Sync {
         inputs: Vec<RemoteFunctionData>,
         on_done: Box<dyn FnOnce(Vec<RemoteFunctionData>, Vec<GearsApiError>)>,
     );
+    /// Scan the key space, Redis is locked for each batch and released between batches.
+    fn scan(&self, options: ScanOptions) -> Result<Box<dyn ScanCursorInterface>, GearsApiError>;
 }
 
 pub trait RunFunctionCtxInterface: ReplyCtxInterface {
     fn next_arg(&mut self) -> Option<&[u8]>;
     fn get_background_client(&self) -> Result<Box<dyn ReplyCtxInterface>, GearsApiError>;
+    /// Returns a reply client that replies on the current invocation, the
+    /// client must not be used after the function returns.
+    fn get_reply_client(&self) -> Box<dyn ReplyCtxInterface>;
     fn get_redis_client(&self) -> Box<dyn RedisClientCtxInterface>;
     fn allow_block(&self) -> bool;
 }
@@ -0,0 +1,96 @@
+/*
+ * Copyright Redis Ltd. 2018 - present
+ * Licensed under your choice of the Redis Source Available License 2.0 (RSALv2) or
+ * the Server Side Public License v1 (SSPLv1).
+ */
+
+use crate::redisgears_plugin_api::run_function_ctx::BackgroundRunFunctionCtxInterface;
+use crate::redisgears_plugin_api::run_function_ctx::RedisClientCtxInterface;
+
+use super::GearsApiError;
+
+use std::str::FromStr;
+
+/// The server events a library can register to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ServerEventType {
+    /// The database was flushed (`FLUSHALL` or `FLUSHDB`).
+    Flush,
+    /// The server became a primary or a replica.
+    RoleChange,
+    /// The server finished loading data (from an RDB, AOF or a primary).
+    Loading,
+    /// A module was loaded or unloaded.
+    ModuleChange,
+    /// Configuration parameters were changed (`CONFIG SET`).
+    ConfigChange,
+    /// The server is shutting down.
+    Shutdown,
+}
+
+impl ServerEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ServerEventType::Flush => "flush",
+            ServerEventType::RoleChange => "role_change",
+            ServerEventType::Loading => "loading",
+            ServerEventType::ModuleChange => "module_change",
+            ServerEventType::ConfigChange => "config_change",
+            ServerEventType::Shutdown => "shutdown",
+        }
+    }
+}
+
+impl FromStr for ServerEventType {
+    type Err = GearsApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flush" => Ok(ServerEventType::Flush),
+            "role_change" => Ok(ServerEventType::RoleChange),
+            "loading" => Ok(ServerEventType::Loading),
+            "module_change" => Ok(ServerEventType::ModuleChange),
+            "config_change" => Ok(ServerEventType::ConfigChange),
+            "shutdown" => Ok(ServerEventType::Shutdown),
+            _ => Err(GearsApiError::new(format!("Unknown server event '{}'", s))),
+        }
+    }
+}
+
+/// A server event along with its data.
+#[derive(Clone, Debug)]
+pub enum ServerEvent {
+    Flush,
+    RoleChange { is_primary: bool },
+    Loading,
+    ModuleChange,
+    ConfigChange { names: Vec<String> },
+    Shutdown,
+}
+
+impl ServerEvent {
+    pub fn get_type(&self) -> ServerEventType {
+        match self {
+            ServerEvent::Flush => ServerEventType::Flush,
+            ServerEvent::RoleChange { .. } => ServerEventType::RoleChange,
+            ServerEvent::Loading => ServerEventType::Loading,
+            ServerEvent::ModuleChange => ServerEventType::ModuleChange,
+            ServerEvent::ConfigChange { .. } => ServerEventType::ConfigChange,
+            ServerEvent::Shutdown => ServerEventType::Shutdown,
+        }
+    }
+}
+
+pub trait ServerEventRunCtxInterface {
+    fn get_redis_client(&self) -> Box<dyn RedisClientCtxInterface>;
+    fn get_background_redis_client(&self) -> Box<dyn BackgroundRunFunctionCtxInterface>;
+}
+
+pub trait ServerEventConsumerCtxInterface {
+    fn on_server_event(
+        &self,
+        event: &ServerEvent,
+        run_ctx: Box<dyn ServerEventRunCtxInterface>,
+        ack_callback: Box<dyn FnOnce(Result<(), GearsApiError>) + Send + Sync>,
+    );
+}
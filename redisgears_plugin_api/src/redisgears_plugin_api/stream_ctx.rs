@@ -9,6 +9,8 @@ use crate::redisgears_plugin_api::run_function_ctx::RedisClientCtxInterface;
 
 use super::GearsApiError;
 
+use std::str::FromStr;
+
 pub trait StreamProcessCtxInterface {
     fn get_redis_client(&self) -> Box<dyn RedisClientCtxInterface>;
     fn get_background_redis_client(&self) -> Box<dyn BackgroundRunFunctionCtxInterface>;
@@ -24,6 +26,84 @@ pub enum StreamRecordAck {
     Nack(GearsApiError),
 }
 
+/// The delay between consecutive attempts to process a failed record.
+#[derive(Clone, Debug)]
+pub enum StreamRetryBackoff {
+    /// Wait the given amount of milliseconds between attempts.
+    Fixed(u64),
+    /// Start with `initial` milliseconds and double the delay on each
+    /// attempt, up to `max` milliseconds.
+    Exponential { initial: u64, max: u64 },
+}
+
+/// Decides whether or not a record that failed processing should be retried.
+#[derive(Clone, Debug)]
+pub struct StreamRetryPolicy {
+    /// The maximum number of times a record will be processed (including the first attempt).
+    pub max_attempts: usize,
+    pub backoff: StreamRetryBackoff,
+    /// Only errors containing one of the given strings will be retried,
+    /// `None` means that all errors will be retried.
+    pub retryable_errors: Option<Vec<String>>,
+}
+
+/// The position from which a consumer starts reading a stream it did not read before.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum StreamStartPosition {
+    /// Read the entire stream.
+    #[default]
+    Beginning,
+    /// Only read records that were added after the consumer was registered (`$`).
+    Now,
+    /// Only read records with id greater than the given id.
+    Id(u64, u64),
+}
+
+impl FromStr for StreamStartPosition {
+    type Err = GearsApiError;
+
+    /// Parse a start position, accepts `from_beginning`, `from_now` (or `$`)
+    /// and a stream id in the form of `<ms>-<seq>` or `<ms>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "from_beginning" => return Ok(StreamStartPosition::Beginning),
+            "from_now" | "$" => return Ok(StreamStartPosition::Now),
+            _ => {}
+        }
+        let err = || GearsApiError::new(format!("Invalid stream id '{}'", s));
+        let (ms, seq) = match s.split_once('-') {
+            Some((ms, seq)) => (ms, seq),
+            None => (s, "0"),
+        };
+        let ms = ms.parse::<u64>().map_err(|_| err())?;
+        let seq = seq.parse::<u64>().map_err(|_| err())?;
+        Ok(StreamStartPosition::Id(ms, seq))
+    }
+}
+
+/// Process records in batches instead of one record at a time.
+#[derive(Clone, Debug)]
+pub struct StreamBatchOptions {
+    /// The maximum number of records on a single batch.
+    pub batch_size: usize,
+    /// The maximum time (in milliseconds) to wait for a batch to fill up
+    /// before processing a partial batch, 0 means do not wait.
+    pub max_wait_ms: u64,
+}
+
+/// Optional settings of a stream consumer.
+#[derive(Clone, Debug, Default)]
+pub struct StreamConsumerOptions {
+    /// A stream to which records that failed processing will be added.
+    pub dead_letter: Option<Vec<u8>>,
+    pub retry_policy: Option<StreamRetryPolicy>,
+    pub start_position: StreamStartPosition,
+    pub batch: Option<StreamBatchOptions>,
+    /// Track the consumer position on a Redis consumer group so it
+    /// will be visible to `XINFO GROUPS` and `XPENDING`.
+    pub consumer_group: bool,
+}
+
 pub trait StreamCtxInterface {
     fn process_record(
         &self,
@@ -32,4 +112,13 @@ pub trait StreamCtxInterface {
         run_ctx: &dyn StreamProcessCtxInterface,
         ack_callback: Box<dyn FnOnce(StreamRecordAck) + Send>,
     ) -> Option<StreamRecordAck>;
+
+    /// Process a batch of records, the acknowledge applies to the entire batch.
+    fn process_batch(
+        &self,
+        stream_name: &[u8],
+        records: Vec<Box<dyn StreamRecordInterface + Send>>,
+        run_ctx: &dyn StreamProcessCtxInterface,
+        ack_callback: Box<dyn FnOnce(StreamRecordAck) + Send>,
+    ) -> Option<StreamRecordAck>;
 }
@@ -0,0 +1,32 @@
+/*
+ * Copyright Redis Ltd. 2018 - present
+ * Licensed under your choice of the Redis Source Available License 2.0 (RSALv2) or
+ * the Server Side Public License v1 (SSPLv1).
+ */
+
+use crate::redisgears_plugin_api::run_function_ctx::BackgroundRunFunctionCtxInterface;
+use crate::redisgears_plugin_api::run_function_ctx::RedisClientCtxInterface;
+
+use super::GearsApiError;
+
+/// When a timer should run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TimerSchedule {
+    /// Run every given amount of milliseconds.
+    Interval(u64),
+    /// Run according to the given cron expression, i.e. `*/5 * * * *`.
+    Cron(String),
+}
+
+pub trait TimerRunCtxInterface {
+    fn get_redis_client(&self) -> Box<dyn RedisClientCtxInterface>;
+    fn get_background_redis_client(&self) -> Box<dyn BackgroundRunFunctionCtxInterface>;
+}
+
+pub trait TimerCtxInterface {
+    fn on_timer_fired(
+        &self,
+        run_ctx: Box<dyn TimerRunCtxInterface>,
+        ack_callback: Box<dyn FnOnce(Result<(), GearsApiError>) + Send + Sync>,
+    );
+}
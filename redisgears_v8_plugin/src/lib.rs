@@ -14,12 +14,16 @@ use redisgears_plugin_api::redisgears_plugin_api::{
 };
 
 mod v8_backend;
+mod v8_channel_consumer_ctx;
+mod v8_consumer_function;
 mod v8_function_ctx;
 mod v8_native_functions;
 mod v8_notifications_ctx;
 mod v8_redisai;
 mod v8_script_ctx;
+mod v8_server_events_ctx;
 mod v8_stream_ctx;
+mod v8_timer_ctx;
 
 use crate::v8_backend::V8Backend;
 use std::sync::{Arc, Mutex};
@@ -87,6 +91,7 @@ pub(crate) fn get_function_flags(
             "no-writes" => flags_val.insert(FunctionFlags::NO_WRITES),
             "allow-oom" => flags_val.insert(FunctionFlags::ALLOW_OOM),
             "raw-arguments" => flags_val.insert(FunctionFlags::RAW_ARGUMENTS),
+            "strict-keys" => flags_val.insert(FunctionFlags::STRICT_KEYS),
             _ => return Err(format!("Unknow flag '{}' was given", flag_str.as_str())),
         }
     }
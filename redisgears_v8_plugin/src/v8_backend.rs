@@ -6,13 +6,17 @@
 
 use redisgears_plugin_api::redisgears_plugin_api::{
     backend_ctx::BackendCtx, backend_ctx::BackendCtxInterface,
-    backend_ctx::CompiledLibraryInterface, backend_ctx::LibraryFatalFailurePolicy,
-    load_library_ctx::LibraryCtxInterface, CallResult, GearsApiError,
+    backend_ctx::CompiledLibraryInterface, backend_ctx::LibraryBundle,
+    backend_ctx::LibraryFatalFailurePolicy, load_library_ctx::LibraryCtxInterface, CallResult,
+    GearsApiError,
 };
 
-use crate::v8_script_ctx::V8ScriptCtx;
+use crate::v8_script_ctx::{V8LibraryCode, V8ScriptCtx};
 
-use v8_rs::v8::{isolate::V8Isolate, v8_init_with_error_handlers};
+use v8_rs::v8::{
+    isolate::V8Isolate, isolate_scope::V8IsolateScope, v8_context_scope::V8ContextScope,
+    v8_init_with_error_handlers, v8_module::V8LocalModule, v8_string::V8LocalString,
+};
 
 use crate::v8_native_functions::initialize_globals;
 
@@ -61,6 +65,50 @@ pub(crate) fn gil_lock_timeout() -> u128 {
     unsafe { (GLOBAL.backend_ctx.as_ref().unwrap().get_lock_timeout)() }
 }
 
+/// Compile a module of a library bundle, module names are
+/// relative to the bundle root (i.e. `./utils.js` and `utils.js`
+/// refer to the same module).
+fn compile_bundle_module<'isolate_scope, 'isolate>(
+    isolate_scope: &'isolate_scope V8IsolateScope<'isolate>,
+    ctx_scope: &V8ContextScope<'isolate_scope, 'isolate>,
+    bundle: &LibraryBundle,
+    name: &str,
+) -> Option<V8LocalModule<'isolate_scope, 'isolate>> {
+    let name = name.strip_prefix("./").unwrap_or(name);
+    let code = match bundle.modules.get(name) {
+        Some(c) => c,
+        None => {
+            isolate_scope.raise_exception_str(&format!(
+                "Module '{}' was not found on the library bundle",
+                name
+            ));
+            return None;
+        }
+    };
+    let v8code_str = isolate_scope.new_string(code);
+    let v8name_str = isolate_scope.new_string(name);
+    ctx_scope.compile_as_module(&v8code_str, &v8name_str, true)
+}
+
+/// Resolve an `import` statement of a bundle module, the bundle
+/// is taken from the context private data.
+fn load_bundle_module<'isolate_scope, 'isolate>(
+    isolate_scope: &'isolate_scope V8IsolateScope<'isolate>,
+    ctx_scope: &V8ContextScope<'isolate_scope, 'isolate>,
+    name_to_load: &V8LocalString,
+    _identity_hash: i64,
+) -> Option<V8LocalModule<'isolate_scope, 'isolate>> {
+    let bundle = match ctx_scope.get_private_data::<&LibraryBundle, _>(1) {
+        Some(b) => *b,
+        None => {
+            isolate_scope.raise_exception_str("Can not import modules out of a library bundle");
+            return None;
+        }
+    };
+    let name = name_to_load.to_value().to_utf8().unwrap();
+    compile_bundle_module(isolate_scope, ctx_scope, bundle, name.as_str())
+}
+
 pub(crate) struct V8Backend {
     pub(crate) script_ctx_vec: Arc<Mutex<Vec<Weak<V8ScriptCtx>>>>,
 }
@@ -167,35 +215,126 @@ impl BackendCtxInterface for V8Backend {
         config: Option<&String>,
         compiled_library_api: Box<dyn CompiledLibraryInterface + Send + Sync>,
     ) -> Result<Box<dyn LibraryCtxInterface>, GearsApiError> {
+        self.compile_library_internal(
+            |isolate_scope, ctx_scope| {
+                let v8code_str = isolate_scope.new_string(blob);
+                ctx_scope
+                    .compile(&v8code_str)
+                    .map(|s| V8LibraryCode::Script(s.persist()))
+            },
+            config,
+            compiled_library_api,
+        )
+    }
+
+    fn compile_library_bundle(
+        &mut self,
+        bundle: &LibraryBundle,
+        config: Option<&String>,
+        compiled_library_api: Box<dyn CompiledLibraryInterface + Send + Sync>,
+    ) -> Result<Box<dyn LibraryCtxInterface>, GearsApiError> {
+        self.compile_library_internal(
+            |isolate_scope, ctx_scope| {
+                let entry = compile_bundle_module(isolate_scope, ctx_scope, bundle, &bundle.entry)?;
+                // the bundle is needed while resolving the entry module imports
+                let _bundle_guard = ctx_scope.set_private_data(1, &bundle);
+                if !entry.initialize(ctx_scope, load_bundle_module) {
+                    return None;
+                }
+                Some(V8LibraryCode::Module(entry.persist()))
+            },
+            config,
+            compiled_library_api,
+        )
+    }
+
+    fn debug(&mut self, args: &[&str]) -> Result<CallResult, GearsApiError> {
+        let mut args = args.iter();
+        let sub_command = args
+            .next()
+            .map_or(Err(GearsApiError::new("Subcommand was not provided")), Ok)?
+            .to_lowercase();
+        match sub_command.as_ref() {
+            "help" => Ok(CallResult::Array(vec![
+                CallResult::BulkStr("isolates_stats - statistics about isolates.".to_string()),
+                CallResult::BulkStr(
+                    "isolates_strong_count - For each isolate returns its strong ref count value."
+                        .to_string(),
+                ),
+                CallResult::BulkStr(
+                    "isolates_gc - Runs GC to clear none active isolates.".to_string(),
+                ),
+                CallResult::BulkStr("help - Print this message.".to_string()),
+            ])),
+            "isolates_stats" => {
+                let l = self.script_ctx_vec.lock().unwrap();
+                let active = l.iter().filter(|v| v.strong_count() > 0).count() as i64;
+                let not_active = l.iter().filter(|v| v.strong_count() == 0).count() as i64;
+                Ok(CallResult::Array(vec![
+                    CallResult::BulkStr("active".to_string()),
+                    CallResult::Long(active),
+                    CallResult::BulkStr("not_active".to_string()),
+                    CallResult::Long(not_active),
+                ]))
+            }
+            "isolates_strong_count" => {
+                let l = self.script_ctx_vec.lock().unwrap();
+                let isolates_strong_count = l
+                    .iter()
+                    .map(|v| CallResult::Long(v.strong_count() as i64))
+                    .collect::<Vec<CallResult>>();
+                Ok(CallResult::Array(isolates_strong_count))
+            }
+            "isolates_gc" => {
+                self.isolates_gc();
+                Ok(CallResult::SimpleStr("OK".to_string()))
+            }
+            _ => Err(GearsApiError::new(format!(
+                "Unknown subcommand '{sub_command}'",
+            ))),
+        }
+    }
+}
+
+impl V8Backend {
+    /// Create a new isolate and compile the library code on it, `compile` is
+    /// called with the new isolate and context and returns the compiled code
+    /// or `None` if an exception was raised.
+    fn compile_library_internal<F>(
+        &mut self,
+        compile: F,
+        config: Option<&String>,
+        compiled_library_api: Box<dyn CompiledLibraryInterface + Send + Sync>,
+    ) -> Result<Box<dyn LibraryCtxInterface>, GearsApiError>
+    where
+        F: FnOnce(&V8IsolateScope, &V8ContextScope) -> Option<V8LibraryCode>,
+    {
         let isolate = V8Isolate::new_with_limits(
             8 * 1024 * 1024, /* 8M */
             compiled_library_api.get_maxmemory(),
         );
 
         let script_ctx = {
-            let (ctx, script, tensor_obj_template) = {
+            let (ctx, code, tensor_obj_template) = {
                 let isolate_scope = isolate.enter();
                 let ctx = isolate_scope.new_context(None);
                 let ctx_scope = ctx.enter(&isolate_scope);
 
-                let v8code_str = isolate_scope.new_string(blob);
-
                 let trycatch = isolate_scope.new_try_catch();
-                let script = match ctx_scope.compile(&v8code_str) {
-                    Some(s) => s,
+                let code = match compile(&isolate_scope, &ctx_scope) {
+                    Some(c) => c,
                     None => {
                         return Err(get_exception_msg(&isolate, trycatch, &ctx_scope));
                     }
                 };
 
-                let script = script.persist();
                 let tensor_obj_template = get_tensor_object_template(&isolate_scope);
-                (ctx, script, tensor_obj_template)
+                (ctx, code, tensor_obj_template)
             };
             let script_ctx = Arc::new(V8ScriptCtx::new(
                 isolate,
                 ctx,
-                script,
+                code,
                 tensor_obj_template,
                 compiled_library_api,
             ));
@@ -266,51 +405,4 @@ impl BackendCtxInterface for V8Backend {
 
         Ok(Box::new(V8LibraryCtx { script_ctx }))
     }
-
-    fn debug(&mut self, args: &[&str]) -> Result<CallResult, GearsApiError> {
-        let mut args = args.iter();
-        let sub_command = args
-            .next()
-            .map_or(Err(GearsApiError::new("Subcommand was not provided")), Ok)?
-            .to_lowercase();
-        match sub_command.as_ref() {
-            "help" => Ok(CallResult::Array(vec![
-                CallResult::BulkStr("isolates_stats - statistics about isolates.".to_string()),
-                CallResult::BulkStr(
-                    "isolates_strong_count - For each isolate returns its strong ref count value."
-                        .to_string(),
-                ),
-                CallResult::BulkStr(
-                    "isolates_gc - Runs GC to clear none active isolates.".to_string(),
-                ),
-                CallResult::BulkStr("help - Print this message.".to_string()),
-            ])),
-            "isolates_stats" => {
-                let l = self.script_ctx_vec.lock().unwrap();
-                let active = l.iter().filter(|v| v.strong_count() > 0).count() as i64;
-                let not_active = l.iter().filter(|v| v.strong_count() == 0).count() as i64;
-                Ok(CallResult::Array(vec![
-                    CallResult::BulkStr("active".to_string()),
-                    CallResult::Long(active),
-                    CallResult::BulkStr("not_active".to_string()),
-                    CallResult::Long(not_active),
-                ]))
-            }
-            "isolates_strong_count" => {
-                let l = self.script_ctx_vec.lock().unwrap();
-                let isolates_strong_count = l
-                    .iter()
-                    .map(|v| CallResult::Long(v.strong_count() as i64))
-                    .collect::<Vec<CallResult>>();
-                Ok(CallResult::Array(isolates_strong_count))
-            }
-            "isolates_gc" => {
-                self.isolates_gc();
-                Ok(CallResult::SimpleStr("OK".to_string()))
-            }
-            _ => Err(GearsApiError::new(format!(
-                "Unknown subcommand '{sub_command}'",
-            ))),
-        }
-    }
 }
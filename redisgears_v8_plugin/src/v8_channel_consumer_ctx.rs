@@ -0,0 +1,100 @@
+/*
+ * Copyright Redis Ltd. 2018 - present
+ * Licensed under your choice of the Redis Source Available License 2.0 (RSALv2) or
+ * the Server Side Public License v1 (SSPLv1).
+ */
+
+use redisgears_plugin_api::redisgears_plugin_api::GearsApiError;
+use redisgears_plugin_api::redisgears_plugin_api::{
+    channel_consumer_ctx::ChannelConsumerCtxInterface,
+    channel_consumer_ctx::ChannelConsumerRunCtxInterface,
+};
+
+use v8_rs::v8::{
+    isolate_scope::V8IsolateScope, v8_context_scope::V8ContextScope, v8_value::V8LocalValue,
+    v8_value::V8PersistValue,
+};
+
+use crate::v8_consumer_function::{V8ConsumerFunction, V8ConsumerFunctionData};
+use crate::v8_script_ctx::V8ScriptCtx;
+
+use std::sync::Arc;
+
+struct V8ChannelMessage {
+    channel: Vec<u8>,
+    message: Vec<u8>,
+}
+
+impl V8ConsumerFunctionData for V8ChannelMessage {
+    fn to_v8_value<'isolate_scope, 'isolate>(
+        &self,
+        isolate_scope: &'isolate_scope V8IsolateScope<'isolate>,
+        ctx_scope: &V8ContextScope,
+    ) -> Option<V8LocalValue<'isolate_scope, 'isolate>> {
+        let message_data = isolate_scope.new_object();
+        if let Ok(channel) = std::str::from_utf8(&self.channel) {
+            message_data.set(
+                ctx_scope,
+                &isolate_scope.new_string("channel").to_value(),
+                &isolate_scope.new_string(channel).to_value(),
+            );
+        }
+        message_data.set(
+            ctx_scope,
+            &isolate_scope.new_string("channel_raw").to_value(),
+            &isolate_scope.new_array_buffer(&self.channel).to_value(),
+        );
+        if let Ok(message) = std::str::from_utf8(&self.message) {
+            message_data.set(
+                ctx_scope,
+                &isolate_scope.new_string("message").to_value(),
+                &isolate_scope.new_string(message).to_value(),
+            );
+        }
+        message_data.set(
+            ctx_scope,
+            &isolate_scope.new_string("message_raw").to_value(),
+            &isolate_scope.new_array_buffer(&self.message).to_value(),
+        );
+        Some(message_data.to_value())
+    }
+}
+
+pub(crate) struct V8ChannelConsumerCtx {
+    internal: Arc<V8ConsumerFunction>,
+    is_async: bool,
+}
+
+impl V8ChannelConsumerCtx {
+    pub(crate) fn new(
+        persisted_function: V8PersistValue,
+        script_ctx: &Arc<V8ScriptCtx>,
+        is_async: bool,
+    ) -> Self {
+        Self {
+            internal: Arc::new(V8ConsumerFunction::new(persisted_function, script_ctx)),
+            is_async,
+        }
+    }
+}
+
+impl ChannelConsumerCtxInterface for V8ChannelConsumerCtx {
+    fn on_message(
+        &self,
+        channel: &[u8],
+        message: &[u8],
+        run_ctx: Box<dyn ChannelConsumerRunCtxInterface>,
+        ack_callback: Box<dyn FnOnce(Result<(), GearsApiError>) + Send + Sync>,
+    ) {
+        self.internal.run(
+            self.is_async,
+            &|| run_ctx.get_redis_client(),
+            &|| run_ctx.get_background_redis_client(),
+            V8ChannelMessage {
+                channel: channel.to_vec(),
+                message: message.to_vec(),
+            },
+            ack_callback,
+        );
+    }
+}
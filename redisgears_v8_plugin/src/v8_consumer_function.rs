@@ -0,0 +1,265 @@
+/*
+ * Copyright Redis Ltd. 2018 - present
+ * Licensed under your choice of the Redis Source Available License 2.0 (RSALv2) or
+ * the Server Side Public License v1 (SSPLv1).
+ */
+
+//! A JS function that is invoked by Redis (keys notifications, timers, server events
+//! and channel consumers) and reports its completion with an ack callback. If the
+//! function returns a pending promise, the ack callback is called when the promise
+//! is settled.
+
+use redisgears_plugin_api::redisgears_plugin_api::GearsApiError;
+use redisgears_plugin_api::redisgears_plugin_api::{
+    run_function_ctx::BackgroundRunFunctionCtxInterface, run_function_ctx::RedisClientCtxInterface,
+};
+
+use v8_rs::v8::{
+    isolate_scope::V8IsolateScope, try_catch::V8TryCatch, v8_context_scope::V8ContextScope,
+    v8_promise::V8PromiseState, v8_value::V8LocalValue, v8_value::V8PersistValue,
+};
+
+use crate::v8_native_functions::{get_backgrounnd_client, get_redis_client, RedisClient};
+use crate::v8_script_ctx::V8ScriptCtx;
+use crate::{get_error_from_object, get_exception_msg};
+
+use std::cell::RefCell;
+use std::sync::Arc;
+
+use v8_derive::new_native_function;
+
+pub(crate) type AckCallback = Box<dyn FnOnce(Result<(), GearsApiError>) + Send + Sync>;
+
+/// The data given to the function as a second argument, after the Redis client.
+pub(crate) trait V8ConsumerFunctionData {
+    /// Returns the data as a JS value, `None` if no data should be given to the function.
+    fn to_v8_value<'isolate_scope, 'isolate>(
+        &self,
+        isolate_scope: &'isolate_scope V8IsolateScope<'isolate>,
+        ctx_scope: &V8ContextScope,
+    ) -> Option<V8LocalValue<'isolate_scope, 'isolate>>;
+}
+
+impl V8ConsumerFunctionData for () {
+    fn to_v8_value<'isolate_scope, 'isolate>(
+        &self,
+        _isolate_scope: &'isolate_scope V8IsolateScope<'isolate>,
+        _ctx_scope: &V8ContextScope,
+    ) -> Option<V8LocalValue<'isolate_scope, 'isolate>> {
+        None
+    }
+}
+
+struct V8AckCallbackInternal {
+    ack_callback: AckCallback,
+    locker: Box<dyn BackgroundRunFunctionCtxInterface>,
+}
+
+struct V8AckCallback {
+    internal: Option<V8AckCallbackInternal>,
+}
+
+pub(crate) struct V8ConsumerFunction {
+    persisted_function: V8PersistValue,
+    script_ctx: Arc<V8ScriptCtx>,
+}
+
+impl V8ConsumerFunction {
+    pub(crate) fn new(
+        mut persisted_function: V8PersistValue,
+        script_ctx: &Arc<V8ScriptCtx>,
+    ) -> V8ConsumerFunction {
+        persisted_function.forget();
+        V8ConsumerFunction {
+            persisted_function,
+            script_ctx: Arc::clone(script_ctx),
+        }
+    }
+
+    /// Run the function, async functions are run on a background thread.
+    pub(crate) fn run<D: V8ConsumerFunctionData + Send + 'static>(
+        self: &Arc<Self>,
+        is_async: bool,
+        get_redis_client: &dyn Fn() -> Box<dyn RedisClientCtxInterface>,
+        get_background_redis_client: &dyn Fn() -> Box<dyn BackgroundRunFunctionCtxInterface>,
+        data: D,
+        ack_callback: AckCallback,
+    ) {
+        if is_async {
+            let redis_background_client = get_background_redis_client();
+            let locker = get_background_redis_client();
+            let internal = Arc::clone(self);
+            self.script_ctx
+                .compiled_library_api
+                .run_on_background(Box::new(move || {
+                    internal.run_async(redis_background_client, locker, &data, ack_callback);
+                }));
+        } else {
+            self.run_sync(
+                get_redis_client(),
+                get_background_redis_client,
+                &data,
+                ack_callback,
+            );
+        }
+    }
+
+    /// Turn the outcome of the function into an ack result. If the function
+    /// returned a pending promise, the ack callback is called (with the given locker
+    /// taken) when the promise is settled and `None` is returned, otherwise the
+    /// result is returned along with the ack callback.
+    fn get_result(
+        &self,
+        res: Option<V8LocalValue>,
+        ctx_scope: &V8ContextScope,
+        try_catch: V8TryCatch,
+        get_locker: &dyn Fn() -> Box<dyn BackgroundRunFunctionCtxInterface>,
+        ack_callback: AckCallback,
+    ) -> Option<(Result<(), GearsApiError>, AckCallback)> {
+        let res = match res {
+            Some(res) => res,
+            None => {
+                let error_msg = get_exception_msg(&self.script_ctx.isolate, try_catch, ctx_scope);
+                return Some((Err(error_msg), ack_callback));
+            }
+        };
+        if !res.is_promise() {
+            return Some((Ok(()), ack_callback));
+        }
+        let res = res.as_promise();
+        if res.state() == V8PromiseState::Rejected {
+            let res = res.get_result();
+            return Some((Err(get_error_from_object(&res, ctx_scope)), ack_callback));
+        }
+        if res.state() == V8PromiseState::Fulfilled {
+            return Some((Ok(()), ack_callback));
+        }
+        let ack_callback_resolve = Arc::new(RefCell::new(V8AckCallback {
+            internal: Some(V8AckCallbackInternal {
+                ack_callback,
+                locker: get_locker(),
+            }),
+        }));
+        let ack_callback_reject = Arc::clone(&ack_callback_resolve);
+        let resolve =
+            ctx_scope.new_native_function(new_native_function!(move |isolate, _context| {
+                let _unlocker = isolate.new_unlocker();
+                if let Some(ack) = ack_callback_resolve.borrow_mut().internal.take() {
+                    let _locker = ack.locker.lock();
+                    (ack.ack_callback)(Ok(()));
+                }
+                Ok::<_, String>(None)
+            }));
+        let reject = ctx_scope.new_native_function(new_native_function!(
+            move |isolate, ctx_scope, res: V8LocalValue| {
+                let res = get_error_from_object(&res, ctx_scope);
+                let _unlocker = isolate.new_unlocker();
+                if let Some(ack) = ack_callback_reject.borrow_mut().internal.take() {
+                    let _locker = ack.locker.lock();
+                    (ack.ack_callback)(Err(res));
+                }
+                Ok::<_, String>(None)
+            }
+        ));
+        res.then(ctx_scope, &resolve, &reject);
+        None
+    }
+
+    fn run_sync(
+        &self,
+        client: Box<dyn RedisClientCtxInterface>,
+        get_background_redis_client: &dyn Fn() -> Box<dyn BackgroundRunFunctionCtxInterface>,
+        data: &dyn V8ConsumerFunctionData,
+        ack_callback: AckCallback,
+    ) {
+        let res = {
+            let isolate_scope = self.script_ctx.isolate.enter();
+            let ctx_scope = self.script_ctx.ctx.enter(&isolate_scope);
+            let try_catch = isolate_scope.new_try_catch();
+
+            let data = data.to_v8_value(&isolate_scope, &ctx_scope);
+
+            let mut redis_client = RedisClient::new();
+            redis_client.set_client(client);
+            let redis_client = Arc::new(RefCell::new(redis_client));
+            let r_client =
+                get_redis_client(&self.script_ctx, &isolate_scope, &ctx_scope, &redis_client)
+                    .to_value();
+            let mut args = vec![&r_client];
+            args.extend(data.as_ref());
+
+            let _block_guard = ctx_scope.set_private_data(0, &true); // indicate we are blocked
+
+            self.script_ctx.before_run();
+            self.script_ctx.after_lock_gil();
+            let res = self
+                .persisted_function
+                .as_local(&isolate_scope)
+                .call(&ctx_scope, Some(args.as_slice()));
+            self.script_ctx.before_release_gil();
+            self.script_ctx.after_run();
+
+            redis_client.borrow_mut().make_invalid();
+
+            self.get_result(
+                res,
+                &ctx_scope,
+                try_catch,
+                get_background_redis_client,
+                ack_callback,
+            )
+        };
+
+        if let Some((res, ack_callback)) = res {
+            ack_callback(res);
+        }
+    }
+
+    fn run_async(
+        &self,
+        background_client: Box<dyn BackgroundRunFunctionCtxInterface>,
+        locker: Box<dyn BackgroundRunFunctionCtxInterface>,
+        data: &dyn V8ConsumerFunctionData,
+        ack_callback: AckCallback,
+    ) {
+        let locker = RefCell::new(Some(locker));
+        let res = {
+            let isolate_scope = self.script_ctx.isolate.enter();
+            let ctx_scope = self.script_ctx.ctx.enter(&isolate_scope);
+            let try_catch = isolate_scope.new_try_catch();
+
+            let data = data.to_v8_value(&isolate_scope, &ctx_scope);
+
+            let r_client = get_backgrounnd_client(
+                &self.script_ctx,
+                &isolate_scope,
+                &ctx_scope,
+                Arc::new(background_client),
+            )
+            .to_value();
+            let mut args = vec![&r_client];
+            args.extend(data.as_ref());
+
+            self.script_ctx.before_run();
+            let res = self
+                .persisted_function
+                .as_local(&isolate_scope)
+                .call(&ctx_scope, Some(args.as_slice()));
+            self.script_ctx.after_run();
+
+            self.get_result(
+                res,
+                &ctx_scope,
+                try_catch,
+                &|| locker.borrow_mut().take().unwrap(),
+                ack_callback,
+            )
+        };
+
+        if let Some((res, ack_callback)) = res {
+            let locker = locker.borrow_mut().take().unwrap();
+            let _locker = locker.lock();
+            ack_callback(res);
+        }
+    }
+}
@@ -6,6 +6,7 @@
 
 use redisgears_plugin_api::redisgears_plugin_api::GearsApiError;
 use redisgears_plugin_api::redisgears_plugin_api::{
+    function_ctx::FunctionArgument, function_ctx::FunctionArgumentType,
     function_ctx::FunctionCtxInterface, run_function_ctx::BackgroundRunFunctionCtxInterface,
     run_function_ctx::ReplyCtxInterface, run_function_ctx::RunFunctionCtxInterface,
     FunctionCallResult,
@@ -16,7 +17,7 @@ use v8_rs::v8::{
     v8_value::V8LocalValue, v8_value::V8PersistValue,
 };
 
-use crate::v8_native_functions::{get_backgrounnd_client, RedisClient};
+use crate::v8_native_functions::{get_backgrounnd_client, RedisClient, StreamingReply};
 use crate::v8_script_ctx::V8ScriptCtx;
 use crate::{get_error_from_object, get_exception_msg};
 
@@ -41,9 +42,47 @@ pub struct V8InternalFunction {
     persisted_client: V8PersistValue,
     persisted_function: V8PersistValue,
     script_ctx: Arc<V8ScriptCtx>,
+    args_schema: Option<Vec<FunctionArgument>>,
 }
 
-fn send_reply(
+/// Convert a function argument to a JS value. If the argument has a type on the function
+/// arguments schema, it is converted according to this type (the core already verified that
+/// the argument is valid), otherwise it is given as a String or as an ArrayBuffer if
+/// the function does not decode its arguments.
+fn arg_to_v8_value<'isolate_scope, 'isolate>(
+    isolate_scope: &'isolate_scope V8IsolateScope<'isolate>,
+    ctx_scope: &V8ContextScope,
+    arg: &[u8],
+    arg_type: Option<FunctionArgumentType>,
+    decode_arguments: bool,
+) -> Result<V8LocalValue<'isolate_scope, 'isolate>, GearsApiError> {
+    let arg_type = match arg_type {
+        Some(arg_type) => arg_type,
+        None if decode_arguments => FunctionArgumentType::String,
+        None => FunctionArgumentType::Binary,
+    };
+    if arg_type == FunctionArgumentType::Binary {
+        return Ok(isolate_scope.new_array_buffer(arg).to_value());
+    }
+    let arg = str::from_utf8(arg)
+        .map_err(|_| GearsApiError::new("Can not convert argument to string"))?;
+    match arg_type {
+        FunctionArgumentType::Int => arg
+            .parse::<i64>()
+            .map(|v| isolate_scope.new_long(v))
+            .map_err(|_| GearsApiError::new("Can not convert argument to integer")),
+        FunctionArgumentType::Float => arg
+            .parse::<f64>()
+            .map(|v| isolate_scope.new_double(v))
+            .map_err(|_| GearsApiError::new("Can not convert argument to float")),
+        FunctionArgumentType::Json => ctx_scope
+            .new_object_from_json(&isolate_scope.new_string(arg))
+            .ok_or_else(|| GearsApiError::new("Can not convert argument to json")),
+        _ => Ok(isolate_scope.new_string(arg).to_value()),
+    }
+}
+
+pub(crate) fn send_reply(
     nesting_level: usize,
     isolate_scope: &V8IsolateScope,
     ctx_scope: &V8ContextScope,
@@ -58,6 +97,8 @@ fn send_reply(
         client.reply_with_long(val.get_long());
     } else if val.is_number() {
         client.reply_with_double(val.get_number());
+    } else if val.is_boolean() {
+        client.reply_with_bool(val.get_boolean());
     } else if val.is_string() {
         client.reply_with_bulk_string(val.to_utf8().unwrap().as_str());
     } else if val.is_string_object() {
@@ -69,9 +110,24 @@ fn send_reply(
         );
         if let Some(t) = reply_type {
             if let Some(reply_type_v8_str) = t.to_utf8() {
-                if reply_type_v8_str.as_str() == "status" {
-                    client.reply_with_simple_string(val.to_utf8().unwrap().as_str());
-                    return;
+                match reply_type_v8_str.as_str() {
+                    "status" => {
+                        client.reply_with_simple_string(val.to_utf8().unwrap().as_str());
+                        return;
+                    }
+                    "big_number" => {
+                        client.reply_with_big_number(val.to_utf8().unwrap().as_str());
+                        return;
+                    }
+                    "verbatim" => {
+                        let ext = obj_reply
+                            .get(ctx_scope, &isolate_scope.new_string("__ext").to_value())
+                            .and_then(|ext| ext.to_utf8());
+                        let ext = ext.as_ref().map_or("txt", |ext| ext.as_str());
+                        client.reply_with_verbatim(val.to_utf8().unwrap().as_str(), ext);
+                        return;
+                    }
+                    _ => (),
                 }
             }
         }
@@ -88,10 +144,25 @@ fn send_reply(
             let val = arr.get(ctx_scope, i);
             send_reply(nesting_level + 1, isolate_scope, ctx_scope, client, val);
         }
+    } else if val.is_set() {
+        let arr = val.as_set().as_array();
+        client.reply_with_set(arr.len());
+        for i in 0..arr.len() {
+            let val = arr.get(ctx_scope, i);
+            send_reply(nesting_level + 1, isolate_scope, ctx_scope, client, val);
+        }
+    } else if val.is_map() {
+        // the map entries are given as a flat array of keys and values
+        let arr = val.as_map().as_array();
+        client.reply_with_map(arr.len() / 2);
+        for i in 0..arr.len() {
+            let val = arr.get(ctx_scope, i);
+            send_reply(nesting_level + 1, isolate_scope, ctx_scope, client, val);
+        }
     } else if val.is_object() {
         let res = val.as_object();
         let keys = res.get_property_names(ctx_scope);
-        client.reply_with_array(keys.len() * 2);
+        client.reply_with_map(keys.len());
         for i in 0..keys.len() {
             let key = keys.get(ctx_scope, i);
             let obj = res.get(ctx_scope, &key).unwrap();
@@ -104,6 +175,18 @@ fn send_reply(
 }
 
 impl V8InternalFunction {
+    /// Returns the type of the argument at the given index according to the arguments schema.
+    fn get_arg_type(&self, index: usize) -> Option<FunctionArgumentType> {
+        let args_schema = self.args_schema.as_ref()?;
+        match args_schema.get(index) {
+            Some(arg) => Some(arg.arg_type),
+            None => args_schema
+                .last()
+                .filter(|arg| arg.variadic)
+                .map(|arg| arg.arg_type),
+        }
+    }
+
     fn call_async(
         &self,
         command_args: Vec<Vec<u8>>,
@@ -125,20 +208,19 @@ impl V8InternalFunction {
             let args = {
                 let mut args = Vec::new();
                 args.push(r_client.to_value());
-                for arg in command_args.iter() {
-                    let arg = if decode_args {
-                        let arg = match str::from_utf8(arg) {
-                            Ok(s) => s,
-                            Err(_) => {
-                                bg_client.reply_with_error(GearsApiError::new(
-                                    "Can not convert argument to string",
-                                ));
-                                return FunctionCallResult::Done;
-                            }
-                        };
-                        isolate_scope.new_string(arg).to_value()
-                    } else {
-                        isolate_scope.new_array_buffer(arg).to_value()
+                for (i, arg) in command_args.iter().enumerate() {
+                    let arg = match arg_to_v8_value(
+                        &isolate_scope,
+                        &ctx_scope,
+                        arg,
+                        self.get_arg_type(i),
+                        decode_args,
+                    ) {
+                        Ok(arg) => arg,
+                        Err(e) => {
+                            bg_client.reply_with_error(e);
+                            return FunctionCallResult::Done;
+                        }
                     };
                     args.push(arg);
                 }
@@ -220,6 +302,7 @@ impl V8InternalFunction {
     fn call_sync(
         &self,
         run_ctx: &mut dyn RunFunctionCtxInterface,
+        redis_client: &RefCell<RedisClient>,
         decode_arguments: bool,
     ) -> FunctionCallResult {
         let isolate_scope = self.script_ctx.isolate.enter();
@@ -231,19 +314,18 @@ impl V8InternalFunction {
                 let mut args = Vec::new();
                 args.push(self.persisted_client.as_local(&isolate_scope));
                 while let Some(a) = run_ctx.next_arg() {
-                    let arg = if decode_arguments {
-                        let arg = match str::from_utf8(a) {
-                            Ok(s) => s,
-                            Err(_) => {
-                                run_ctx.reply_with_error(GearsApiError::new(
-                                    "Can not convert argument to string",
-                                ));
-                                return FunctionCallResult::Done;
-                            }
-                        };
-                        isolate_scope.new_string(arg).to_value()
-                    } else {
-                        isolate_scope.new_array_buffer(a).to_value()
+                    let arg = match arg_to_v8_value(
+                        &isolate_scope,
+                        &ctx_scope,
+                        a,
+                        self.get_arg_type(args.len() - 1),
+                        decode_arguments,
+                    ) {
+                        Ok(arg) => arg,
+                        Err(e) => {
+                            run_ctx.reply_with_error(e);
+                            return FunctionCallResult::Done;
+                        }
                     };
                     args.push(arg);
                 }
@@ -268,6 +350,21 @@ impl V8InternalFunction {
             res
         };
 
+        // if the function streamed its reply, the returned value is ignored.
+        let streaming_reply = redis_client.borrow_mut().take_streaming_reply();
+        if let Some(streaming_reply) = streaming_reply.filter(|r| r.is_started()) {
+            let error = match res {
+                Some(_) => None,
+                None => Some(get_exception_msg(
+                    &self.script_ctx.isolate,
+                    trycatch,
+                    &ctx_scope,
+                )),
+            };
+            streaming_reply.finish(error);
+            return FunctionCallResult::Done;
+        }
+
         match res {
             Some(r) => {
                 if r.is_promise() {
@@ -347,6 +444,7 @@ impl V8Function {
         client: &Arc<RefCell<RedisClient>>,
         is_async: bool,
         decode_arguments: bool,
+        args_schema: Option<Vec<FunctionArgument>>,
     ) -> Self {
         persisted_function.forget();
         persisted_client.forget();
@@ -355,6 +453,7 @@ impl V8Function {
                 script_ctx: Arc::clone(script_ctx),
                 persisted_function,
                 persisted_client,
+                args_schema,
             }),
             client: Arc::clone(client),
             is_async,
@@ -397,8 +496,11 @@ impl FunctionCtxInterface for V8Function {
             self.client
                 .borrow_mut()
                 .set_allow_block(run_ctx.allow_block());
+            self.client
+                .borrow_mut()
+                .set_streaming_reply(StreamingReply::new(run_ctx.get_reply_client()));
             self.inner_function
-                .call_sync(run_ctx, self.decode_arguments);
+                .call_sync(run_ctx, &self.client, self.decode_arguments);
             self.client.borrow_mut().make_invalid();
             FunctionCallResult::Done
         }
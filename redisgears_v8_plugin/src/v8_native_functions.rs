@@ -6,9 +6,17 @@
 
 use redisgears_plugin_api::redisgears_plugin_api::load_library_ctx::FunctionFlags;
 use redisgears_plugin_api::redisgears_plugin_api::{
+    channel_consumer_ctx::ChannelConsumerOptions, channel_consumer_ctx::RegisteredChannels,
+    function_ctx::FunctionArgument, function_ctx::FunctionArgumentType, key_ctx::KeyCtxInterface,
+    key_ctx::ListEnd, keys_notifications_consumer_ctx::KeysNotificationsConsumerOptions,
+    keys_notifications_consumer_ctx::KeysNotificationsEventClasses,
     load_library_ctx::LoadLibraryCtxInterface, load_library_ctx::RegisteredKeys,
     run_function_ctx::BackgroundRunFunctionCtxInterface, run_function_ctx::RedisClientCtxInterface,
-    run_function_ctx::RemoteFunctionData, CallResult, GearsApiError, RefCellWrapper,
+    run_function_ctx::RemoteFunctionData, run_function_ctx::ReplyCtxInterface,
+    run_function_ctx::ScanOptions, server_events_ctx::ServerEventType,
+    stream_ctx::StreamBatchOptions, stream_ctx::StreamConsumerOptions,
+    stream_ctx::StreamRetryBackoff, stream_ctx::StreamRetryPolicy, stream_ctx::StreamStartPosition,
+    timer_ctx::TimerSchedule, CallResult, GearsApiError, RefCellWrapper,
 };
 
 use v8_rs::v8::v8_array::V8LocalArray;
@@ -24,13 +32,17 @@ use v8_derive::new_native_function;
 use crate::v8_redisai::{get_redisai_api, get_redisai_client};
 
 use crate::v8_backend::log;
-use crate::v8_function_ctx::V8Function;
+use crate::v8_channel_consumer_ctx::V8ChannelConsumerCtx;
+use crate::v8_function_ctx::{send_reply, V8Function};
 use crate::v8_notifications_ctx::V8NotificationsCtx;
 use crate::v8_script_ctx::V8ScriptCtx;
+use crate::v8_server_events_ctx::V8ServerEventCtx;
 use crate::v8_stream_ctx::V8StreamCtx;
+use crate::v8_timer_ctx::V8TimerCtx;
 use crate::{get_exception_msg, get_exception_v8_value, get_function_flags};
 
 use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::sync::{Arc, Weak};
 
 pub(crate) fn call_result_to_js_object<'isolate_scope, 'isolate>(
@@ -183,9 +195,101 @@ pub(crate) fn call_result_to_js_object<'isolate_scope, 'isolate>(
     }
 }
 
+/// A reply that is streamed by the function while it runs, using `client.reply_array_begin`,
+/// `client.reply_item` and `client.reply_end`, instead of being built and returned at once.
+pub(crate) struct StreamingReply {
+    client: Box<dyn ReplyCtxInterface>,
+    /// The number of elements sent so far on each of the currently open arrays.
+    open_arrays: Vec<usize>,
+    /// The length of the outermost array once the function closed it. Setting it is
+    /// postponed until the function returns so that a later error can still be added.
+    closed_len: Option<usize>,
+    started: bool,
+}
+
+impl StreamingReply {
+    pub(crate) fn new(client: Box<dyn ReplyCtxInterface>) -> StreamingReply {
+        StreamingReply {
+            client,
+            open_arrays: Vec::new(),
+            closed_len: None,
+            started: false,
+        }
+    }
+
+    pub(crate) fn is_started(&self) -> bool {
+        self.started
+    }
+
+    fn add_element(&mut self) -> Result<(), &'static str> {
+        match self.open_arrays.last_mut() {
+            Some(len) => {
+                *len += 1;
+                Ok(())
+            }
+            None => Err("No reply array is open, use 'reply_array_begin' first"),
+        }
+    }
+
+    fn array_begin(&mut self) -> Result<(), &'static str> {
+        if self.started {
+            self.add_element()
+                .map_err(|_| "The reply was already sent")?;
+        }
+        self.started = true;
+        self.open_arrays.push(0);
+        self.client.reply_with_postponed_array();
+        Ok(())
+    }
+
+    fn item(
+        &mut self,
+        isolate_scope: &V8IsolateScope,
+        ctx_scope: &V8ContextScope,
+        val: V8LocalValue,
+    ) -> Result<(), &'static str> {
+        self.add_element()?;
+        send_reply(0, isolate_scope, ctx_scope, self.client.as_ref(), val);
+        Ok(())
+    }
+
+    fn array_end(&mut self) -> Result<(), &'static str> {
+        let len = self
+            .open_arrays
+            .pop()
+            .ok_or("No reply array is open, use 'reply_array_begin' first")?;
+        if self.open_arrays.is_empty() {
+            self.closed_len = Some(len);
+        } else {
+            self.client.reply_set_array_length(len);
+        }
+        Ok(())
+    }
+
+    /// Close all the arrays that the function left open. If the function failed, the
+    /// error is added as the last element of the innermost open array, or as the last
+    /// element of the outermost array if the function already closed it.
+    pub(crate) fn finish(mut self, mut error: Option<GearsApiError>) {
+        if self.open_arrays.is_empty() {
+            self.open_arrays.extend(self.closed_len.take());
+        }
+        while let Some(mut len) = self.open_arrays.pop() {
+            if let Some(e) = error.take() {
+                self.client.reply_with_error(e);
+                len += 1;
+            }
+            self.client.reply_set_array_length(len);
+        }
+    }
+}
+
+const STREAMING_REPLY_UNAVAILABLE: &str =
+    "Streaming replies can only be used while a synchronous function is running";
+
 pub(crate) struct RedisClient {
     pub(crate) client: Option<Box<dyn RedisClientCtxInterface>>,
     allow_block: Option<bool>,
+    streaming_reply: Option<StreamingReply>,
 }
 
 impl RedisClient {
@@ -193,12 +297,22 @@ impl RedisClient {
         Self {
             client: None,
             allow_block: Some(true),
+            streaming_reply: None,
         }
     }
 
     pub(crate) fn make_invalid(&mut self) {
         self.client = None;
         self.allow_block = None;
+        self.streaming_reply = None;
+    }
+
+    pub(crate) fn set_streaming_reply(&mut self, streaming_reply: StreamingReply) {
+        self.streaming_reply = Some(streaming_reply);
+    }
+
+    pub(crate) fn take_streaming_reply(&mut self) -> Option<StreamingReply> {
+        self.streaming_reply.take()
     }
 
     pub(crate) fn set_client(&mut self, c: Box<dyn RedisClientCtxInterface>) {
@@ -231,6 +345,318 @@ fn js_value_to_remote_function_data(
     }
 }
 
+/// Parse the options given to `scan_iter`, an optional object with the
+/// optional fields `match`, `type` and `count`.
+fn get_scan_options(
+    ctx_scope: &V8ContextScope,
+    options: Option<V8LocalValue>,
+) -> Result<ScanOptions, String> {
+    let mut res = ScanOptions::default();
+    let options = match options {
+        Some(options) if options.is_object() => options.as_object(),
+        Some(_) => return Err("Argument to 'scan_iter' must be an object".into()),
+        None => return Ok(res),
+    };
+    if let Some(pattern) = options.get_str_field(ctx_scope, "match") {
+        let pattern: V8RedisCallArgs = pattern
+            .try_into()
+            .map_err(|_| "'match' option must be a String or an ArrayBuffer")?;
+        res.pattern = Some(pattern.as_bytes().to_vec());
+    }
+    if let Some(key_type) = options.get_str_field(ctx_scope, "type") {
+        if !key_type.is_string() {
+            return Err("'type' option must be a String".into());
+        }
+        res.key_type = Some(key_type.to_utf8().unwrap().as_str().to_string());
+    }
+    if let Some(count) = options.get_str_field(ctx_scope, "count") {
+        if !count.is_long() || count.get_long() <= 0 {
+            return Err("'count' option must be a positive integer".into());
+        }
+        res.count = Some(count.get_long() as usize);
+    }
+    Ok(res)
+}
+
+/// Convert data read from Redis to a JS String if `decode` is true (raising an error if the
+/// data is not a valid utf8 string), or to an ArrayBuffer otherwise, same as `call` and `call_raw`.
+fn bytes_to_v8_value<'isolate_scope, 'isolate>(
+    isolate_scope: &'isolate_scope V8IsolateScope<'isolate>,
+    data: Vec<u8>,
+    decode: bool,
+) -> Result<V8LocalValue<'isolate_scope, 'isolate>, String> {
+    if !decode {
+        return Ok(isolate_scope.new_array_buffer(&data).to_value());
+    }
+    String::from_utf8(data)
+        .map(|s| isolate_scope.new_string(&s).to_value())
+        .map_err(|_| "Could not decode value as string".into())
+}
+
+fn optional_bytes_to_v8_value<'isolate_scope, 'isolate>(
+    isolate_scope: &'isolate_scope V8IsolateScope<'isolate>,
+    data: Option<Vec<u8>>,
+    decode: bool,
+) -> Result<V8LocalValue<'isolate_scope, 'isolate>, String> {
+    match data {
+        Some(data) => bytes_to_v8_value(isolate_scope, data, decode),
+        None => Ok(isolate_scope.new_null()),
+    }
+}
+
+/// Returns an error if the client is no longer valid, a key that was opened by
+/// the client can only be accessed while the client is valid (Redis is locked).
+fn verify_client_valid(redis_client: &RefCell<RedisClient>) -> Result<(), String> {
+    match redis_client.borrow().client {
+        Some(_) => Ok(()),
+        None => Err("Used on invalid client".into()),
+    }
+}
+
+/// Create the JS object of a key that was opened using `client.open_key`.
+fn get_key_object<'isolate_scope, 'isolate>(
+    isolate_scope: &'isolate_scope V8IsolateScope<'isolate>,
+    ctx_scope: &V8ContextScope,
+    redis_client: &Arc<RefCell<RedisClient>>,
+    key: Box<dyn KeyCtxInterface>,
+) -> V8LocalObject<'isolate_scope, 'isolate> {
+    let key_obj = isolate_scope.new_object();
+    let key: Arc<Box<dyn KeyCtxInterface>> = Arc::new(key);
+
+    let (redis_client_ref, key_ref) = (Arc::clone(redis_client), Arc::clone(&key));
+    key_obj.set_native_function(
+        ctx_scope,
+        "type",
+        new_native_function!(move |isolate_scope, _ctx_scope| {
+            verify_client_valid(&redis_client_ref)?;
+            Ok::<_, String>(Some(
+                isolate_scope.new_string(key_ref.key_type()).to_value(),
+            ))
+        }),
+    );
+
+    let (redis_client_ref, key_ref) = (Arc::clone(redis_client), Arc::clone(&key));
+    key_obj.set_native_function(
+        ctx_scope,
+        "delete",
+        new_native_function!(move |isolate_scope, _ctx_scope| {
+            verify_client_valid(&redis_client_ref)?;
+            let res = key_ref.delete().map_err(|e| e.get_msg().to_string())?;
+            Ok::<_, String>(Some(isolate_scope.new_bool(res)))
+        }),
+    );
+
+    for (name, decode) in [("string_get", true), ("string_get_raw", false)] {
+        let (redis_client_ref, key_ref) = (Arc::clone(redis_client), Arc::clone(&key));
+        key_obj.set_native_function(
+            ctx_scope,
+            name,
+            new_native_function!(move |isolate_scope, _ctx_scope| {
+                verify_client_valid(&redis_client_ref)?;
+                let res = key_ref.string_get().map_err(|e| e.get_msg().to_string())?;
+                Ok::<_, String>(Some(optional_bytes_to_v8_value(isolate_scope, res, decode)?))
+            }),
+        );
+    }
+
+    let (redis_client_ref, key_ref) = (Arc::clone(redis_client), Arc::clone(&key));
+    key_obj.set_native_function(
+        ctx_scope,
+        "string_set",
+        new_native_function!(move |_isolate_scope, _ctx_scope, val: V8RedisCallArgs| {
+            verify_client_valid(&redis_client_ref)?;
+            key_ref
+                .string_set(val.as_bytes())
+                .map_err(|e| e.get_msg().to_string())?;
+            Ok::<_, String>(None)
+        }),
+    );
+
+    for (name, decode) in [("hash_get", true), ("hash_get_raw", false)] {
+        let (redis_client_ref, key_ref) = (Arc::clone(redis_client), Arc::clone(&key));
+        key_obj.set_native_function(
+            ctx_scope,
+            name,
+            new_native_function!(move |isolate_scope, _ctx_scope, field: V8RedisCallArgs| {
+                verify_client_valid(&redis_client_ref)?;
+                let res = key_ref
+                    .hash_get(field.as_bytes())
+                    .map_err(|e| e.get_msg().to_string())?;
+                Ok::<_, String>(Some(optional_bytes_to_v8_value(isolate_scope, res, decode)?))
+            }),
+        );
+    }
+
+    let (redis_client_ref, key_ref) = (Arc::clone(redis_client), Arc::clone(&key));
+    key_obj.set_native_function(
+        ctx_scope,
+        "hash_set",
+        new_native_function!(move |isolate_scope,
+                                   _ctx_scope,
+                                   field: V8RedisCallArgs,
+                                   val: V8RedisCallArgs| {
+            verify_client_valid(&redis_client_ref)?;
+            let res = key_ref
+                .hash_set(field.as_bytes(), val.as_bytes())
+                .map_err(|e| e.get_msg().to_string())?;
+            Ok::<_, String>(Some(isolate_scope.new_bool(res)))
+        }),
+    );
+
+    let (redis_client_ref, key_ref) = (Arc::clone(redis_client), Arc::clone(&key));
+    key_obj.set_native_function(
+        ctx_scope,
+        "hash_del",
+        new_native_function!(move |isolate_scope, _ctx_scope, field: V8RedisCallArgs| {
+            verify_client_valid(&redis_client_ref)?;
+            let res = key_ref
+                .hash_del(field.as_bytes())
+                .map_err(|e| e.get_msg().to_string())?;
+            Ok::<_, String>(Some(isolate_scope.new_bool(res)))
+        }),
+    );
+
+    for (name, end) in [
+        ("list_push_head", ListEnd::Head),
+        ("list_push_tail", ListEnd::Tail),
+    ] {
+        let (redis_client_ref, key_ref) = (Arc::clone(redis_client), Arc::clone(&key));
+        key_obj.set_native_function(
+            ctx_scope,
+            name,
+            new_native_function!(move |_isolate_scope, _ctx_scope, val: V8RedisCallArgs| {
+                verify_client_valid(&redis_client_ref)?;
+                key_ref
+                    .list_push(val.as_bytes(), end)
+                    .map_err(|e| e.get_msg().to_string())?;
+                Ok::<_, String>(None)
+            }),
+        );
+    }
+
+    for (name, end, decode) in [
+        ("list_pop_head", ListEnd::Head, true),
+        ("list_pop_tail", ListEnd::Tail, true),
+        ("list_pop_head_raw", ListEnd::Head, false),
+        ("list_pop_tail_raw", ListEnd::Tail, false),
+    ] {
+        let (redis_client_ref, key_ref) = (Arc::clone(redis_client), Arc::clone(&key));
+        key_obj.set_native_function(
+            ctx_scope,
+            name,
+            new_native_function!(move |isolate_scope, _ctx_scope| {
+                verify_client_valid(&redis_client_ref)?;
+                let res = key_ref.list_pop(end).map_err(|e| e.get_msg().to_string())?;
+                Ok::<_, String>(Some(optional_bytes_to_v8_value(isolate_scope, res, decode)?))
+            }),
+        );
+    }
+
+    let (redis_client_ref, key_ref) = (Arc::clone(redis_client), Arc::clone(&key));
+    key_obj.set_native_function(
+        ctx_scope,
+        "value_length",
+        new_native_function!(move |isolate_scope, _ctx_scope| {
+            verify_client_valid(&redis_client_ref)?;
+            Ok::<_, String>(Some(isolate_scope.new_long(key_ref.value_length() as i64)))
+        }),
+    );
+
+    let (redis_client_ref, key_ref) = (Arc::clone(redis_client), Arc::clone(&key));
+    key_obj.set_native_function(
+        ctx_scope,
+        "stream_add",
+        new_native_function!(move |isolate_scope, ctx_scope, fields: V8LocalValue| {
+            verify_client_valid(&redis_client_ref)?;
+            if !fields.is_object() {
+                return Err("Argument to 'stream_add' must be an object".into());
+            }
+            let fields = fields.as_object();
+            let names = fields.get_property_names(ctx_scope);
+            let fields = (0..names.len())
+                .map(|i| {
+                    let name = names.get(ctx_scope, i);
+                    let val = fields.get(ctx_scope, &name).unwrap();
+                    let name: V8RedisCallArgs = name.try_into()?;
+                    let val: V8RedisCallArgs = val
+                        .try_into()
+                        .map_err(|_| "Stream field value must be a String or an ArrayBuffer")?;
+                    Ok((name, val))
+                })
+                .collect::<Result<Vec<_>, &str>>()?;
+            let fields = fields
+                .iter()
+                .map(|(name, val)| (name.as_bytes(), val.as_bytes()))
+                .collect::<Vec<_>>();
+            let id = key_ref
+                .stream_add(&fields)
+                .map_err(|e| e.get_msg().to_string())?;
+            Ok::<_, String>(Some(isolate_scope.new_string(&id).to_value()))
+        }),
+    );
+
+    key_obj
+}
+
+/// Create a JS iterator over the keys returned by the given `next_batch` callback, the next
+/// batch is only requested when all the keys of the previous batch were consumed.
+/// Keys are given as JS Strings if `decode` is true, or as ArrayBuffers otherwise.
+fn new_keys_iterator<'isolate_scope, 'isolate>(
+    script_ctx: &V8ScriptCtx,
+    isolate_scope: &'isolate_scope V8IsolateScope<'isolate>,
+    ctx_scope: &V8ContextScope<'isolate_scope, 'isolate>,
+    decode: bool,
+    next_batch: impl Fn(&V8IsolateScope, &V8ContextScope) -> Result<Option<Vec<Vec<u8>>>, String>
+        + 'static,
+) -> Result<V8LocalValue<'isolate_scope, 'isolate>, String> {
+    let iterator = isolate_scope.new_object();
+    let pending_keys = RefCell::new(VecDeque::<Vec<u8>>::new());
+    let done = RefCell::new(false);
+    iterator.set_native_function(
+        ctx_scope,
+        "next",
+        new_native_function!(move |isolate_scope, ctx_scope| {
+            let res = isolate_scope.new_object();
+            while pending_keys.borrow().is_empty() && !*done.borrow() {
+                match next_batch(isolate_scope, ctx_scope)? {
+                    Some(keys) => pending_keys.borrow_mut().extend(keys),
+                    None => *done.borrow_mut() = true,
+                }
+            }
+            match pending_keys.borrow_mut().pop_front() {
+                Some(key) => {
+                    let key = bytes_to_v8_value(isolate_scope, key, decode)?;
+                    res.set(
+                        ctx_scope,
+                        &isolate_scope.new_string("value").to_value(),
+                        &key,
+                    );
+                    res.set(
+                        ctx_scope,
+                        &isolate_scope.new_string("done").to_value(),
+                        &isolate_scope.new_bool(false),
+                    );
+                }
+                None => {
+                    res.set(
+                        ctx_scope,
+                        &isolate_scope.new_string("done").to_value(),
+                        &isolate_scope.new_bool(true),
+                    );
+                }
+            }
+            Ok::<_, String>(Some(res.to_value()))
+        }),
+    );
+    // make the iterator iterable so it can be used on a `for...of` loop.
+    let make_iterable = script_ctx
+        .get_make_iterable(isolate_scope, ctx_scope)
+        .ok_or("Failed creating keys iterator")?;
+    make_iterable
+        .call(ctx_scope, Some(&[&iterator.to_value()]))
+        .ok_or_else(|| "Failed creating keys iterator".into())
+}
+
 pub(crate) fn get_backgrounnd_client<'isolate_scope, 'isolate>(
     script_ctx: &Arc<V8ScriptCtx>,
     isolate_scope: &'isolate_scope V8IsolateScope<'isolate>,
@@ -285,6 +711,66 @@ pub(crate) fn get_backgrounnd_client<'isolate_scope, 'isolate>(
         }),
     );
 
+    let redis_background_client_ref = Arc::clone(&redis_background_client);
+    let script_ctx_ref = Arc::downgrade(script_ctx);
+    bg_client.set_native_function(
+        ctx_scope,
+        "atomic",
+        new_native_function!(move |isolate_scope, ctx_scope, f: V8LocalValue| {
+            if !f.is_function() {
+                return Err("Argument to 'atomic' must be a function".into());
+            }
+
+            let is_already_blocked = ctx_scope.get_private_data::<bool, _>(0);
+            if is_already_blocked.is_some() && *is_already_blocked.unwrap() {
+                return Err("Main thread is already blocked".into());
+            }
+
+            let atomic_block = {
+                let _unlocker = isolate_scope.new_unlocker();
+                match redis_background_client_ref.lock_atomic() {
+                    Ok(l) => l,
+                    Err(err) => {
+                        return Err(format!("Can not lock Redis, {}", err.get_msg()));
+                    }
+                }
+            };
+            let script_ctx_ref = match script_ctx_ref.upgrade() {
+                Some(s) => s,
+                None => {
+                    atomic_block.abort();
+                    return Err("Function were unregistered".into());
+                }
+            };
+
+            let r_client = Arc::new(RefCell::new(RedisClient::new()));
+            r_client.borrow_mut().set_client(atomic_block.get_client());
+            let c = get_redis_client(&script_ctx_ref, isolate_scope, ctx_scope, &r_client);
+
+            let _block_guard = ctx_scope.set_private_data(0, &true); // indicate we are blocked
+
+            script_ctx_ref.after_lock_gil();
+            let res = f.call(ctx_scope, Some(&[&c.to_value()]));
+            script_ctx_ref.before_release_gil();
+
+            r_client.borrow_mut().make_invalid();
+            match res {
+                Some(res) if res.is_promise() => {
+                    // the block can not wait for the promise, Redis must
+                    // be released when the block returns.
+                    atomic_block.abort();
+                    Err("Function given to 'atomic' must not be async".into())
+                }
+                Some(res) => Ok(Some(res)),
+                None => {
+                    // an exception was raised, undo the block and let the exception propagate.
+                    atomic_block.abort();
+                    Ok(None)
+                }
+            }
+        }),
+    );
+
     let redis_background_client_ref = Arc::clone(&redis_background_client);
     let script_ctx_weak_ref = Arc::downgrade(script_ctx);
     bg_client.set_native_function(ctx_scope, "run_on_key", new_native_function!(move |
@@ -419,6 +905,46 @@ pub(crate) fn get_backgrounnd_client<'isolate_scope, 'isolate>(
         Ok(Some(promise.to_value()))
     }));
 
+    for (name, decode) in [("scan_iter", true), ("scan_iter_raw", false)] {
+        let redis_background_client_ref = Arc::clone(&redis_background_client);
+        let script_ctx_ref = Arc::downgrade(script_ctx);
+        bg_client.set_native_function(
+            ctx_scope,
+            name,
+            new_native_function!(
+                move |isolate_scope, ctx_scope, options: Option<V8LocalValue>| {
+                    let script_ctx_ref = match script_ctx_ref.upgrade() {
+                        Some(s) => s,
+                        None => {
+                            return Err("Function were unregistered".into());
+                        }
+                    };
+                    let options = get_scan_options(ctx_scope, options)?;
+                    let cursor = redis_background_client_ref
+                        .scan(options)
+                        .map_err(|e| e.get_msg().to_string())?;
+                    let cursor = RefCell::new(cursor);
+                    let res = new_keys_iterator(
+                        &script_ctx_ref,
+                        isolate_scope,
+                        ctx_scope,
+                        decode,
+                        move |isolate_scope, ctx_scope| {
+                            let is_already_blocked = ctx_scope.get_private_data::<bool, _>(0);
+                            if is_already_blocked.is_some() && *is_already_blocked.unwrap() {
+                                return Err("Main thread is already blocked".into());
+                            }
+                            // Redis is locked for each batch and released between batches.
+                            let _unlocker = isolate_scope.new_unlocker();
+                            Ok(cursor.borrow_mut().next_batch())
+                        },
+                    )?;
+                    Ok::<_, String>(Some(res))
+                }
+            ),
+        );
+    }
+
     bg_client
 }
 
@@ -538,6 +1064,101 @@ pub(crate) fn get_redis_client<'isolate_scope, 'isolate>(
         }),
     );
 
+    let redis_client_ref = Arc::clone(redis_client);
+    client.set_native_function(
+        ctx_scope,
+        "open_key",
+        new_native_function!(move |isolate_scope, ctx_scope, name: V8RedisCallArgs| {
+            let key = match redis_client_ref.borrow().client.as_ref() {
+                Some(c) => c
+                    .open_key(name.as_bytes())
+                    .map_err(|e| e.get_msg().to_string())?,
+                None => return Err("Used on invalid client".to_string()),
+            };
+            let key_obj = get_key_object(isolate_scope, ctx_scope, &redis_client_ref, key);
+            Ok::<_, String>(Some(key_obj.to_value()))
+        }),
+    );
+
+    for (name, decode) in [("scan_iter", true), ("scan_iter_raw", false)] {
+        let redis_client_ref = Arc::clone(redis_client);
+        let script_ctx_ref = Arc::downgrade(script_ctx);
+        client.set_native_function(
+            ctx_scope,
+            name,
+            new_native_function!(
+                move |isolate_scope, ctx_scope, options: Option<V8LocalValue>| {
+                    let script_ctx_ref = match script_ctx_ref.upgrade() {
+                        Some(s) => s,
+                        None => {
+                            return Err("Function were unregistered".into());
+                        }
+                    };
+                    let options = get_scan_options(ctx_scope, options)?;
+                    let cursor = match redis_client_ref.borrow().client.as_ref() {
+                        Some(c) => c.scan(options).map_err(|e| e.get_msg().to_string())?,
+                        None => return Err("Used on invalid client".into()),
+                    };
+                    let cursor = RefCell::new(cursor);
+                    let redis_client_ref = Arc::clone(&redis_client_ref);
+                    let res = new_keys_iterator(
+                        &script_ctx_ref,
+                        isolate_scope,
+                        ctx_scope,
+                        decode,
+                        move |_isolate_scope, _ctx_scope| {
+                            // the cursor can only be used while the client is valid (Redis is locked).
+                            if redis_client_ref.borrow().client.is_none() {
+                                return Err("Used on invalid client".into());
+                            }
+                            Ok(cursor.borrow_mut().next_batch())
+                        },
+                    )?;
+                    Ok::<_, String>(Some(res))
+                }
+            ),
+        );
+    }
+
+    let redis_client_ref = Arc::clone(redis_client);
+    client.set_native_function(
+        ctx_scope,
+        "reply_array_begin",
+        new_native_function!(move |_isolate_scope, _ctx_scope| {
+            match redis_client_ref.borrow_mut().streaming_reply.as_mut() {
+                Some(r) => r.array_begin()?,
+                None => return Err(STREAMING_REPLY_UNAVAILABLE),
+            }
+            Ok::<_, &'static str>(None)
+        }),
+    );
+
+    let redis_client_ref = Arc::clone(redis_client);
+    client.set_native_function(
+        ctx_scope,
+        "reply_item",
+        new_native_function!(move |isolate_scope, ctx_scope, val: V8LocalValue| {
+            match redis_client_ref.borrow_mut().streaming_reply.as_mut() {
+                Some(r) => r.item(isolate_scope, ctx_scope, val)?,
+                None => return Err(STREAMING_REPLY_UNAVAILABLE),
+            }
+            Ok::<_, &'static str>(None)
+        }),
+    );
+
+    let redis_client_ref = Arc::clone(redis_client);
+    client.set_native_function(
+        ctx_scope,
+        "reply_end",
+        new_native_function!(move |_isolate_scope, _ctx_scope| {
+            match redis_client_ref.borrow_mut().streaming_reply.as_mut() {
+                Some(r) => r.array_end()?,
+                None => return Err(STREAMING_REPLY_UNAVAILABLE),
+            }
+            Ok::<_, &'static str>(None)
+        }),
+    );
+
     let redisai_client = get_redisai_client(script_ctx, isolate_scope, ctx_scope, redis_client);
     client.set(
         ctx_scope,
@@ -611,6 +1232,428 @@ pub(crate) fn get_redis_client<'isolate_scope, 'isolate>(
     client
 }
 
+fn get_positive_long_field(
+    ctx_scope: &V8ContextScope,
+    obj: &V8LocalObject,
+    name: &str,
+) -> Result<Option<u64>, String> {
+    match obj.get_str_field(ctx_scope, name) {
+        Some(val) => {
+            if !val.is_long() || val.get_long() < 0 {
+                return Err(format!("'{}' must be a positive integer", name));
+            }
+            Ok(Some(val.get_long() as u64))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Parse the `retry` option of `register_stream_consumer`, i.e.
+/// `{max_attempts: 3, backoff: 'exponential', delay: 100, max_delay: 1000, retryable_errors: ['LOCKED']}`.
+fn get_stream_retry_policy(
+    ctx_scope: &V8ContextScope,
+    retry: &V8LocalValue,
+) -> Result<StreamRetryPolicy, String> {
+    if !retry.is_object() {
+        return Err("'retry' option must be an object".into());
+    }
+    let retry = retry.as_object();
+    let max_attempts = get_positive_long_field(ctx_scope, &retry, "max_attempts")?
+        .ok_or("'max_attempts' must be given on 'retry' option")?;
+    let delay = get_positive_long_field(ctx_scope, &retry, "delay")?.unwrap_or(0);
+    let backoff = match retry.get_str_field(ctx_scope, "backoff") {
+        Some(backoff) => {
+            if !backoff.is_string() {
+                return Err("'backoff' must be a string".into());
+            }
+            backoff.to_utf8().unwrap().as_str().to_string()
+        }
+        None => "fixed".to_string(),
+    };
+    let backoff = match backoff.as_str() {
+        "fixed" => StreamRetryBackoff::Fixed(delay),
+        "exponential" => StreamRetryBackoff::Exponential {
+            initial: delay,
+            max: get_positive_long_field(ctx_scope, &retry, "max_delay")?.unwrap_or(u64::MAX),
+        },
+        _ => return Err(format!("Unknown backoff '{}'", backoff)),
+    };
+    let retryable_errors = match retry.get_str_field(ctx_scope, "retryable_errors") {
+        Some(errors) => {
+            if !errors.is_array() {
+                return Err("'retryable_errors' must be an array of strings".into());
+            }
+            let errors = errors.as_array();
+            let mut res = Vec::new();
+            for i in 0..errors.len() {
+                let error = errors.get(ctx_scope, i);
+                if !error.is_string() {
+                    return Err("'retryable_errors' must be an array of strings".into());
+                }
+                res.push(error.to_utf8().unwrap().as_str().to_string());
+            }
+            Some(res)
+        }
+        None => None,
+    };
+    Ok(StreamRetryPolicy {
+        max_attempts: max_attempts as usize,
+        backoff,
+        retryable_errors,
+    })
+}
+
+/// Parse the optional settings object given to `register_stream_consumer`.
+fn get_stream_consumer_options(
+    ctx_scope: &V8ContextScope,
+    options: &V8LocalValue,
+) -> Result<StreamConsumerOptions, String> {
+    if !options.is_object() {
+        return Err("Sixth argument to 'register_stream_consumer' must be an object".into());
+    }
+    let options = options.as_object();
+    let mut res = StreamConsumerOptions::default();
+    if let Some(dead_letter) = options.get_str_field(ctx_scope, "dead_letter") {
+        res.dead_letter = if dead_letter.is_string() {
+            Some(dead_letter.to_utf8().unwrap().as_str().as_bytes().to_vec())
+        } else if dead_letter.is_array_buffer() {
+            Some(dead_letter.as_array_buffer().data().to_vec())
+        } else {
+            return Err(
+                "'dead_letter' option must be a String or ArrayBuffer representing the stream name"
+                    .into(),
+            );
+        };
+    }
+    if let Some(retry) = options.get_str_field(ctx_scope, "retry") {
+        res.retry_policy = Some(get_stream_retry_policy(ctx_scope, &retry)?);
+    }
+    if let Some(start_position) = options.get_str_field(ctx_scope, "start_position") {
+        if !start_position.is_string() {
+            return Err("'start_position' option must be a String".into());
+        }
+        res.start_position = start_position
+            .to_utf8()
+            .unwrap()
+            .as_str()
+            .parse::<StreamStartPosition>()
+            .map_err(|e| e.get_msg().to_string())?;
+    }
+    let max_wait_ms = get_positive_long_field(ctx_scope, &options, "max_wait_ms")?;
+    match get_positive_long_field(ctx_scope, &options, "batch_size")? {
+        Some(0) => return Err("'batch_size' must be greater than 0".into()),
+        Some(batch_size) => {
+            res.batch = Some(StreamBatchOptions {
+                batch_size: batch_size as usize,
+                max_wait_ms: max_wait_ms.unwrap_or(0),
+            })
+        }
+        None => {
+            if max_wait_ms.is_some() {
+                return Err("'max_wait_ms' option requires the 'batch_size' option".into());
+            }
+        }
+    }
+    if let Some(consumer_group) = options.get_str_field(ctx_scope, "consumer_group") {
+        res.consumer_group = match consumer_group.to_utf8().unwrap().as_str() {
+            "true" => true,
+            "false" => false,
+            _ => return Err("'consumer_group' option must be a Boolean".into()),
+        };
+    }
+    Ok(res)
+}
+
+/// The keys a notifications consumer is registered on, an owned version of `RegisteredKeys`.
+enum NotificationsConsumerKeys {
+    Key(Vec<u8>),
+    Prefix(Vec<u8>),
+    Prefixes(Vec<Vec<u8>>),
+    Suffix(Vec<u8>),
+    Pattern(Vec<u8>),
+}
+
+impl NotificationsConsumerKeys {
+    fn as_registered_keys(&self) -> RegisteredKeys {
+        match self {
+            NotificationsConsumerKeys::Key(k) => RegisteredKeys::Key(k),
+            NotificationsConsumerKeys::Prefix(p) => RegisteredKeys::Prefix(p),
+            NotificationsConsumerKeys::Prefixes(p) => {
+                RegisteredKeys::Prefixes(p.iter().map(|p| p.as_slice()).collect())
+            }
+            NotificationsConsumerKeys::Suffix(s) => RegisteredKeys::Suffix(s),
+            NotificationsConsumerKeys::Pattern(p) => RegisteredKeys::Pattern(p),
+        }
+    }
+}
+
+/// Returns the bytes of a String or ArrayBuffer value, `None` if the value is of any other type.
+fn get_bytes_value(val: &V8LocalValue) -> Option<Vec<u8>> {
+    if val.is_string() {
+        Some(val.to_utf8().unwrap().as_str().as_bytes().to_vec())
+    } else if val.is_array_buffer() {
+        Some(val.as_array_buffer().data().to_vec())
+    } else {
+        None
+    }
+}
+
+fn get_prefixes(
+    ctx_scope: &V8ContextScope,
+    prefixes: &V8LocalValue,
+) -> Result<Vec<Vec<u8>>, String> {
+    let prefixes = prefixes.as_array();
+    let len = prefixes.len();
+    if len == 0 {
+        return Err("Prefixes list can not be empty".into());
+    }
+    (0..len)
+        .map(|i| {
+            get_bytes_value(&prefixes.get(ctx_scope, i)).ok_or_else(|| {
+                "Prefixes list must contain only Strings or ArrayBuffers".to_string()
+            })
+        })
+        .collect()
+}
+
+/// Parse the keys given to `register_notifications_consumer`. The keys can be a prefix,
+/// a list of prefixes or an object with one of the fields `key`, `prefix`, `suffix` or `pattern`.
+fn get_notifications_consumer_keys(
+    ctx_scope: &V8ContextScope,
+    keys: &V8LocalValue,
+) -> Result<NotificationsConsumerKeys, String> {
+    if let Some(prefix) = get_bytes_value(keys) {
+        return Ok(NotificationsConsumerKeys::Prefix(prefix));
+    }
+    if keys.is_array() {
+        return Ok(NotificationsConsumerKeys::Prefixes(get_prefixes(
+            ctx_scope, keys,
+        )?));
+    }
+    if !keys.is_object() {
+        return Err("Second argument to 'register_notifications_consumer' must be a String or ArrayBuffer representing the prefix, an array of prefixes or an object".into());
+    }
+    let keys = keys.as_object();
+    let mut res = None;
+    for field in ["key", "prefix", "suffix", "pattern"] {
+        let val = match keys.get_str_field(ctx_scope, field) {
+            Some(val) => val,
+            None => continue,
+        };
+        if res.is_some() {
+            return Err("Only one of 'key', 'prefix', 'suffix' or 'pattern' can be given".into());
+        }
+        if field == "prefix" && val.is_array() {
+            res = Some(NotificationsConsumerKeys::Prefixes(get_prefixes(
+                ctx_scope, &val,
+            )?));
+            continue;
+        }
+        let val = get_bytes_value(&val)
+            .ok_or_else(|| format!("'{}' must be a String or ArrayBuffer", field))?;
+        res = Some(match field {
+            "key" => NotificationsConsumerKeys::Key(val),
+            "prefix" => NotificationsConsumerKeys::Prefix(val),
+            "suffix" => NotificationsConsumerKeys::Suffix(val),
+            _ => NotificationsConsumerKeys::Pattern(val),
+        });
+    }
+    res.ok_or_else(|| "One of 'key', 'prefix', 'suffix' or 'pattern' must be given".into())
+}
+
+/// Returns the value of an optional field that should contain an array of strings.
+fn get_strings_array_field(
+    ctx_scope: &V8ContextScope,
+    obj: &V8LocalObject,
+    name: &str,
+) -> Result<Option<Vec<String>>, String> {
+    let val = match obj.get_str_field(ctx_scope, name) {
+        Some(val) => val,
+        None => return Ok(None),
+    };
+    let err = || format!("'{}' must be an array of strings", name);
+    if !val.is_array() {
+        return Err(err());
+    }
+    let arr = val.as_array();
+    (0..arr.len())
+        .map(|i| {
+            let v = arr.get(ctx_scope, i);
+            if !v.is_string() {
+                return Err(err());
+            }
+            Ok(v.to_utf8().unwrap().as_str().to_string())
+        })
+        .collect::<Result<Vec<String>, String>>()
+        .map(Some)
+}
+
+/// Parse the optional settings object given to `register_notifications_consumer`.
+fn get_notifications_consumer_options(
+    ctx_scope: &V8ContextScope,
+    options: &V8LocalValue,
+) -> Result<KeysNotificationsConsumerOptions, String> {
+    if !options.is_object() {
+        return Err(
+            "Fourth argument to 'register_notifications_consumer' must be an object".into(),
+        );
+    }
+    let options = options.as_object();
+    let mut res = KeysNotificationsConsumerOptions::default();
+    if let Some(events) = get_strings_array_field(ctx_scope, &options, "events")? {
+        res.events = events;
+    }
+    if let Some(classes) = get_strings_array_field(ctx_scope, &options, "event_classes")? {
+        for class in classes {
+            res.event_classes |= class
+                .parse::<KeysNotificationsEventClasses>()
+                .map_err(|e| e.get_msg().to_string())?;
+        }
+    }
+    res.coalesce_ms = match get_positive_long_field(ctx_scope, &options, "coalesce_ms")? {
+        Some(0) => return Err("'coalesce_ms' must be greater than 0".into()),
+        v => v,
+    };
+    Ok(res)
+}
+
+/// Parse the schedule argument of `register_timer`, either an interval
+/// in milliseconds or a cron expression.
+fn get_timer_schedule(schedule: &V8LocalValue) -> Result<TimerSchedule, String> {
+    if schedule.is_long() {
+        let interval = schedule.get_long();
+        if interval <= 0 {
+            return Err("Timer interval must be greater than 0".into());
+        }
+        Ok(TimerSchedule::Interval(interval as u64))
+    } else if schedule.is_string() {
+        Ok(TimerSchedule::Cron(
+            schedule.to_utf8().unwrap().as_str().to_string(),
+        ))
+    } else {
+        Err("Second argument to 'register_timer' must be an interval in milliseconds or a cron expression".into())
+    }
+}
+
+/// The channel a channel consumer is registered on, an owned version of `RegisteredChannels`.
+enum ConsumerChannel {
+    Channel(Vec<u8>),
+    Pattern(Vec<u8>),
+}
+
+impl ConsumerChannel {
+    fn as_registered_channels(&self) -> RegisteredChannels {
+        match self {
+            ConsumerChannel::Channel(c) => RegisteredChannels::Channel(c),
+            ConsumerChannel::Pattern(p) => RegisteredChannels::Pattern(p),
+        }
+    }
+}
+
+/// Parse the channel given to `register_channel_consumer`. The channel can be a channel
+/// name or an object with one of the fields `channel` or `pattern`.
+fn get_consumer_channel(
+    ctx_scope: &V8ContextScope,
+    channel: &V8LocalValue,
+) -> Result<ConsumerChannel, String> {
+    if let Some(channel) = get_bytes_value(channel) {
+        return Ok(ConsumerChannel::Channel(channel));
+    }
+    if !channel.is_object() {
+        return Err("Second argument to 'register_channel_consumer' must be a String or ArrayBuffer representing the channel or an object".into());
+    }
+    let channel = channel.as_object();
+    let mut res = None;
+    for field in ["channel", "pattern"] {
+        let val = match channel.get_str_field(ctx_scope, field) {
+            Some(val) => val,
+            None => continue,
+        };
+        if res.is_some() {
+            return Err("Only one of 'channel' or 'pattern' can be given".into());
+        }
+        let val = get_bytes_value(&val)
+            .ok_or_else(|| format!("'{}' must be a String or ArrayBuffer", field))?;
+        res = Some(match field {
+            "channel" => ConsumerChannel::Channel(val),
+            _ => ConsumerChannel::Pattern(val),
+        });
+    }
+    res.ok_or_else(|| "One of 'channel' or 'pattern' must be given".into())
+}
+
+/// Parse the optional settings object given to `register_channel_consumer`.
+fn get_channel_consumer_options(
+    ctx_scope: &V8ContextScope,
+    options: &V8LocalValue,
+) -> Result<ChannelConsumerOptions, String> {
+    if !options.is_object() {
+        return Err("Fourth argument to 'register_channel_consumer' must be an object".into());
+    }
+    let options = options.as_object();
+    let mut res = ChannelConsumerOptions::default();
+    if let Some(run_on_replicas) = options.get_str_field(ctx_scope, "run_on_replicas") {
+        res.run_on_replicas = match run_on_replicas.to_utf8().unwrap().as_str() {
+            "true" => true,
+            "false" => false,
+            _ => return Err("'run_on_replicas' option must be a Boolean".into()),
+        };
+    }
+    Ok(res)
+}
+
+/// Returns the value of an optional boolean field.
+fn get_bool_field(
+    ctx_scope: &V8ContextScope,
+    obj: &V8LocalObject,
+    name: &str,
+) -> Result<bool, String> {
+    match obj.get_str_field(ctx_scope, name) {
+        Some(val) => match val.to_utf8().unwrap().as_str() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(format!("'{}' must be a Boolean", name)),
+        },
+        None => Ok(false),
+    }
+}
+
+/// Parse the arguments schema given to `register_function`, an array of objects
+/// with the fields `name`, `type` and optionally `optional` and `variadic`.
+fn get_function_args_schema(
+    ctx_scope: &V8ContextScope,
+    args_schema: &V8LocalArray,
+) -> Result<Vec<FunctionArgument>, String> {
+    (0..args_schema.len())
+        .map(|i| {
+            let arg = args_schema.get(ctx_scope, i);
+            if !arg.is_object() {
+                return Err("Argument schema entry must be an object".to_string());
+            }
+            let arg = arg.as_object();
+            let name = match arg.get_str_field(ctx_scope, "name") {
+                Some(name) if name.is_string() => name.to_utf8().unwrap().as_str().to_string(),
+                _ => return Err("Argument 'name' must be a String".to_string()),
+            };
+            let arg_type = match arg.get_str_field(ctx_scope, "type") {
+                Some(arg_type) if arg_type.is_string() => arg_type
+                    .to_utf8()
+                    .unwrap()
+                    .as_str()
+                    .parse::<FunctionArgumentType>()
+                    .map_err(|e| e.get_msg().to_string())?,
+                _ => return Err(format!("Argument '{}' must have a 'type' String", name)),
+            };
+            Ok(FunctionArgument {
+                arg_type,
+                optional: get_bool_field(ctx_scope, &arg, "optional")?,
+                variadic: get_bool_field(ctx_scope, &arg, "variadic")?,
+                name,
+            })
+        })
+        .collect()
+}
+
 pub(crate) fn initialize_globals(
     script_ctx: &Arc<V8ScriptCtx>,
     globals: &V8LocalObject,
@@ -653,10 +1696,15 @@ pub(crate) fn initialize_globals(
         window: i64,
         trim: bool,
         function_callback: V8LocalValue,
+        options: Option<V8LocalValue>,
     | {
         if !function_callback.is_function() {
             return Err("Fith argument to 'register_stream_consumer' must be a function".into());
         }
+        let options = match options {
+            Some(options) => get_stream_consumer_options(curr_ctx_scope, &options)?,
+            None => StreamConsumerOptions::default(),
+        };
         let persisted_function = function_callback.persist();
 
         let load_ctx = curr_ctx_scope.get_private_data_mut::<&mut dyn LoadLibraryCtxInterface, _>(0);
@@ -675,10 +1723,10 @@ pub(crate) fn initialize_globals(
         let v8_stream_ctx = V8StreamCtx::new(persisted_function, &script_ctx_ref, function_callback.is_async_function());
         let res = if prefix.is_string() {
             let prefix = prefix.to_utf8().unwrap();
-            load_ctx.register_stream_consumer(registration_name_utf8.as_str(), prefix.as_str().as_bytes(), Box::new(v8_stream_ctx), window as usize, trim)
+            load_ctx.register_stream_consumer(registration_name_utf8.as_str(), prefix.as_str().as_bytes(), Box::new(v8_stream_ctx), window as usize, trim, options)
         } else if prefix.is_array_buffer() {
             let prefix = prefix.as_array_buffer();
-            load_ctx.register_stream_consumer(registration_name_utf8.as_str(), prefix.data(), Box::new(v8_stream_ctx), window as usize, trim)
+            load_ctx.register_stream_consumer(registration_name_utf8.as_str(), prefix.data(), Box::new(v8_stream_ctx), window as usize, trim, options)
         } else {
             return Err("Second argument to 'register_stream_consumer' must be a String or ArrayBuffer representing the prefix".into());
         };
@@ -689,46 +1737,201 @@ pub(crate) fn initialize_globals(
     }));
 
     let script_ctx_ref = Arc::downgrade(script_ctx);
-    redis.set_native_function(ctx_scope, "register_notifications_consumer", new_native_function!(move|
-        _isolate_scope,
-        curr_ctx_scope,
-        registration_name_utf8: V8LocalUtf8,
-        prefix: V8LocalValue,
-        function_callback: V8LocalValue,
-    | {
-        if !function_callback.is_function() {
-            return Err("Third argument to 'register_notifications_consumer' must be a function".into());
-        }
-        let persisted_function = function_callback.persist();
+    redis.set_native_function(
+        ctx_scope,
+        "register_notifications_consumer",
+        new_native_function!(move |_isolate_scope,
+                                   curr_ctx_scope,
+                                   registration_name_utf8: V8LocalUtf8,
+                                   keys: V8LocalValue,
+                                   function_callback: V8LocalValue,
+                                   options: Option<V8LocalValue>| {
+            if !function_callback.is_function() {
+                return Err(
+                    "Third argument to 'register_notifications_consumer' must be a function".into(),
+                );
+            }
+            let persisted_function = function_callback.persist();
 
-        let load_ctx = curr_ctx_scope.get_private_data_mut::<&mut dyn LoadLibraryCtxInterface, _>(0);
-        if load_ctx.is_none() {
-            return Err("Called 'register_notifications_consumer' out of context".into());
-        }
-        let load_ctx = load_ctx.unwrap();
+            let load_ctx =
+                curr_ctx_scope.get_private_data_mut::<&mut dyn LoadLibraryCtxInterface, _>(0);
+            if load_ctx.is_none() {
+                return Err("Called 'register_notifications_consumer' out of context".into());
+            }
+            let load_ctx = load_ctx.unwrap();
 
-        let script_ctx_ref = match script_ctx_ref.upgrade() {
-            Some(s) => s,
-            None => {
-                return Err("Use of uninitialized script context".into());
+            let script_ctx_ref = match script_ctx_ref.upgrade() {
+                Some(s) => s,
+                None => {
+                    return Err("Use of uninitialized script context".into());
+                }
+            };
+            let v8_notification_ctx = V8NotificationsCtx::new(
+                persisted_function,
+                &script_ctx_ref,
+                function_callback.is_async_function(),
+            );
+
+            let keys = get_notifications_consumer_keys(curr_ctx_scope, &keys)?;
+            let options = match options {
+                Some(options) => get_notifications_consumer_options(curr_ctx_scope, &options)?,
+                None => KeysNotificationsConsumerOptions::default(),
+            };
+            let res = load_ctx.register_key_space_notification_consumer(
+                registration_name_utf8.as_str(),
+                keys.as_registered_keys(),
+                Box::new(v8_notification_ctx),
+                options,
+            );
+            if let Err(err) = res {
+                return Err(err.get_msg().to_string());
             }
-        };
-        let v8_notification_ctx = V8NotificationsCtx::new(persisted_function, &script_ctx_ref, function_callback.is_async_function());
+            Ok(None)
+        }),
+    );
 
-        let res = if prefix.is_string() {
-            let prefix = prefix.to_utf8().unwrap();
-            load_ctx.register_key_space_notification_consumer(registration_name_utf8.as_str(), RegisteredKeys::Prefix(prefix.as_str().as_bytes()), Box::new(v8_notification_ctx))
-        } else if prefix.is_array_buffer() {
-            let prefix = prefix.as_array_buffer();
-            load_ctx.register_key_space_notification_consumer(registration_name_utf8.as_str(), RegisteredKeys::Prefix(prefix.data()), Box::new(v8_notification_ctx))
-        } else {
-            return Err("Second argument to 'register_notifications_consumer' must be a string or ArrayBuffer representing the prefix".into());
-        };
-        if let Err(err) = res {
-            return Err(err.get_msg().to_string());
-        }
-        Ok(None)
-    }));
+    let script_ctx_ref = Arc::downgrade(script_ctx);
+    redis.set_native_function(
+        ctx_scope,
+        "register_timer",
+        new_native_function!(move |_isolate_scope,
+                                   curr_ctx_scope,
+                                   registration_name_utf8: V8LocalUtf8,
+                                   schedule: V8LocalValue,
+                                   function_callback: V8LocalValue| {
+            if !function_callback.is_function() {
+                return Err("Third argument to 'register_timer' must be a function".into());
+            }
+            let schedule = get_timer_schedule(&schedule)?;
+            let persisted_function = function_callback.persist();
+
+            let load_ctx =
+                curr_ctx_scope.get_private_data_mut::<&mut dyn LoadLibraryCtxInterface, _>(0);
+            if load_ctx.is_none() {
+                return Err("Called 'register_timer' out of context".into());
+            }
+            let load_ctx = load_ctx.unwrap();
+
+            let script_ctx_ref = match script_ctx_ref.upgrade() {
+                Some(s) => s,
+                None => {
+                    return Err("Use of uninitialized script context".into());
+                }
+            };
+            let v8_timer_ctx = V8TimerCtx::new(
+                persisted_function,
+                &script_ctx_ref,
+                function_callback.is_async_function(),
+            );
+            let res = load_ctx.register_timer(
+                registration_name_utf8.as_str(),
+                schedule,
+                Box::new(v8_timer_ctx),
+            );
+            if let Err(err) = res {
+                return Err(err.get_msg().to_string());
+            }
+            Ok(None)
+        }),
+    );
+
+    let script_ctx_ref = Arc::downgrade(script_ctx);
+    redis.set_native_function(
+        ctx_scope,
+        "register_server_event_consumer",
+        new_native_function!(move |_isolate_scope,
+                                   curr_ctx_scope,
+                                   event_utf8: V8LocalUtf8,
+                                   function_callback: V8LocalValue| {
+            if !function_callback.is_function() {
+                return Err(
+                    "Second argument to 'register_server_event_consumer' must be a function".into(),
+                );
+            }
+            let event = event_utf8
+                .as_str()
+                .parse::<ServerEventType>()
+                .map_err(|e| e.get_msg().to_string())?;
+            let persisted_function = function_callback.persist();
+
+            let load_ctx =
+                curr_ctx_scope.get_private_data_mut::<&mut dyn LoadLibraryCtxInterface, _>(0);
+            if load_ctx.is_none() {
+                return Err("Called 'register_server_event_consumer' out of context".into());
+            }
+            let load_ctx = load_ctx.unwrap();
+
+            let script_ctx_ref = match script_ctx_ref.upgrade() {
+                Some(s) => s,
+                None => {
+                    return Err("Use of uninitialized script context".into());
+                }
+            };
+            let v8_server_event_ctx = V8ServerEventCtx::new(
+                persisted_function,
+                &script_ctx_ref,
+                function_callback.is_async_function(),
+            );
+            let res = load_ctx.register_server_event_consumer(event, Box::new(v8_server_event_ctx));
+            if let Err(err) = res {
+                return Err(err.get_msg().to_string());
+            }
+            Ok(None)
+        }),
+    );
+
+    let script_ctx_ref = Arc::downgrade(script_ctx);
+    redis.set_native_function(
+        ctx_scope,
+        "register_channel_consumer",
+        new_native_function!(move |_isolate_scope,
+                                   curr_ctx_scope,
+                                   registration_name_utf8: V8LocalUtf8,
+                                   channel: V8LocalValue,
+                                   function_callback: V8LocalValue,
+                                   options: Option<V8LocalValue>| {
+            if !function_callback.is_function() {
+                return Err(
+                    "Third argument to 'register_channel_consumer' must be a function".into(),
+                );
+            }
+            let channel = get_consumer_channel(curr_ctx_scope, &channel)?;
+            let options = match options {
+                Some(options) => get_channel_consumer_options(curr_ctx_scope, &options)?,
+                None => ChannelConsumerOptions::default(),
+            };
+            let persisted_function = function_callback.persist();
+
+            let load_ctx =
+                curr_ctx_scope.get_private_data_mut::<&mut dyn LoadLibraryCtxInterface, _>(0);
+            if load_ctx.is_none() {
+                return Err("Called 'register_channel_consumer' out of context".into());
+            }
+            let load_ctx = load_ctx.unwrap();
+
+            let script_ctx_ref = match script_ctx_ref.upgrade() {
+                Some(s) => s,
+                None => {
+                    return Err("Use of uninitialized script context".into());
+                }
+            };
+            let v8_channel_consumer_ctx = V8ChannelConsumerCtx::new(
+                persisted_function,
+                &script_ctx_ref,
+                function_callback.is_async_function(),
+            );
+            let res = load_ctx.register_channel_consumer(
+                registration_name_utf8.as_str(),
+                channel.as_registered_channels(),
+                Box::new(v8_channel_consumer_ctx),
+                options,
+            );
+            if let Err(err) = res {
+                return Err(err.get_msg().to_string());
+            }
+            Ok(None)
+        }),
+    );
 
     let script_ctx_ref = Arc::downgrade(script_ctx);
     redis.set_native_function(
@@ -739,7 +1942,8 @@ pub(crate) fn initialize_globals(
                   curr_ctx_scope,
                   function_name_utf8: V8LocalUtf8,
                   function_callback: V8LocalValue,
-                  function_flags: Option<V8LocalArray>| {
+                  function_flags: Option<V8LocalArray>,
+                  args_schema: Option<V8LocalArray>| {
                 if !function_callback.is_function() {
                     return Err(
                         "Second argument to 'register_function' must be a function".to_owned()
@@ -753,6 +1957,14 @@ pub(crate) fn initialize_globals(
                     None => FunctionFlags::empty(),
                 };
 
+                let args_schema = match args_schema {
+                    Some(args_schema) => Some(
+                        get_function_args_schema(curr_ctx_scope, &args_schema)
+                            .map_err(|e| format!("Failed parsing arguments schema, {}", e))?,
+                    ),
+                    None => None,
+                };
+
                 let load_ctx =
                     curr_ctx_scope.get_private_data_mut::<&mut dyn LoadLibraryCtxInterface, _>(0);
                 if load_ctx.is_none() {
@@ -778,12 +1990,14 @@ pub(crate) fn initialize_globals(
                     &c,
                     function_callback.is_async_function(),
                     !function_flags.contains(FunctionFlags::RAW_ARGUMENTS),
+                    args_schema.clone(),
                 );
 
                 let res = load_ctx.register_function(
                     function_name_utf8.as_str(),
                     Box::new(f),
                     function_flags,
+                    args_schema,
                 );
                 if let Err(err) = res {
                     return Err(err.get_msg().into());
@@ -954,6 +2168,132 @@ pub(crate) fn initialize_globals(
         Ok(None)
     }));
 
+    let script_ctx_ref = Arc::downgrade(script_ctx);
+    redis.set_native_function(ctx_scope, "export_function", new_native_function!(move|
+        _isolate_scope,
+        curr_ctx_scope,
+        function_name_utf8: V8LocalUtf8,
+        function_callback: V8LocalValue,
+    | {
+        if !function_callback.is_function() {
+            return Err("Second argument to 'export_function' must be a function".into());
+        }
+
+        if function_callback.is_async_function() {
+            return Err("Exported function can not be async".into());
+        }
+
+        let load_ctx = curr_ctx_scope.get_private_data_mut::<&mut dyn LoadLibraryCtxInterface, _>(0);
+        if load_ctx.is_none() {
+            return Err("Called 'export_function' out of context".into());
+        }
+
+        let mut persisted_function = function_callback.persist();
+        persisted_function.forget();
+        let persisted_function = Arc::new(persisted_function);
+
+        let load_ctx = load_ctx.unwrap();
+        let new_script_ctx_ref = Weak::clone(&script_ctx_ref);
+        let res = load_ctx.register_exported_function(function_name_utf8.as_str(), Box::new(move |inputs| {
+            let script_ctx = match new_script_ctx_ref.upgrade() {
+                Some(s) => s,
+                None => return Err(GearsApiError::new("Use of uninitialized script context".to_string())),
+            };
+            let isolate_scope = script_ctx.isolate.enter();
+            let ctx_scope = script_ctx.ctx.enter(&isolate_scope);
+            let trycatch = isolate_scope.new_try_catch();
+
+            let mut args = Vec::new();
+            for input in inputs {
+                args.push(match input {
+                    RemoteFunctionData::Binary(b) => isolate_scope.new_array_buffer(&b).to_value(),
+                    RemoteFunctionData::String(s) => {
+                        let v8_str = isolate_scope.new_string(&s);
+                        let v8_obj = ctx_scope.new_object_from_json(&v8_str);
+                        if v8_obj.is_none() {
+                            return Err(GearsApiError::new("Failed deserializing exported function argument".to_string()));
+                        }
+                        v8_obj.unwrap()
+                    }
+                });
+            }
+            let args_refs = args.iter().collect::<Vec<&V8LocalValue>>();
+
+            script_ctx.before_run();
+            let res = persisted_function
+                .as_local(&isolate_scope)
+                .call(&ctx_scope, Some(&args_refs));
+            script_ctx.after_run();
+            match res {
+                Some(r) => {
+                    if r.is_promise() {
+                        return Err(GearsApiError::new("Exported function returned a promise".to_string()));
+                    }
+                    js_value_to_remote_function_data(&ctx_scope, r).ok_or_else(|| {
+                        GearsApiError::new("Failed serializing exported function result".to_string())
+                    })
+                }
+                None => Err(get_exception_msg(&script_ctx.isolate, trycatch, &ctx_scope)),
+            }
+        }));
+
+        if let Err(err) = res {
+            return Err(err.get_msg().to_string());
+        }
+        Ok(None)
+    }));
+
+    redis.set_native_function(ctx_scope, "import_library", new_native_function!(move|
+        isolate_scope,
+        curr_ctx_scope,
+        library_name_utf8: V8LocalUtf8,
+    | {
+        let load_ctx = curr_ctx_scope.get_private_data_mut::<&mut dyn LoadLibraryCtxInterface, _>(0);
+        if load_ctx.is_none() {
+            return Err("Called 'import_library' out of context".into());
+        }
+        let load_ctx = load_ctx.unwrap();
+        let imported_library = load_ctx
+            .import_library(library_name_utf8.as_str())
+            .map_err(|e| e.get_msg().to_string())?;
+        let imported_library = Arc::new(imported_library);
+
+        let library_obj = isolate_scope.new_object();
+        for function_name in imported_library.get_exported_functions() {
+            let imported_library = Arc::clone(&imported_library);
+            let name = function_name.clone();
+            library_obj.set_native_function(curr_ctx_scope, &function_name, new_native_function!(move|
+                isolate_scope,
+                ctx_scope,
+                args: Vec<V8LocalValue>,
+            | {
+                let mut inputs = Vec::new();
+                for arg in args {
+                    let input = js_value_to_remote_function_data(ctx_scope, arg);
+                    if input.is_none() {
+                        return Err(format!("Failed serializing argument for exported function '{}'", name));
+                    }
+                    inputs.push(input.unwrap());
+                }
+                let res = imported_library
+                    .call_exported_function(&name, inputs)
+                    .map_err(|e| e.get_msg().to_string())?;
+                match res {
+                    RemoteFunctionData::Binary(b) => Ok(Some(isolate_scope.new_array_buffer(&b).to_value())),
+                    RemoteFunctionData::String(s) => {
+                        let v8_str = isolate_scope.new_string(&s);
+                        let v8_obj = ctx_scope.new_object_from_json(&v8_str);
+                        if v8_obj.is_none() {
+                            return Err(format!("Failed deserializing result of exported function '{}'", name));
+                        }
+                        Ok(v8_obj)
+                    }
+                }
+            }));
+        }
+        Ok(Some(library_obj.to_value()))
+    }));
+
     redis.set_native_function(
         ctx_scope,
         "v8_version",
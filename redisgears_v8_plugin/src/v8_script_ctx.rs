@@ -10,8 +10,10 @@ use redisgears_plugin_api::redisgears_plugin_api::{
 };
 
 use v8_rs::v8::{
-    isolate::V8Isolate, v8_context::V8Context, v8_object_template::V8PersistedObjectTemplate,
-    v8_promise::V8PromiseState, v8_script::V8PersistedScript,
+    isolate::V8Isolate, isolate_scope::V8IsolateScope, v8_context::V8Context,
+    v8_context_scope::V8ContextScope, v8_module::V8PersistedModule,
+    v8_object_template::V8PersistedObjectTemplate, v8_promise::V8PromiseState,
+    v8_script::V8PersistedScript, v8_value::V8LocalValue, v8_value::V8PersistValue,
 };
 
 use redisgears_plugin_api::redisgears_plugin_api::RefCellWrapper;
@@ -77,9 +79,17 @@ impl GilStateCtx {
     }
 }
 
+/// The compiled library code, either a single script or
+/// the entry module of a library bundle.
+pub(crate) enum V8LibraryCode {
+    Script(V8PersistedScript),
+    Module(V8PersistedModule),
+}
+
 pub(crate) struct V8ScriptCtx {
-    pub(crate) script: V8PersistedScript,
+    pub(crate) code: V8LibraryCode,
     pub(crate) tensor_object_template: V8PersistedObjectTemplate,
+    make_iterable: RefCellWrapper<Option<V8PersistValue>>,
     pub(crate) ctx: V8Context,
     pub(crate) isolate: V8Isolate,
     pub(crate) compiled_library_api: Box<dyn CompiledLibraryInterface + Send + Sync>,
@@ -91,15 +101,18 @@ impl V8ScriptCtx {
     pub(crate) fn new(
         isolate: V8Isolate,
         ctx: V8Context,
-        script: V8PersistedScript,
+        code: V8LibraryCode,
         tensor_object_template: V8PersistedObjectTemplate,
         compiled_library_api: Box<dyn CompiledLibraryInterface + Send + Sync>,
     ) -> Self {
         Self {
             isolate,
             ctx,
-            script,
+            code,
             tensor_object_template,
+            make_iterable: RefCellWrapper {
+                ref_cell: RefCell::new(None),
+            },
             compiled_library_api,
             is_running: AtomicBool::new(false),
             lock_state: RefCellWrapper {
@@ -108,6 +121,26 @@ impl V8ScriptCtx {
         }
     }
 
+    /// Return a JS function that makes the given iterator object iterable, so it can be
+    /// used on a `for...of` loop. The function is compiled on first use and kept for the
+    /// lifetime of the script context.
+    pub(crate) fn get_make_iterable<'isolate_scope, 'isolate>(
+        &self,
+        isolate_scope: &'isolate_scope V8IsolateScope<'isolate>,
+        ctx_scope: &V8ContextScope<'isolate_scope, 'isolate>,
+    ) -> Option<V8LocalValue<'isolate_scope, 'isolate>> {
+        let mut make_iterable = self.make_iterable.ref_cell.borrow_mut();
+        if make_iterable.is_none() {
+            let f = ctx_scope
+                .compile(&isolate_scope.new_string(
+                    "(function(it) { it[Symbol.iterator] = function() { return this; }; return it; })",
+                ))?
+                .run(ctx_scope)?;
+            *make_iterable = Some(f.persist());
+        }
+        make_iterable.as_ref().map(|f| f.as_local(isolate_scope))
+    }
+
     pub(crate) fn before_run(&self) {
         self.is_running.store(true, Ordering::Relaxed);
     }
@@ -154,14 +187,15 @@ impl LibraryCtxInterface for V8LibraryCtx {
         let ctx_scope = self.script_ctx.ctx.enter(&isolate_scope);
         let trycatch = isolate_scope.new_try_catch();
 
-        let script = self.script_ctx.script.to_local(&isolate_scope);
-
         // set private content
         let _load_library_guard = self.script_ctx.ctx.set_private_data(0, &load_library_ctx);
 
         self.script_ctx.before_run();
         self.script_ctx.after_lock_gil();
-        let res = script.run(&ctx_scope);
+        let res = match &self.script_ctx.code {
+            V8LibraryCode::Script(script) => script.to_local(&isolate_scope).run(&ctx_scope),
+            V8LibraryCode::Module(module) => module.to_local(&isolate_scope).evaluate(&ctx_scope),
+        };
         self.script_ctx.before_release_gil();
         self.script_ctx.after_run();
 
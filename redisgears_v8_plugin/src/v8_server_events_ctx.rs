@@ -0,0 +1,96 @@
+/*
+ * Copyright Redis Ltd. 2018 - present
+ * Licensed under your choice of the Redis Source Available License 2.0 (RSALv2) or
+ * the Server Side Public License v1 (SSPLv1).
+ */
+
+use redisgears_plugin_api::redisgears_plugin_api::GearsApiError;
+use redisgears_plugin_api::redisgears_plugin_api::{
+    server_events_ctx::ServerEvent, server_events_ctx::ServerEventConsumerCtxInterface,
+    server_events_ctx::ServerEventRunCtxInterface,
+};
+
+use v8_rs::v8::{
+    isolate_scope::V8IsolateScope, v8_context_scope::V8ContextScope, v8_value::V8LocalValue,
+    v8_value::V8PersistValue,
+};
+
+use crate::v8_consumer_function::{V8ConsumerFunction, V8ConsumerFunctionData};
+use crate::v8_script_ctx::V8ScriptCtx;
+
+use std::sync::Arc;
+
+impl V8ConsumerFunctionData for ServerEvent {
+    fn to_v8_value<'isolate_scope, 'isolate>(
+        &self,
+        isolate_scope: &'isolate_scope V8IsolateScope<'isolate>,
+        ctx_scope: &V8ContextScope,
+    ) -> Option<V8LocalValue<'isolate_scope, 'isolate>> {
+        let event_data = isolate_scope.new_object();
+        event_data.set(
+            ctx_scope,
+            &isolate_scope.new_string("event").to_value(),
+            &isolate_scope
+                .new_string(self.get_type().as_str())
+                .to_value(),
+        );
+        if let ServerEvent::RoleChange { is_primary } = self {
+            event_data.set(
+                ctx_scope,
+                &isolate_scope.new_string("role").to_value(),
+                &isolate_scope
+                    .new_string(if *is_primary { "primary" } else { "replica" })
+                    .to_value(),
+            );
+        }
+        if let ServerEvent::ConfigChange { names } = self {
+            let names = names
+                .iter()
+                .map(|n| isolate_scope.new_string(n).to_value())
+                .collect::<Vec<V8LocalValue>>();
+            event_data.set(
+                ctx_scope,
+                &isolate_scope.new_string("configs").to_value(),
+                &isolate_scope
+                    .new_array(&names.iter().collect::<Vec<&V8LocalValue>>())
+                    .to_value(),
+            );
+        }
+        Some(event_data.to_value())
+    }
+}
+
+pub(crate) struct V8ServerEventCtx {
+    internal: Arc<V8ConsumerFunction>,
+    is_async: bool,
+}
+
+impl V8ServerEventCtx {
+    pub(crate) fn new(
+        persisted_function: V8PersistValue,
+        script_ctx: &Arc<V8ScriptCtx>,
+        is_async: bool,
+    ) -> Self {
+        Self {
+            internal: Arc::new(V8ConsumerFunction::new(persisted_function, script_ctx)),
+            is_async,
+        }
+    }
+}
+
+impl ServerEventConsumerCtxInterface for V8ServerEventCtx {
+    fn on_server_event(
+        &self,
+        event: &ServerEvent,
+        run_ctx: Box<dyn ServerEventRunCtxInterface>,
+        ack_callback: Box<dyn FnOnce(Result<(), GearsApiError>) + Send + Sync>,
+    ) {
+        self.internal.run(
+            self.is_async,
+            &|| run_ctx.get_redis_client(),
+            &|| run_ctx.get_background_redis_client(),
+            event.clone(),
+            ack_callback,
+        );
+    }
+}
@@ -4,7 +4,10 @@
  * the Server Side Public License v1 (SSPLv1).
  */
 
-use v8_rs::v8::{v8_promise::V8PromiseState, v8_value::V8LocalValue, v8_value::V8PersistValue};
+use v8_rs::v8::{
+    isolate_scope::V8IsolateScope, v8_context_scope::V8ContextScope, v8_object::V8LocalObject,
+    v8_promise::V8PromiseState, v8_value::V8LocalValue, v8_value::V8PersistValue,
+};
 
 use redisgears_plugin_api::redisgears_plugin_api::stream_ctx::{
     StreamCtxInterface, StreamProcessCtxInterface, StreamRecordAck, StreamRecordInterface,
@@ -24,6 +27,122 @@ use v8_derive::new_native_function;
 
 use crate::{get_error_from_object, get_exception_msg};
 
+/// The records given to the consumer callback, a single record
+/// or an array of records if the consumer process batches.
+enum V8StreamRecords {
+    Record(Box<dyn StreamRecordInterface + Send>),
+    Batch(Vec<Box<dyn StreamRecordInterface + Send>>),
+}
+
+impl V8StreamRecords {
+    fn to_v8_value<'isolate_scope, 'isolate>(
+        &self,
+        isolate_scope: &'isolate_scope V8IsolateScope<'isolate>,
+        ctx_scope: &V8ContextScope,
+        stream_name: &[u8],
+    ) -> V8LocalValue<'isolate_scope, 'isolate> {
+        match self {
+            V8StreamRecords::Record(record) => {
+                stream_record_to_v8_object(isolate_scope, ctx_scope, stream_name, record.as_ref())
+                    .to_value()
+            }
+            V8StreamRecords::Batch(records) => {
+                let records = records
+                    .iter()
+                    .map(|record| {
+                        stream_record_to_v8_object(
+                            isolate_scope,
+                            ctx_scope,
+                            stream_name,
+                            record.as_ref(),
+                        )
+                        .to_value()
+                    })
+                    .collect::<Vec<V8LocalValue>>();
+                isolate_scope
+                    .new_array(&records.iter().collect::<Vec<&V8LocalValue>>())
+                    .to_value()
+            }
+        }
+    }
+}
+
+fn stream_record_to_v8_object<'isolate_scope, 'isolate>(
+    isolate_scope: &'isolate_scope V8IsolateScope<'isolate>,
+    ctx_scope: &V8ContextScope,
+    stream_name: &[u8],
+    record: &dyn StreamRecordInterface,
+) -> V8LocalObject<'isolate_scope, 'isolate> {
+    let id = record.get_id();
+    let id_v8_arr = isolate_scope.new_array(&[
+        &isolate_scope.new_long(id.0 as i64),
+        &isolate_scope.new_long(id.1 as i64),
+    ]);
+    let stream_name_v8_str = match std::str::from_utf8(stream_name) {
+        Ok(s) => isolate_scope.new_string(s).to_value(),
+        Err(_) => isolate_scope.new_null(),
+    };
+
+    let vals = record
+        .fields()
+        .map(|(f, v)| {
+            let f = match str::from_utf8(f) {
+                Ok(s) => isolate_scope.new_string(s).to_value(),
+                Err(_) => isolate_scope.new_null(),
+            };
+            let v = match str::from_utf8(v) {
+                Ok(s) => isolate_scope.new_string(s).to_value(),
+                Err(_) => isolate_scope.new_null(),
+            };
+            isolate_scope.new_array(&[&f, &v]).to_value()
+        })
+        .collect::<Vec<V8LocalValue>>();
+
+    let raw_vals = record
+        .fields()
+        .map(|(f, v)| {
+            isolate_scope
+                .new_array(&[
+                    &isolate_scope.new_array_buffer(f).to_value(),
+                    &isolate_scope.new_array_buffer(v).to_value(),
+                ])
+                .to_value()
+        })
+        .collect::<Vec<V8LocalValue>>();
+
+    let val_v8_arr = isolate_scope.new_array(&vals.iter().collect::<Vec<&V8LocalValue>>());
+
+    let raw_val_v8_arr = isolate_scope.new_array(&raw_vals.iter().collect::<Vec<&V8LocalValue>>());
+
+    let stream_data = isolate_scope.new_object();
+    stream_data.set(
+        ctx_scope,
+        &isolate_scope.new_string("id").to_value(),
+        &id_v8_arr.to_value(),
+    );
+    stream_data.set(
+        ctx_scope,
+        &isolate_scope.new_string("stream_name").to_value(),
+        &stream_name_v8_str,
+    );
+    stream_data.set(
+        ctx_scope,
+        &isolate_scope.new_string("stream_name_raw").to_value(),
+        &isolate_scope.new_array_buffer(stream_name).to_value(),
+    );
+    stream_data.set(
+        ctx_scope,
+        &isolate_scope.new_string("record").to_value(),
+        &val_v8_arr.to_value(),
+    );
+    stream_data.set(
+        ctx_scope,
+        &isolate_scope.new_string("record_raw").to_value(),
+        &raw_val_v8_arr.to_value(),
+    );
+    stream_data
+}
+
 struct V8StreamAckCtx {
     ack: Option<Box<dyn FnOnce(StreamRecordAck) + Send>>,
 }
@@ -53,87 +172,49 @@ impl V8StreamCtx {
             is_async,
         }
     }
+
+    fn process_records(
+        &self,
+        stream_name: &[u8],
+        records: V8StreamRecords,
+        run_ctx: &dyn StreamProcessCtxInterface,
+        ack_callback: Box<dyn FnOnce(StreamRecordAck) + Send>,
+    ) -> Option<StreamRecordAck> {
+        if self.is_async {
+            let internals = Arc::clone(&self.internals);
+            let stream_name: Vec<u8> = stream_name.to_vec();
+            let bg_redis_client = run_ctx.get_background_redis_client();
+            self.internals
+                .script_ctx
+                .compiled_library_api
+                .run_on_background(Box::new(move || {
+                    internals.process_record_internal_async(
+                        &stream_name.clone(),
+                        records,
+                        bg_redis_client,
+                        ack_callback,
+                    );
+                }));
+            None
+        } else {
+            self.internals
+                .process_record_internal_sync(stream_name, records, run_ctx)
+        }
+    }
 }
 
 impl V8StreamCtxInternals {
     fn process_record_internal_sync(
         &self,
         stream_name: &[u8],
-        record: Box<dyn StreamRecordInterface>,
+        records: V8StreamRecords,
         run_ctx: &dyn StreamProcessCtxInterface,
     ) -> Option<StreamRecordAck> {
         let isolate_scope = self.script_ctx.isolate.enter();
         let ctx_scope = self.script_ctx.ctx.enter(&isolate_scope);
         let trycatch = isolate_scope.new_try_catch();
 
-        let id = record.get_id();
-        let id_v8_arr = isolate_scope.new_array(&[
-            &isolate_scope.new_long(id.0 as i64),
-            &isolate_scope.new_long(id.1 as i64),
-        ]);
-        let stream_name_v8_str = match std::str::from_utf8(stream_name) {
-            Ok(s) => isolate_scope.new_string(s).to_value(),
-            Err(_) => isolate_scope.new_null(),
-        };
-
-        let vals = record
-            .fields()
-            .map(|(f, v)| {
-                let f = match str::from_utf8(f) {
-                    Ok(s) => isolate_scope.new_string(s).to_value(),
-                    Err(_) => isolate_scope.new_null(),
-                };
-                let v = match str::from_utf8(v) {
-                    Ok(s) => isolate_scope.new_string(s).to_value(),
-                    Err(_) => isolate_scope.new_null(),
-                };
-                isolate_scope.new_array(&[&f, &v]).to_value()
-            })
-            .collect::<Vec<V8LocalValue>>();
-
-        let raw_vals = record
-            .fields()
-            .map(|(f, v)| {
-                isolate_scope
-                    .new_array(&[
-                        &isolate_scope.new_array_buffer(f).to_value(),
-                        &isolate_scope.new_array_buffer(v).to_value(),
-                    ])
-                    .to_value()
-            })
-            .collect::<Vec<V8LocalValue>>();
-
-        let val_v8_arr = isolate_scope.new_array(&vals.iter().collect::<Vec<&V8LocalValue>>());
-
-        let raw_val_v8_arr =
-            isolate_scope.new_array(&raw_vals.iter().collect::<Vec<&V8LocalValue>>());
-
-        let stream_data = isolate_scope.new_object();
-        stream_data.set(
-            &ctx_scope,
-            &isolate_scope.new_string("id").to_value(),
-            &id_v8_arr.to_value(),
-        );
-        stream_data.set(
-            &ctx_scope,
-            &isolate_scope.new_string("stream_name").to_value(),
-            &stream_name_v8_str,
-        );
-        stream_data.set(
-            &ctx_scope,
-            &isolate_scope.new_string("stream_name_raw").to_value(),
-            &isolate_scope.new_array_buffer(stream_name).to_value(),
-        );
-        stream_data.set(
-            &ctx_scope,
-            &isolate_scope.new_string("record").to_value(),
-            &val_v8_arr.to_value(),
-        );
-        stream_data.set(
-            &ctx_scope,
-            &isolate_scope.new_string("record_raw").to_value(),
-            &raw_val_v8_arr.to_value(),
-        );
+        let stream_data = records.to_v8_value(&isolate_scope, &ctx_scope, stream_name);
 
         let c = run_ctx.get_redis_client();
         let mut redis_client = RedisClient::new();
@@ -146,10 +227,10 @@ impl V8StreamCtxInternals {
 
         self.script_ctx.before_run();
         self.script_ctx.after_lock_gil();
-        let res = self.persisted_function.as_local(&isolate_scope).call(
-            &ctx_scope,
-            Some(&[&r_client.to_value(), &stream_data.to_value()]),
-        );
+        let res = self
+            .persisted_function
+            .as_local(&isolate_scope)
+            .call(&ctx_scope, Some(&[&r_client.to_value(), &stream_data]));
         self.script_ctx.before_release_gil();
         self.script_ctx.after_run();
 
@@ -168,7 +249,7 @@ impl V8StreamCtxInternals {
     fn process_record_internal_async(
         &self,
         stream_name: &[u8],
-        record: Box<dyn StreamRecordInterface>,
+        records: V8StreamRecords,
         redis_client: Box<dyn BackgroundRunFunctionCtxInterface>,
         ack_callback: Box<dyn FnOnce(StreamRecordAck) + Send>,
     ) {
@@ -180,74 +261,7 @@ impl V8StreamCtxInternals {
             let ctx_scope = self.script_ctx.ctx.enter(&isolate_scope);
             let trycatch = isolate_scope.new_try_catch();
 
-            let id = record.get_id();
-            let id_v8_arr = isolate_scope.new_array(&[
-                &isolate_scope.new_long(id.0 as i64),
-                &isolate_scope.new_long(id.1 as i64),
-            ]);
-            let stream_name_v8_str = match std::str::from_utf8(stream_name) {
-                Ok(s) => isolate_scope.new_string(s).to_value(),
-                Err(_) => isolate_scope.new_null(),
-            };
-
-            let vals = record
-                .fields()
-                .map(|(f, v)| {
-                    let f = match str::from_utf8(f) {
-                        Ok(s) => isolate_scope.new_string(s).to_value(),
-                        Err(_) => isolate_scope.new_null(),
-                    };
-                    let v = match str::from_utf8(v) {
-                        Ok(s) => isolate_scope.new_string(s).to_value(),
-                        Err(_) => isolate_scope.new_null(),
-                    };
-                    isolate_scope.new_array(&[&f, &v]).to_value()
-                })
-                .collect::<Vec<V8LocalValue>>();
-
-            let raw_vals = record
-                .fields()
-                .map(|(f, v)| {
-                    isolate_scope
-                        .new_array(&[
-                            &isolate_scope.new_array_buffer(f).to_value(),
-                            &isolate_scope.new_array_buffer(v).to_value(),
-                        ])
-                        .to_value()
-                })
-                .collect::<Vec<V8LocalValue>>();
-
-            let val_v8_arr = isolate_scope.new_array(&vals.iter().collect::<Vec<&V8LocalValue>>());
-
-            let raw_val_v8_arr =
-                isolate_scope.new_array(&raw_vals.iter().collect::<Vec<&V8LocalValue>>());
-
-            let stream_data = isolate_scope.new_object();
-            stream_data.set(
-                &ctx_scope,
-                &isolate_scope.new_string("id").to_value(),
-                &id_v8_arr.to_value(),
-            );
-            stream_data.set(
-                &ctx_scope,
-                &isolate_scope.new_string("stream_name").to_value(),
-                &stream_name_v8_str,
-            );
-            stream_data.set(
-                &ctx_scope,
-                &isolate_scope.new_string("stream_name_raw").to_value(),
-                &isolate_scope.new_array_buffer(stream_name).to_value(),
-            );
-            stream_data.set(
-                &ctx_scope,
-                &isolate_scope.new_string("record").to_value(),
-                &val_v8_arr.to_value(),
-            );
-            stream_data.set(
-                &ctx_scope,
-                &isolate_scope.new_string("record_raw").to_value(),
-                &raw_val_v8_arr.to_value(),
-            );
+            let stream_data = records.to_v8_value(&isolate_scope, &ctx_scope, stream_name);
 
             let r_client = get_backgrounnd_client(
                 &self.script_ctx,
@@ -257,10 +271,10 @@ impl V8StreamCtxInternals {
             );
 
             self.script_ctx.before_run();
-            let res = self.persisted_function.as_local(&isolate_scope).call(
-                &ctx_scope,
-                Some(&[&r_client.to_value(), &stream_data.to_value()]),
-            );
+            let res = self
+                .persisted_function
+                .as_local(&isolate_scope)
+                .call(&ctx_scope, Some(&[&r_client.to_value(), &stream_data]));
             self.script_ctx.after_run();
 
             match res {
@@ -327,25 +341,26 @@ impl StreamCtxInterface for V8StreamCtx {
         run_ctx: &dyn StreamProcessCtxInterface,
         ack_callback: Box<dyn FnOnce(StreamRecordAck) + Send>,
     ) -> Option<StreamRecordAck> {
-        if self.is_async {
-            let internals = Arc::clone(&self.internals);
-            let stream_name: Vec<u8> = stream_name.to_vec();
-            let bg_redis_client = run_ctx.get_background_redis_client();
-            self.internals
-                .script_ctx
-                .compiled_library_api
-                .run_on_background(Box::new(move || {
-                    internals.process_record_internal_async(
-                        &stream_name.clone(),
-                        record,
-                        bg_redis_client,
-                        ack_callback,
-                    );
-                }));
-            None
-        } else {
-            self.internals
-                .process_record_internal_sync(stream_name, record, run_ctx)
-        }
+        self.process_records(
+            stream_name,
+            V8StreamRecords::Record(record),
+            run_ctx,
+            ack_callback,
+        )
+    }
+
+    fn process_batch(
+        &self,
+        stream_name: &[u8],
+        records: Vec<Box<dyn StreamRecordInterface + Send>>,
+        run_ctx: &dyn StreamProcessCtxInterface,
+        ack_callback: Box<dyn FnOnce(StreamRecordAck) + Send>,
+    ) -> Option<StreamRecordAck> {
+        self.process_records(
+            stream_name,
+            V8StreamRecords::Batch(records),
+            run_ctx,
+            ack_callback,
+        )
     }
 }
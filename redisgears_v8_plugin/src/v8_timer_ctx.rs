@@ -0,0 +1,51 @@
+/*
+ * Copyright Redis Ltd. 2018 - present
+ * Licensed under your choice of the Redis Source Available License 2.0 (RSALv2) or
+ * the Server Side Public License v1 (SSPLv1).
+ */
+
+use redisgears_plugin_api::redisgears_plugin_api::GearsApiError;
+use redisgears_plugin_api::redisgears_plugin_api::{
+    timer_ctx::TimerCtxInterface, timer_ctx::TimerRunCtxInterface,
+};
+
+use v8_rs::v8::v8_value::V8PersistValue;
+
+use crate::v8_consumer_function::V8ConsumerFunction;
+use crate::v8_script_ctx::V8ScriptCtx;
+
+use std::sync::Arc;
+
+pub(crate) struct V8TimerCtx {
+    internal: Arc<V8ConsumerFunction>,
+    is_async: bool,
+}
+
+impl V8TimerCtx {
+    pub(crate) fn new(
+        persisted_function: V8PersistValue,
+        script_ctx: &Arc<V8ScriptCtx>,
+        is_async: bool,
+    ) -> Self {
+        Self {
+            internal: Arc::new(V8ConsumerFunction::new(persisted_function, script_ctx)),
+            is_async,
+        }
+    }
+}
+
+impl TimerCtxInterface for V8TimerCtx {
+    fn on_timer_fired(
+        &self,
+        run_ctx: Box<dyn TimerRunCtxInterface>,
+        ack_callback: Box<dyn FnOnce(Result<(), GearsApiError>) + Send + Sync>,
+    ) {
+        self.internal.run(
+            self.is_async,
+            &|| run_ctx.get_redis_client(),
+            &|| run_ctx.get_background_redis_client(),
+            (),
+            ack_callback,
+        );
+    }
+}